@@ -0,0 +1,136 @@
+// Lights and the clusters they are assigned to, written by
+// lynch::lights::encode_lights and light_clusters.comp. Define
+// LIGHTS_BINDING and CLUSTERS_BINDING before including.
+
+#ifndef CLUSTERS_ACCESS
+#define CLUSTERS_ACCESS readonly
+#endif
+
+#define LIGHT_POINT 0
+#define LIGHT_SPOT 1
+#define LIGHT_DIRECTIONAL 2
+
+struct GpuLight {
+    // xyz position, w range.
+    vec4 position_range;
+    // xyz linear colour, w intensity.
+    vec4 color_intensity;
+    // xyz direction, w kind.
+    vec4 direction_kind;
+    // Cosines of the inner and outer spot angles.
+    vec4 spot;
+};
+
+layout (set = 2, binding = LIGHTS_BINDING) readonly buffer Lights {
+    mat4 view;
+    // Projection x and y scale, near and far planes.
+    vec4 projection;
+    // Lights, directional lights.
+    uvec4 counts;
+    // Tiles across and down, slices, lights per cluster.
+    uvec4 grid;
+    GpuLight lights[];
+} lightsSSBO;
+
+// The light count of every cluster, followed by grid.w light indices for
+// each.
+layout (set = 2, binding = CLUSTERS_BINDING) CLUSTERS_ACCESS buffer Clusters {
+    uint cluster_data[];
+} clustersSSBO;
+
+uint cluster_count() {
+    uvec4 grid = lightsSSBO.grid;
+    return grid.x * grid.y * grid.z;
+}
+
+uint cluster_slice(float depth) {
+    float near = lightsSSBO.projection.z;
+    float far = lightsSSBO.projection.w;
+    float slice = log(depth / near) / log(far / near) * float(lightsSSBO.grid.z);
+    return min(uint(max(floor(slice), 0.0)), lightsSSBO.grid.z - 1);
+}
+
+// The cluster of the pixel at frag_coord, depth in front of the camera.
+uint cluster_index(vec2 frag_coord, vec2 viewport, float depth) {
+    uvec4 grid = lightsSSBO.grid;
+    uvec2 tile = min(uvec2(frag_coord / viewport * vec2(grid.xy)), grid.xy - 1);
+    return (cluster_slice(depth) * grid.y + tile.y) * grid.x + tile.x;
+}
+
+uint cluster_light_count(uint cluster) {
+    return clustersSSBO.cluster_data[cluster];
+}
+
+GpuLight cluster_light(uint cluster, uint i) {
+    uint index = clustersSSBO.cluster_data[cluster_count() + cluster * lightsSSBO.grid.w + i];
+    return lightsSSBO.lights[index];
+}
+
+float slice_depth(uint slice) {
+    float near = lightsSSBO.projection.z;
+    float far = lightsSSBO.projection.w;
+    return near * pow(far / near, float(slice) / float(lightsSSBO.grid.z));
+}
+
+// View space box around a cluster, tile rows counting down from the top.
+void cluster_bounds(uint cluster, out vec3 bounds_min, out vec3 bounds_max) {
+    uvec4 grid = lightsSSBO.grid;
+    uint x = cluster % grid.x;
+    uint y = (cluster / grid.x) % grid.y;
+    uint slice = cluster / (grid.x * grid.y);
+
+    float near = slice_depth(slice);
+    float far = slice_depth(slice + 1);
+    vec2 scale = 1.0 / lightsSSBO.projection.xy;
+    vec2 lower = vec2(-1.0 + 2.0 * float(x) / float(grid.x), 1.0 - 2.0 * float(y + 1) / float(grid.y));
+    vec2 upper = vec2(-1.0 + 2.0 * float(x + 1) / float(grid.x), 1.0 - 2.0 * float(y) / float(grid.y));
+
+    vec3 near_min = vec3(lower * scale * near, -near);
+    vec3 near_max = vec3(upper * scale * near, -near);
+    vec3 far_min = vec3(lower * scale * far, -far);
+    vec3 far_max = vec3(upper * scale * far, -far);
+    bounds_min = min(near_min, far_min);
+    bounds_max = max(near_max, far_max);
+}
+
+// World space sphere holding everything a point or spot light reaches.
+vec4 light_bounding_sphere(GpuLight light) {
+    vec3 position = light.position_range.xyz;
+    float range = light.position_range.w;
+    if (uint(light.direction_kind.w) != LIGHT_SPOT) {
+        return vec4(position, range);
+    }
+    vec3 direction = light.direction_kind.xyz;
+    float cos_outer = light.spot.y;
+    if (cos_outer > 0.70710678) {
+        float radius = range / (2.0 * cos_outer);
+        return vec4(position + direction * radius, radius);
+    }
+    float sin_outer = sqrt(1.0 - cos_outer * cos_outer);
+    return vec4(position + direction * range * cos_outer, range * sin_outer);
+}
+
+// Radiance arriving at world_pos from light, and the direction towards it
+// in light_dir.
+vec3 light_radiance(GpuLight light, vec3 world_pos, out vec3 light_dir) {
+    vec3 radiance = light.color_intensity.rgb * light.color_intensity.w;
+    uint kind = uint(light.direction_kind.w);
+    if (kind == LIGHT_DIRECTIONAL) {
+        light_dir = -light.direction_kind.xyz;
+        return radiance;
+    }
+
+    vec3 to_light = light.position_range.xyz - world_pos;
+    float distance_squared = max(dot(to_light, to_light), 1e-4);
+    light_dir = to_light * inversesqrt(distance_squared);
+
+    // Inverse square falloff, windowed to reach zero at the range.
+    float range = light.position_range.w;
+    float window = clamp(1.0 - pow(distance_squared / (range * range), 2.0), 0.0, 1.0);
+    float attenuation = window * window / distance_squared;
+    if (kind == LIGHT_SPOT) {
+        float cos_angle = dot(-light_dir, light.direction_kind.xyz);
+        attenuation *= smoothstep(light.spot.y, light.spot.x, cos_angle);
+    }
+    return radiance * attenuation;
+}
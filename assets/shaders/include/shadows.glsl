@@ -0,0 +1,187 @@
+// Shadows of the sun's cascades and of point and spot lights in the shadow
+// atlas, written by lynch::render_tools::shadow. Define SHADOW_MAP_BINDING,
+// and with LIGHT_SHADOWS also SHADOW_ATLAS_BINDING and SHADOW_FACES_BINDING,
+// before including. Light shadows need include/clustered_lights.glsl
+// included first.
+
+#define SHADOW_FILTER_HARD 0
+#define SHADOW_FILTER_PCF 1
+#define SHADOW_FILTER_PCSS 2
+
+#ifndef SHADOW_FILTER
+#define SHADOW_FILTER SHADOW_FILTER_PCF
+#endif
+
+// 0 when shadows are turned off, so the sun lights everything.
+#ifndef SUN_SHADOWS
+#define SUN_SHADOWS 1
+#endif
+
+#ifndef SHADOW_BIAS
+#define SHADOW_BIAS 0.0005
+#endif
+
+#define MAX_CASCADES 8
+#define POISSON_SAMPLES 16
+
+layout (std140, set = 3, binding = 0) uniform ShadowParams {
+    mat4 cascade_matrices[MAX_CASCADES];
+    // Split depth of every cascade, four to a vector.
+    vec4 cascade_splits[MAX_CASCADES / 4];
+    // Cascades, cascade resolution, atlas size.
+    uvec4 counts;
+    // PCF: radius. PCSS: light size, blocker samples, filter samples.
+    vec4 filter_params;
+} shadowmapParams;
+
+layout (set = 2, binding = SHADOW_MAP_BINDING) uniform sampler2DArray shadow_map;
+
+#ifdef LIGHT_SHADOWS
+struct ShadowFace {
+    mat4 view_projection;
+    // Offset and scale in the atlas.
+    vec4 rect;
+};
+
+layout (set = 2, binding = SHADOW_ATLAS_BINDING) uniform sampler2D shadow_atlas;
+
+layout (set = 2, binding = SHADOW_FACES_BINDING) readonly buffer ShadowFaces {
+    ShadowFace faces[];
+} shadowFacesSSBO;
+#endif
+
+const vec2 POISSON_DISK[POISSON_SAMPLES] = vec2[](
+    vec2(-0.94201624, -0.39906216), vec2(0.94558609, -0.76890725),
+    vec2(-0.09418410, -0.92938870), vec2(0.34495938, 0.29387760),
+    vec2(-0.91588581, 0.45771432), vec2(-0.81544232, -0.87912464),
+    vec2(-0.38277543, 0.27676845), vec2(0.97484398, 0.75648379),
+    vec2(0.44323325, -0.97511554), vec2(0.53742981, -0.47373420),
+    vec2(-0.26496911, -0.41893023), vec2(0.79197514, 0.19090188),
+    vec2(-0.24188840, 0.99706507), vec2(-0.81409955, 0.91437590),
+    vec2(0.19984126, 0.78641367), vec2(0.14383161, -0.14100790)
+);
+
+// Depth stored at uv of cascade layer, or of the atlas tile rect when layer
+// is negative. Atlas samples are kept inside the tile.
+float shadow_map_depth(vec2 uv, int layer, vec4 rect) {
+#ifdef LIGHT_SHADOWS
+    if (layer < 0) {
+        vec2 margin = 0.5 / (rect.zw * float(shadowmapParams.counts.z));
+        return texture(shadow_atlas, rect.xy + clamp(uv, margin, 1.0 - margin) * rect.zw).r;
+    }
+#endif
+    return texture(shadow_map, vec3(uv, float(layer))).r;
+}
+
+float shadow_compare(vec2 uv, float depth, int layer, vec4 rect) {
+    return depth - SHADOW_BIAS > shadow_map_depth(uv, layer, rect) ? 0.0 : 1.0;
+}
+
+// How lit a point at coord, in shadow map UVs and depth, is. 1 is fully lit.
+float filter_shadow(vec3 coord, int layer, vec4 rect, float texel_size) {
+    vec4 params = shadowmapParams.filter_params;
+#if SHADOW_FILTER == SHADOW_FILTER_PCF
+    int radius = int(params.x);
+    float lit = 0.0;
+    for (int x = -radius; x <= radius; x++) {
+        for (int y = -radius; y <= radius; y++) {
+            lit += shadow_compare(coord.xy + vec2(x, y) * texel_size, coord.z, layer, rect);
+        }
+    }
+    float width = float(2 * radius + 1);
+    return lit / (width * width);
+#elif SHADOW_FILTER == SHADOW_FILTER_PCSS
+    float light_size = params.x;
+    int blocker_samples = clamp(int(params.y), 1, POISSON_SAMPLES);
+    int filter_samples = clamp(int(params.z), 1, POISSON_SAMPLES);
+
+    // Average depth of whatever blocks the light around the point.
+    float blocker_depth = 0.0;
+    float blockers = 0.0;
+    for (int i = 0; i < blocker_samples; i++) {
+        float depth = shadow_map_depth(coord.xy + POISSON_DISK[i] * light_size, layer, rect);
+        if (depth < coord.z - SHADOW_BIAS) {
+            blocker_depth += depth;
+            blockers += 1.0;
+        }
+    }
+    if (blockers == 0.0) {
+        return 1.0;
+    }
+    blocker_depth /= blockers;
+
+    // Penumbrae widen with the distance from the blockers.
+    float penumbra = (coord.z - blocker_depth) / max(blocker_depth, 1e-4) * light_size;
+    float radius = max(penumbra, texel_size);
+    float lit = 0.0;
+    for (int i = 0; i < filter_samples; i++) {
+        lit += shadow_compare(coord.xy + POISSON_DISK[i] * radius, coord.z, layer, rect);
+    }
+    return lit / float(filter_samples);
+#else
+    return shadow_compare(coord.xy, coord.z, layer, rect);
+#endif
+}
+
+// Shadow map UVs and depth of world_pos seen through view_projection, with
+// the y flip of the viewport shadows are rendered with.
+vec3 shadow_coord(mat4 view_projection, vec3 world_pos) {
+    vec4 clip = view_projection * vec4(world_pos, 1.0);
+    vec3 ndc = clip.xyz / clip.w;
+    return vec3(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5, ndc.z);
+}
+
+// How lit by the sun world_pos, view_depth in front of the camera, is.
+float sun_shadow(vec3 world_pos, float view_depth) {
+#if !SUN_SHADOWS
+    return 1.0;
+#else
+    uint count = shadowmapParams.counts.x;
+    if (count == 0) {
+        return 1.0;
+    }
+    uint cascade = count - 1;
+    for (uint i = 0; i < count; i++) {
+        if (view_depth < shadowmapParams.cascade_splits[i / 4][i % 4]) {
+            cascade = i;
+            break;
+        }
+    }
+
+    vec3 coord = shadow_coord(shadowmapParams.cascade_matrices[cascade], world_pos);
+    if (coord.z > 1.0) {
+        return 1.0;
+    }
+    return filter_shadow(coord, int(cascade), vec4(0.0), 1.0 / float(shadowmapParams.counts.y));
+#endif
+}
+
+#ifdef LIGHT_SHADOWS
+// How lit by light world_pos is. Point lights pick the face of their cube
+// along the major axis towards world_pos.
+float light_shadow(GpuLight light, vec3 world_pos) {
+    int face = int(light.spot.z);
+    if (face < 0) {
+        return 1.0;
+    }
+    if (uint(light.direction_kind.w) == LIGHT_POINT) {
+        vec3 to_point = world_pos - light.position_range.xyz;
+        vec3 extent = abs(to_point);
+        if (extent.x >= extent.y && extent.x >= extent.z) {
+            face += to_point.x > 0.0 ? 0 : 1;
+        } else if (extent.y >= extent.z) {
+            face += to_point.y > 0.0 ? 2 : 3;
+        } else {
+            face += to_point.z > 0.0 ? 4 : 5;
+        }
+    }
+
+    ShadowFace shadow_face = shadowFacesSSBO.faces[face];
+    vec3 coord = shadow_coord(shadow_face.view_projection, world_pos);
+    if (any(lessThan(coord, vec3(0.0))) || any(greaterThan(coord, vec3(1.0)))) {
+        return 1.0;
+    }
+    float resolution = shadow_face.rect.z * float(shadowmapParams.counts.z);
+    return filter_shadow(coord, -1, shadow_face.rect, 1.0 / resolution);
+}
+#endif
@@ -1,10 +1,16 @@
 use frost::obb::CollisionPoint;
 use frost::physics::math::physics_system;
-use frost::{Input, RigidBody, SearchIter, World};
+use frost::{Input, IntoSystem, RetrieveError, RigidBody, SearchIter, System, World};
 use glam::{Mat4, Vec3};
 use imgui::{Condition, FontConfig, FontGlyphRanges, FontSource, Ui};
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
-use lynch::render_graph::RenderGraph;
+use lynch::culling::{CullingSettings, CullingStatistics};
+use lynch::render_graph::export::{ExportedEdge, GraphExport};
+use lynch::render_graph::compile::AccessKind;
+use lynch::render_graph::{MemoryUsage, RenderGraph};
+use lynch::assets::Handle;
+use lynch::gltf_loader::Model;
+use lynch::scene::{animation_state_system, animation_system, spawn_gltf, GlobalTransform, ScenePose};
 
 use lynch::vulkan::renderer::RenderStatistics;
 
@@ -15,7 +21,6 @@ use crate::{
     EngineSettings,
     DEFAULT_UPDATE_RATE,
 };
-use frost::System;
 use std::collections::HashMap;
 use std::sync::mpsc::{self, Sender};
 
@@ -23,7 +28,19 @@ use std::sync::mpsc::{self, Sender};
 use std::time::{Duration, Instant};
 use winit::event::{Event, WindowEvent};
 use winit::event_loop::{ControlFlow, EventLoop};
+
+/// Passes added after the built-in ones, reloaded when the file changes.
+const RENDER_GRAPH_FILE: &str = "assets/render_graph.ron";
 pub struct GfxLocation(pub usize);
+/// A glTF scene drawn at `location` whose entities are spawned once the
+/// renderer has loaded its model.
+struct PendingScene {
+    model: Handle<Model>,
+    transform: Mat4,
+    location: usize,
+}
+/// A system with its parameters erased, see [`IntoSystem`].
+type ScheduledSystem = Box<dyn FnMut(&World, f32) -> Result<(), RetrieveError> + Send + Sync>;
 #[derive(PartialEq, Eq, Hash)]
 pub enum Schedule {
     OnStart,
@@ -38,7 +55,7 @@ pub struct CooperApplication {
     event_loop: EventLoop<()>,
     engine_settings: EngineSettings,
     ui: CooperUI,
-    systems: HashMap<Schedule, Vec<Box<dyn System<World>>>>
+    systems: HashMap<Schedule, Vec<ScheduledSystem>>
 }
 
 
@@ -128,6 +145,9 @@ pub enum GameEvent {
     Input,
     MoveEvent(usize, Mat4),
     Spawn(String),
+    /// Spawns the nodes of a glTF as entities, animated and drawn at the
+    /// given transform.
+    SpawnScene(String, Mat4),
     NextFrame,
 }
 pub struct DebugInfo {
@@ -138,7 +158,13 @@ pub struct DebugInfo {
     frame_rate: f32,
     fixed_frame_rate: f32,
     mesh_instances: usize,
+    culling: CullingStatistics,
+    graph_memory: MemoryUsage,
     opened: bool,
+    graph_opened: bool,
+    /// Snapshot shown by the render graph view, taken when it opens or is
+    /// refreshed.
+    graph_export: Option<GraphExport>,
 }
 impl DebugInfo {
     pub fn new(
@@ -158,7 +184,11 @@ impl DebugInfo {
             frame_rate,
             fixed_frame_rate,
             mesh_instances,
+            culling: CullingStatistics::default(),
+            graph_memory: MemoryUsage::default(),
             opened: true,
+            graph_opened: false,
+            graph_export: None,
         }
     }
     pub fn update(
@@ -170,6 +200,8 @@ impl DebugInfo {
         frame_rate: f32,
         fixed_frame_rate: f32,
         mesh_instances: usize,
+        culling: CullingStatistics,
+        graph_memory: MemoryUsage,
     ) -> &Self {
         self.camera_location = camera_location;
         self.recent_collisions.extend(recent_collisions);
@@ -177,6 +209,8 @@ impl DebugInfo {
         self.fixed_delta_time = fixed_delta_time;
         self.frame_rate = frame_rate;
         self.mesh_instances = mesh_instances;
+        self.culling = culling;
+        self.graph_memory = graph_memory;
         self.fixed_frame_rate = fixed_frame_rate;
         return self;
     }
@@ -230,13 +264,11 @@ impl CooperApplication {
         let mut run = true;
         let mut rigidbody_list: Vec<RigidBody> = vec![];
         let mut render_statistics = RenderStatistics::default();
+        let mut pending_scenes: Vec<PendingScene> = vec![];
 
         if let Some(onstart_systems) = self.systems.get_mut(&Schedule::OnStart) {
             for onstart_system in onstart_systems.iter_mut() {
-                onstart_system
-                    .as_mut()
-                    .run_fixed( &mut world, self.engine_settings.fixed_update_rate.as_secs_f32())
-                    .unwrap();
+                onstart_system(&world, self.engine_settings.fixed_update_rate.as_secs_f32()).unwrap();
             }
         }
 
@@ -282,9 +314,7 @@ impl CooperApplication {
                         }
                         if let Some(onfixed_update_system) = self.systems.get_mut(&Schedule::OnFixedUpdate) {
                             for onfixed_update_system in onfixed_update_system.iter_mut() {
-                                onfixed_update_system
-                                    .as_mut()
-                                    .run_fixed( &mut world, self.engine_settings.fixed_update_rate.as_secs_f32())
+                                onfixed_update_system(&world, self.engine_settings.fixed_update_rate.as_secs_f32())
                                     .unwrap();
                             }
                         }
@@ -306,6 +336,15 @@ impl CooperApplication {
                         }
                     }
 
+                    if input.key_pressed(winit::event::VirtualKeyCode::G) {
+                        debug_info.graph_opened = !debug_info.graph_opened;
+                    }
+                    match (debug_info.graph_opened, &debug_info.graph_export) {
+                        (false, _) => debug_info.graph_export = None,
+                        (true, None) => debug_info.graph_export = Some(self.graph.export()),
+                        (true, Some(_)) => {}
+                    }
+
                     let mut gui_frame = self.ui.gui.frame();
                     Self::debug_ui(
                         gui_frame,
@@ -314,10 +353,21 @@ impl CooperApplication {
                         &mut physics_control,
                         &input,
                         &mut world,
+                        &mut self.renderer.internal_renderer.culling_settings,
                     );
+                    let refresh = match &debug_info.graph_export {
+                        Some(graph_export) => {
+                            Self::render_graph_ui(gui_frame, &mut debug_info.graph_opened, graph_export)
+                        }
+                        None => false,
+                    };
+                    if refresh {
+                        debug_info.graph_export = None;
+                    }
                     ui_func(&mut run, &mut gui_frame);
 
                     let draw_data = self.ui.gui.render();
+                    self.renderer.internal_renderer.lights = lynch::lights::gather_lights(&world);
                     render_statistics.full_render_time = self.renderer.render(
                         &mut self.graph,
                         &self.camera,
@@ -333,12 +383,20 @@ impl CooperApplication {
                     update(&update_transmitter, render_statistics.full_render_time);
                     if let Some(on_update_system) = self.systems.get_mut(&Schedule::OnUpdate) {
                         for on_update_system in on_update_system.iter_mut() {
-                            on_update_system
-                                .as_mut()
-                                .run_fixed(&mut world, render_statistics.full_render_time)
-                                .unwrap();
+                            on_update_system(&world, render_statistics.full_render_time).unwrap();
                         }
                     }
+                    Self::spawn_loaded_scenes(&self.renderer, &mut world, &mut pending_scenes);
+                    // spawned glTF scenes carrying a GfxLocation follow their pose
+                    world
+                        .search::<(&GfxLocation, &GlobalTransform, &ScenePose)>()
+                        .unwrap()
+                        .iter()
+                        .for_each(|(gfx, transform, pose)| {
+                            let renderer = &mut self.renderer.internal_renderer;
+                            renderer.apply_pose(gfx.0, pose);
+                            renderer.instances[gfx.0].transform = transform.0;
+                        });
                     // submit input data to camera
                     self.camera
                         .update(&input, render_statistics.full_render_time);
@@ -365,6 +423,9 @@ impl CooperApplication {
                                         let translation = Mat4::default();
                                         self.renderer.add_model(sphere, translation);
                                     }
+                                    GameEvent::SpawnScene(path, transform) => {
+                                        pending_scenes.push(Self::load_scene(&mut self.renderer, &path, transform));
+                                    }
                                     GameEvent::NextFrame => {
                                         // MARK FRAME COMPLETE
                                         break;
@@ -399,6 +460,8 @@ impl CooperApplication {
                         1. / render_statistics.full_render_time,
                         count as f32,
                         self.renderer.internal_renderer.instances.len(),
+                        render_statistics.culling,
+                        render_statistics.graph_memory,
                     );
                     input.end_frame();
                 }
@@ -410,6 +473,10 @@ impl CooperApplication {
                     }
                     WindowEvent::Resized(resize_value) => {
                         self.renderer.resize(resize_value);
+                        if resize_value.height > 0 {
+                            self.camera
+                                .set_aspect_ratio(resize_value.width as f32 / resize_value.height as f32);
+                        }
                     }
                     WindowEvent::MouseInput { .. }
                     | WindowEvent::CursorMoved { .. }
@@ -430,6 +497,7 @@ impl CooperApplication {
         physics_control: &mut PhysicsControl,
         input: &Input,
         world: &mut World,
+        culling_settings: &mut CullingSettings,
     ) {
         if input.key_pressed(winit::event::VirtualKeyCode::M) {
             debug_info.opened = !debug_info.opened;
@@ -439,13 +507,38 @@ impl CooperApplication {
             .window("Debug Menu")
             .opened(&mut debug_info.opened)
             .position([1000.0, 20.0], Condition::Appearing)
-            .size([300.0, 100.0], Condition::Appearing);
+            .size([300.0, 270.0], Condition::Appearing);
 
         w.build(|| {
             gui_frame.text(format!("FPS: {}", debug_info.frame_rate));
             gui_frame.text(format!("Delta Time: {}", debug_info.delta_time));
 
             gui_frame.text(format!("Camera location: {:?}", debug_info.camera_location));
+
+            let culling = &debug_info.culling;
+            gui_frame.separator();
+            gui_frame.text(format!("Meshes drawn: {} / {}", culling.visible(), culling.meshes));
+            gui_frame.text(format!("Frustum culled: {}", culling.frustum_culled));
+            gui_frame.text(format!("Occlusion culled: {}", culling.occlusion_culled));
+            gui_frame.text(format!(
+                "Occluders: {} ({} triangles)",
+                culling.occluders, culling.occluder_triangles
+            ));
+            gui_frame.checkbox("Frustum culling", &mut culling_settings.frustum);
+            gui_frame.checkbox("Occlusion culling", &mut culling_settings.occlusion);
+
+            let memory = &debug_info.graph_memory;
+            let mib = |bytes: u64| bytes as f32 / (1024.0 * 1024.0);
+            gui_frame.separator();
+            gui_frame.text(format!(
+                "Transient memory: {:.1} / {:.1} MiB",
+                mib(memory.allocated),
+                mib(memory.requested)
+            ));
+            gui_frame.text(format!(
+                "Transient resources: {} in {} blocks",
+                memory.resources, memory.blocks
+            ));
         });
 
         gui_frame
@@ -508,6 +601,194 @@ impl CooperApplication {
                 physics_control.step = gui_frame.button("Step");
             });
     }
+    /// Draws the snapshot `graph` of a frame's render graph: passes in
+    /// compiled order on top, the resources they use below, culled passes
+    /// greyed out at the end. Returns whether a fresh snapshot was asked for.
+    fn render_graph_ui(gui_frame: &mut Ui, opened: &mut bool, graph: &GraphExport) -> bool {
+        let mut refresh = false;
+        const NODE_SIZE: [f32; 2] = [160.0, 36.0];
+        const NODE_SPACING: f32 = 24.0;
+        const ROW_SPACING: f32 = 110.0;
+
+        gui_frame
+            .window("Render Graph")
+            .opened(opened)
+            .position([20.0, 420.0], Condition::Appearing)
+            .size([900.0, 300.0], Condition::Appearing)
+            .build(|| {
+                refresh = gui_frame.button("Refresh");
+                gui_frame.same_line();
+                for (label, path, json) in [
+                    ("Export DOT", "render_graph.dot", false),
+                    ("Export JSON", "render_graph.json", true),
+                ] {
+                    if gui_frame.button(label) {
+                        let contents = match json {
+                            true => graph.to_json(),
+                            false => graph.to_dot(),
+                        };
+                        match std::fs::write(path, contents) {
+                            Ok(()) => log::info!("Wrote the render graph to {}", path),
+                            Err(err) => log::error!("Failed to write {}: {}", path, err),
+                        }
+                    }
+                    gui_frame.same_line();
+                }
+                gui_frame.new_line();
+
+                gui_frame.child_window("graph").horizontal_scrollbar(true).build(|| {
+                    let origin = gui_frame.cursor_screen_pos();
+                    let node_min = |column: usize, row: usize| {
+                        [
+                            origin[0] + column as f32 * (NODE_SIZE[0] + NODE_SPACING),
+                            origin[1] + row as f32 * (NODE_SIZE[1] + ROW_SPACING),
+                        ]
+                    };
+                    let node_max = |min: [f32; 2]| [min[0] + NODE_SIZE[0], min[1] + NODE_SIZE[1]];
+
+                    let mut passes: Vec<usize> = (0..graph.passes.len()).collect();
+                    passes.sort_by_key(|&pass| graph.passes[pass].position.unwrap_or(usize::MAX));
+                    let pass_column = |pass: usize| passes.iter().position(|&other| other == pass).unwrap();
+                    // Resources sit under the first pass using them.
+                    let mut resources: Vec<_> = graph
+                        .resources
+                        .iter()
+                        .filter_map(|resource| {
+                            let first = graph
+                                .edges
+                                .iter()
+                                .filter(|edge| edge.resource == resource.resource)
+                                .map(|edge| pass_column(edge.pass))
+                                .min()?;
+                            Some((first, resource))
+                        })
+                        .collect();
+                    resources.sort_by_key(|(first, _)| *first);
+                    let resource_column = |edge: &ExportedEdge| {
+                        resources.iter().position(|(_, resource)| resource.resource == edge.resource)
+                    };
+
+                    let draw_list = gui_frame.get_window_draw_list();
+                    for edge in &graph.edges {
+                        let Some(column) = resource_column(edge) else { continue };
+                        let pass = node_min(pass_column(edge.pass), 0);
+                        let resource = node_min(column, 1);
+                        let from = [pass[0] + NODE_SIZE[0] * 0.5, pass[1] + NODE_SIZE[1]];
+                        let to = [resource[0] + NODE_SIZE[0] * 0.5, resource[1]];
+                        let color = match edge.kind {
+                            AccessKind::Read => [0.4, 0.8, 0.4, 1.0],
+                            AccessKind::Write => [0.9, 0.5, 0.3, 1.0],
+                            AccessKind::ReadWrite => [0.9, 0.8, 0.3, 1.0],
+                        };
+                        let bend = ROW_SPACING * 0.5;
+                        draw_list
+                            .add_bezier_curve(from, [from[0], from[1] + bend], [to[0], to[1] - bend], to, color)
+                            .thickness(1.5)
+                            .build();
+                    }
+
+                    let mut hovered_pass = None;
+                    let mut hovered_resource = None;
+                    for (column, &pass) in passes.iter().enumerate() {
+                        let exported = &graph.passes[pass];
+                        let min = node_min(column, 0);
+                        let color = match (exported.position, exported.presentation) {
+                            (None, _) => [0.25, 0.25, 0.25, 1.0],
+                            (_, true) => [0.25, 0.45, 0.25, 1.0],
+                            _ => [0.2, 0.3, 0.5, 1.0],
+                        };
+                        draw_list.add_rect(min, node_max(min), color).filled(true).rounding(4.0).build();
+                        draw_list.with_clip_rect(min, node_max(min), || {
+                            draw_list.add_text([min[0] + 4.0, min[1] + 4.0], [1.0, 1.0, 1.0, 1.0], &exported.name);
+                        });
+                        if gui_frame.is_mouse_hovering_rect(min, node_max(min)) {
+                            hovered_pass = Some(pass);
+                        }
+                    }
+                    for (column, (_, resource)) in resources.iter().enumerate() {
+                        let min = node_min(column, 1);
+                        let color = match resource.transient {
+                            true => [0.45, 0.35, 0.15, 1.0],
+                            false => [0.35, 0.3, 0.25, 1.0],
+                        };
+                        draw_list.add_rect(min, node_max(min), color).filled(true).rounding(12.0).build();
+                        draw_list.with_clip_rect(min, node_max(min), || {
+                            draw_list.add_text([min[0] + 6.0, min[1] + 4.0], [1.0, 1.0, 1.0, 1.0], &resource.name);
+                            draw_list.add_text([min[0] + 6.0, min[1] + 18.0], [0.8, 0.8, 0.8, 1.0], resource.summary());
+                        });
+                        if gui_frame.is_mouse_hovering_rect(min, node_max(min)) {
+                            hovered_resource = Some(*resource);
+                        }
+                    }
+                    drop(draw_list);
+
+                    let columns = passes.len().max(resources.len());
+                    let extent = node_max(node_min(columns.saturating_sub(1), 1));
+                    gui_frame.dummy([extent[0] - origin[0], extent[1] - origin[1]]);
+
+                    if let Some(pass) = hovered_pass {
+                        gui_frame.tooltip(|| {
+                            let exported = &graph.passes[pass];
+                            gui_frame.text(&exported.name);
+                            match exported.position {
+                                Some(position) => gui_frame.text(format!("Position: {}", position)),
+                                None => gui_frame.text("Culled"),
+                            }
+                            if let Some(pipeline) = graph.pipelines.get(exported.pipeline) {
+                                for path in pipeline.shader_paths() {
+                                    gui_frame.text(path);
+                                }
+                            }
+                            gui_frame.separator();
+                            for transition in graph.transitions.iter().filter(|transition| transition.pass == pass) {
+                                let name = graph.resource(transition.resource).map_or("?", |resource| &resource.name);
+                                gui_frame.text(format!("{}: {:?} -> {:?}", name, transition.from, transition.to));
+                            }
+                        });
+                    }
+                    if let Some(resource) = hovered_resource {
+                        gui_frame.tooltip(|| {
+                            gui_frame.text(&resource.name);
+                            gui_frame.text(resource.summary());
+                            gui_frame.text(format!("{:.2} MiB", resource.size as f32 / (1024.0 * 1024.0)));
+                            if resource.transient {
+                                gui_frame.text("Transient");
+                            }
+                        });
+                    }
+                });
+            });
+        refresh
+    }
+    /// Starts loading the glTF at `path` and draws it once loaded. Its
+    /// entities are spawned by [`CooperApplication::spawn_loaded_scenes`].
+    fn load_scene(renderer: &mut VulkanRenderer, path: &str, transform: Mat4) -> PendingScene {
+        let model = renderer.load_model(path);
+        renderer.add_model(model.clone(), transform);
+        PendingScene {
+            model,
+            transform,
+            location: renderer.internal_renderer.instances.len() - 1,
+        }
+    }
+    /// Spawns the scenes whose model finished loading with [`spawn_gltf`],
+    /// from the model the renderer imported. The scene entity carries the
+    /// `GfxLocation` of the drawn model, so its `ScenePose` is applied every
+    /// frame.
+    fn spawn_loaded_scenes(renderer: &VulkanRenderer, world: &mut World, pending: &mut Vec<PendingScene>) {
+        pending.retain(|scene| {
+            let Some(model) = renderer.internal_renderer.assets.models.get(&scene.model) else {
+                return true;
+            };
+            match spawn_gltf(world, &model.scene, scene.transform) {
+                Ok(entity) => {
+                    world.add_component(entity, GfxLocation(scene.location)).unwrap();
+                }
+                Err(err) => log::error!("Spawning scene failed: {:?}", err),
+            }
+            false
+        });
+    }
     fn create_scene(&mut self) {
         self.renderer.initialize();
         self.build_scene();
@@ -527,11 +808,11 @@ pub struct CooperApplicationBuilder {
     window: Option<Window>,
     camera: Option<Camera>,
     engine_settings: Option<EngineSettings>,
-    systems: HashMap<Schedule, Vec<Box<dyn System<World>>>>
+    systems: HashMap<Schedule, Vec<ScheduledSystem>>
 }
 impl CooperApplicationBuilder {
     pub fn new() -> Self {
-        let systems : HashMap<Schedule, Vec<Box<dyn System<World>>>> = HashMap::new();
+        let systems: HashMap<Schedule, Vec<ScheduledSystem>> = HashMap::new();
         Self {
             window: None,
             camera: None,
@@ -539,14 +820,10 @@ impl CooperApplicationBuilder {
             systems
         }
     }
-    pub fn schedule_system(mut self, schedule: Schedule, system: Box<dyn System<World>>){
-        match self.systems.get_mut(&schedule){
-            Some(system_list) =>{ system_list.push(system) },
-            None => {
-                self.systems.insert(schedule, vec![system]);
-            }
-        }
-    }   
+    pub fn schedule_system<P>(mut self, schedule: Schedule, system: impl IntoSystem<P>) -> Self {
+        self.systems.entry(schedule).or_default().push(system.system());
+        self
+    }
     pub fn window(mut self, window:Window) -> Self{
         self.window = Some(window);
         self
@@ -583,12 +860,22 @@ impl CooperApplicationBuilder {
 
         let renderer = VulkanRenderer::create(&window, &camera, ui.mut_ui());
 
-        let graph = RenderGraph::new(
+        let mut graph = RenderGraph::new(
             renderer.vk_context.arc_device(),
             &renderer.camera_uniform_buffer,
             renderer.image_count,
         );
-        let systems = self.systems;
+        if std::path::Path::new(RENDER_GRAPH_FILE).exists() {
+            if let Err(err) = graph.load_declared(RENDER_GRAPH_FILE) {
+                log::error!("{}: {}", RENDER_GRAPH_FILE, err);
+            }
+        }
+        let mut systems = self.systems;
+        // Animation runs ahead of the user's systems, so they see this frame's pose.
+        systems.entry(Schedule::OnUpdate).or_default().splice(
+            0..0,
+            [animation_state_system.system(), animation_system.system()],
+        );
         CooperApplication {
             window,
             event_loop,
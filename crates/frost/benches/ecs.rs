@@ -1,4 +1,4 @@
-use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
 use frost::*;
 struct SampleStruct {
     a: i32,
@@ -45,6 +45,35 @@ fn insertions(world: &mut World, amount: usize){
         world.new_entity((SampleStruct{a:i as i32,b:i as f32,c:i as i64},)).unwrap();
     }
 }
+fn sample(i: usize) -> SampleStruct {
+    SampleStruct{a:i as i32,b:i as f32,c:i as i64}
+}
+fn sample_b(i: usize) -> SampleStructB {
+    SampleStructB{a:i as i32,b:i as f32,c:i as i64,d:1}
+}
+fn sample_c(i: usize) -> SampleStructC {
+    SampleStructC{a:i as i32,b:i as f32,c:i as i64,d:1}
+}
+fn world_spawn_batch(amount: usize){
+    let mut world = World::new();
+    world.spawn_batch((0..amount).map(|i| (sample(i),))).unwrap();
+}
+fn spawned_entities(amount: usize) -> (World, Vec<Entity>) {
+    let mut world = World::new();
+    let entities = world.spawn_batch((0..amount).map(|i| (sample(i),))).unwrap();
+    (world, entities)
+}
+fn world_add_component_each((mut world, entities): (World, Vec<Entity>)){
+    for (i, entity) in entities.into_iter().enumerate() {
+        world.add_component(entity, sample_b(i)).unwrap();
+        world.add_component(entity, sample_c(i)).unwrap();
+    }
+}
+fn world_add_components((mut world, entities): (World, Vec<Entity>)){
+    for (i, entity) in entities.into_iter().enumerate() {
+        world.add_components(entity, (sample_b(i), sample_c(i))).unwrap();
+    }
+}
 fn world_search(mut search: Search<(&SampleStruct,)>, _delta_time: f32){
     for _ in search.iter() {
     }
@@ -54,6 +83,18 @@ fn world_search_mut(mut search: Search<(&mut SampleStruct,)>, _delta_time: f32){
         i.a += 1;   
     }
 }
+fn world_iterate_serial(mut search: Search<(&mut SampleStruct, &SampleStructB)>, _delta_time: f32){
+    for (s, b) in search.iter() {
+        s.b = (s.b + b.b).sqrt() * 0.5;
+        s.c += b.c;
+    }
+}
+fn world_iterate_parallel(mut search: Search<(&mut SampleStruct, &SampleStructB)>, _delta_time: f32){
+    search.par_for_each(DEFAULT_BATCH_SIZE, |(s, b)| {
+        s.b = (s.b + b.b).sqrt() * 0.5;
+        s.c += b.c;
+    });
+}
 fn criterion_benchmark(c: &mut Criterion) {
     let sizes = [10,20,50,100,200,400,500,1000,1500,2000,5000,10000,20000,50000,100000,200000,500000,1000000];    
     for size in sizes {
@@ -76,6 +117,26 @@ fn criterion_benchmark(c: &mut Criterion) {
         });
         
     }
+    for size in [100, 1000, 10000, 100000] {
+        c.bench_with_input(BenchmarkId::new("Spawn Batch", size), &size, |b, &s| {
+            b.iter(||{world_spawn_batch(s)});
+        });
+        c.bench_with_input(BenchmarkId::new("Add Component (one at a time)", size), &size, |b, &s| {
+            b.iter_batched(||spawned_entities(s), world_add_component_each, BatchSize::LargeInput);
+        });
+        c.bench_with_input(BenchmarkId::new("Add Components (pack)", size), &size, |b, &s| {
+            b.iter_batched(||spawned_entities(s), world_add_components, BatchSize::LargeInput);
+        });
+    }
+    let size = 100000;
+    let mut world = World::new();
+    world.spawn_batch((0..size).map(|i| (sample(i), sample_b(i)))).unwrap();
+    c.bench_with_input(BenchmarkId::new("Iterate (serial)", size), &size, |b, _| {
+        b.iter(||{world_iterate_serial.run(&world, 0.0)});
+    });
+    c.bench_with_input(BenchmarkId::new("Iterate (parallel)", size), &size, |b, _| {
+        b.iter(||{world_iterate_parallel.run(&world, 0.0)});
+    });
 }
 
 criterion_group!(benches, criterion_benchmark);
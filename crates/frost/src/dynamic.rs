@@ -0,0 +1,797 @@
+use std::{
+    alloc::{self, Layout},
+    any::Any,
+    borrow::Cow,
+    ptr::{self, NonNull},
+    sync::{RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use smallvec::SmallVec;
+
+use crate::{
+    Archetype, ComponentAlreadyBorrowed, ComponentError, ComponentId, ComponentDescriptorMismatch,
+    ComponentNotInEntity, ComponentNotRegistered, ComponentStore, ComponentVec, Entity, EntityId, RetrieveError, World,
+};
+
+/// Describes a component type defined at runtime, e.g. by scripts or data
+/// files, which frost only knows by its memory layout.
+#[derive(Clone)]
+pub struct ComponentDescriptor {
+    name: String,
+    layout: Layout,
+    drop: Option<unsafe fn(*mut u8)>,
+}
+
+impl ComponentDescriptor {
+    /// # Safety
+    /// `drop` must be sound to call once on any value stored under this
+    /// descriptor, and those values must be safe to send and share between
+    /// threads.
+    pub unsafe fn new(
+        name: impl Into<String>,
+        layout: Layout,
+        drop: Option<unsafe fn(*mut u8)>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            layout,
+            drop,
+        }
+    }
+    /// Descriptor with the layout and drop glue of the Rust type `T`.
+    pub fn of<T: Send + Sync + 'static>(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            layout: Layout::new::<T>(),
+            drop: std::mem::needs_drop::<T>().then_some(drop_ptr::<T> as unsafe fn(*mut u8)),
+        }
+    }
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+}
+
+unsafe fn drop_ptr<T>(ptr: *mut u8) {
+    ptr.cast::<T>().drop_in_place()
+}
+
+/// Type erased column backing a dynamic component.
+pub(crate) struct BlobVec {
+    item_layout: Layout,
+    drop: Option<unsafe fn(*mut u8)>,
+    data: NonNull<u8>,
+    len: usize,
+    capacity: usize,
+}
+
+// Thread safety is part of the `ComponentDescriptor::new` contract.
+unsafe impl Send for BlobVec {}
+unsafe impl Sync for BlobVec {}
+
+impl BlobVec {
+    fn new(layout: Layout, drop: Option<unsafe fn(*mut u8)>) -> Self {
+        let item_layout = layout.pad_to_align();
+        Self {
+            item_layout,
+            drop,
+            data: NonNull::new(item_layout.align() as *mut u8).unwrap(),
+            len: 0,
+            capacity: if item_layout.size() == 0 { usize::MAX } else { 0 },
+        }
+    }
+
+    fn array_layout(&self, capacity: usize) -> Layout {
+        Layout::from_size_align(self.item_layout.size() * capacity, self.item_layout.align())
+            .unwrap()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        if self.capacity - self.len >= additional {
+            return;
+        }
+        let capacity = (self.capacity * 2).max(self.len + additional).max(4);
+        let layout = self.array_layout(capacity);
+        let data = unsafe {
+            if self.capacity == 0 {
+                alloc::alloc(layout)
+            } else {
+                alloc::realloc(
+                    self.data.as_ptr(),
+                    self.array_layout(self.capacity),
+                    layout.size(),
+                )
+            }
+        };
+        self.data = NonNull::new(data).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        self.capacity = capacity;
+    }
+
+    fn get_ptr(&self, index: usize) -> *mut u8 {
+        self.data
+            .as_ptr()
+            .wrapping_add(index * self.item_layout.size())
+    }
+
+    /// # Safety
+    /// `value` must point to a value matching the column layout. It is moved
+    /// into the column.
+    unsafe fn push(&mut self, value: *const u8) {
+        self.reserve(1);
+        ptr::copy_nonoverlapping(value, self.get_ptr(self.len), self.item_layout.size());
+        self.len += 1;
+    }
+
+    /// # Safety
+    /// Same as `push`, the previous value is dropped.
+    unsafe fn replace(&mut self, index: usize, value: *const u8) {
+        let slot = self.get_ptr(index);
+        if let Some(drop) = self.drop {
+            drop(slot);
+        }
+        ptr::copy_nonoverlapping(value, slot, self.item_layout.size());
+    }
+
+    fn swap_remove_and_drop(&mut self, index: usize) {
+        assert!(index < self.len);
+        let last = self.len - 1;
+        unsafe {
+            if index != last {
+                ptr::swap_nonoverlapping(
+                    self.get_ptr(index),
+                    self.get_ptr(last),
+                    self.item_layout.size(),
+                );
+            }
+            self.len = last;
+            if let Some(drop) = self.drop {
+                drop(self.get_ptr(last));
+            }
+        }
+    }
+
+    fn swap_remove_into(&mut self, index: usize, other: &mut BlobVec) {
+        assert!(index < self.len);
+        debug_assert_eq!(self.item_layout, other.item_layout);
+        let last = self.len - 1;
+        unsafe {
+            other.push(self.get_ptr(index));
+            if index != last {
+                ptr::copy_nonoverlapping(
+                    self.get_ptr(last),
+                    self.get_ptr(index),
+                    self.item_layout.size(),
+                );
+            }
+        }
+        self.len = last;
+    }
+}
+
+impl Drop for BlobVec {
+    fn drop(&mut self) {
+        if let Some(drop) = self.drop {
+            for i in 0..self.len {
+                unsafe { drop(self.get_ptr(i)) }
+            }
+        }
+        self.len = 0;
+        if self.item_layout.size() != 0 && self.capacity != 0 {
+            unsafe { alloc::dealloc(self.data.as_ptr(), self.array_layout(self.capacity)) }
+        }
+    }
+}
+
+impl ComponentVec for RwLock<BlobVec> {
+    fn to_any(&self) -> &dyn Any {
+        self
+    }
+    fn to_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn len(&mut self) -> usize {
+        self.get_mut().unwrap().len
+    }
+
+    fn swap_remove(&mut self, index: EntityId) {
+        self.get_mut().unwrap().swap_remove_and_drop(index as usize);
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.get_mut().unwrap().reserve(additional);
+    }
+
+    fn migrate(&mut self, entity_index: EntityId, other_component_vec: &mut dyn ComponentVec) {
+        let other = other_component_vec
+            .to_any_mut()
+            .downcast_mut::<RwLock<BlobVec>>()
+            .expect("dynamic component migrated into a typed column")
+            .get_mut()
+            .unwrap();
+        self.get_mut()
+            .unwrap()
+            .swap_remove_into(entity_index as usize, other);
+    }
+
+    fn new_same_type(&self) -> Box<dyn ComponentVec + Send + Sync> {
+        let blob = self.read().unwrap();
+        Box::new(RwLock::new(BlobVec::new(blob.item_layout, blob.drop)))
+    }
+
+    fn try_read_column(&self) -> Option<Box<dyn ColumnGuard + '_>> {
+        Some(Box::new(self.try_read().ok()?))
+    }
+
+    fn try_write_column(&self) -> Option<Box<dyn ColumnGuard + '_>> {
+        Some(Box::new(self.try_write().ok()?))
+    }
+}
+
+/// A held borrow of a whole column, typed or dynamic.
+pub(crate) trait ColumnGuard {
+    /// Pointer to the first element and the stride in bytes between elements.
+    fn column(&mut self) -> (*mut u8, usize);
+}
+
+impl<T> ColumnGuard for RwLockReadGuard<'_, Vec<T>> {
+    fn column(&mut self) -> (*mut u8, usize) {
+        (self.as_ptr() as *mut u8, std::mem::size_of::<T>())
+    }
+}
+
+impl<T> ColumnGuard for RwLockWriteGuard<'_, Vec<T>> {
+    fn column(&mut self) -> (*mut u8, usize) {
+        (self.as_mut_ptr() as *mut u8, std::mem::size_of::<T>())
+    }
+}
+
+impl ColumnGuard for RwLockReadGuard<'_, BlobVec> {
+    fn column(&mut self) -> (*mut u8, usize) {
+        (self.data.as_ptr(), self.item_layout.size())
+    }
+}
+
+impl ColumnGuard for RwLockWriteGuard<'_, BlobVec> {
+    fn column(&mut self) -> (*mut u8, usize) {
+        (self.data.as_ptr(), self.item_layout.size())
+    }
+}
+
+impl ComponentStore {
+    pub(crate) fn new_dynamic(
+        id: ComponentId,
+        layout: Layout,
+        drop: Option<unsafe fn(*mut u8)>,
+    ) -> Self {
+        Self {
+            id,
+            data: Box::new(RwLock::new(BlobVec::new(layout, drop))),
+        }
+    }
+}
+
+impl Archetype {
+    fn blob_store_mut(&mut self, component_index: usize) -> &mut BlobVec {
+        self.components[component_index]
+            .data
+            .to_any_mut()
+            .downcast_mut::<RwLock<BlobVec>>()
+            .expect("component is not dynamic")
+            .get_mut()
+            .unwrap()
+    }
+}
+
+/// Read borrow of a single component returned by [`World::get_by_id`].
+pub struct ComponentRef<'world> {
+    _guard: Box<dyn ColumnGuard + 'world>,
+    ptr: *const u8,
+}
+
+impl ComponentRef<'_> {
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+}
+
+/// Write borrow of a single component returned by [`World::get_by_id_mut`].
+pub struct ComponentRefMut<'world> {
+    _guard: Box<dyn ColumnGuard + 'world>,
+    ptr: *mut u8,
+}
+
+impl ComponentRefMut<'_> {
+    pub fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.ptr
+    }
+}
+
+impl World {
+    /// Registers a component type defined at runtime. Registering a name
+    /// again returns the id handed out the first time, as long as the layout
+    /// and drop function are the same.
+    pub fn register_component(
+        &mut self,
+        descriptor: ComponentDescriptor,
+    ) -> Result<ComponentId, ComponentError> {
+        if let Some(id) = self.component_id(&descriptor.name) {
+            let registered = self.component_descriptor(id).unwrap();
+            let same_drop = match (registered.drop, descriptor.drop) {
+                (Some(a), Some(b)) => std::ptr::fn_addr_eq(a, b),
+                (a, b) => a.is_none() && b.is_none(),
+            };
+            if registered.layout != descriptor.layout || !same_drop {
+                return Err(ComponentDescriptorMismatch::new_with_value(
+                    descriptor.name,
+                    registered.layout,
+                    descriptor.layout,
+                )
+                .into());
+            }
+            return Ok(id);
+        }
+        self.component_descriptors.push(descriptor);
+        Ok(ComponentId::Dynamic((self.component_descriptors.len() - 1) as u32))
+    }
+
+    pub fn component_id(&self, name: &str) -> Option<ComponentId> {
+        self.component_descriptors
+            .iter()
+            .position(|d| d.name == name)
+            .map(|i| ComponentId::Dynamic(i as u32))
+    }
+
+    pub fn component_descriptor(&self, id: ComponentId) -> Option<&ComponentDescriptor> {
+        match id {
+            ComponentId::Dynamic(index) => self.component_descriptors.get(index as usize),
+            ComponentId::Type(_) => None,
+        }
+    }
+
+    pub(crate) fn component_name(&self, id: ComponentId) -> Cow<'static, str> {
+        match self.component_descriptor(id) {
+            Some(descriptor) => Cow::Owned(descriptor.name.clone()),
+            None => Cow::Borrowed("Component"),
+        }
+    }
+
+    /// Adds or replaces the dynamic component `id` on `entity`.
+    ///
+    /// # Safety
+    /// `value` must point to a valid, initialized value of the type registered
+    /// for `id`, since the world drops it with that descriptor's drop function.
+    /// Ownership of the value moves into the world, even when the call
+    /// replaces an existing component, so the caller must not drop or reuse it
+    /// afterwards. On error the value is left untouched.
+    pub unsafe fn insert_by_id(
+        &mut self,
+        entity: Entity,
+        id: ComponentId,
+        value: *const u8,
+    ) -> Result<(), ComponentError> {
+        let entity_meta = self.entity_meta(entity)?;
+        let descriptor = self
+            .component_descriptor(id)
+            .ok_or_else(|| ComponentNotRegistered::new_with_value(id))?;
+        let (layout, drop) = (descriptor.layout, descriptor.drop);
+
+        let archetype = &mut self.archetypes[entity_meta.archetype_index() as usize];
+        match archetype.components.binary_search_by(|c| c.id.cmp(&id)) {
+            Ok(component_index) => {
+                archetype
+                    .blob_store_mut(component_index)
+                    .replace(entity_meta.index_in_archetype() as usize, value);
+            }
+            Err(insert_index) => {
+                let new_archetype_index =
+                    self.migrate_with(entity, entity_meta, insert_index, id, || {
+                        ComponentStore::new_dynamic(id, layout, drop)
+                    });
+                self.archetypes[new_archetype_index]
+                    .blob_store_mut(insert_index)
+                    .push(value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Untyped read access to any component of `entity`, typed or dynamic.
+    pub fn get_by_id(&self, entity: Entity, id: ComponentId) -> Result<ComponentRef<'_>, ComponentError> {
+        let (mut guard, row) = self.column_by_id(entity, id, false)?;
+        let (ptr, stride) = guard.column();
+        Ok(ComponentRef {
+            _guard: guard,
+            ptr: ptr.wrapping_add(row * stride),
+        })
+    }
+
+    /// Untyped write access to any component of `entity`, typed or dynamic.
+    pub fn get_by_id_mut(&self, entity: Entity, id: ComponentId) -> Result<ComponentRefMut<'_>, ComponentError> {
+        let (mut guard, row) = self.column_by_id(entity, id, true)?;
+        let (ptr, stride) = guard.column();
+        Ok(ComponentRefMut {
+            _guard: guard,
+            ptr: ptr.wrapping_add(row * stride),
+        })
+    }
+
+    fn column_by_id(
+        &self,
+        entity: Entity,
+        id: ComponentId,
+        write: bool,
+    ) -> Result<(Box<dyn ColumnGuard + '_>, usize), ComponentError> {
+        let entity_meta = self.entity_meta(entity)?;
+        let archetype = &self.archetypes[entity_meta.archetype_index() as usize];
+        let component_index = archetype
+            .component_index(id)
+            .ok_or_else(|| ComponentNotInEntity::new_with_name(entity.index, self.component_name(id)))?;
+
+        let data = &archetype.components[component_index].data;
+        let guard = match write {
+            true => data.try_write_column(),
+            false => data.try_read_column(),
+        }
+        .ok_or_else(ComponentAlreadyBorrowed::default)?;
+        Ok((guard, entity_meta.index_in_archetype() as usize))
+    }
+
+    /// Starts a search over a set of component ids only known at runtime.
+    pub fn dynamic_search(&self) -> DynamicSearchBuilder<'_> {
+        DynamicSearchBuilder {
+            world: self,
+            fetch: Vec::new(),
+            with: Vec::new(),
+            without: Vec::new(),
+        }
+    }
+}
+
+pub struct DynamicSearchBuilder<'world> {
+    world: &'world World,
+    fetch: Vec<(ComponentId, bool)>,
+    with: Vec<ComponentId>,
+    without: Vec<ComponentId>,
+}
+
+impl<'world> DynamicSearchBuilder<'world> {
+    /// Fetches `id` for reading, rows expose it at the position it was added.
+    pub fn read(mut self, id: ComponentId) -> Self {
+        self.fetch.push((id, false));
+        self
+    }
+    /// Fetches `id` for writing, rows expose it at the position it was added.
+    pub fn write(mut self, id: ComponentId) -> Self {
+        self.fetch.push((id, true));
+        self
+    }
+    /// Only matches archetypes containing `id` without fetching it.
+    pub fn with(mut self, id: ComponentId) -> Self {
+        self.with.push(id);
+        self
+    }
+    /// Only matches archetypes lacking `id`.
+    pub fn without(mut self, id: ComponentId) -> Self {
+        self.without.push(id);
+        self
+    }
+
+    pub fn build(self) -> Result<DynamicSearch<'world>, RetrieveError> {
+        let mut archetypes = Vec::new();
+        for archetype in self.world.archetypes.iter() {
+            let matches = self
+                .fetch
+                .iter()
+                .map(|(id, _)| id)
+                .chain(self.with.iter())
+                .all(|id| archetype.component_index(*id).is_some())
+                && self
+                    .without
+                    .iter()
+                    .all(|id| archetype.component_index(*id).is_none());
+            if !matches {
+                continue;
+            }
+
+            let mut columns = Vec::with_capacity(self.fetch.len());
+            for (id, write) in self.fetch.iter() {
+                let data = &archetype.components[archetype.component_index(*id).unwrap()].data;
+                let guard = match write {
+                    true => data.try_write_column(),
+                    false => data.try_read_column(),
+                };
+                let mut guard = guard.ok_or_else(|| {
+                    RetrieveError::ComponentAlreadyBorrowed(Default::default())
+                })?;
+                let (ptr, stride) = guard.column();
+                columns.push(DynamicColumn {
+                    _guard: guard,
+                    ptr,
+                    stride,
+                });
+            }
+            archetypes.push(DynamicArchetype {
+                entities: &archetype.entities,
+                columns,
+            });
+        }
+
+        Ok(DynamicSearch {
+            world: self.world,
+            archetypes,
+            writable: self.fetch.iter().map(|(_, write)| *write).collect(),
+        })
+    }
+}
+
+struct DynamicColumn<'world> {
+    _guard: Box<dyn ColumnGuard + 'world>,
+    ptr: *mut u8,
+    stride: usize,
+}
+
+struct DynamicArchetype<'world> {
+    entities: &'world [EntityId],
+    columns: Vec<DynamicColumn<'world>>,
+}
+
+/// Search over runtime component ids, built with [`World::dynamic_search`].
+/// Holds the column borrows until dropped, like a typed `Search`.
+pub struct DynamicSearch<'world> {
+    world: &'world World,
+    archetypes: Vec<DynamicArchetype<'world>>,
+    writable: Vec<bool>,
+}
+
+impl<'world> DynamicSearch<'world> {
+    pub fn len(&self) -> usize {
+        self.archetypes.iter().map(|a| a.entities.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn for_each(&mut self, mut f: impl FnMut(DynamicRow<'_>)) {
+        let mut pointers: SmallVec<[*mut u8; 8]> = SmallVec::new();
+        for archetype in self.archetypes.iter() {
+            for (row, index) in archetype.entities.iter().enumerate() {
+                pointers.clear();
+                pointers.extend(
+                    archetype
+                        .columns
+                        .iter()
+                        .map(|c| c.ptr.wrapping_add(row * c.stride)),
+                );
+                f(DynamicRow {
+                    entity: Entity {
+                        index: *index,
+                        generation: self.world.entities[*index as usize].generation,
+                    },
+                    pointers: &pointers,
+                    writable: &self.writable,
+                });
+            }
+        }
+    }
+}
+
+/// One matched entity, components are indexed in the order they were added
+/// to the builder.
+pub struct DynamicRow<'a> {
+    entity: Entity,
+    pointers: &'a [*mut u8],
+    writable: &'a [bool],
+}
+
+impl DynamicRow<'_> {
+    pub fn entity(&self) -> Entity {
+        self.entity
+    }
+    pub fn get(&self, index: usize) -> *const u8 {
+        self.pointers[index]
+    }
+    /// `None` if the component at `index` was only fetched for reading.
+    pub fn get_mut(&self, index: usize) -> Option<*mut u8> {
+        self.writable[index].then(|| self.pointers[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        mem::ManuallyDrop,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+
+    use super::*;
+
+    struct Name(&'static str);
+
+    fn position(world: &mut World) -> ComponentId {
+        let layout = Layout::new::<[f32; 2]>();
+        world
+            .register_component(unsafe { ComponentDescriptor::new("Position", layout, None) })
+            .unwrap()
+    }
+
+    fn insert<T>(world: &mut World, entity: Entity, id: ComponentId, value: T) {
+        let value = ManuallyDrop::new(value);
+        unsafe {
+            world
+                .insert_by_id(entity, id, &*value as *const T as *const u8)
+                .unwrap()
+        };
+    }
+
+    #[test]
+    fn register_is_idempotent() {
+        let mut world = World::new();
+        let id = position(&mut world);
+        assert_eq!(position(&mut world), id);
+        assert_eq!(world.component_id("Position"), Some(id));
+        assert_eq!(world.component_descriptor(id).unwrap().name(), "Position");
+
+        let result = world.register_component(ComponentDescriptor::of::<[f64; 2]>("Position"));
+        assert!(matches!(result, Err(ComponentError::ComponentDescriptorMismatch(_))));
+        assert_eq!(world.component_id("Position"), Some(id));
+    }
+
+    #[test]
+    fn register_rejects_another_drop() {
+        let mut world = World::new();
+        let id = world
+            .register_component(ComponentDescriptor::of::<String>("Label"))
+            .unwrap();
+
+        let layout = Layout::new::<String>();
+        let result =
+            world.register_component(unsafe { ComponentDescriptor::new("Label", layout, None) });
+        assert!(matches!(result, Err(ComponentError::ComponentDescriptorMismatch(_))));
+
+        let result = world.register_component(ComponentDescriptor::of::<String>("Label"));
+        assert_eq!(result.unwrap(), id);
+    }
+
+    #[test]
+    fn insert_and_get_by_id() {
+        let mut world = World::new();
+        let id = position(&mut world);
+        let entity = world.new_entity((Name("a"),)).unwrap();
+
+        insert(&mut world, entity, id, [1.0f32, 2.0]);
+        let value = unsafe { *(world.get_by_id(entity, id).unwrap().as_ptr() as *const [f32; 2]) };
+        assert_eq!(value, [1.0, 2.0]);
+
+        insert(&mut world, entity, id, [3.0f32, 4.0]);
+        {
+            let mut component = world.get_by_id_mut(entity, id).unwrap();
+            unsafe { (*(component.as_mut_ptr() as *mut [f32; 2]))[0] += 1.0 };
+        }
+        let value = unsafe { *(world.get_by_id(entity, id).unwrap().as_ptr() as *const [f32; 2]) };
+        assert_eq!(value, [4.0, 4.0]);
+
+        let name = world.get_by_id(entity, ComponentId::of::<Name>()).unwrap();
+        assert_eq!(unsafe { (*(name.as_ptr() as *const Name)).0 }, "a");
+    }
+
+    #[test]
+    fn unregistered_and_missing_components() {
+        let mut world = World::new();
+        let id = position(&mut world);
+        let entity = world.new_entity((Name("a"),)).unwrap();
+
+        let value = [0.0f32; 2];
+        let result =
+            unsafe { world.insert_by_id(entity, ComponentId::of::<Name>(), value.as_ptr() as *const u8) };
+        assert!(matches!(result, Err(ComponentError::ComponentNotRegistered(_))));
+        assert!(matches!(
+            world.get_by_id(entity, id),
+            Err(ComponentError::ComponentNotInEntity(_))
+        ));
+    }
+
+    #[test]
+    fn dynamic_search_matches_runtime_ids() {
+        let mut world = World::new();
+        let position = position(&mut world);
+        let velocity = world
+            .register_component(ComponentDescriptor::of::<[f32; 2]>("Velocity"))
+            .unwrap();
+
+        let moving = world.new_entity((Name("moving"),)).unwrap();
+        insert(&mut world, moving, position, [0.0f32, 0.0]);
+        insert(&mut world, moving, velocity, [1.0f32, 2.0]);
+        let still = world.new_entity((Name("still"),)).unwrap();
+        insert(&mut world, still, position, [5.0f32, 5.0]);
+
+        let mut search = world
+            .dynamic_search()
+            .write(position)
+            .read(velocity)
+            .with(ComponentId::of::<Name>())
+            .build()
+            .unwrap();
+        assert_eq!(search.len(), 1);
+        search.for_each(|row| {
+            assert!(row.entity() == moving);
+            assert!(row.get_mut(1).is_none());
+            let velocity = unsafe { *(row.get(1) as *const [f32; 2]) };
+            let position = unsafe { &mut *(row.get_mut(0).unwrap() as *mut [f32; 2]) };
+            position[0] += velocity[0];
+            position[1] += velocity[1];
+        });
+        drop(search);
+
+        let mut seen = Vec::new();
+        world
+            .dynamic_search()
+            .read(position)
+            .without(velocity)
+            .build()
+            .unwrap()
+            .for_each(|row| seen.push(row.entity()));
+        assert!(seen == vec![still]);
+
+        let value = unsafe { *(world.get_by_id(moving, position).unwrap().as_ptr() as *const [f32; 2]) };
+        assert_eq!(value, [1.0, 2.0]);
+    }
+
+    #[test]
+    fn conflicting_borrows_are_reported() {
+        let mut world = World::new();
+        let id = position(&mut world);
+        let entity = world.new_entity((Name("a"),)).unwrap();
+        insert(&mut world, entity, id, [0.0f32; 2]);
+
+        let _search = world.dynamic_search().write(id).build().unwrap();
+        assert!(world.dynamic_search().read(id).build().is_err());
+        assert!(matches!(
+            world.get_by_id(entity, id),
+            Err(ComponentError::ComponentAlreadyBorrowed(_))
+        ));
+    }
+
+    #[test]
+    fn dynamic_values_are_dropped() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        struct Counted(Arc<AtomicUsize>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let mut world = World::new();
+        let id = world
+            .register_component(ComponentDescriptor::of::<Counted>("Counted"))
+            .unwrap();
+        let entities: Vec<Entity> = (0..3)
+            .map(|_| world.new_entity((Name("a"),)).unwrap())
+            .collect();
+        for entity in entities.iter() {
+            insert(&mut world, *entity, id, Counted(drops.clone()));
+        }
+
+        insert(&mut world, entities[0], id, Counted(drops.clone()));
+        assert_eq!(drops.load(Ordering::SeqCst), 1);
+
+        world.despawn(entities[1]).unwrap();
+        assert_eq!(drops.load(Ordering::SeqCst), 2);
+
+        world.remove_by_id(entities[2], id).unwrap();
+        assert_eq!(drops.load(Ordering::SeqCst), 3);
+
+        drop(world);
+        assert_eq!(drops.load(Ordering::SeqCst), 4);
+    }
+}
@@ -1,4 +1,6 @@
-use crate::EntityId;
+use std::{alloc::Layout, borrow::Cow};
+
+use crate::{ComponentId, EntityId};
 
 pub trait FrostError: std::error::Error + std::fmt::Display {
     fn new() -> Self;
@@ -49,11 +51,14 @@ impl std::fmt::Display for EntityNotFound {
 impl std::error::Error for EntityNotFound {}
 
 #[derive(Debug)]
-pub struct ComponentNotInEntity(EntityId, &'static str);
+pub struct ComponentNotInEntity(EntityId, Cow<'static, str>);
 
 impl ComponentNotInEntity {
     pub fn new_with_value<T>(entity_id: EntityId) -> Self {
-        Self(entity_id, std::any::type_name::<T>())
+        Self(entity_id, Cow::Borrowed(std::any::type_name::<T>()))
+    }
+    pub fn new_with_name(entity_id: EntityId, name: impl Into<Cow<'static, str>>) -> Self {
+        Self(entity_id, name.into())
     }
 }
 impl std::fmt::Display for ComponentNotInEntity {
@@ -71,8 +76,55 @@ impl std::error::Error for ComponentNotInEntity {}
 pub enum ComponentError {
     ComponentNotInEntity(ComponentNotInEntity),
     EntityNotFound(EntityNotFound ),
+    ComponentNotRegistered(ComponentNotRegistered),
+    ComponentAlreadyBorrowed(ComponentAlreadyBorrowed),
+    ComponentDescriptorMismatch(ComponentDescriptorMismatch),
+}
+
+impl From<EntityNotFound> for ComponentError {
+    fn from(err: EntityNotFound) -> Self {
+        ComponentError::EntityNotFound(err)
+    }
+}
+
+impl From<ComponentNotInEntity> for ComponentError {
+    fn from(err: ComponentNotInEntity) -> Self {
+        ComponentError::ComponentNotInEntity(err)
+    }
+}
+
+impl From<ComponentNotRegistered> for ComponentError {
+    fn from(err: ComponentNotRegistered) -> Self {
+        ComponentError::ComponentNotRegistered(err)
+    }
+}
+
+impl From<ComponentAlreadyBorrowed> for ComponentError {
+    fn from(err: ComponentAlreadyBorrowed) -> Self {
+        ComponentError::ComponentAlreadyBorrowed(err)
+    }
+}
+
+impl From<ComponentDescriptorMismatch> for ComponentError {
+    fn from(err: ComponentDescriptorMismatch) -> Self {
+        ComponentError::ComponentDescriptorMismatch(err)
+    }
+}
+
+impl std::fmt::Display for ComponentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ComponentError::ComponentNotInEntity(err) => err.fmt(f),
+            ComponentError::EntityNotFound(err) => err.fmt(f),
+            ComponentError::ComponentNotRegistered(err) => err.fmt(f),
+            ComponentError::ComponentAlreadyBorrowed(err) => err.fmt(f),
+            ComponentError::ComponentDescriptorMismatch(err) => err.fmt(f),
+        }
+    }
 }
 
+impl std::error::Error for ComponentError {}
+
 #[derive(Debug)]
 pub enum RetrieveError {
     ComponentAlreadyBorrowed(ComponentAlreadyBorrowed),
@@ -120,4 +172,57 @@ impl std::fmt::Display for ComponentDoesNotExist {
     }
 }
 
-impl std::error::Error for ComponentDoesNotExist {}
\ No newline at end of file
+impl std::error::Error for ComponentDoesNotExist {}
+
+#[derive(Debug)]
+pub struct ComponentNotRegistered(ComponentId);
+
+impl ComponentNotRegistered {
+    pub fn new_with_value(id: ComponentId) -> Self {
+        Self(id)
+    }
+}
+impl std::fmt::Display for ComponentNotRegistered {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} is not a registered dynamic component", self.0)
+    }
+}
+
+impl std::error::Error for ComponentNotRegistered {}
+
+/// A dynamic component name registered again with another layout or drop
+/// function.
+#[derive(Debug)]
+pub struct ComponentDescriptorMismatch {
+    name: String,
+    registered: Layout,
+    requested: Layout,
+}
+
+impl ComponentDescriptorMismatch {
+    pub fn new_with_value(name: impl Into<String>, registered: Layout, requested: Layout) -> Self {
+        Self {
+            name: name.into(),
+            registered,
+            requested,
+        }
+    }
+}
+impl std::fmt::Display for ComponentDescriptorMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.registered == self.requested {
+            return write!(f, "[{}] is registered with another drop function", self.name);
+        }
+        write!(
+            f,
+            "[{}] is registered with size {} and alignment {}, not size {} and alignment {}",
+            self.name,
+            self.registered.size(),
+            self.registered.align(),
+            self.requested.size(),
+            self.requested.align()
+        )
+    }
+}
+
+impl std::error::Error for ComponentDescriptorMismatch {}
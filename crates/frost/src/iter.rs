@@ -95,19 +95,12 @@ impl<I: Iterator> Iterator for ChainedIterator<I> {
         // Chain the iterators together.
         // If the end of one iterator is reached go to the next.
 
-        match self.current_iter {
-            Some(ref mut iter) => match iter.next() {
-                None => {
-                    self.current_iter = self.iterators.pop();
-                    if let Some(ref mut iter) = self.current_iter {
-                        iter.next()
-                    } else {
-                        None
-                    }
-                }
-                item => item,
-            },
-            None => None,
+        // Empty archetypes are skipped rather than ending the chain.
+        loop {
+            match self.current_iter.as_mut()?.next() {
+                None => self.current_iter = self.iterators.pop(),
+                item => return item,
+            }
         }
     }
 
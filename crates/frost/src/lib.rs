@@ -1,3 +1,4 @@
+pub mod lights;
 pub mod math;
 pub mod obb;
 pub mod shapes;
@@ -8,20 +9,25 @@ mod iter;
 pub mod physics;
 mod utils;
 use utils::retrieve_two_mutable;
+mod dynamic;
 mod errors;
+mod lookup;
+mod parallel;
+mod relationship;
 mod search;
 mod system;
 use std::{
     any::{Any, TypeId},
-    borrow::BorrowMut,
     collections::HashMap,
     hash::{Hash, Hasher},
     sync::RwLock,
 };
 use std::collections::hash_map::DefaultHasher;
+use smallvec::SmallVec;
 
 pub use crate::{Retrieve, RetrieveError, SearchParameters, SearchRetrieve, Single, SingleMut};
 pub use input::Input;
+pub use lights::{light_direction, DirectionalLight, PointLight, SpotLight};
 pub(crate) type EntityId = u32;
 pub(crate) type Generation = EntityId;
 pub(crate) type PackId = u64;
@@ -30,13 +36,24 @@ pub use physics::*;
 pub use search::Search;
 pub use search::*;
 
+pub use dynamic::{
+    ComponentDescriptor, ComponentRef, ComponentRefMut, DynamicRow, DynamicSearch,
+    DynamicSearchBuilder,
+};
+use dynamic::ColumnGuard;
 pub use errors::*;
+pub use lookup::{LookupColumn, LookupColumnMut, Ref, SearchLookup};
+pub use parallel::{ParSearchIter, SearchChunks, DEFAULT_BATCH_SIZE};
+pub use relationship::{Related, Relations};
+use relationship::RelationIndex;
 pub use system::*;
 pub struct World {
     pub(crate) entities: Vec<EntityMeta>,
     available_entities: Vec<EntityId>,
     pack_id_to_archetype: HashMap<PackId, usize>,
     archetypes: Vec<Archetype>,
+    pub(crate) relations: RelationIndex,
+    pub(crate) component_descriptors: Vec<ComponentDescriptor>,
 }
 
 impl World {
@@ -46,28 +63,82 @@ impl World {
             entities: Vec::new(),
             pack_id_to_archetype: HashMap::new(),
             available_entities: Vec::new(),
+            relations: RelationIndex::default(),
+            component_descriptors: Vec::new(),
         }
     }
     pub fn new_entity(&mut self, components: impl ComponentPack) -> Result<Entity, WorldFull> {
-        let (index, generation) = if let Some(index) = self.available_entities.pop() {
-            let (generation, _) = self.entities[index as usize].generation.overflowing_add(1);
-            (index, generation)
-        } else {
-            self.entities.push(EntityMeta::null());
-
-            match self.entities.len() >= EntityId::MAX as usize {
-                true => return Err(WorldFull::new()),
-                false => (),
-            }
-            ((self.entities.len() - 1) as EntityId, 0)
-        };
+        let (index, generation) = self.allocate_entity()?;
 
         self.entities[index as usize] = EntityMeta {
             location: components.spawn(self, index),
-            generation: generation,
+            generation,
         };
         Ok(Entity { index, generation })
     }
+    /// Spawns every pack of `batch` into a single archetype, reserving its
+    /// storage up front from the iterator's size hint.
+    pub fn spawn_batch<P, I>(&mut self, batch: I) -> Result<Vec<Entity>, WorldFull>
+    where
+        P: ComponentPack,
+        I: IntoIterator<Item = P>,
+    {
+        let mut batch = batch.into_iter().peekable();
+        let first = match batch.peek() {
+            Some(first) => first,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut component_ids = P::component_ids();
+        component_ids.sort_unstable();
+        let pack_id = calculate_pack_id(&component_ids);
+        let archetype_index = match self.pack_id_to_archetype.get(&pack_id) {
+            Some(index) => *index,
+            None => {
+                let index = self.archetypes.len();
+                self.pack_id_to_archetype.insert(pack_id, index);
+                self.archetypes.push(first.new_archetype());
+                index
+            }
+        };
+
+        let additional = batch.size_hint().0;
+        self.entities
+            .reserve(additional.saturating_sub(self.available_entities.len()));
+        let archetype = &mut self.archetypes[archetype_index];
+        archetype.reserve(additional);
+        let columns = archetype.columns_of::<P>();
+
+        let mut spawned = Vec::with_capacity(additional);
+        for components in batch {
+            let (index, generation) = self.allocate_entity()?;
+            let archetype = &mut self.archetypes[archetype_index];
+            components.push_into(archetype, &columns);
+            archetype.entities.push(index);
+
+            self.entities[index as usize] = EntityMeta {
+                location: EntityLocation::new(
+                    archetype_index as EntityId,
+                    (archetype.len() - 1) as EntityId,
+                ),
+                generation,
+            };
+            spawned.push(Entity { index, generation });
+        }
+        Ok(spawned)
+    }
+    fn allocate_entity(&mut self) -> Result<(EntityId, Generation), WorldFull> {
+        // `despawn` already bumped the generation of recycled slots.
+        if let Some(index) = self.available_entities.pop() {
+            return Ok((index, self.entities[index as usize].generation));
+        }
+        self.entities.push(EntityMeta::null());
+
+        match self.entities.len() >= EntityId::MAX as usize {
+            true => Err(WorldFull::new()),
+            false => Ok(((self.entities.len() - 1) as EntityId, 0)),
+        }
+    }
     #[inline]
     pub fn retrieve_single<T: 'static>(&self) -> Result<Single<T>, RetrieveError> {
         <&T>::retrieve(self)
@@ -94,106 +165,343 @@ let search = world.search<(&bool, &String)>();
             Err(e) => Err(e),
         }
     }
-    pub fn add_component<T>(&mut self, entity: Entity, t: T) -> Result<(), EntityNotFound>
+    /// Returns true while `entity` has not been despawned.
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.entity_meta(entity).is_ok()
+    }
+    fn entity_meta(&self, entity: Entity) -> Result<EntityMeta, EntityNotFound> {
+        match self.entities.get(entity.index as usize) {
+            Some(entity_meta) if entity_meta.generation == entity.generation => Ok(*entity_meta),
+            _ => Err(EntityNotFound::new_with_value(entity.index)),
+        }
+    }
+    /// Removes `entity` and all of its components from the world.
+    ///
+    /// Relationships are cleaned up on both sides: the reverse index forgets
+    /// `entity` as a source, and every entity that was `Related` to it loses
+    /// that component.
+    pub fn despawn(&mut self, entity: Entity) -> Result<(), EntityNotFound> {
+        self.entity_meta(entity)?;
+
+        for (relation, source) in self.relations.remove_entity(entity) {
+            if source != entity {
+                self.remove_by_id(source, relation).ok();
+            }
+        }
+
+        let entity_meta = self.entities[entity.index as usize];
+        let moved = self.archetypes[entity_meta.archetype_index() as usize]
+            .remove_entity(entity_meta.index_in_archetype());
+        self.entities[moved as usize].location = entity_meta.location;
+
+        let entity_meta = &mut self.entities[entity.index as usize];
+        entity_meta.generation = entity_meta.generation.wrapping_add(1);
+        entity_meta.location = EntityLocation::null();
+        self.available_entities.push(entity.index);
+        Ok(())
+    }
+    pub fn remove_component<T>(&mut self, entity: Entity) -> Result<T, ComponentError>
     where
         T: 'static + Send + Sync,
     {
-        let entity_meta = self.entities[entity.index as usize];
+        let entity_meta = self.entity_meta(entity)?;
+        let archetype = &mut self.archetypes[entity_meta.archetype_index() as usize];
+        let component_index = archetype
+            .component_index(ComponentId::of::<T>())
+            .ok_or_else(|| ComponentNotInEntity::new_with_value::<T>(entity.index))?;
 
-        if entity_meta.generation != entity.generation {
-            return Err(EntityNotFound::new_with_value(entity.index));
-        }
-        let type_id = TypeId::of::<T>();
+        let component = archetype
+            .mutable_component_store::<T>(component_index)
+            .swap_remove(entity_meta.index_in_archetype() as usize);
+        self.migrate_without(entity, entity_meta, component_index);
+        self.relations.remove(ComponentId::of::<T>(), entity);
+        Ok(component)
+    }
+    /// Untyped counterpart of `remove_component`, the removed value is dropped.
+    pub fn remove_by_id(&mut self, entity: Entity, id: ComponentId) -> Result<(), ComponentError> {
+        let entity_meta = self.entity_meta(entity)?;
+        let component_index = self.archetypes[entity_meta.archetype_index() as usize]
+            .component_index(id)
+            .ok_or_else(|| ComponentNotInEntity::new_with_name(entity.index, self.component_name(id)))?;
 
-        let current_archetype = &self.archetypes[entity_meta.archetype_index() as usize];
+        self.archetypes[entity_meta.archetype_index() as usize].components[component_index]
+            .data
+            .swap_remove(entity_meta.index_in_archetype());
+        self.migrate_without(entity, entity_meta, component_index);
+        self.relations.remove(id, entity);
+        Ok(())
+    }
+    // Moves `entity` into the archetype lacking `removed_index`, whose value
+    // must already have been taken out of its column.
+    fn migrate_without(&mut self, entity: Entity, entity_meta: EntityMeta, removed_index: usize) {
+        let old_index = entity_meta.archetype_index() as usize;
+        let component_id = self.archetypes[old_index].components[removed_index].id;
 
-        let mut type_ids: Vec<TypeId> = current_archetype
-            .components
-            .iter()
-            .map(|c| c.type_id)
-            .collect();
+        let new_archetype_index = match self.archetypes[old_index].edges.remove.get(&component_id) {
+            Some(index) => *index,
+            None => {
+                let current_archetype = &self.archetypes[old_index];
+                let component_ids: Vec<ComponentId> = current_archetype
+                    .components
+                    .iter()
+                    .filter(|c| c.id != component_id)
+                    .map(|c| c.id)
+                    .collect();
+                let new_index = match self.archetype_with(&component_ids) {
+                    Some(index) => index,
+                    None => {
+                        let stores = current_archetype
+                            .components
+                            .iter()
+                            .filter(|c| c.id != component_id)
+                            .map(|c| c.new_same_type())
+                            .collect();
+                        self.push_archetype(&component_ids, stores)
+                    }
+                };
+                self.archetypes[old_index]
+                    .edges
+                    .remove
+                    .insert(component_id, new_index);
+                self.archetypes[new_index]
+                    .edges
+                    .add
+                    .insert(component_id, old_index);
+                new_index
+            }
+        };
+
+        self.move_entity(entity, entity_meta, new_archetype_index, &[component_id]);
+    }
+    pub fn add_component<T>(&mut self, entity: Entity, t: T) -> Result<(), EntityNotFound>
+    where
+        T: 'static + Send + Sync,
+    {
+        let entity_meta = self.entity_meta(entity)?;
+        let component_id = ComponentId::of::<T>();
 
-        let type_index = type_ids.binary_search(&type_id);
+        let archetype = &mut self.archetypes[entity_meta.archetype_index() as usize];
+        match archetype
+            .components
+            .binary_search_by(|c| c.id.cmp(&component_id))
+        {
+            Ok(component_index) => {
+                archetype.replace_component(component_index, entity_meta.index_in_archetype(), t);
+            }
+            Err(insert_index) => {
+                let new_archetype_index = self.migrate_with(
+                    entity,
+                    entity_meta,
+                    insert_index,
+                    component_id,
+                    ComponentStore::new::<T>,
+                );
+                self.archetypes[new_archetype_index].push(insert_index, t);
+            }
+        }
 
-        if let Ok(insert_index) = type_index {
-            let archetype = &mut self.archetypes[entity_meta.archetype_index() as usize];
+        Ok(())
+    }
+    /// Adds every component of `components` to `entity`, moving it between
+    /// archetypes at most once. Components the entity already has are replaced.
+    pub fn add_components<P: ComponentPack>(&mut self, entity: Entity, components: P) -> Result<(), EntityNotFound> {
+        let entity_meta = self.entity_meta(entity)?;
+        let old_index = entity_meta.archetype_index() as usize;
+        let pack_type = TypeId::of::<P>();
 
-            archetype.replace_component(insert_index, entity_meta.index_in_archetype(), t);
-        } else {
-            let insert_index = type_index.unwrap_or_else(|err| err);
-            type_ids.insert(insert_index, type_id);
-            let pack_id = calculate_pack_id(&type_ids);
-
-            let new_archetype_index: usize = match self.pack_id_to_archetype.get(&pack_id) {
-                Some(index) => *index,
-                None => {
-                    let mut archetype = Archetype::new();
-                    current_archetype
-                        .components
+        let edge = match self.archetypes[old_index].edges.add_pack.get(&pack_type) {
+            Some(edge) => edge.clone(),
+            None => {
+                let current_archetype = &self.archetypes[old_index];
+                let pack_ids = P::component_ids();
+                let mut component_ids: Vec<ComponentId> =
+                    current_archetype.components.iter().map(|c| c.id).collect();
+                component_ids.extend(
+                    pack_ids
                         .iter()
-                        .for_each(|c| archetype.components.push(c.new_same_type()));
+                        .filter(|id| current_archetype.component_index(**id).is_none()),
+                );
+                component_ids.sort_unstable();
 
-                    let new_index = self.archetypes.len();
-                    self.pack_id_to_archetype.insert(pack_id, new_index);
+                let new_index = match self.archetype_with(&component_ids) {
+                    Some(index) => index,
+                    None => {
+                        let mut stores: Vec<ComponentStore> = current_archetype
+                            .components
+                            .iter()
+                            .map(|c| c.new_same_type())
+                            .collect();
+                        stores.extend(
+                            components
+                                .new_archetype()
+                                .components
+                                .into_iter()
+                                .filter(|c| current_archetype.component_index(c.id).is_none()),
+                        );
+                        stores.sort_unstable_by_key(|c| c.id);
+                        self.push_archetype(&component_ids, stores)
+                    }
+                };
+                let edge = PackEdge {
+                    archetype: new_index,
+                    replaced: pack_ids
+                        .iter()
+                        .copied()
+                        .filter(|id| self.archetypes[old_index].component_index(*id).is_some())
+                        .collect(),
+                    columns: self.archetypes[new_index].columns_of::<P>(),
+                };
+                self.archetypes[old_index]
+                    .edges
+                    .add_pack
+                    .insert(pack_type, edge.clone());
+                edge
+            }
+        };
 
-                    self.archetypes.push(archetype);
+        let row = entity_meta.index_in_archetype();
+        if edge.archetype == old_index {
+            components.replace_in(&mut self.archetypes[old_index], row, &edge.columns);
+            return Ok(());
+        }
 
-                    new_index
-                }
-            };
+        let old_archetype = &mut self.archetypes[old_index];
+        for id in edge.replaced.iter() {
+            let component_index = old_archetype.component_index(*id).unwrap();
+            old_archetype.components[component_index].data.swap_remove(row);
+        }
+        self.move_entity(entity, entity_meta, edge.archetype, &edge.replaced);
+        components.push_into(&mut self.archetypes[edge.archetype], &edge.columns);
+        Ok(())
+    }
+    // Moves `entity` into the archetype that additionally holds `component_id`
+    // at `insert_index`. The caller pushes the new value into that column.
+    fn migrate_with(
+        &mut self,
+        entity: Entity,
+        entity_meta: EntityMeta,
+        insert_index: usize,
+        component_id: ComponentId,
+        new_store: impl FnOnce() -> ComponentStore,
+    ) -> usize {
+        let old_index = entity_meta.archetype_index() as usize;
 
-            let (old_archetype, new_archetype): (&mut Archetype, &mut Archetype) =
-                retrieve_two_mutable(
-                    &mut self.archetypes,
-                    entity_meta.archetype_index() as usize,
-                    new_archetype_index,
-                );
-            match old_archetype.entities.last() {
-                Some(last) => {
-                    self.entities[*last as usize].location = entity_meta.location;
-                }
-                _ => (),
-            }
-            self.entities[entity.index as usize].location = EntityLocation::new(
-                new_archetype_index as EntityId,
-                new_archetype.len() as EntityId,
-            );
+        let new_archetype_index = match self.archetypes[old_index].edges.add.get(&component_id) {
+            Some(index) => *index,
+            None => {
+                let current_archetype = &self.archetypes[old_index];
+                let mut component_ids: Vec<ComponentId> =
+                    current_archetype.components.iter().map(|c| c.id).collect();
+                component_ids.insert(insert_index, component_id);
 
-            for i in 0..insert_index {
-                old_archetype.migrate_component(
-                    i,
-                    entity_meta.index_in_archetype(),
-                    new_archetype,
-                    i,
-                );
+                let new_index = match self.archetype_with(&component_ids) {
+                    Some(index) => index,
+                    None => {
+                        let mut stores: Vec<ComponentStore> = current_archetype
+                            .components
+                            .iter()
+                            .map(|c| c.new_same_type())
+                            .collect();
+                        stores.insert(insert_index, new_store());
+                        self.push_archetype(&component_ids, stores)
+                    }
+                };
+                self.archetypes[old_index]
+                    .edges
+                    .add
+                    .insert(component_id, new_index);
+                self.archetypes[new_index]
+                    .edges
+                    .remove
+                    .insert(component_id, old_index);
+                new_index
             }
+        };
 
-            new_archetype.push(insert_index, t);
+        self.move_entity(entity, entity_meta, new_archetype_index, &[]);
+        new_archetype_index
+    }
+    fn archetype_with(&self, component_ids: &[ComponentId]) -> Option<usize> {
+        self.pack_id_to_archetype
+            .get(&calculate_pack_id(component_ids))
+            .copied()
+    }
+    fn push_archetype(&mut self, component_ids: &[ComponentId], stores: Vec<ComponentStore>) -> usize {
+        let mut archetype = Archetype::new();
+        archetype.components = stores;
 
-            let components_in_archetype = old_archetype.components.len();
+        let new_index = self.archetypes.len();
+        self.pack_id_to_archetype
+            .insert(calculate_pack_id(component_ids), new_index);
+        self.archetypes.push(archetype);
+        new_index
+    }
+    // Moves the row of `entity` into `new_archetype_index`. Columns listed in
+    // `taken` must already have had the entity's value removed.
+    fn move_entity(
+        &mut self,
+        entity: Entity,
+        entity_meta: EntityMeta,
+        new_archetype_index: usize,
+        taken: &[ComponentId],
+    ) {
+        let (old_archetype, new_archetype): (&mut Archetype, &mut Archetype) =
+            retrieve_two_mutable(
+                &mut self.archetypes,
+                entity_meta.archetype_index() as usize,
+                new_archetype_index,
+            );
+        if let Some(last) = old_archetype.entities.last() {
+            self.entities[*last as usize].location = entity_meta.location;
+        }
+        self.entities[entity.index as usize].location = EntityLocation::new(
+            new_archetype_index as EntityId,
+            new_archetype.len() as EntityId,
+        );
 
-            for i in insert_index..components_in_archetype {
-                old_archetype.migrate_component(
-                    i,
-                    entity_meta.index_in_archetype(),
-                    new_archetype,
-                    i.overflowing_add(1).0,
-                );
+        for i in 0..old_archetype.components.len() {
+            let component_id = old_archetype.components[i].id;
+            if taken.contains(&component_id) {
+                continue;
             }
-
-            old_archetype
-                .entities
-                .swap_remove(entity_meta.index_in_archetype() as usize);
-            new_archetype.entities.push(entity.index);
+            let other_index = new_archetype.component_index(component_id).unwrap();
+            old_archetype.migrate_component(
+                i,
+                entity_meta.index_in_archetype(),
+                new_archetype,
+                other_index,
+            );
         }
 
-        Ok(())
+        old_archetype
+            .entities
+            .swap_remove(entity_meta.index_in_archetype() as usize);
+        new_archetype.entities.push(entity.index);
     }
 }
 
 pub struct Archetype {
     pub(crate) entities: Vec<EntityId>,
     pub(crate) components: Vec<ComponentStore>,
+    edges: ArchetypeEdges,
+}
+
+/// Cached archetype transitions, keyed by the component (or pack type) added
+/// or removed.
+#[derive(Default)]
+struct ArchetypeEdges {
+    add: HashMap<ComponentId, usize>,
+    remove: HashMap<ComponentId, usize>,
+    add_pack: HashMap<TypeId, PackEdge>,
+}
+
+#[derive(Clone)]
+struct PackEdge {
+    archetype: usize,
+    // Components of the pack the source archetype already holds.
+    replaced: SmallVec<[ComponentId; 4]>,
+    // Column of each pack component in `archetype`, in pack order.
+    columns: SmallVec<[usize; 8]>,
 }
 
 impl Archetype {
@@ -201,8 +509,22 @@ impl Archetype {
         Self {
             entities: Vec::new(),
             components: Vec::new(),
+            edges: ArchetypeEdges::default(),
         }
     }
+    fn reserve(&mut self, additional: usize) {
+        self.entities.reserve(additional);
+        for c in self.components.iter_mut() {
+            c.data.reserve(additional);
+        }
+    }
+    // Column of each component of `P`, in pack order.
+    fn columns_of<P: ComponentPack>(&self) -> SmallVec<[usize; 8]> {
+        P::component_ids()
+            .into_iter()
+            .map(|id| self.component_index(id).unwrap())
+            .collect()
+    }
     pub(crate) fn retrieve<T: 'static>(&self, index: usize) -> &RwLock<Vec<T>> {
         let downcast_ref = self.components[index]
             .data
@@ -211,6 +533,12 @@ impl Archetype {
         downcast_ref.unwrap()
     }
 
+    fn component_index(&self, id: ComponentId) -> Option<usize> {
+        self.components
+            .binary_search_by(|c| c.id.cmp(&id))
+            .ok()
+    }
+
     fn remove_entity(&mut self, index: EntityId) -> EntityId {
         for c in self.components.iter_mut() {
             c.data.swap_remove(index)
@@ -244,10 +572,10 @@ impl Archetype {
         &mut self,
         index: EntityId,
     ) -> Result<&mut T, ComponentNotInEntity> {
-        let type_id = TypeId::of::<T>();
+        let component_id = ComponentId::of::<T>();
         let mut component_index = None;
         for (i, c) in self.components.iter().enumerate() {
-            if c.type_id == type_id {
+            if c.id == component_id {
                 component_index = Some(i);
                 break;
             }
@@ -296,7 +624,7 @@ impl EntityLocation {
         }
     }
 }
-pub(crate) fn calculate_pack_id(types: &[TypeId]) -> PackId {
+pub(crate) fn calculate_pack_id(types: &[ComponentId]) -> PackId {
     let mut s = <DefaultHasher as std::default::Default>::default();
     types.hash(&mut s);
     s.finish()
@@ -321,26 +649,39 @@ impl EntityMeta {
     }
 }
 
-#[derive(Clone, Copy, Eq, Hash, PartialEq, PartialOrd)]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, PartialOrd)]
 pub struct Entity {
     pub(crate) index: EntityId,
     pub(crate) generation: EntityId,
 }
+/// Identifies a column of an archetype, either a Rust type or a component
+/// registered at runtime through [`World::register_component`].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ComponentId {
+    Type(TypeId),
+    Dynamic(u32),
+}
+
+impl ComponentId {
+    pub fn of<T: 'static>() -> Self {
+        ComponentId::Type(TypeId::of::<T>())
+    }
+}
 pub(crate) struct ComponentStore {
-    pub(crate) type_id: TypeId,
+    pub(crate) id: ComponentId,
     data: Box<dyn ComponentVec + Send + Sync>,
 }
 
 impl ComponentStore {
     pub fn new<T: 'static + Send + Sync>() -> Self {
         Self {
-            type_id: TypeId::of::<T>(),
+            id: ComponentId::of::<T>(),
             data: Box::new(RwLock::new(Vec::<T>::new())),
         }
     }
     pub fn new_same_type(&self) -> Self {
         Self {
-            type_id: self.type_id,
+            id: self.id,
             data: self.data.new_same_type(),
         }
     }
@@ -352,8 +693,11 @@ trait ComponentVec: Sync + Send {
     fn to_any_mut(&mut self) -> &mut dyn Any;
     fn len(&mut self) -> usize;
     fn swap_remove(&mut self, index: EntityId);
+    fn reserve(&mut self, additional: usize);
     fn migrate(&mut self, entity_index: EntityId, other_archetype: &mut dyn ComponentVec);
     fn new_same_type(&self) -> Box<dyn ComponentVec + Send + Sync>;
+    fn try_read_column(&self) -> Option<Box<dyn ColumnGuard + '_>>;
+    fn try_write_column(&self) -> Option<Box<dyn ColumnGuard + '_>>;
 }
 
 impl<T: Component> ComponentVec for RwLock<Vec<T>> {
@@ -372,6 +716,10 @@ impl<T: Component> ComponentVec for RwLock<Vec<T>> {
         self.get_mut().unwrap().swap_remove(index as usize);
     }
 
+    fn reserve(&mut self, additional: usize) {
+        self.get_mut().unwrap().reserve(additional);
+    }
+
     fn migrate(&mut self, entity_index: EntityId, other_component_vec: &mut dyn ComponentVec) {
         let data: T = self.get_mut().unwrap().swap_remove(entity_index as usize);
         Result::unwrap(
@@ -392,17 +740,31 @@ impl<T: Component> ComponentVec for RwLock<Vec<T>> {
     fn new_same_type(&self) -> Box<dyn ComponentVec + Send + Sync> {
         Box::new(RwLock::new(Vec::<T>::new()))
     }
+
+    fn try_read_column(&self) -> Option<Box<dyn ColumnGuard + '_>> {
+        Some(Box::new(self.try_read().ok()?))
+    }
+
+    fn try_write_column(&self) -> Option<Box<dyn ColumnGuard + '_>> {
+        Some(Box::new(self.try_write().ok()?))
+    }
 }
 pub trait ComponentPack: 'static + Send + Sync {
     fn new_archetype(&self) -> Archetype;
     fn spawn(self, world: &mut World, entity_index: EntityId) -> EntityLocation;
+    fn component_ids() -> Vec<ComponentId>
+    where
+        Self: Sized;
+    /// Pushes each component onto the column given by `columns`, in pack order.
+    fn push_into(self, archetype: &mut Archetype, columns: &[usize]);
+    fn replace_in(self, archetype: &mut Archetype, index: EntityId, columns: &[usize]);
 }
 
 macro_rules! component_pack {
     ($count: expr, $(($name: ident, $index: tt)),*) => {
         impl< $($name: 'static + Send + Sync),*> ComponentPack for ($($name,)*) {
             fn spawn(self, world: &mut World, entity_index: EntityId) -> EntityLocation {
-                let mut types = [$(($index, TypeId::of::<$name>())), *];
+                let mut types = [$(($index, ComponentId::of::<$name>())), *];
                 types.sort_unstable_by(|a, b| a.1.cmp(&b.1));
                 debug_assert!( types.windows(2).all(|x| x[0].1 != x[1].1), "`ComponentPack`s cannot contain duplicate components." );
 
@@ -434,8 +796,20 @@ macro_rules! component_pack {
 
             fn new_archetype(&self) -> Archetype {
                 let mut components = vec![$(ComponentStore::new::<$name>()), *];
-                components.sort_unstable_by(|a, b| a.type_id.cmp(&b.type_id));
-                Archetype { components, entities: Vec::new() }
+                components.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+                Archetype { components, entities: Vec::new(), edges: ArchetypeEdges::default() }
+            }
+
+            fn component_ids() -> Vec<ComponentId> {
+                vec![$(ComponentId::of::<$name>()), *]
+            }
+
+            fn push_into(self, archetype: &mut Archetype, columns: &[usize]) {
+                $(archetype.push(columns[$index], self.$index);)*
+            }
+
+            fn replace_in(self, archetype: &mut Archetype, index: EntityId, columns: &[usize]) {
+                $(archetype.replace_component(columns[$index], index, self.$index);)*
             }
         }
     }
@@ -444,14 +818,24 @@ macro_rules! component_pack {
 impl<A: 'static + Send + Sync> ComponentPack for (A,) {
     fn new_archetype(&self) -> Archetype {
         let mut components = vec![ComponentStore::new::<A>()];
-        components.sort_unstable_by(|a, b| a.type_id.cmp(&b.type_id));
+        components.sort_unstable_by(|a, b| a.id.cmp(&b.id));
         Archetype {
             components,
             entities: Vec::new(),
+            edges: ArchetypeEdges::default(),
         }
     }
+    fn component_ids() -> Vec<ComponentId> {
+        vec![ComponentId::of::<A>()]
+    }
+    fn push_into(self, archetype: &mut Archetype, columns: &[usize]) {
+        archetype.push(columns[0], self.0);
+    }
+    fn replace_in(self, archetype: &mut Archetype, index: EntityId, columns: &[usize]) {
+        archetype.replace_component(columns[0], index, self.0);
+    }
     fn spawn(self, world: &mut World, entity_index: EntityId) -> EntityLocation {
-        let mut types = [(0, TypeId::of::<A>())];
+        let mut types = [(0, ComponentId::of::<A>())];
         types.sort_unstable_by(|a, b| a.1.cmp(&b.1));
         debug_assert!(
             types.windows(2).all(|x| x[0].1 != x[1].1),
@@ -580,6 +964,100 @@ pub mod tests {
     }
     struct Name(String);
 
+    struct Position(f32);
+    struct Velocity(f32);
+
+    fn positions(world: &World) -> Vec<f32> {
+        let mut search = world.search::<(&Position,)>().unwrap();
+        let mut positions: Vec<f32> = search.iter().map(|p| p.0).collect();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        positions
+    }
+
+    #[test]
+    fn add_and_remove_reuse_edges() {
+        let mut world = World::new();
+        let a = world.new_entity((Position(0.0),)).unwrap();
+        let b = world.new_entity((Position(1.0),)).unwrap();
+
+        world.add_component(a, Velocity(1.0)).unwrap();
+        let archetypes = world.archetypes.len();
+        let from = world.entities[b.index as usize].archetype_index() as usize;
+        assert_eq!(
+            world.archetypes[from].edges.add.get(&ComponentId::of::<Velocity>()),
+            Some(&(world.entities[a.index as usize].archetype_index() as usize))
+        );
+
+        world.add_component(b, Velocity(2.0)).unwrap();
+        assert_eq!(world.archetypes.len(), archetypes);
+
+        let velocity = world.remove_component::<Velocity>(a).unwrap();
+        assert_eq!(velocity.0, 1.0);
+        assert_eq!(world.entities[a.index as usize].archetype_index() as usize, from);
+        assert_eq!(world.archetypes.len(), archetypes);
+        assert_eq!(positions(&world), vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn add_components_moves_once_and_replaces() {
+        let mut world = World::new();
+        let entity = world.new_entity((Position(0.0),)).unwrap();
+        let other = world.new_entity((Position(5.0),)).unwrap();
+
+        world
+            .add_components(entity, (Velocity(3.0), Name("a".to_string())))
+            .unwrap();
+        world
+            .add_components(entity, (Position(2.0), Velocity(4.0)))
+            .unwrap();
+        world
+            .add_components(other, (Position(6.0), Velocity(1.0)))
+            .unwrap();
+
+        let mut search = world
+            .search::<(&Position, &Velocity, &Name)>()
+            .unwrap();
+        let found: Vec<(f32, f32)> = search.iter().map(|(p, v, _)| (p.0, v.0)).collect();
+        assert_eq!(found, vec![(2.0, 4.0)]);
+        drop(search);
+        assert_eq!(positions(&world), vec![2.0, 6.0]);
+    }
+
+    #[test]
+    fn spawn_batch_shares_archetype() {
+        let mut world = World::new();
+        world.new_entity((Position(-1.0), Velocity(0.0))).unwrap();
+        let spawned = world
+            .spawn_batch((0..100).map(|i| (Velocity(i as f32), Position(i as f32))))
+            .unwrap();
+
+        assert_eq!(spawned.len(), 100);
+        assert_eq!(world.archetypes.len(), 1);
+        let mut search = world.search::<(&Position, &Velocity)>().unwrap();
+        assert!(search.iter().all(|(p, v)| p.0 == v.0 || p.0 == -1.0));
+        drop(search);
+
+        world.despawn(spawned[10]).unwrap();
+        let reused = world.spawn_batch(vec![(Position(500.0),)]).unwrap();
+        assert_eq!(reused[0].index, spawned[10].index);
+        assert!(world.spawn_batch(Vec::<(Position,)>::new()).unwrap().is_empty());
+        assert_eq!(positions(&world).len(), 101);
+    }
+
+    #[test]
+    fn search_skips_emptied_archetypes() {
+        let mut world = World::new();
+        world.new_entity((Position(0.0),)).unwrap();
+        let a = world.new_entity((Position(1.0), Velocity(0.0))).unwrap();
+        let b = world.new_entity((Position(2.0), Name("b".to_string()))).unwrap();
+
+        // Both end up in a new archetype, leaving the two before it empty.
+        world.add_component(a, Name("a".to_string())).unwrap();
+        world.add_component(b, Velocity(0.0)).unwrap();
+
+        assert_eq!(positions(&world), vec![0.0, 1.0, 2.0]);
+    }
+
     fn physics_print(world: &World) {
         let mut search = world.search::<(&Name, &RigidBody)>().unwrap();
         for (name, rb) in search.iter() {
@@ -0,0 +1,130 @@
+//! Light components, placed by the [`Transform`] of their entity. Spot and
+//! directional lights shine along the entity's forward axis, -Z rotated by
+//! the transform.
+
+use glam::Vec3;
+
+use crate::Transform;
+
+/// Shines in every direction, fading out to nothing at `range`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PointLight {
+    /// Linear RGB.
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    /// Rendered into a cube of six shadow maps when there is room for it.
+    pub casts_shadows: bool,
+}
+
+/// Shines in a cone, fading out to nothing at `range` and between the
+/// inner and outer angles, both measured from the cone's axis in radians.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpotLight {
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: f32,
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    pub casts_shadows: bool,
+}
+
+/// Shines from infinitely far away, lighting the whole scene, so unlike the
+/// others it has no range. Cascaded shadows are only cast by the sun.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirectionalLight {
+    pub color: Vec3,
+    pub intensity: f32,
+}
+
+impl PointLight {
+    pub fn new(color: Vec3, intensity: f32, range: f32) -> Self {
+        Self {
+            color,
+            intensity,
+            range,
+            casts_shadows: false,
+        }
+    }
+
+    pub fn with_shadows(self) -> Self {
+        Self {
+            casts_shadows: true,
+            ..self
+        }
+    }
+}
+
+impl SpotLight {
+    pub fn new(color: Vec3, intensity: f32, range: f32, inner_angle: f32, outer_angle: f32) -> Self {
+        Self {
+            color,
+            intensity,
+            range,
+            inner_angle,
+            outer_angle,
+            casts_shadows: false,
+        }
+    }
+
+    pub fn with_shadows(self) -> Self {
+        Self {
+            casts_shadows: true,
+            ..self
+        }
+    }
+}
+
+impl DirectionalLight {
+    pub fn new(color: Vec3, intensity: f32) -> Self {
+        Self { color, intensity }
+    }
+}
+
+impl Default for PointLight {
+    fn default() -> Self {
+        Self::new(Vec3::ONE, 1.0, 10.0)
+    }
+}
+
+impl Default for SpotLight {
+    fn default() -> Self {
+        Self::new(
+            Vec3::ONE,
+            1.0,
+            10.0,
+            std::f32::consts::FRAC_PI_8,
+            std::f32::consts::FRAC_PI_4,
+        )
+    }
+}
+
+impl Default for DirectionalLight {
+    fn default() -> Self {
+        Self::new(Vec3::ONE, 1.0)
+    }
+}
+
+/// The direction spot and directional lights on an entity placed by
+/// `transform` shine in.
+pub fn light_direction(transform: &Transform) -> Vec3 {
+    (transform.rotation * -Vec3::Z).normalize()
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::Quat;
+
+    use super::*;
+
+    #[test]
+    fn lights_shine_along_the_rotated_forward_axis() {
+        assert_eq!(light_direction(&Transform::default()), -Vec3::Z);
+
+        let down = Transform {
+            rotation: Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+            ..Default::default()
+        };
+        assert!(light_direction(&down).abs_diff_eq(-Vec3::Y, 1e-6));
+    }
+}
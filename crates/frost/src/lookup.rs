@@ -0,0 +1,312 @@
+use std::{
+    ops::Deref,
+    sync::{RwLockReadGuard, RwLockWriteGuard},
+};
+
+use crate::{
+    ComponentAlreadyBorrowed, ComponentError, ComponentId, ComponentNotInEntity, Entity,
+    Retrieve, RetrieveError, RetrieveItem, SearchParameter, SearchParameterRetrieve, SysParam,
+    World,
+};
+
+/// Read borrow of one component, returned by [`World::get`].
+pub struct Ref<'world, T> {
+    guard: RwLockReadGuard<'world, Vec<T>>,
+    index: usize,
+}
+
+impl<T> Deref for Ref<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard[self.index]
+    }
+}
+
+impl World {
+    pub fn get<T: 'static>(&self, entity: Entity) -> Result<Ref<'_, T>, ComponentError> {
+        let entity_meta = self.entity_meta(entity)?;
+        let archetype = &self.archetypes[entity_meta.archetype_index() as usize];
+        let component_index = archetype
+            .component_index(ComponentId::of::<T>())
+            .ok_or_else(|| ComponentNotInEntity::new_with_value::<T>(entity.index))?;
+
+        let guard = archetype
+            .retrieve::<T>(component_index)
+            .try_read()
+            .map_err(|_| ComponentAlreadyBorrowed::new::<T>())?;
+        Ok(Ref {
+            guard,
+            index: entity_meta.index_in_archetype() as usize,
+        })
+    }
+
+    pub fn get_mut<T: 'static>(&mut self, entity: Entity) -> Result<&mut T, ComponentError> {
+        // The pointer stays within the column borrowed through `self`.
+        Ok(unsafe { &mut *self.component_ptr::<T>(entity)? })
+    }
+
+    fn component_ptr<T: 'static>(&mut self, entity: Entity) -> Result<*mut T, ComponentError> {
+        let entity_meta = self.entity_meta(entity)?;
+        let archetype = &mut self.archetypes[entity_meta.archetype_index() as usize];
+        let component_index = archetype
+            .component_index(ComponentId::of::<T>())
+            .ok_or_else(|| ComponentNotInEntity::new_with_value::<T>(entity.index))?;
+
+        let column = archetype.mutable_component_store::<T>(component_index);
+        let row = entity_meta.index_in_archetype() as usize;
+        assert!(row < column.len());
+        Ok(column.as_mut_ptr().wrapping_add(row))
+    }
+
+    /// Mutable access to `T` on several distinct entities at once. Passing the
+    /// same entity twice is reported as an already borrowed component.
+    pub fn get_many_mut<T: 'static, const N: usize>(
+        &mut self,
+        entities: [Entity; N],
+    ) -> Result<[&mut T; N], ComponentError> {
+        if has_duplicates(&entities) {
+            return Err(ComponentAlreadyBorrowed::new::<T>().into());
+        }
+
+        let mut pointers = [std::ptr::null_mut::<T>(); N];
+        for (pointer, entity) in pointers.iter_mut().zip(entities) {
+            *pointer = self.component_ptr::<T>(entity)?;
+        }
+        // Distinct live entities never share a slot, and nothing reallocates
+        // the columns while `self` stays mutably borrowed.
+        Ok(pointers.map(|pointer| unsafe { &mut *pointer }))
+    }
+}
+
+fn has_duplicates(entities: &[Entity]) -> bool {
+    entities
+        .iter()
+        .enumerate()
+        .any(|(i, a)| entities[..i].contains(a))
+}
+
+/// A borrowed column a [`SearchLookup`] can index into.
+pub trait LookupColumn {
+    type Item;
+    fn slice(&self) -> &[Self::Item];
+}
+
+pub trait LookupColumnMut: LookupColumn {
+    fn slice_mut(&mut self) -> &mut [Self::Item];
+    fn as_mut_ptr(&mut self) -> *mut Self::Item;
+}
+
+impl<T> LookupColumn for RwLockReadGuard<'_, Vec<T>> {
+    type Item = T;
+    fn slice(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T> LookupColumn for RwLockWriteGuard<'_, Vec<T>> {
+    type Item = T;
+    fn slice(&self) -> &[T] {
+        self
+    }
+}
+
+impl<T> LookupColumnMut for RwLockWriteGuard<'_, Vec<T>> {
+    fn slice_mut(&mut self) -> &mut [T] {
+        self
+    }
+    fn as_mut_ptr(&mut self) -> *mut T {
+        Vec::as_mut_ptr(self)
+    }
+}
+
+type LookupParameterItem<'world, P> =
+    <<P as SearchParameter>::SearchParameterRetrieve as SearchParameterRetrieve<'world>>::RetrieveItem;
+type LookupItem<'world, P> = <LookupParameterItem<'world, P> as LookupColumn>::Item;
+
+/// System parameter giving random access by [`Entity`] to one component,
+/// e.g. `SearchLookup<&mut RigidBody>`. Holds the same column borrows a
+/// `Search` of that parameter would.
+pub struct SearchLookup<'world, P: SearchParameter> {
+    world: &'world World,
+    // Indexed by archetype, `None` where the parameter does not match.
+    columns: Vec<Option<LookupParameterItem<'world, P>>>,
+}
+
+impl<'world, P: SearchParameter> SearchLookup<'world, P> {
+    fn location(&self, entity: Entity) -> Option<(usize, usize)> {
+        let entity_meta = self.world.entity_meta(entity).ok()?;
+        let archetype_index = entity_meta.archetype_index() as usize;
+        self.columns.get(archetype_index)?.as_ref()?;
+        Some((archetype_index, entity_meta.index_in_archetype() as usize))
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.location(entity).is_some()
+    }
+
+    /// Every entity the lookup can reach.
+    pub fn entities(&self) -> impl Iterator<Item = Entity> + 'world {
+        let world = self.world;
+        world
+            .archetypes
+            .iter()
+            .filter(|archetype| P::matches(archetype))
+            .flat_map(move |archetype| {
+                archetype.entities.iter().map(move |index| Entity {
+                    index: *index,
+                    generation: world.entities[*index as usize].generation,
+                })
+            })
+    }
+}
+
+impl<'world, P: SearchParameter> SearchLookup<'world, P>
+where
+    LookupParameterItem<'world, P>: LookupColumn,
+{
+    pub fn get(&self, entity: Entity) -> Option<&LookupItem<'world, P>> {
+        let (archetype_index, row) = self.location(entity)?;
+        self.columns[archetype_index].as_ref()?.slice().get(row)
+    }
+}
+
+impl<'world, P: SearchParameter> SearchLookup<'world, P>
+where
+    LookupParameterItem<'world, P>: LookupColumnMut,
+{
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut LookupItem<'world, P>> {
+        let (archetype_index, row) = self.location(entity)?;
+        self.columns[archetype_index]
+            .as_mut()?
+            .slice_mut()
+            .get_mut(row)
+    }
+
+    /// Mutable access to several distinct entities, `None` if any entity is
+    /// missing or repeated.
+    pub fn get_many_mut<const N: usize>(
+        &mut self,
+        entities: [Entity; N],
+    ) -> Option<[&mut LookupItem<'world, P>; N]> {
+        if has_duplicates(&entities) {
+            return None;
+        }
+
+        let mut pointers = [std::ptr::null_mut::<LookupItem<'world, P>>(); N];
+        for (pointer, entity) in pointers.iter_mut().zip(entities) {
+            let (archetype_index, row) = self.location(entity)?;
+            let column = self.columns[archetype_index].as_mut()?;
+            if row >= column.slice().len() {
+                return None;
+            }
+            *pointer = column.as_mut_ptr().wrapping_add(row);
+        }
+        // Distinct entities map to distinct rows of the held columns.
+        Some(pointers.map(|pointer| unsafe { &mut *pointer }))
+    }
+}
+
+impl<'a, P: SearchParameter> SysParam for SearchLookup<'a, P> {
+    type Retrieve = SearchLookupRetrieve<P>;
+}
+
+#[doc(hidden)]
+pub struct SearchLookupRetrieve<P> {
+    phantom: std::marker::PhantomData<P>,
+}
+
+impl<'world, P: SearchParameter> Retrieve<'world> for SearchLookupRetrieve<P> {
+    type Item = Option<SearchLookup<'world, P>>;
+    fn retrieve(world: &'world World) -> Result<Self::Item, RetrieveError> {
+        let mut columns = Vec::with_capacity(world.archetypes.len());
+        for (i, archetype) in world.archetypes.iter().enumerate() {
+            columns.push(match P::matches(archetype) {
+                true => Some(P::SearchParameterRetrieve::retrieve(world, i)?),
+                false => None,
+            });
+        }
+        Ok(Some(SearchLookup { world, columns }))
+    }
+}
+
+impl<'a, 'world, P: SearchParameter> RetrieveItem<'a> for Option<SearchLookup<'world, P>> {
+    type InnerComponent = SearchLookup<'world, P>;
+    fn inner(&'a mut self) -> Self::InnerComponent {
+        self.take().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::System;
+
+    struct Health(u32);
+    struct Armour(u32);
+
+    #[test]
+    fn get_checks_generation() {
+        let mut world = World::new();
+        let entity = world.new_entity((Health(3),)).unwrap();
+        assert_eq!(world.get::<Health>(entity).unwrap().0, 3);
+        assert!(matches!(
+            world.get::<Armour>(entity),
+            Err(ComponentError::ComponentNotInEntity(_))
+        ));
+
+        world.get_mut::<Health>(entity).unwrap().0 = 7;
+        assert_eq!(world.get::<Health>(entity).unwrap().0, 7);
+
+        world.despawn(entity).unwrap();
+        let reused = world.new_entity((Health(1),)).unwrap();
+        assert!(matches!(
+            world.get::<Health>(entity),
+            Err(ComponentError::EntityNotFound(_))
+        ));
+        assert_eq!(world.get::<Health>(reused).unwrap().0, 1);
+    }
+
+    #[test]
+    fn get_many_mut_across_archetypes() {
+        let mut world = World::new();
+        let a = world.new_entity((Health(1),)).unwrap();
+        let b = world.new_entity((Health(2), Armour(5))).unwrap();
+
+        let [ha, hb] = world.get_many_mut::<Health, 2>([a, b]).unwrap();
+        std::mem::swap(&mut ha.0, &mut hb.0);
+        assert_eq!(world.get::<Health>(a).unwrap().0, 2);
+        assert_eq!(world.get::<Health>(b).unwrap().0, 1);
+
+        assert!(matches!(
+            world.get_many_mut::<Health, 2>([a, a]),
+            Err(ComponentError::ComponentAlreadyBorrowed(_))
+        ));
+        assert!(world.get_many_mut::<Armour, 2>([a, b]).is_err());
+    }
+
+    #[test]
+    fn search_lookup_system_param() {
+        let mut world = World::new();
+        let a = world.new_entity((Health(10),)).unwrap();
+        let b = world.new_entity((Health(20), Armour(1))).unwrap();
+        let c = world.new_entity((Armour(2),)).unwrap();
+
+        fn transfer(mut health: SearchLookup<&mut Health>, armour: SearchLookup<&Armour>, _: f32) {
+            let mut entities: Vec<Entity> = health.entities().collect();
+            entities.sort_by_key(|e| e.index);
+            assert_eq!(entities.len(), 2);
+            assert!(health.get_many_mut([entities[0], entities[0]]).is_none());
+
+            let [first, second] = health.get_many_mut([entities[0], entities[1]]).unwrap();
+            first.0 -= 5;
+            second.0 += 5;
+            let armoured = entities.iter().filter_map(|e| armour.get(*e)).map(|a| a.0).sum::<u32>();
+            assert_eq!(armoured, 1);
+        }
+        transfer.run(&world, 0.0).unwrap();
+
+        assert_eq!(world.get::<Health>(a).unwrap().0, 5);
+        assert_eq!(world.get::<Health>(b).unwrap().0, 25);
+        assert!(world.get::<Health>(c).is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use std::iter::{RepeatN, Zip};
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+use rayon::iter::{FlatMapIter, IntoParallelIterator, ParallelIterator};
+
+use crate::iter::*;
+use crate::search::{HasColumn, SearchParameterItem};
+use crate::{Search, SearchParameter};
+
+/// Entities handed to a worker at a time by [`ParSearchIter::par_iter`].
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
+
+/// Splits one borrowed column into chunks of at most `batch_size` items. The
+/// chunks borrow from the guard, so the lock stays held while workers run.
+pub trait SearchChunks<'a> {
+    type Item: Send;
+    type Chunk: IntoIterator<Item = Self::Item> + Send;
+    type Chunks: Iterator<Item = Self::Chunk>;
+    fn chunks(&'a mut self, batch_size: usize) -> Self::Chunks;
+}
+
+impl<'a, 'world, T: Sync + 'static> SearchChunks<'a> for RwLockReadGuard<'world, Vec<T>> {
+    type Item = &'a T;
+    type Chunk = &'a [T];
+    type Chunks = std::slice::Chunks<'a, T>;
+    fn chunks(&'a mut self, batch_size: usize) -> Self::Chunks {
+        <[T]>::chunks(self, batch_size)
+    }
+}
+
+impl<'a, 'world, T: Send + 'static> SearchChunks<'a> for RwLockWriteGuard<'world, Vec<T>> {
+    type Item = &'a mut T;
+    type Chunk = &'a mut [T];
+    type Chunks = std::slice::ChunksMut<'a, T>;
+    fn chunks(&'a mut self, batch_size: usize) -> Self::Chunks {
+        <[T]>::chunks_mut(self, batch_size)
+    }
+}
+
+impl<'a> SearchChunks<'a> for HasColumn {
+    type Item = bool;
+    type Chunk = RepeatN<bool>;
+    type Chunks = HasChunks;
+    fn chunks(&'a mut self, batch_size: usize) -> Self::Chunks {
+        HasChunks {
+            value: self.value,
+            remaining: self.len,
+            batch_size,
+        }
+    }
+}
+
+/// Chunks of a [`HasColumn`], as long as the archetype's other columns.
+pub struct HasChunks {
+    value: bool,
+    remaining: usize,
+    batch_size: usize,
+}
+
+impl Iterator for HasChunks {
+    type Item = RepeatN<bool>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let len = self.remaining.min(self.batch_size);
+        if len == 0 {
+            return None;
+        }
+        self.remaining -= len;
+        Some(std::iter::repeat_n(self.value, len))
+    }
+}
+
+type ChunkOf<'a, 'world, A> = <SearchParameterItem<'world, A> as SearchChunks<'a>>::Chunk;
+type ChunkIter<'a, 'world, A> = <ChunkOf<'a, 'world, A> as IntoIterator>::IntoIter;
+
+/// Parallel counterpart of [`SearchIter`](crate::SearchIter). Matching
+/// archetype columns are cut into batches which run on the rayon pool.
+pub trait ParSearchIter<'a> {
+    type Item: Send;
+    type ParIter: ParallelIterator<Item = Self::Item>;
+
+    fn par_iter_batched(&'a mut self, batch_size: usize) -> Self::ParIter;
+
+    fn par_iter(&'a mut self) -> Self::ParIter {
+        self.par_iter_batched(DEFAULT_BATCH_SIZE)
+    }
+
+    fn par_for_each<F>(&'a mut self, batch_size: usize, f: F)
+    where
+        F: Fn(Self::Item) + Send + Sync,
+    {
+        self.par_iter_batched(batch_size).for_each(f)
+    }
+}
+
+impl<'a, 'world, A: SearchParameter> ParSearchIter<'a> for Search<'world, (A,)>
+where
+    SearchParameterItem<'world, A>: SearchChunks<'a>,
+{
+    type Item = <SearchParameterItem<'world, A> as SearchChunks<'a>>::Item;
+    #[allow(clippy::type_complexity)]
+    type ParIter = FlatMapIter<
+        rayon::vec::IntoIter<ChunkOf<'a, 'world, A>>,
+        fn(ChunkOf<'a, 'world, A>) -> ChunkIter<'a, 'world, A>,
+    >;
+
+    fn par_iter_batched(&'a mut self, batch_size: usize) -> Self::ParIter {
+        assert!(batch_size > 0);
+        let chunks: Vec<_> = self
+            .data
+            .iter_mut()
+            .flat_map(|a| a.chunks(batch_size))
+            .collect();
+        chunks.into_par_iter().flat_map_iter(IntoIterator::into_iter)
+    }
+}
+
+impl<'a, 'world, A: SearchParameter, B: SearchParameter> ParSearchIter<'a> for Search<'world, (A, B)>
+where
+    SearchParameterItem<'world, A>: SearchChunks<'a>,
+    SearchParameterItem<'world, B>: SearchChunks<'a>,
+{
+    type Item = (
+        <SearchParameterItem<'world, A> as SearchChunks<'a>>::Item,
+        <SearchParameterItem<'world, B> as SearchChunks<'a>>::Item,
+    );
+    #[allow(clippy::type_complexity)]
+    type ParIter = FlatMapIter<
+        rayon::vec::IntoIter<(ChunkOf<'a, 'world, A>, ChunkOf<'a, 'world, B>)>,
+        fn(
+            (ChunkOf<'a, 'world, A>, ChunkOf<'a, 'world, B>),
+        ) -> Zip<ChunkIter<'a, 'world, A>, ChunkIter<'a, 'world, B>>,
+    >;
+
+    fn par_iter_batched(&'a mut self, batch_size: usize) -> Self::ParIter {
+        assert!(batch_size > 0);
+        let chunks: Vec<_> = self
+            .data
+            .iter_mut()
+            .flat_map(|(a, b)| a.chunks(batch_size).zip(b.chunks(batch_size)))
+            .collect();
+        chunks
+            .into_par_iter()
+            .flat_map_iter(|(a, b)| a.into_iter().zip(b))
+    }
+}
+
+macro_rules! par_search_iter {
+    ($zip_type: ident, $($name: ident),*) => {
+        #[allow(non_snake_case)]
+        impl<'a, 'world, $($name: SearchParameter),*> ParSearchIter<'a> for Search<'world, ($($name,)*)>
+        where
+            $(SearchParameterItem<'world, $name>: SearchChunks<'a>),*
+        {
+            type Item = ($(<SearchParameterItem<'world, $name> as SearchChunks<'a>>::Item,)*);
+            #[allow(clippy::type_complexity)]
+            type ParIter = FlatMapIter<
+                rayon::vec::IntoIter<($(ChunkOf<'a, 'world, $name>,)*)>,
+                fn(($(ChunkOf<'a, 'world, $name>,)*)) -> $zip_type<$(ChunkIter<'a, 'world, $name>,)*>,
+            >;
+
+            fn par_iter_batched(&'a mut self, batch_size: usize) -> Self::ParIter {
+                assert!(batch_size > 0);
+                let chunks: Vec<_> = self
+                    .data
+                    .iter_mut()
+                    .flat_map(|($(ref mut $name,)*)| $zip_type::new($($name.chunks(batch_size),)*))
+                    .collect();
+                chunks
+                    .into_par_iter()
+                    .flat_map_iter(|($($name,)*)| $zip_type::new($($name.into_iter(),)*))
+            }
+        }
+    }
+}
+
+par_search_iter! {Zip3Items, A, B, C}
+par_search_iter! {Zip4Items, A, B, C, D}
+par_search_iter! {Zip5Items, A, B, C, D, E}
+par_search_iter! {Zip6Items, A, B, C, D, E, F}
+par_search_iter! {Zip7Items, A, B, C, D, E, F, G}
+par_search_iter! {Zip8Items, A, B, C, D, E, F, G, H}
+par_search_iter! {Zip9Items, A, B, C, D, E, F, G, H, I}
+par_search_iter! {Zip10Items, A, B, C, D, E, F, G, H, I, J}
+par_search_iter! {Zip11Items, A, B, C, D, E, F, G, H, I, J, K}
+par_search_iter! {Zip12Items, A, B, C, D, E, F, G, H, I, J, K, L}
+par_search_iter! {Zip13Items, A, B, C, D, E, F, G, H, I, J, K, L, M}
+par_search_iter! {Zip14Items, A, B, C, D, E, F, G, H, I, J, K, L, M, N}
+par_search_iter! {Zip15Items, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O}
+par_search_iter! {Zip16Items, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P}
+par_search_iter! {Zip17Items, A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ComponentId, Has, SearchIter, System, World};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct Position(f32);
+    struct Velocity(f32);
+    struct Frozen;
+
+    fn spawn(world: &mut World, amount: usize) {
+        world
+            .spawn_batch((0..amount).map(|i| (Position(i as f32), Velocity(1.0))))
+            .unwrap();
+        world
+            .spawn_batch((0..amount).map(|i| (Position(i as f32), Velocity(2.0), Frozen)))
+            .unwrap();
+    }
+
+    #[test]
+    fn par_for_each_matches_serial() {
+        let mut world = World::new();
+        spawn(&mut world, 1000);
+
+        let mut search = world.search::<(&mut Position, &Velocity)>().unwrap();
+        search.par_for_each(64, |(position, velocity)| position.0 += velocity.0);
+        let total: f32 = search.iter().map(|(position, _)| position.0).sum();
+        drop(search);
+
+        let expected = 2.0 * (0..1000).map(|i| i as f32).sum::<f32>() + 3000.0;
+        assert_eq!(total, expected);
+    }
+
+    #[test]
+    fn par_iter_visits_every_entity_once() {
+        let mut world = World::new();
+        spawn(&mut world, 3000);
+
+        let visited = AtomicUsize::new(0);
+        let frozen = AtomicUsize::new(0);
+        fn count(
+            mut search: Search<(&Position, Has<Frozen>)>,
+            visited: &AtomicUsize,
+            frozen: &AtomicUsize,
+        ) {
+            search.par_iter().for_each(|(_, has_frozen)| {
+                visited.fetch_add(1, Ordering::Relaxed);
+                if has_frozen {
+                    frozen.fetch_add(1, Ordering::Relaxed);
+                }
+            });
+        }
+        count(world.search().unwrap(), &visited, &frozen);
+
+        assert_eq!(visited.load(Ordering::Relaxed), 6000);
+        assert_eq!(frozen.load(Ordering::Relaxed), 3000);
+    }
+
+    #[test]
+    fn has_only_searches_end_with_their_archetypes() {
+        let mut world = World::new();
+        spawn(&mut world, 3000);
+
+        let mut search = world.search::<(Has<Frozen>,)>().unwrap();
+        assert_eq!(search.par_iter_batched(64).count(), 6000);
+        assert_eq!(search.par_iter().filter(|has_frozen| *has_frozen).count(), 3000);
+        assert_eq!(search.iter().count(), 6000);
+    }
+
+    #[test]
+    fn guards_held_during_parallel_iteration() {
+        let mut world = World::new();
+        spawn(&mut world, 10);
+
+        let entity = world.new_entity((Position(0.0), Velocity(1.0))).unwrap();
+
+        fn step(mut search: Search<(&mut Position,)>, _: f32) {
+            search.par_for_each(4, |position| position.0 = -1.0);
+        }
+        step.run(&world, 0.0).unwrap();
+
+        let mut search = world.search::<(&mut Position,)>().unwrap();
+        let position = ComponentId::of::<Position>();
+        search.par_for_each(4, |_| {
+            assert!(world.get::<Position>(entity).is_err());
+            assert!(world.get_by_id_mut(entity, position).is_err());
+        });
+        assert!(world.search::<(&Position,)>().is_err());
+        assert!(search.iter().all(|position| position.0 == -1.0));
+    }
+}
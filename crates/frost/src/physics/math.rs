@@ -1,5 +1,3 @@
-use std::cell::RefCell;
-
 use glam::{Mat3, Quat, Vec3};
 
 use self::obb::DynamicOBB;
@@ -255,51 +253,44 @@ impl Default for Transform {
     }
 }
 
-pub fn physics_system<'a>(
-    mut search: Search<(&mut RigidBody, &'a mut obb::DynamicOBB)>,
+pub fn physics_system(
+    mut bodies: SearchLookup<&mut RigidBody>,
+    mut boxes: SearchLookup<&mut DynamicOBB>,
     fixed_update: f32,
-) where
-    'a: 'static,
-{ 
-    let bodies_and_boxes = search.iter().collect::<Vec<_>>();
+) {
+    let entities: Vec<Entity> = bodies.entities().filter(|e| boxes.contains(*e)).collect();
 
     let mut collision_details = Vec::new();
-    for i in 0..bodies_and_boxes.len() {
-        for j in (i + 1)..bodies_and_boxes.len() {
-            let (rb, obb1) = &bodies_and_boxes[i];
-            let (rb2, obb2) = &bodies_and_boxes[j];
-            if rb.is_static && rb2.is_static {
+    for (i, a) in entities.iter().enumerate() {
+        for b in entities[(i + 1)..].iter() {
+            if bodies.get(*a).unwrap().is_static && bodies.get(*b).unwrap().is_static {
                 continue;
             }
 
-            if let Some(collision_point) = obb1.get_collision_point_normal(*obb2) {
-                collision_details.push((i, j, collision_point));
+            let obb1 = boxes.get(*a).unwrap();
+            if let Some(collision_point) = obb1.get_collision_point_normal(boxes.get(*b).unwrap()) {
+                collision_details.push((*a, *b, collision_point));
             }
         }
     }
-    let mut bodies_and_boxes: Vec<RefCell<(&mut RigidBody, &mut DynamicOBB)>> = search
-        .iter()
-        .map(|(rb, obb)| RefCell::new((rb, obb)))
-        .collect::<Vec<RefCell<(&mut RigidBody, &mut DynamicOBB)>>>();
-    collision_details.into_iter().for_each(|collision| {
-        let rb1 = &mut bodies_and_boxes[collision.0].borrow_mut().0;
-        let rb2 = &mut bodies_and_boxes[collision.1].borrow_mut().0;
-        handle_collision(rb1, rb2, &collision.2);
-    });
-
-    bodies_and_boxes.iter_mut().for_each(|b_b| {
-        let (rb, obb) = b_b.borrow_mut().get_mut();
+    for (a, b, collision_point) in collision_details {
+        let [rb1, rb2] = bodies.get_many_mut([a, b]).unwrap();
+        handle_collision(rb1, rb2, &collision_point);
+    }
 
+    for entity in entities {
+        let rb = bodies.get_mut(entity).unwrap();
         if !rb.is_static {
             rb.apply_gravity();
             rb.apply_angular_drag(fixed_update);
             rb.integrate(fixed_update);
         }
+        let obb = boxes.get_mut(entity).unwrap();
         obb.center = rb.transform.position;
         obb.orientation = rb.transform.rotation;
         obb.half_extents = rb.transform.scale * 0.5;
         obb.update_vertices();
-    });
+    }
 }
 
 pub trait InertiaTensor {
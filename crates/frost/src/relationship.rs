@@ -0,0 +1,299 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use crate::{
+    ComponentError, ComponentId, Entity, EntityNotFound, Retrieve, RetrieveError, RetrieveItem, SysParam, World,
+};
+
+/// Component pointing its entity at a target through the relation kind `R`,
+/// e.g. `Related<Targets>` or `Related<OwnedBy>`.
+///
+/// Created through [`World::relate`] so the reverse index stays in sync.
+pub struct Related<R: 'static>(Entity, PhantomData<fn() -> R>);
+
+impl<R: 'static> Related<R> {
+    pub fn target(&self) -> Entity {
+        self.0
+    }
+}
+
+/// Reverse lookup from a relation target to every entity pointing at it,
+/// indexed by both ends so despawning only touches the relations of the
+/// despawned entity.
+#[derive(Default)]
+pub(crate) struct RelationIndex {
+    sources: HashMap<(ComponentId, Entity), Vec<Entity>>,
+    targets: HashMap<Entity, Vec<(ComponentId, Entity)>>,
+    /// Relation kinds with at least one source pointing at each target.
+    incoming: HashMap<Entity, Vec<ComponentId>>,
+}
+
+impl RelationIndex {
+    fn insert(&mut self, relation: ComponentId, source: Entity, target: Entity) {
+        let sources = self.sources.entry((relation, target)).or_default();
+        if sources.is_empty() {
+            self.incoming.entry(target).or_default().push(relation);
+        }
+        sources.push(source);
+        self.targets.entry(source).or_default().push((relation, target));
+    }
+
+    /// Forgets the `relation` of `source`, returning its target. Does
+    /// nothing for components that are not relations.
+    pub(crate) fn remove(&mut self, relation: ComponentId, source: Entity) -> Option<Entity> {
+        let targets = self.targets.get_mut(&source)?;
+        let position = targets.iter().position(|(r, _)| *r == relation)?;
+        let (_, target) = targets.swap_remove(position);
+        if targets.is_empty() {
+            self.targets.remove(&source);
+        }
+        self.remove_source(relation, target, source);
+        Some(target)
+    }
+
+    fn remove_source(&mut self, relation: ComponentId, target: Entity, source: Entity) {
+        let Some(sources) = self.sources.get_mut(&(relation, target)) else {
+            return;
+        };
+        sources.retain(|s| *s != source);
+        if !sources.is_empty() {
+            return;
+        }
+        self.sources.remove(&(relation, target));
+        if let Some(relations) = self.incoming.get_mut(&target) {
+            relations.retain(|r| *r != relation);
+            if relations.is_empty() {
+                self.incoming.remove(&target);
+            }
+        }
+    }
+
+    fn related_to(&self, relation: ComponentId, target: Entity) -> &[Entity] {
+        self.sources
+            .get(&(relation, target))
+            .map_or(&[], |sources| sources.as_slice())
+    }
+
+    /// Forgets every relation `entity` takes part in. Returns the relations
+    /// that pointed at `entity` as `(relation, source)` pairs so the caller can
+    /// strip the now dangling components.
+    pub(crate) fn remove_entity(&mut self, entity: Entity) -> Vec<(ComponentId, Entity)> {
+        for (relation, target) in self.targets.remove(&entity).unwrap_or_default() {
+            self.remove_source(relation, target, entity);
+        }
+
+        let incoming: Vec<(ComponentId, Entity)> = self
+            .incoming
+            .get(&entity)
+            .into_iter()
+            .flatten()
+            .flat_map(|relation| {
+                self.sources[&(*relation, entity)]
+                    .iter()
+                    .map(|source| (*relation, *source))
+            })
+            .collect();
+        for (relation, source) in incoming.iter() {
+            self.remove(*relation, *source);
+        }
+        incoming
+    }
+}
+
+impl World {
+    /// Adds a `Related<R>` component to `source` pointing at `target`,
+    /// replacing any previous `R` relation of `source`.
+    pub fn relate<R: 'static>(&mut self, source: Entity, target: Entity) -> Result<(), EntityNotFound> {
+        if !self.contains(target) {
+            return Err(EntityNotFound::new_with_value(target.index));
+        }
+        self.add_component(source, Related::<R>(target, PhantomData))?;
+
+        let relation = ComponentId::of::<Related<R>>();
+        self.relations.remove(relation, source);
+        self.relations.insert(relation, source, target);
+        Ok(())
+    }
+
+    /// Removes the `R` relation of `source`, returning its former target.
+    /// Same as removing its `Related<R>` component.
+    pub fn unrelate<R: 'static>(&mut self, source: Entity) -> Result<Entity, ComponentError> {
+        Ok(self.remove_component::<Related<R>>(source)?.target())
+    }
+
+    /// All entities related to `target` by `R`.
+    pub fn related_to<R: 'static>(&self, target: Entity) -> &[Entity] {
+        self.relations
+            .related_to(ComponentId::of::<Related<R>>(), target)
+    }
+}
+
+/// System parameter answering reverse relationship queries.
+pub struct Relations<'world, R: 'static> {
+    index: &'world RelationIndex,
+    phantom: PhantomData<fn() -> R>,
+}
+
+impl<'world, R: 'static> Relations<'world, R> {
+    pub fn related_to(&self, target: Entity) -> &[Entity] {
+        self.index.related_to(ComponentId::of::<Related<R>>(), target)
+    }
+}
+
+impl<'a, R: 'static> SysParam for Relations<'a, R> {
+    type Retrieve = RelationsRetrieve<R>;
+}
+
+#[doc(hidden)]
+pub struct RelationsRetrieve<R> {
+    phantom: PhantomData<R>,
+}
+
+impl<'world, R: 'static> Retrieve<'world> for RelationsRetrieve<R> {
+    type Item = Option<Relations<'world, R>>;
+    fn retrieve(world: &'world World) -> Result<Self::Item, RetrieveError> {
+        Ok(Some(Relations {
+            index: &world.relations,
+            phantom: PhantomData,
+        }))
+    }
+}
+
+impl<'a, 'world, R: 'static> RetrieveItem<'a> for Option<Relations<'world, R>> {
+    type InnerComponent = Relations<'world, R>;
+    fn inner(&'a mut self) -> Self::InnerComponent {
+        self.take().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Search, SearchIter, System};
+
+    struct Targets;
+    struct OwnedBy;
+    struct Health(u32);
+
+    #[test]
+    fn reverse_lookup() {
+        let mut world = World::new();
+        let target = world.new_entity((Health(10),)).unwrap();
+        let a = world.new_entity((Health(1),)).unwrap();
+        let b = world.new_entity((Health(2),)).unwrap();
+
+        world.relate::<Targets>(a, target).unwrap();
+        world.relate::<Targets>(b, target).unwrap();
+        world.relate::<OwnedBy>(a, b).unwrap();
+
+        assert_eq!(world.related_to::<Targets>(target), &[a, b]);
+        assert_eq!(world.related_to::<OwnedBy>(b), &[a]);
+        assert!(world.related_to::<OwnedBy>(target).is_empty());
+
+        assert_eq!(world.unrelate::<Targets>(a).unwrap(), target);
+        assert_eq!(world.related_to::<Targets>(target), &[b]);
+    }
+
+    #[test]
+    fn relate_replaces_previous_target() {
+        let mut world = World::new();
+        let first = world.new_entity((Health(1),)).unwrap();
+        let second = world.new_entity((Health(2),)).unwrap();
+        let source = world.new_entity((Health(3),)).unwrap();
+
+        world.relate::<Targets>(source, first).unwrap();
+        world.relate::<Targets>(source, second).unwrap();
+
+        assert!(world.related_to::<Targets>(first).is_empty());
+        assert_eq!(world.related_to::<Targets>(second), &[source]);
+    }
+
+    #[test]
+    fn despawn_target_strips_components() {
+        let mut world = World::new();
+        let target = world.new_entity((Health(10),)).unwrap();
+        let source = world.new_entity((Health(1),)).unwrap();
+        world.relate::<Targets>(source, target).unwrap();
+
+        world.despawn(target).unwrap();
+
+        assert!(!world.contains(target));
+        assert!(world.contains(source));
+        assert!(world.unrelate::<Targets>(source).is_err());
+        let mut search = world.search::<(&Health,)>().unwrap();
+        assert_eq!(search.iter().map(|h| h.0).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn despawn_source_updates_index() {
+        let mut world = World::new();
+        let target = world.new_entity((Health(10),)).unwrap();
+        let a = world.new_entity((Health(1),)).unwrap();
+        let b = world.new_entity((Health(2),)).unwrap();
+        world.relate::<Targets>(a, target).unwrap();
+        world.relate::<Targets>(b, target).unwrap();
+
+        world.despawn(a).unwrap();
+
+        assert_eq!(world.related_to::<Targets>(target), &[b]);
+        assert!(world.relate::<Targets>(a, target).is_err());
+
+        let reused = world.new_entity((Health(4),)).unwrap();
+        assert!(world.related_to::<Targets>(reused).is_empty());
+        assert!(!world.contains(a));
+    }
+
+    #[test]
+    fn removing_the_component_updates_index() {
+        let mut world = World::new();
+        let target = world.new_entity((Health(10),)).unwrap();
+        let a = world.new_entity((Health(1),)).unwrap();
+        let b = world.new_entity((Health(2),)).unwrap();
+        world.relate::<Targets>(a, target).unwrap();
+        world.relate::<Targets>(b, target).unwrap();
+
+        world.remove_component::<Related<Targets>>(a).unwrap();
+        assert_eq!(world.related_to::<Targets>(target), &[b]);
+        world.remove_by_id(b, ComponentId::of::<Related<Targets>>()).unwrap();
+        assert!(world.related_to::<Targets>(target).is_empty());
+
+        // Nothing points at the target any more, so despawning it leaves
+        // the former sources alone.
+        world.despawn(target).unwrap();
+        assert!(world.contains(a) && world.contains(b));
+        assert!(world.relations.incoming.is_empty() && world.relations.sources.is_empty());
+    }
+
+    #[test]
+    fn despawn_only_touches_relations_of_the_entity() {
+        let mut world = World::new();
+        let target = world.new_entity((Health(10),)).unwrap();
+        let other = world.new_entity((Health(20),)).unwrap();
+        let a = world.new_entity((Health(1),)).unwrap();
+        let b = world.new_entity((Health(2),)).unwrap();
+        world.relate::<Targets>(a, target).unwrap();
+        world.relate::<OwnedBy>(b, target).unwrap();
+        world.relate::<Targets>(b, other).unwrap();
+
+        world.despawn(target).unwrap();
+
+        assert!(world.unrelate::<Targets>(a).is_err());
+        assert!(world.unrelate::<OwnedBy>(b).is_err());
+        assert_eq!(world.related_to::<Targets>(other), &[b]);
+        assert!(!world.relations.incoming.contains_key(&target));
+    }
+
+    #[test]
+    fn relations_system_param() {
+        let mut world = World::new();
+        let target = world.new_entity((Health(10),)).unwrap();
+        let source = world.new_entity((Health(1),)).unwrap();
+        world.relate::<Targets>(source, target).unwrap();
+
+        fn count_targeting(mut search: Search<(&Related<Targets>,)>, relations: Relations<Targets>, _: f32) {
+            for related in search.iter() {
+                assert_eq!(relations.related_to(related.target()).len(), 1);
+            }
+        }
+        count_targeting.run(&world, 0.0).unwrap();
+    }
+}
@@ -1,8 +1,8 @@
 use crate::iter::*;
-use crate::{Archetype, ChainedIterator, ComponentAlreadyBorrowed, RetrieveError, World};
+use crate::{Archetype, ChainedIterator, ComponentAlreadyBorrowed, ComponentId, RetrieveError, World};
 use std::iter::Zip;
 use std::sync::{RwLockReadGuard, RwLockWriteGuard};
-use std::{any::TypeId, usize};
+use std::usize;
 
 pub trait SysParam {
     type Retrieve: for<'a> Retrieve<'a>;
@@ -93,10 +93,10 @@ impl<'a, 'world, T: 'a> RetrieveItem<'a> for SingleMut<'world, T> {
 impl<'world, T: 'static> Retrieve<'world> for &T {
     type Item = Single<'world, T>;
     fn retrieve(world: &'world World) -> Result<Self::Item, RetrieveError> {
-        let type_id = TypeId::of::<T>();
+        let component_id = ComponentId::of::<T>();
         for archetype in world.archetypes.iter() {
             for (i, c) in archetype.components.iter().enumerate() {
-                if c.type_id == type_id {
+                if c.id == component_id {
                     return Ok(Single {
                         borrow: archetype.retrieve(i).try_read().unwrap(),
                     });
@@ -111,10 +111,10 @@ impl<'world, T: 'static> Retrieve<'world> for &T {
 impl<'world, T: 'static> Retrieve<'world> for &mut T {
     type Item = SingleMut<'world, T>;
     fn retrieve(world: &'world World) -> Result<Self::Item, RetrieveError> {
-        let type_id = TypeId::of::<T>();
+        let component_id = ComponentId::of::<T>();
         for archetype in world.archetypes.iter() {
             for (i, c) in archetype.components.iter().enumerate() {
-                if c.type_id == type_id {
+                if c.id == component_id {
                     return Ok(SingleMut {
                         borrow: archetype.retrieve(i).try_write().unwrap(),
                     });
@@ -141,12 +141,12 @@ impl<'a, T: 'static> SearchParameterRetrieve<'a> for ReadSearchParameterRetrieve
     type RetrieveItem = RwLockReadGuard<'a, Vec<T>>;
     fn retrieve(world: &'a World, archetype: usize) -> Result<Self::RetrieveItem, RetrieveError> {
         let archetype: &Archetype = &world.archetypes[archetype];
-        let type_id = TypeId::of::<T>();
+        let component_id = ComponentId::of::<T>();
 
         let index = archetype
             .components
             .iter()
-            .position(|c| c.type_id == type_id)
+            .position(|c| c.id == component_id)
             .unwrap();
         if let Ok(read_guard) = archetype.retrieve(index).try_read() {
             Ok(read_guard)
@@ -165,8 +165,8 @@ impl<T: 'static> SearchParameter for &T {
     type SearchParameterRetrieve = ReadSearchParameterRetrieve<T>;
 
     fn matches(archetype: &Archetype) -> bool {
-        let type_id = TypeId::of::<T>();
-        archetype.components.iter().any(|c| c.type_id == type_id)
+        let component_id = ComponentId::of::<T>();
+        archetype.components.iter().any(|c| c.id == component_id)
     }
 }
 
@@ -174,8 +174,8 @@ impl<T: 'static> SearchParameter for &mut T {
     type SearchParameterRetrieve = WriteSearchParameterRetrieve<T>;
 
     fn matches(archetype: &Archetype) -> bool {
-        let type_id = TypeId::of::<T>();
-        archetype.components.iter().any(|c| c.type_id == type_id)
+        let component_id = ComponentId::of::<T>();
+        archetype.components.iter().any(|c| c.id == component_id)
     }
 }
 
@@ -184,24 +184,33 @@ pub struct Has<T> {
     phantom: std::marker::PhantomData<T>,
 }
 
+/// Whether an archetype has a component, repeated once per entity.
+pub struct HasColumn {
+    pub(crate) value: bool,
+    pub(crate) len: usize,
+}
+
 impl<'world, T: 'static> SearchParameterRetrieve<'world> for Has<T> {
-    type RetrieveItem = bool;
+    type RetrieveItem = HasColumn;
     fn retrieve(
         world: &'world World,
         archetype: usize,
     ) -> Result<Self::RetrieveItem, RetrieveError> {
         let archetype = &world.archetypes[archetype];
-        let type_id = TypeId::of::<T>();
+        let component_id = ComponentId::of::<T>();
 
-        let contains = archetype.components.iter().any(|c| c.type_id == type_id);
-        Ok(contains)
+        let contains = archetype.components.iter().any(|c| c.id == component_id);
+        Ok(HasColumn {
+            value: contains,
+            len: archetype.entities.len(),
+        })
     }
 }
 
-impl<'a, 'world> SearchIter<'a> for bool {
-    type Iter = std::iter::Repeat<bool>;
+impl<'a> SearchIter<'a> for HasColumn {
+    type Iter = std::iter::RepeatN<bool>;
     fn iter(&'a mut self) -> Self::Iter {
-        std::iter::repeat::<_>(*self)
+        std::iter::repeat_n(self.value, self.len)
     }
 }
 
@@ -225,12 +234,12 @@ impl<'world, T: 'static> SearchParameterRetrieve<'world> for WriteSearchParamete
         archetype: usize,
     ) -> Result<Self::RetrieveItem, RetrieveError> {
         let archetype = &world.archetypes[archetype];
-        let type_id: TypeId = TypeId::of::<T>();
+        let component_id = ComponentId::of::<T>();
 
         let index: usize = archetype
             .components
             .iter()
-            .position(|c| c.type_id == type_id)
+            .position(|c| c.id == component_id)
             .unwrap();
         if let Ok(write_guard) = archetype.retrieve(index).try_write() {
             Ok(write_guard)
@@ -242,7 +251,7 @@ impl<'world, T: 'static> SearchParameterRetrieve<'world> for WriteSearchParamete
     }
 }
 
-type SearchParameterItem<'world, S> =
+pub(crate) type SearchParameterItem<'world, S> =
     <<S as SearchParameter>::SearchParameterRetrieve as SearchParameterRetrieve<'world>>::RetrieveItem;
 
 pub trait SearchIter<'a> {
@@ -0,0 +1,21 @@
+#version 450
+
+layout (location = 0) in vec2 in_uv;
+
+layout (location = 0) out vec4 out_color;
+layout (location = 1) out vec4 out_bloom;
+
+layout (set = 2, binding = 0) uniform sampler2D in_color;
+layout (set = 2, binding = 1) uniform sampler2D in_depth;
+
+layout (std140, set = 3, binding = 0) uniform BlurSettings {
+    vec4 direction_radius;
+    mat4 transform;
+    float weights[2];
+} blur_settings;
+
+layout (push_constant) uniform PushConsts {
+    uint index;
+} push_consts;
+
+void main() {}
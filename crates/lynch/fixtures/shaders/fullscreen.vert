@@ -0,0 +1,5 @@
+#version 450
+
+layout (location = 0) out vec2 out_uv;
+
+void main() {}
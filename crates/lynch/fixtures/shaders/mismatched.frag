@@ -0,0 +1,8 @@
+#version 450
+
+layout (location = 0) out vec4 out_color;
+
+// A sampler in blur.frag.
+layout (set = 2, binding = 0, rgba8) uniform image2D in_color;
+
+void main() {}
@@ -0,0 +1,8 @@
+#version 450
+
+// Larger than the block in blur.frag.
+layout (push_constant) uniform PushConsts {
+    mat4 world;
+} push_consts;
+
+void main() {}
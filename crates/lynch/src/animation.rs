@@ -0,0 +1,837 @@
+use std::collections::HashMap;
+
+use glam::{Mat4, Quat, Vec3, Vec4};
+
+/// Local translation, rotation and scale of a node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NodeTransform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Default for NodeTransform {
+    fn default() -> Self {
+        Self {
+            translation: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+            scale: Vec3::ONE,
+        }
+    }
+}
+
+impl NodeTransform {
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Step,
+    Linear,
+    /// Every keyframe stores an in-tangent, a value and an out-tangent.
+    CubicSpline,
+}
+
+impl From<gltf::animation::Interpolation> for Interpolation {
+    fn from(interpolation: gltf::animation::Interpolation) -> Self {
+        match interpolation {
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+    /// All morph weights of a keyframe are stored next to each other.
+    MorphWeights(Vec<f32>),
+}
+
+/// Keyframes driving one property of one node.
+#[derive(Clone, Debug)]
+pub struct AnimationChannel {
+    /// Index of the animated node in its model.
+    pub node: usize,
+    pub interpolation: Interpolation,
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+impl AnimationChannel {
+    /// Writes the channel's value at `time` into `transform` or `weights`.
+    pub fn sample(&self, time: f32, transform: &mut NodeTransform, weights: &mut [f32]) {
+        match &self.keyframes {
+            Keyframes::Translation(values) => {
+                transform.translation = self.sample_keys(time, |i| values[i]);
+            }
+            Keyframes::Rotation(values) => {
+                transform.rotation = self.sample_keys(time, |i| values[i]);
+            }
+            Keyframes::Scale(values) => {
+                transform.scale = self.sample_keys(time, |i| values[i]);
+            }
+            Keyframes::MorphWeights(values) => {
+                let outputs = match self.interpolation {
+                    Interpolation::CubicSpline => self.times.len() * 3,
+                    _ => self.times.len(),
+                };
+                let count = values.len() / outputs.max(1);
+                for (target, weight) in weights.iter_mut().enumerate().take(count) {
+                    *weight = self.sample_keys(time, |i| values[i * count + target]);
+                }
+            }
+        }
+    }
+
+    fn sample_keys<T: Keyframe>(&self, time: f32, value: impl Fn(usize) -> T) -> T {
+        let cubic = self.interpolation == Interpolation::CubicSpline;
+        let key = |k: usize| if cubic { value(3 * k + 1) } else { value(k) };
+
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return key(0);
+        }
+        if time >= self.times[last] {
+            return key(last);
+        }
+
+        let next = self.times.partition_point(|t| *t <= time);
+        let previous = next - 1;
+        let delta = self.times[next] - self.times[previous];
+        let t = (time - self.times[previous]) / delta;
+        match self.interpolation {
+            Interpolation::Step => key(previous),
+            Interpolation::Linear => T::lerp(key(previous), key(next), t),
+            Interpolation::CubicSpline => T::hermite(
+                value(3 * previous + 1),
+                value(3 * previous + 2) * delta,
+                value(3 * next + 1),
+                value(3 * next) * delta,
+                t,
+            ),
+        }
+    }
+}
+
+trait Keyframe: Copy + std::ops::Mul<f32, Output = Self> {
+    fn lerp(a: Self, b: Self, t: f32) -> Self;
+    /// Cubic Hermite spline between `v0` and `v1` with scaled tangents.
+    fn hermite(v0: Self, out_tangent: Self, v1: Self, in_tangent: Self, t: f32) -> Self;
+}
+
+fn hermite_weights(t: f32) -> [f32; 4] {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    [
+        2.0 * t3 - 3.0 * t2 + 1.0,
+        t3 - 2.0 * t2 + t,
+        -2.0 * t3 + 3.0 * t2,
+        t3 - t2,
+    ]
+}
+
+impl Keyframe for f32 {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        a + (b - a) * t
+    }
+    fn hermite(v0: Self, out_tangent: Self, v1: Self, in_tangent: Self, t: f32) -> Self {
+        let [a, b, c, d] = hermite_weights(t);
+        v0 * a + out_tangent * b + v1 * c + in_tangent * d
+    }
+}
+
+impl Keyframe for Vec3 {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        a.lerp(b, t)
+    }
+    fn hermite(v0: Self, out_tangent: Self, v1: Self, in_tangent: Self, t: f32) -> Self {
+        let [a, b, c, d] = hermite_weights(t);
+        v0 * a + out_tangent * b + v1 * c + in_tangent * d
+    }
+}
+
+impl Keyframe for Quat {
+    fn lerp(a: Self, b: Self, t: f32) -> Self {
+        a.slerp(b, t)
+    }
+    fn hermite(v0: Self, out_tangent: Self, v1: Self, in_tangent: Self, t: f32) -> Self {
+        let [a, b, c, d] = hermite_weights(t);
+        let q = Vec4::from(v0) * a + Vec4::from(out_tangent) * b + Vec4::from(v1) * c
+            + Vec4::from(in_tangent) * d;
+        Quat::from_vec4(q).normalize()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: Option<String>,
+    pub channels: Vec<AnimationChannel>,
+    /// Time of the last keyframe of any channel, in seconds.
+    pub duration: f32,
+}
+
+impl AnimationClip {
+    /// Writes every channel of the clip at `time` into `pose`.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in &self.channels {
+            if let (Some(transform), Some(weights)) = (
+                pose.transforms.get_mut(channel.node),
+                pose.weights.get_mut(channel.node),
+            ) {
+                channel.sample(time, transform, weights);
+            }
+        }
+    }
+}
+
+impl NodeTransform {
+    pub fn lerp(&self, other: &NodeTransform, t: f32) -> NodeTransform {
+        NodeTransform {
+            translation: self.translation.lerp(other.translation, t),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale.lerp(other.scale, t),
+        }
+    }
+}
+
+/// Local transforms and morph weights of every node of a model, indexed like
+/// `CpuModel::nodes`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Pose {
+    pub transforms: Vec<NodeTransform>,
+    pub weights: Vec<Vec<f32>>,
+}
+
+impl Pose {
+    /// Moves the pose towards `other` by `weight`, 1.0 replacing it.
+    pub fn blend(&mut self, other: &Pose, weight: f32) {
+        if weight >= 1.0 {
+            self.clone_from(other);
+            return;
+        }
+        for (transform, target) in self.transforms.iter_mut().zip(&other.transforms) {
+            *transform = transform.lerp(target, weight);
+        }
+        for (weights, targets) in self.weights.iter_mut().zip(&other.weights) {
+            for (value, target) in weights.iter_mut().zip(targets) {
+                *value += (target - *value) * weight;
+            }
+        }
+    }
+
+    /// Adds how far `additive` moved away from `reference`, scaled by `weight`.
+    pub fn add(&mut self, additive: &Pose, reference: &Pose, weight: f32) {
+        let transforms = additive.transforms.iter().zip(&reference.transforms);
+        for (transform, (added, base)) in self.transforms.iter_mut().zip(transforms) {
+            let rotation = Quat::IDENTITY.slerp(added.rotation * base.rotation.inverse(), weight);
+            transform.translation += (added.translation - base.translation) * weight;
+            transform.rotation = (rotation * transform.rotation).normalize();
+            transform.scale *= Vec3::ONE.lerp(added.scale / base.scale, weight);
+        }
+        let weights = additive.weights.iter().zip(&reference.weights);
+        for (values, (added, base)) in self.weights.iter_mut().zip(weights) {
+            for (value, (added, base)) in values.iter_mut().zip(added.iter().zip(base)) {
+                *value += (added - base) * weight;
+            }
+        }
+    }
+
+    /// Model space transform of every node, given each node's parent.
+    pub fn global_transforms(&self, parents: &[Option<usize>]) -> Vec<Mat4> {
+        let mut globals: Vec<Option<Mat4>> = vec![None; self.transforms.len()];
+        let mut chain = vec![];
+        for node in 0..globals.len() {
+            // Walk up to the first resolved ancestor, then back down.
+            let mut next = Some(node);
+            while let Some(index) = next {
+                if globals[index].is_some() || chain.len() > globals.len() {
+                    break;
+                }
+                chain.push(index);
+                next = parents.get(index).copied().flatten();
+            }
+            let mut global = next.and_then(|index| globals[index]).unwrap_or(Mat4::IDENTITY);
+            for index in chain.drain(..).rev() {
+                global *= self.transforms[index].matrix();
+                globals[index] = Some(global);
+            }
+        }
+        globals.into_iter().map(|global| global.unwrap_or(Mat4::IDENTITY)).collect()
+    }
+}
+
+/// A clip being played back, `clip` indexing the clips of an `AnimationLibrary`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlayingClip {
+    pub clip: usize,
+    pub time: f32,
+    pub speed: f32,
+    pub looping: bool,
+}
+
+impl PlayingClip {
+    pub fn new(clip: usize) -> Self {
+        Self {
+            clip,
+            time: 0.0,
+            speed: 1.0,
+            looping: true,
+        }
+    }
+
+    pub fn looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn speed(mut self, speed: f32) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    fn advance(&mut self, delta_time: f32, duration: f32) {
+        self.time += delta_time * self.speed;
+        self.time = match self.looping && duration > 0.0 {
+            true => self.time.rem_euclid(duration),
+            false => self.time.clamp(0.0, duration),
+        };
+    }
+
+    /// Whether a non looping clip has reached its end.
+    pub fn is_finished(&self, duration: f32) -> bool {
+        !self.looping
+            && match self.speed < 0.0 {
+                true => self.time <= 0.0,
+                false => self.time >= duration,
+            }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Blends the layer's pose over the layers below it.
+    Override,
+    /// Adds the layer's offset from the first frame of its clip.
+    Additive,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Crossfade {
+    from: PlayingClip,
+    elapsed: f32,
+    duration: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationLayer {
+    pub mode: BlendMode,
+    pub weight: f32,
+    current: Option<PlayingClip>,
+    fade: Option<Crossfade>,
+}
+
+impl AnimationLayer {
+    pub fn new(mode: BlendMode, weight: f32) -> Self {
+        Self {
+            mode,
+            weight,
+            current: None,
+            fade: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<&PlayingClip> {
+        self.current.as_ref()
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut PlayingClip> {
+        self.current.as_mut()
+    }
+
+    pub fn is_fading(&self) -> bool {
+        self.fade.is_some()
+    }
+
+    pub fn play(&mut self, clip: PlayingClip) {
+        self.current = Some(clip);
+        self.fade = None;
+    }
+
+    /// Fades from the current clip to `clip` over `duration` seconds.
+    pub fn crossfade(&mut self, clip: PlayingClip, duration: f32) {
+        self.fade = match self.current {
+            Some(from) if duration > 0.0 => Some(Crossfade {
+                from,
+                elapsed: 0.0,
+                duration,
+            }),
+            _ => None,
+        };
+        self.current = Some(clip);
+    }
+
+    pub fn stop(&mut self) {
+        self.current = None;
+        self.fade = None;
+    }
+
+    fn advance(&mut self, delta_time: f32, clips: &[AnimationClip]) {
+        let duration = |clip: &PlayingClip| clips.get(clip.clip).map_or(0.0, |clip| clip.duration);
+        if let Some(current) = &mut self.current {
+            current.advance(delta_time, duration(current));
+        }
+        if let Some(fade) = &mut self.fade {
+            fade.from.advance(delta_time, duration(&fade.from));
+            fade.elapsed += delta_time;
+            if fade.elapsed >= fade.duration {
+                self.fade = None;
+            }
+        }
+    }
+
+    /// Samples the layer on top of `rest` into `out`, false if nothing plays.
+    fn sample(&self, clips: &[AnimationClip], rest: &Pose, out: &mut Pose, scratch: &mut Pose) -> bool {
+        let Some(current) = self.current else {
+            return false;
+        };
+        let Some(clip) = clips.get(current.clip) else {
+            return false;
+        };
+        out.clone_from(rest);
+        clip.sample(current.time, out);
+
+        if let Some(fade) = self.fade {
+            if let Some(from) = clips.get(fade.from.clip) {
+                scratch.clone_from(rest);
+                from.sample(fade.from.time, scratch);
+                scratch.blend(out, fade.elapsed / fade.duration);
+                std::mem::swap(out, scratch);
+            }
+        }
+        true
+    }
+}
+
+/// Plays the clips of an `AnimationLibrary` on a stack of layers, each one
+/// blended over the layers before it. Layer 0 is the base layer.
+#[derive(Clone, Debug)]
+pub struct AnimationPlayer {
+    pub layers: Vec<AnimationLayer>,
+    pub paused: bool,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            layers: vec![AnimationLayer::new(BlendMode::Override, 1.0)],
+            paused: false,
+        }
+    }
+}
+
+impl AnimationPlayer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a layer on top of the existing ones and returns its index.
+    pub fn add_layer(&mut self, mode: BlendMode, weight: f32) -> usize {
+        self.layers.push(AnimationLayer::new(mode, weight));
+        self.layers.len() - 1
+    }
+
+    pub fn layer(&self, layer: usize) -> Option<&AnimationLayer> {
+        self.layers.get(layer)
+    }
+
+    pub fn layer_mut(&mut self, layer: usize) -> Option<&mut AnimationLayer> {
+        self.layers.get_mut(layer)
+    }
+
+    pub fn play(&mut self, clip: usize) {
+        self.play_on(0, PlayingClip::new(clip));
+    }
+
+    pub fn crossfade(&mut self, clip: usize, duration: f32) {
+        self.crossfade_on(0, PlayingClip::new(clip), duration);
+    }
+
+    pub fn play_on(&mut self, layer: usize, clip: PlayingClip) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.play(clip);
+        }
+    }
+
+    pub fn crossfade_on(&mut self, layer: usize, clip: PlayingClip, duration: f32) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.crossfade(clip, duration);
+        }
+    }
+
+    pub fn set_layer_weight(&mut self, layer: usize, weight: f32) {
+        if let Some(layer) = self.layers.get_mut(layer) {
+            layer.weight = weight;
+        }
+    }
+
+    pub fn advance(&mut self, delta_time: f32, clips: &[AnimationClip]) {
+        if self.paused {
+            return;
+        }
+        for layer in &mut self.layers {
+            layer.advance(delta_time, clips);
+        }
+    }
+
+    /// Blends every layer over the `rest` pose into `pose`.
+    pub fn evaluate(&self, clips: &[AnimationClip], rest: &Pose, pose: &mut Pose) {
+        pose.clone_from(rest);
+        let mut layer_pose = Pose::default();
+        let mut scratch = Pose::default();
+        for layer in &self.layers {
+            if layer.weight <= 0.0 || !layer.sample(clips, rest, &mut layer_pose, &mut scratch) {
+                continue;
+            }
+            match layer.mode {
+                BlendMode::Override => pose.blend(&layer_pose, layer.weight),
+                BlendMode::Additive => {
+                    let clip = &clips[layer.current.unwrap().clip];
+                    scratch.clone_from(rest);
+                    clip.sample(0.0, &mut scratch);
+                    pose.add(&layer_pose, &scratch, layer.weight);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    Greater(String, f32),
+    Less(String, f32),
+    /// The state's clip reached its end, never true for looping states.
+    Finished,
+}
+
+#[derive(Clone, Debug)]
+pub struct AnimationState {
+    pub name: String,
+    pub clip: PlayingClip,
+}
+
+#[derive(Clone, Debug)]
+pub struct StateTransition {
+    pub from: usize,
+    pub to: usize,
+    /// Crossfade time in seconds.
+    pub duration: f32,
+    /// All of them have to hold for the transition to be taken.
+    pub conditions: Vec<Condition>,
+}
+
+/// Drives one layer of an `AnimationPlayer` from named float parameters.
+#[derive(Clone, Debug)]
+pub struct AnimationStateMachine {
+    pub states: Vec<AnimationState>,
+    pub transitions: Vec<StateTransition>,
+    pub layer: usize,
+    parameters: HashMap<String, f32>,
+    current: usize,
+    started: bool,
+}
+
+impl AnimationStateMachine {
+    pub fn new(states: Vec<AnimationState>, initial: usize) -> Self {
+        Self {
+            states,
+            transitions: vec![],
+            layer: 0,
+            parameters: HashMap::new(),
+            current: initial,
+            started: false,
+        }
+    }
+
+    pub fn on_layer(mut self, layer: usize) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    pub fn transition(mut self, from: usize, to: usize, duration: f32, conditions: Vec<Condition>) -> Self {
+        self.transitions.push(StateTransition {
+            from,
+            to,
+            duration,
+            conditions,
+        });
+        self
+    }
+
+    pub fn set_parameter(&mut self, name: &str, value: f32) {
+        self.parameters.insert(name.to_string(), value);
+    }
+
+    /// Value of a parameter, 0.0 if it was never set.
+    pub fn parameter(&self, name: &str) -> f32 {
+        self.parameters.get(name).copied().unwrap_or(0.0)
+    }
+
+    pub fn current_state(&self) -> &AnimationState {
+        &self.states[self.current]
+    }
+
+    /// Starts the initial state on the first call, then takes the first
+    /// transition out of the current state whose conditions all hold.
+    /// Returns whether the state changed.
+    pub fn update(&mut self, player: &mut AnimationPlayer, clips: &[AnimationClip]) -> bool {
+        if !self.started {
+            self.started = true;
+            player.play_on(self.layer, self.states[self.current].clip);
+        }
+
+        let finished = player
+            .layer(self.layer)
+            .and_then(AnimationLayer::current)
+            .is_none_or(|playing| {
+                playing.is_finished(clips.get(playing.clip).map_or(0.0, |clip| clip.duration))
+            });
+        let holds = |condition: &Condition| match condition {
+            Condition::Greater(name, value) => self.parameter(name) > *value,
+            Condition::Less(name, value) => self.parameter(name) < *value,
+            Condition::Finished => finished,
+        };
+        let Some(transition) = self
+            .transitions
+            .iter()
+            .find(|t| t.from == self.current && t.conditions.iter().all(&holds))
+        else {
+            return false;
+        };
+
+        let (to, duration) = (transition.to, transition.duration);
+        self.current = to;
+        player.crossfade_on(self.layer, self.states[to].clip, duration);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(interpolation: Interpolation, times: Vec<f32>, keyframes: Keyframes) -> AnimationChannel {
+        AnimationChannel {
+            node: 0,
+            interpolation,
+            times,
+            keyframes,
+        }
+    }
+
+    fn translation_at(channel: &AnimationChannel, time: f32) -> Vec3 {
+        let mut transform = NodeTransform::default();
+        channel.sample(time, &mut transform, &mut []);
+        transform.translation
+    }
+
+    #[test]
+    fn step_and_linear() {
+        let values = vec![Vec3::ZERO, Vec3::new(2.0, 0.0, 0.0), Vec3::new(2.0, 4.0, 0.0)];
+        let step = channel(
+            Interpolation::Step,
+            vec![0.0, 1.0, 2.0],
+            Keyframes::Translation(values.clone()),
+        );
+        let linear = channel(
+            Interpolation::Linear,
+            vec![0.0, 1.0, 2.0],
+            Keyframes::Translation(values),
+        );
+
+        assert_eq!(translation_at(&step, 0.5), Vec3::ZERO);
+        assert_eq!(translation_at(&step, 1.0), Vec3::new(2.0, 0.0, 0.0));
+        assert_eq!(translation_at(&linear, 0.5), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(translation_at(&linear, 1.5), Vec3::new(2.0, 2.0, 0.0));
+        // Clamped outside the keyframe range.
+        assert_eq!(translation_at(&linear, -1.0), Vec3::ZERO);
+        assert_eq!(translation_at(&linear, 5.0), Vec3::new(2.0, 4.0, 0.0));
+    }
+
+    #[test]
+    fn cubic_spline_hits_keys_and_follows_tangents() {
+        // Zero tangents give a smoothstep between the two values.
+        let values = vec![
+            Vec3::ZERO,
+            Vec3::ZERO,
+            Vec3::ZERO,
+            Vec3::ZERO,
+            Vec3::ONE,
+            Vec3::ZERO,
+        ];
+        let cubic = channel(
+            Interpolation::CubicSpline,
+            vec![0.0, 2.0],
+            Keyframes::Translation(values),
+        );
+
+        assert_eq!(translation_at(&cubic, 0.0), Vec3::ZERO);
+        assert_eq!(translation_at(&cubic, 1.0), Vec3::splat(0.5));
+        assert_eq!(translation_at(&cubic, 0.5), Vec3::splat(0.15625));
+        assert_eq!(translation_at(&cubic, 2.0), Vec3::ONE);
+    }
+
+    #[test]
+    fn rotations_slerp() {
+        let quarter = Quat::from_rotation_y(std::f32::consts::FRAC_PI_2);
+        let linear = channel(
+            Interpolation::Linear,
+            vec![0.0, 1.0],
+            Keyframes::Rotation(vec![Quat::IDENTITY, quarter]),
+        );
+        let mut transform = NodeTransform::default();
+        linear.sample(0.5, &mut transform, &mut []);
+
+        let expected = Quat::from_rotation_y(std::f32::consts::FRAC_PI_4);
+        assert!(transform.rotation.abs_diff_eq(expected, 1e-5));
+    }
+
+    #[test]
+    fn morph_weights_are_interleaved() {
+        let linear = channel(
+            Interpolation::Linear,
+            vec![0.0, 1.0],
+            Keyframes::MorphWeights(vec![0.0, 1.0, 1.0, 0.0]),
+        );
+        let mut weights = [0.0; 2];
+        linear.sample(0.25, &mut NodeTransform::default(), &mut weights);
+        assert_eq!(weights, [0.25, 0.75]);
+    }
+
+    /// Clip moving node 0 from `from` to `to` over one second.
+    fn slide(from: Vec3, to: Vec3) -> AnimationClip {
+        AnimationClip {
+            name: None,
+            channels: vec![channel(
+                Interpolation::Linear,
+                vec![0.0, 1.0],
+                Keyframes::Translation(vec![from, to]),
+            )],
+            duration: 1.0,
+        }
+    }
+
+    fn rest() -> Pose {
+        Pose {
+            transforms: vec![NodeTransform::default()],
+            weights: vec![vec![]],
+        }
+    }
+
+    fn evaluate(player: &AnimationPlayer, clips: &[AnimationClip]) -> Vec3 {
+        let mut pose = Pose::default();
+        player.evaluate(clips, &rest(), &mut pose);
+        pose.transforms[0].translation
+    }
+
+    #[test]
+    fn crossfade_blends_both_clips() {
+        let clips = [slide(Vec3::ZERO, Vec3::ZERO), slide(Vec3::X * 4.0, Vec3::X * 4.0)];
+        let mut player = AnimationPlayer::new();
+        player.play(0);
+        player.crossfade(1, 1.0);
+        assert!(player.layer(0).unwrap().is_fading());
+
+        player.advance(0.25, &clips);
+        assert!(evaluate(&player, &clips).abs_diff_eq(Vec3::X, 1e-5));
+        player.advance(1.0, &clips);
+        assert!(!player.layer(0).unwrap().is_fading());
+        assert_eq!(evaluate(&player, &clips), Vec3::X * 4.0);
+    }
+
+    #[test]
+    fn additive_layers_offset_the_base() {
+        let clips = [slide(Vec3::Y, Vec3::Y), slide(Vec3::ZERO, Vec3::Z * 2.0)];
+        let mut player = AnimationPlayer::new();
+        player.play(0);
+        let layer = player.add_layer(BlendMode::Additive, 0.5);
+        player.play_on(layer, PlayingClip::new(1).looping(false));
+
+        player.advance(1.0, &clips);
+        assert_eq!(evaluate(&player, &clips), Vec3::new(0.0, 1.0, 1.0));
+        player.set_layer_weight(layer, 0.0);
+        assert_eq!(evaluate(&player, &clips), Vec3::Y);
+    }
+
+    #[test]
+    fn looping_wraps_and_one_shots_clamp() {
+        let clips = [slide(Vec3::ZERO, Vec3::X)];
+        let mut clip = PlayingClip::new(0);
+        clip.advance(1.25, clips[0].duration);
+        assert_eq!(clip.time, 0.25);
+
+        let mut once = PlayingClip::new(0).looping(false);
+        once.advance(1.25, clips[0].duration);
+        assert_eq!(once.time, 1.0);
+        assert!(once.is_finished(clips[0].duration));
+    }
+
+    #[test]
+    fn state_machine_follows_parameters() {
+        let clips = [
+            slide(Vec3::ZERO, Vec3::ZERO),
+            slide(Vec3::X, Vec3::X),
+            slide(Vec3::Y, Vec3::Y),
+        ];
+        let states = vec![
+            AnimationState { name: "idle".into(), clip: PlayingClip::new(0) },
+            AnimationState { name: "walk".into(), clip: PlayingClip::new(1) },
+            AnimationState { name: "jump".into(), clip: PlayingClip::new(2).looping(false) },
+        ];
+        let mut machine = AnimationStateMachine::new(states, 0)
+            .transition(0, 1, 0.0, vec![Condition::Greater("speed".into(), 0.5)])
+            .transition(1, 0, 0.5, vec![Condition::Less("speed".into(), 0.5)])
+            .transition(1, 2, 0.0, vec![Condition::Greater("jump".into(), 0.0)])
+            .transition(2, 1, 0.0, vec![Condition::Finished]);
+        let mut player = AnimationPlayer::new();
+
+        assert!(!machine.update(&mut player, &clips));
+        assert_eq!(evaluate(&player, &clips), Vec3::ZERO);
+
+        machine.set_parameter("speed", 1.0);
+        assert!(machine.update(&mut player, &clips));
+        assert_eq!(machine.current_state().name, "walk");
+        assert_eq!(evaluate(&player, &clips), Vec3::X);
+
+        machine.set_parameter("jump", 1.0);
+        assert!(machine.update(&mut player, &clips));
+        assert_eq!(machine.current_state().name, "jump");
+        machine.set_parameter("jump", 0.0);
+        assert!(!machine.update(&mut player, &clips));
+
+        player.advance(1.0, &clips);
+        assert!(machine.update(&mut player, &clips));
+        assert_eq!(machine.current_state().name, "walk");
+
+        machine.set_parameter("speed", 0.0);
+        assert!(machine.update(&mut player, &clips));
+        assert!(player.layer(0).unwrap().is_fading());
+    }
+
+    #[test]
+    fn global_transforms_follow_parents_in_any_order() {
+        let mut pose = rest();
+        pose.transforms = vec![
+            NodeTransform { translation: Vec3::X, ..Default::default() },
+            NodeTransform { translation: Vec3::Y, ..Default::default() },
+        ];
+        let globals = pose.global_transforms(&[Some(1), None]);
+        assert_eq!(globals[1], Mat4::from_translation(Vec3::Y));
+        assert_eq!(globals[0], Mat4::from_translation(Vec3::new(1.0, 1.0, 0.0)));
+    }
+}
@@ -0,0 +1,815 @@
+//! Typed, reference counted handles to models and textures. Files are
+//! imported on background threads and uploaded on the render thread once
+//! finished, see [`Assets::poll`]. Loading a path twice hands out the same
+//! asset, and an import that fails is replaced by a placeholder. Files
+//! changed on disk can be imported again in place, see [`Assets::reload`].
+
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Formatter},
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, Weak,
+    },
+    thread::JoinHandle,
+};
+
+use glam::{Vec3, Vec4};
+
+use crate::{
+    animation::NodeTransform,
+    cook::{is_cooked, load_cooked_model, load_cooked_texture, SourceKind},
+    gltf_loader::{
+        import_gltf, CpuImage, CpuMesh, CpuModel, CpuNode, LodGeneration, Material, MaterialExtensions, Model,
+        DEFAULT_TEXTURE_MAP,
+    },
+    hot_reload::{absolute_path, watched_file, AssetWatcher, WatchedFile},
+    mesh::Vertex,
+    obj_loader::import_obj,
+    texture_processing::{load_texture, TextureSettings, TextureUsage},
+    Texture,
+};
+
+/// Reference to an asset in an [`Assets`]. The asset stays cached while a
+/// clone of the handle is alive.
+pub struct Handle<T> {
+    id: usize,
+    refs: Arc<()>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Handle {
+            id: self.id,
+            refs: self.refs.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> Debug for Handle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle<{}>({})", std::any::type_name::<T>(), self.id)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    /// The last import failed. The placeholder is used instead, or the
+    /// previous version if the asset was being reloaded.
+    Failed(String),
+    /// Imported again after a change, the current version stays in use
+    /// until the new one is complete.
+    Reloading,
+}
+
+/// An asset type [`Assets`] can import. `import` runs on a loader thread,
+/// turning the asset into a GPU resource is left to the caller of
+/// [`Assets::poll`].
+pub trait Asset: Sized + 'static {
+    type Cpu: Send + 'static;
+    /// How files are imported, shared by every asset of the type.
+    type Settings: Clone + Default + Send + 'static;
+
+    fn import(path: &Path, settings: &Self::Settings) -> Result<Self::Cpu, String>;
+
+    /// Stand-in for assets whose import failed.
+    fn placeholder() -> Self::Cpu;
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModelImport {
+    /// Levels of detail to generate for meshes without authored ones. Off by
+    /// default, as cooked models already carry their levels.
+    pub lods: Option<LodGeneration>,
+    pub textures: TextureSettings,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureImport {
+    /// What standalone textures are sampled as.
+    pub usage: TextureUsage,
+    pub textures: TextureSettings,
+}
+
+impl Default for TextureImport {
+    fn default() -> Self {
+        TextureImport {
+            usage: TextureUsage::Data,
+            textures: TextureSettings::default(),
+        }
+    }
+}
+
+impl Asset for Model {
+    type Cpu = CpuModel;
+    type Settings = ModelImport;
+
+    fn import(path: &Path, settings: &ModelImport) -> Result<CpuModel, String> {
+        if is_cooked(path, SourceKind::Model) {
+            return load_cooked_model(path, &settings.textures).map_err(|err| err.to_string());
+        }
+        let is_obj = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("obj"));
+        let mut model = match is_obj {
+            true => import_obj(path).map_err(|err| err.to_string())?,
+            false => import_gltf(path).map_err(|err| err.to_string())?,
+        };
+        if let Some(lods) = &settings.lods {
+            model.generate_lods(lods);
+        }
+        model.process_images(&settings.textures);
+        Ok(model)
+    }
+
+    fn placeholder() -> CpuModel {
+        placeholder_model()
+    }
+}
+
+impl Asset for Texture {
+    type Cpu = CpuImage;
+    type Settings = TextureImport;
+
+    fn import(path: &Path, settings: &TextureImport) -> Result<CpuImage, String> {
+        if is_cooked(path, SourceKind::Image) {
+            return load_cooked_texture(path, &settings.textures).map_err(|err| err.to_string());
+        }
+        load_texture(path, settings.usage, &settings.textures).map_err(|err| err.to_string())
+    }
+
+    fn placeholder() -> CpuImage {
+        placeholder_image()
+    }
+}
+
+/// Magenta and black checkerboard, hard to miss on screen.
+pub fn placeholder_image() -> CpuImage {
+    const MAGENTA: [u8; 4] = [255, 0, 255, 255];
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    CpuImage::rgba8(2, 2, [MAGENTA, BLACK, BLACK, MAGENTA].concat())
+}
+
+/// Magenta unit cube around the origin.
+pub fn placeholder_model() -> CpuModel {
+    let mut vertices = vec![];
+    let mut indices = vec![];
+    for axis in [Vec3::X, Vec3::Y, Vec3::Z, -Vec3::X, -Vec3::Y, -Vec3::Z] {
+        let u = Vec3::new(axis.y, axis.z, axis.x);
+        let v = axis.cross(u);
+        let base = vertices.len() as u32;
+        for (x, y) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            vertices.push(Vertex {
+                pos: ((axis + u * (x * 2.0 - 1.0) + v * (y * 2.0 - 1.0)) * 0.5).extend(0.0),
+                normal: axis.extend(0.0),
+                uv: glam::Vec2::new(x, y),
+                color: Vec4::ONE,
+                tangent: u.extend(1.0),
+            });
+        }
+        indices.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    CpuModel {
+        meshes: vec![CpuMesh {
+            indices,
+            vertices,
+            material: Material {
+                diffuse_map: DEFAULT_TEXTURE_MAP,
+                normal_map: DEFAULT_TEXTURE_MAP,
+                metallic_roughness_map: DEFAULT_TEXTURE_MAP,
+                occlusion_map: DEFAULT_TEXTURE_MAP,
+                base_color_factor: Vec4::new(1.0, 0.0, 1.0, 1.0),
+                metallic_factor: 0.0,
+                roughness_factor: 1.0,
+            },
+            material_extensions: MaterialExtensions::default(),
+            joints: vec![],
+            joint_weights: vec![],
+            morph_targets: vec![],
+            node: 0,
+            lods: vec![],
+        }],
+        nodes: vec![CpuNode {
+            name: Some(String::from("placeholder")),
+            parent: None,
+            children: vec![],
+            transform: NodeTransform::default(),
+            local_transform: glam::Mat4::IDENTITY,
+            world_transform: glam::Mat4::IDENTITY,
+            meshes: vec![0],
+            skin: None,
+            camera: None,
+            light: None,
+            morph_weights: vec![],
+        }],
+        roots: vec![0],
+        images: vec![],
+        skins: vec![],
+        animations: vec![],
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads running imports in the order they were queued.
+pub struct LoadQueue {
+    jobs: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl LoadQueue {
+    pub fn new(threads: usize) -> Self {
+        let (jobs, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+                std::thread::Builder::new()
+                    .name(format!("asset loader {i}"))
+                    .spawn(move || loop {
+                        // The lock is released before the job runs.
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            // A panicking job must not take the thread with it.
+                            Ok(job) => {
+                                let _ = panic::catch_unwind(AssertUnwindSafe(job));
+                            }
+                            Err(_) => break,
+                        }
+                    })
+                    .expect("Spawning asset loader thread failed")
+            })
+            .collect();
+        LoadQueue {
+            jobs: Some(jobs),
+            workers,
+        }
+    }
+
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+        if let Some(jobs) = &self.jobs {
+            jobs.send(Box::new(job)).expect("Asset loader threads are gone");
+        }
+    }
+}
+
+impl Drop for LoadQueue {
+    fn drop(&mut self) {
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+struct Slot<T> {
+    path: PathBuf,
+    /// `path` made absolute, for matching the paths of changed files.
+    absolute: PathBuf,
+    refs: Weak<()>,
+    state: LoadState,
+    asset: Option<T>,
+}
+
+/// An import that finished, to be turned into an asset and handed back
+/// through [`Assets::complete`] along with `id` and `error`. Failed imports
+/// carry the placeholder.
+pub struct Finished<T: Asset> {
+    pub id: usize,
+    pub path: PathBuf,
+    pub cpu: T::Cpu,
+    pub error: Option<String>,
+}
+
+type Imported<T> = (usize, Result<<T as Asset>::Cpu, String>);
+
+fn import<T: Asset>(
+    sender: &Sender<Imported<T>>,
+    id: usize,
+    path: PathBuf,
+    settings: &T::Settings,
+    queue: &LoadQueue,
+) {
+    let sender = sender.clone();
+    let settings = settings.clone();
+    queue.spawn(move || {
+        // A panicking importer fails the asset instead of leaving it loading.
+        let imported = panic::catch_unwind(AssertUnwindSafe(|| T::import(&path, &settings)))
+            .unwrap_or_else(|payload| Err(panic_message(payload.as_ref())));
+        // The cache may be gone by the time the import finishes.
+        let _ = sender.send((id, imported));
+    });
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown cause");
+    format!("Import panicked: {}", message)
+}
+
+/// Cache of one asset type, keyed by path.
+pub struct Assets<T: Asset> {
+    /// Used by imports queued from now on.
+    pub settings: T::Settings,
+    slots: HashMap<usize, Slot<T>>,
+    by_path: HashMap<PathBuf, usize>,
+    next_id: usize,
+    sender: Sender<Imported<T>>,
+    receiver: Receiver<Imported<T>>,
+}
+
+impl<T: Asset> Default for Assets<T> {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Assets {
+            settings: T::Settings::default(),
+            slots: HashMap::new(),
+            by_path: HashMap::new(),
+            next_id: 0,
+            sender,
+            receiver,
+        }
+    }
+}
+
+impl<T: Asset> Assets<T> {
+    /// Handle to the asset at `path`, queueing its import unless the path
+    /// is already cached.
+    pub fn load(&mut self, path: impl AsRef<Path>, queue: &LoadQueue) -> Handle<T> {
+        let path = path.as_ref().to_path_buf();
+        if let Some((id, slot)) = self.by_path.get(&path).and_then(|id| Some((*id, self.slots.get_mut(id)?))) {
+            let refs = slot.refs.upgrade().unwrap_or_else(|| {
+                let refs = Arc::new(());
+                slot.refs = Arc::downgrade(&refs);
+                refs
+            });
+            return Handle {
+                id,
+                refs,
+                marker: PhantomData,
+            };
+        }
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let refs = Arc::new(());
+        self.slots.insert(
+            id,
+            Slot {
+                path: path.clone(),
+                absolute: absolute_path(&path),
+                refs: Arc::downgrade(&refs),
+                state: LoadState::Loading,
+                asset: None,
+            },
+        );
+        self.by_path.insert(path.clone(), id);
+        import::<T>(&self.sender, id, path, &self.settings, queue);
+        Handle {
+            id,
+            refs,
+            marker: PhantomData,
+        }
+    }
+
+    /// Imports the asset at the absolute `path` again, if it is loaded.
+    /// Handles keep pointing at it and see the new version once it is
+    /// complete.
+    pub fn reload(&mut self, path: &Path, queue: &LoadQueue) -> bool {
+        self.reload_where(queue, |absolute| absolute == path) > 0
+    }
+
+    /// Imports every loaded asset whose absolute path matches again,
+    /// returning how many were queued.
+    pub fn reload_where(&mut self, queue: &LoadQueue, matches: impl Fn(&Path) -> bool) -> usize {
+        let mut reloaded = 0;
+        for (id, slot) in self.slots.iter_mut() {
+            // Assets still loading will pick up the change anyway.
+            if slot.asset.is_none() || !matches(&slot.absolute) {
+                continue;
+            }
+            slot.state = LoadState::Reloading;
+            import::<T>(&self.sender, *id, slot.path.clone(), &self.settings, queue);
+            reloaded += 1;
+        }
+        reloaded
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.slots.values().map(|slot| slot.path.as_path())
+    }
+
+    pub fn state(&self, handle: &Handle<T>) -> LoadState {
+        self.slots
+            .get(&handle.id)
+            .map_or(LoadState::Loading, |slot| slot.state.clone())
+    }
+
+    pub fn is_ready(&self, handle: &Handle<T>) -> bool {
+        self.get(handle).is_some()
+    }
+
+    /// The asset once loaded, or its placeholder if loading failed.
+    pub fn get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.slots.get(&handle.id).and_then(|slot| slot.asset.as_ref())
+    }
+
+    pub fn get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T> {
+        self.slots.get_mut(&handle.id).and_then(|slot| slot.asset.as_mut())
+    }
+
+    pub fn path(&self, handle: &Handle<T>) -> Option<&Path> {
+        self.slots.get(&handle.id).map(|slot| slot.path.as_path())
+    }
+
+    /// Live handles to the asset.
+    pub fn ref_count(&self, handle: &Handle<T>) -> usize {
+        self.slots.get(&handle.id).map_or(0, |slot| slot.refs.strong_count())
+    }
+
+    fn finish(&mut self, (id, result): Imported<T>) -> Option<Finished<T>> {
+        let slot = self.slots.get_mut(&id)?;
+        let path = slot.path.clone();
+        if let (Err(error), Some(_)) = (&result, &slot.asset) {
+            // A half written file shouldn't swap a working asset for the
+            // placeholder.
+            log::error!("Reloading {} failed, keeping the previous version: {}", path.display(), error);
+            slot.state = LoadState::Failed(error.clone());
+            return None;
+        }
+        Some(match result {
+            Ok(cpu) => Finished {
+                id,
+                path,
+                cpu,
+                error: None,
+            },
+            Err(error) => {
+                log::error!("Loading {} failed: {}", path.display(), error);
+                Finished {
+                    id,
+                    path,
+                    cpu: T::placeholder(),
+                    error: Some(error),
+                }
+            }
+        })
+    }
+
+    /// Imports finished since the last poll, without blocking.
+    pub fn poll(&mut self) -> Vec<Finished<T>> {
+        let results: Vec<_> = self.receiver.try_iter().collect();
+        results.into_iter().filter_map(|result| self.finish(result)).collect()
+    }
+
+    /// Blocks until `handle` has been imported, returning it along with
+    /// anything else that finished meanwhile.
+    pub fn poll_until(&mut self, handle: &Handle<T>) -> Vec<Finished<T>> {
+        let mut finished = self.poll();
+        let done = |finished: &[Finished<T>]| finished.iter().any(|f| f.id == handle.id);
+        while self.get(handle).is_none() && !done(&finished) {
+            let Ok(result) = self.receiver.recv() else {
+                break;
+            };
+            finished.extend(self.finish(result));
+        }
+        finished
+    }
+
+    /// Removes the asset of a [`Finished`] import that replaces it, so its
+    /// resources can be handed over before calling [`Assets::complete`].
+    pub fn take(&mut self, id: usize) -> Option<T> {
+        self.slots.get_mut(&id).and_then(|slot| slot.asset.take())
+    }
+
+    /// Stores the asset built from a [`Finished`] import.
+    pub fn complete(&mut self, id: usize, error: Option<String>, asset: T) {
+        if let Some(slot) = self.slots.get_mut(&id) {
+            slot.state = match error {
+                Some(error) => LoadState::Failed(error),
+                None => LoadState::Loaded,
+            };
+            slot.asset = Some(asset);
+        }
+    }
+
+    /// Drops every loaded asset no handle refers to any more, handing them
+    /// back with their ids so their GPU resources can be released.
+    pub fn unload_unused(&mut self) -> Vec<(usize, T)> {
+        let unused: Vec<usize> = self
+            .slots
+            .iter()
+            .filter(|(_, slot)| slot.refs.strong_count() == 0 && slot.asset.is_some())
+            .map(|(id, _)| *id)
+            .collect();
+        unused
+            .into_iter()
+            .filter_map(|id| {
+                let slot = self.slots.remove(&id)?;
+                self.by_path.remove(&slot.path);
+                Some((id, slot.asset?))
+            })
+            .collect()
+    }
+}
+
+/// Models and textures sharing one set of loader threads.
+pub struct AssetManager {
+    queue: LoadQueue,
+    pub models: Assets<Model>,
+    pub textures: Assets<Texture>,
+    watcher: Option<AssetWatcher>,
+}
+
+impl Default for AssetManager {
+    fn default() -> Self {
+        AssetManager {
+            queue: LoadQueue::new(2),
+            models: Assets::default(),
+            textures: Assets::default(),
+            watcher: None,
+        }
+    }
+}
+
+impl AssetManager {
+    pub fn load_model(&mut self, path: impl AsRef<Path>) -> Handle<Model> {
+        self.watch(path.as_ref());
+        self.models.load(path, &self.queue)
+    }
+
+    pub fn load_texture(&mut self, path: impl AsRef<Path>) -> Handle<Texture> {
+        self.watch(path.as_ref());
+        self.textures.load(path, &self.queue)
+    }
+
+    /// Watches the directories of every asset loaded so far and from now on,
+    /// see [`AssetManager::reload_changed`].
+    pub fn enable_hot_reload(&mut self) {
+        if self.watcher.is_some() {
+            return;
+        }
+        match AssetWatcher::new() {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(err) => {
+                log::warn!("Hot reloading assets is unavailable: {}", err);
+                return;
+            }
+        }
+        let paths: Vec<PathBuf> = self
+            .models
+            .paths()
+            .chain(self.textures.paths())
+            .map(Path::to_path_buf)
+            .collect();
+        for path in paths {
+            self.watch(&path);
+        }
+    }
+
+    fn watch(&mut self, path: &Path) {
+        if let Some(watcher) = &mut self.watcher {
+            if let Err(err) = watcher.watch_file(path) {
+                log::warn!("Watching {} failed: {}", path.display(), err);
+            }
+        }
+    }
+
+    /// Queues the import of every asset affected by files changed on disk,
+    /// returning the changed files. Models are assumed to sit next to the
+    /// buffers and images they reference.
+    pub fn reload_changed(&mut self) -> Vec<PathBuf> {
+        let Some(watcher) = &self.watcher else {
+            return vec![];
+        };
+        let changed = watcher.changed();
+        for path in &changed {
+            let beside = |model: &Path| model.parent() == path.parent();
+            match watched_file(path) {
+                Some(WatchedFile::Model) => {
+                    self.models.reload(path, &self.queue);
+                }
+                Some(WatchedFile::ModelData) => {
+                    self.models.reload_where(&self.queue, beside);
+                }
+                Some(WatchedFile::Image) => {
+                    // Pre-compressed copies are named after the source image.
+                    let stem = path.with_extension("");
+                    self.textures.reload_where(&self.queue, |texture| texture.with_extension("") == stem);
+                    self.models.reload_where(&self.queue, beside);
+                }
+                // Reloaded by the render graph that loaded it.
+                Some(WatchedFile::RenderGraph) | None => {}
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::model_path;
+
+    /// Text file standing in for a GPU asset.
+    struct Text(String);
+
+    impl Asset for Text {
+        type Cpu = String;
+        type Settings = ();
+
+        fn import(path: &Path, _: &()) -> Result<String, String> {
+            std::fs::read_to_string(path).map_err(|err| err.to_string())
+        }
+
+        fn placeholder() -> String {
+            String::from("placeholder")
+        }
+    }
+
+    /// Asset whose importer panics.
+    struct Panics;
+
+    impl Asset for Panics {
+        type Cpu = ();
+        type Settings = ();
+
+        fn import(path: &Path, _: &()) -> Result<(), String> {
+            panic!("cannot import {}", path.display());
+        }
+
+        fn placeholder() {}
+    }
+
+    fn wait(assets: &mut Assets<Text>, handle: &Handle<Text>) {
+        for Finished { id, cpu, error, .. } in assets.poll_until(handle) {
+            assets.complete(id, error, Text(cpu));
+        }
+    }
+
+    #[test]
+    fn paths_are_loaded_once_and_counted() {
+        let queue = LoadQueue::new(1);
+        let mut assets = Assets::<Text>::default();
+        let first = assets.load(model_path("triangle.gltf"), &queue);
+        let second = assets.load(model_path("triangle.gltf"), &queue);
+        let other = assets.load(model_path("unindexed.gltf"), &queue);
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+        assert_eq!(assets.ref_count(&first), 2);
+        drop(second);
+        assert_eq!(assets.ref_count(&first), 1);
+
+        wait(&mut assets, &first);
+        assert_eq!(assets.state(&first), LoadState::Loaded);
+        assert!(assets.get(&first).unwrap().0.contains("\"asset\""));
+        wait(&mut assets, &other);
+        assert!(assets.is_ready(&other));
+    }
+
+    #[test]
+    fn failed_loads_fall_back_to_the_placeholder() {
+        let queue = LoadQueue::new(1);
+        let mut assets = Assets::<Text>::default();
+        let handle = assets.load(model_path("does_not_exist.gltf"), &queue);
+        wait(&mut assets, &handle);
+
+        assert!(matches!(assets.state(&handle), LoadState::Failed(_)));
+        assert_eq!(assets.get(&handle).unwrap().0, "placeholder");
+    }
+
+    #[test]
+    fn panicking_imports_fail() {
+        let queue = LoadQueue::new(1);
+        let mut panics = Assets::<Panics>::default();
+        let handle = panics.load(model_path("triangle.gltf"), &queue);
+        for Finished { id, error, .. } in panics.poll_until(&handle) {
+            panics.complete(id, error, Panics);
+        }
+        assert!(matches!(panics.state(&handle), LoadState::Failed(ref error) if error.contains("cannot import")));
+
+        // The loader thread survives the panic.
+        let mut assets = Assets::<Text>::default();
+        let text = assets.load(model_path("triangle.gltf"), &queue);
+        wait(&mut assets, &text);
+        assert_eq!(assets.state(&text), LoadState::Loaded);
+    }
+
+    #[test]
+    fn unused_assets_are_unloaded() {
+        let queue = LoadQueue::new(1);
+        let mut assets = Assets::<Text>::default();
+        let kept = assets.load(model_path("triangle.gltf"), &queue);
+        let dropped = assets.load(model_path("unindexed.gltf"), &queue);
+        wait(&mut assets, &kept);
+        wait(&mut assets, &dropped);
+        let dropped_id = dropped.id();
+        drop(dropped);
+
+        let unloaded = assets.unload_unused();
+        assert_eq!(unloaded.len(), 1);
+        assert!(assets.is_ready(&kept));
+
+        // Loading the path again starts over with a new asset.
+        let reloaded = assets.load(model_path("unindexed.gltf"), &queue);
+        assert_ne!(reloaded.id(), dropped_id);
+        assert_eq!(assets.state(&reloaded), LoadState::Loading);
+    }
+
+    #[test]
+    fn reloads_replace_assets_in_place() {
+        let path = std::env::temp_dir().join(format!("lynch_reload_{}.txt", std::process::id()));
+        std::fs::write(&path, "first").unwrap();
+        let queue = LoadQueue::new(1);
+        let mut assets = Assets::<Text>::default();
+        let handle = assets.load(&path, &queue);
+        wait(&mut assets, &handle);
+
+        let wait_for_reload = |assets: &mut Assets<Text>| {
+            while assets.state(&handle) == LoadState::Reloading {
+                for Finished { id, cpu, error, .. } in assets.poll() {
+                    assets.complete(id, error, Text(cpu));
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+        };
+
+        std::fs::write(&path, "second").unwrap();
+        assert!(assets.reload(&absolute_path(&path), &queue));
+        assert!(!assets.reload(&absolute_path(&model_path("triangle.gltf")), &queue));
+        assert_eq!(assets.state(&handle), LoadState::Reloading);
+        wait_for_reload(&mut assets);
+        assert_eq!(assets.state(&handle), LoadState::Loaded);
+        assert_eq!(assets.get(&handle).unwrap().0, "second");
+
+        // A file that can't be read keeps the previous version.
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(assets.reload_where(&queue, |_| true), 1);
+        wait_for_reload(&mut assets);
+        assert!(matches!(assets.state(&handle), LoadState::Failed(_)));
+        assert_eq!(assets.get(&handle).unwrap().0, "second");
+    }
+
+    #[test]
+    fn model_imports_fall_back_to_a_cube() {
+        assert!(<Model as Asset>::import(&model_path("truncated.gltf"), &ModelImport::default()).is_err());
+        let placeholder = <Model as Asset>::placeholder();
+        assert_eq!(placeholder.meshes[0].vertices.len(), 24);
+        assert_eq!(placeholder.meshes[0].indices.len(), 36);
+        for triangle in placeholder.meshes[0].indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|i| placeholder.meshes[0].vertices[triangle[i] as usize]);
+            let normal = (b.pos - a.pos).truncate().cross((c.pos - a.pos).truncate());
+            // Outward facing, counter clockwise.
+            assert!(normal.dot(a.normal.truncate()) > 0.0);
+        }
+        assert_eq!(placeholder_image().pixels.len(), 16);
+    }
+
+    #[test]
+    fn model_imports_generate_lods_only_when_asked() {
+        let path = model_path("lod_extra_primitive.gltf");
+        let model = <Model as Asset>::import(&path, &ModelImport::default()).unwrap();
+        assert!(model.meshes.iter().all(|mesh| mesh.lods.is_empty()));
+
+        let settings = ModelImport {
+            lods: Some(LodGeneration {
+                max_relative_error: 1.0,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let model = <Model as Asset>::import(&path, &settings).unwrap();
+        assert!(model.meshes.iter().any(|mesh| !mesh.lods.is_empty()));
+    }
+}
@@ -0,0 +1,149 @@
+//! Cooks glTF, OBJ and image sources into `.cmodel`/`.ctex` files that load
+//! without any runtime processing. Unchanged sources are skipped, see
+//! `lynch::cook::CookCache`.
+
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use lynch::{
+    cook::{cook_file, cooked_path, source_kind, CookCache, CookOutcome, CookSettings},
+    texture_processing::{CompressionPolicy, TextureUsage},
+};
+
+const USAGE: &str = "Usage: cooper-cook [options] <source>...
+
+Sources are files or directories, searched recursively.
+
+Options:
+  -o, --out <dir>        Output directory [default: cooked]
+      --force            Cook everything, ignoring the cache
+      --no-mips          Keep images at a single level
+      --no-lods          Do not generate levels of detail
+      --compressed       Use .ktx2/.dds copies of images, needs BC support at runtime
+      --usage <usage>    What standalone images are sampled as: color, data or normal [default: data]
+  -h, --help             Print this message";
+
+struct Options {
+    sources: Vec<PathBuf>,
+    output_dir: PathBuf,
+    force: bool,
+    settings: CookSettings,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        sources: vec![],
+        output_dir: PathBuf::from("cooked"),
+        force: false,
+        settings: CookSettings::default(),
+    };
+    options.settings.textures.compression = CompressionPolicy::Never;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--out" => options.output_dir = args.next().ok_or("--out needs a directory")?.into(),
+            "--force" => options.force = true,
+            "--no-mips" => options.settings.textures.generate_mips = false,
+            "--no-lods" => options.settings.lods.max_levels = 1,
+            "--compressed" => {
+                options.settings.textures.compression = CompressionPolicy::PreferCompressed;
+                options.settings.textures.bc_supported = true;
+            }
+            "--usage" => {
+                options.settings.image_usage = match args.next().as_deref() {
+                    Some("color") => TextureUsage::Color,
+                    Some("data") => TextureUsage::Data,
+                    Some("normal") => TextureUsage::Normal,
+                    _ => return Err("--usage is one of color, data or normal".to_owned()),
+                }
+            }
+            "-h" | "--help" => return Err(String::new()),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option {}", flag)),
+            source => options.sources.push(source.into()),
+        }
+    }
+    if options.sources.is_empty() {
+        return Err("No sources given".to_owned());
+    }
+    Ok(options)
+}
+
+/// Every source below `path`, paired with its path relative to the
+/// directory it was found in.
+fn collect_sources(path: &Path, relative: &Path, sources: &mut Vec<(PathBuf, PathBuf)>) -> std::io::Result<()> {
+    if !path.is_dir() {
+        if source_kind(path).is_some() {
+            sources.push((path.to_path_buf(), relative.to_path_buf()));
+        }
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for entry in entries {
+        let name = entry.file_name().map(PathBuf::from).unwrap_or_default();
+        collect_sources(&entry, &relative.join(name), sources)?;
+    }
+    Ok(())
+}
+
+fn main() -> ExitCode {
+    env_logger::init();
+
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("{}\n", err);
+            }
+            eprintln!("{}", USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut sources = vec![];
+    for source in &options.sources {
+        let relative = match source.is_dir() {
+            true => PathBuf::new(),
+            false => source.file_name().map(PathBuf::from).unwrap_or_default(),
+        };
+        if let Err(err) = collect_sources(source, &relative, &mut sources) {
+            eprintln!("Could not read {}: {}", source.display(), err);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let mut cache = CookCache::load(&options.output_dir);
+    let (mut cooked, mut up_to_date, mut failed) = (0, 0, 0);
+    for (source, relative) in &sources {
+        let Some(output) = cooked_path(&options.output_dir, relative) else {
+            continue;
+        };
+        if options.force {
+            cache.insert(&output, 0);
+        }
+        match cook_file(source, &output, &options.settings, &mut cache) {
+            Ok(CookOutcome::Cooked) => {
+                println!("cooked {} -> {}", source.display(), output.display());
+                cooked += 1;
+            }
+            Ok(CookOutcome::UpToDate) => up_to_date += 1,
+            Err(err) => {
+                eprintln!("failed {}: {}", source.display(), err);
+                failed += 1;
+            }
+        }
+    }
+    if let Err(err) = cache.save() {
+        eprintln!("Could not save the cook cache: {}", err);
+    }
+
+    println!("{} cooked, {} up to date, {} failed", cooked, up_to_date, failed);
+    match failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
@@ -0,0 +1,129 @@
+use glam::{Mat4, Vec3};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BoundingSphere {
+    pub center: Vec3,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// Sphere around the centre of the points' bounding box. Not the tightest
+    /// fit, but cheap and never smaller than the points.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3> + Clone) -> Self {
+        let (low, high) = points
+            .clone()
+            .into_iter()
+            .fold((Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)), |(low, high), p| (low.min(p), high.max(p)));
+        if low.x > high.x {
+            return BoundingSphere::default();
+        }
+        let center = (low + high) * 0.5;
+        let radius = points.into_iter().map(|p| p.distance(center)).fold(0.0, f32::max);
+        BoundingSphere { center, radius }
+    }
+
+    /// The sphere moved by `transform`, its radius grown by the largest axis
+    /// scale so non-uniform scaling stays covered.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        BoundingSphere {
+            center: transform.transform_point3(self.center),
+            radius: self.radius * max_scale(transform),
+        }
+    }
+}
+
+/// Axis aligned bounding box.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        let (min, max) = points
+            .into_iter()
+            .fold((Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)), |(min, max), p| (min.min(p), max.max(p)));
+        if min.x > max.x {
+            return Aabb::default();
+        }
+        Aabb { min, max }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn half_extents(&self) -> Vec3 {
+        (self.max - self.min) * 0.5
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        [0, 1, 2, 3, 4, 5, 6, 7].map(|i| {
+            Vec3::new(
+                if i & 1 == 0 { self.min.x } else { self.max.x },
+                if i & 2 == 0 { self.min.y } else { self.max.y },
+                if i & 4 == 0 { self.min.z } else { self.max.z },
+            )
+        })
+    }
+
+    /// Box around the transformed box, after Arvo's Graphics Gems method.
+    pub fn transformed(&self, transform: &Mat4) -> Self {
+        let center = transform.transform_point3(self.center());
+        let half = self.half_extents();
+        let extents = transform.x_axis.truncate().abs() * half.x
+            + transform.y_axis.truncate().abs() * half.y
+            + transform.z_axis.truncate().abs() * half.z;
+        Aabb {
+            min: center - extents,
+            max: center + extents,
+        }
+    }
+}
+
+/// Largest scale factor `transform` applies along any of its axes.
+pub fn max_scale(transform: &Mat4) -> f32 {
+    transform
+        .x_axis
+        .truncate()
+        .length()
+        .max(transform.y_axis.truncate().length())
+        .max(transform.z_axis.truncate().length())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spheres_cover_their_points_through_transforms() {
+        let points = [Vec3::ZERO, Vec3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0)];
+        let sphere = BoundingSphere::from_points(points);
+        assert_eq!(sphere.center, Vec3::new(1.0, 0.5, 0.0));
+        assert!((sphere.radius - 1.25f32.sqrt()).abs() < 1e-6);
+        assert_eq!(BoundingSphere::from_points([Vec3::ZERO; 0]), BoundingSphere::default());
+
+        let transform = Mat4::from_translation(Vec3::Z) * Mat4::from_scale(Vec3::new(1.0, 3.0, 1.0));
+        let moved = sphere.transformed(&transform);
+        assert_eq!(moved.center, Vec3::new(1.0, 1.5, 1.0));
+        assert!((moved.radius - 3.0 * sphere.radius).abs() < 1e-6);
+    }
+
+    #[test]
+    fn boxes_stay_tight_through_transforms() {
+        let aabb = Aabb::from_points([Vec3::ZERO, Vec3::new(2.0, 1.0, 0.0), Vec3::new(1.0, -1.0, 3.0)]);
+        assert_eq!(aabb.min, Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(aabb.max, Vec3::new(2.0, 1.0, 3.0));
+        assert_eq!(aabb.corners()[5], Vec3::new(2.0, -1.0, 3.0));
+
+        let rotated = aabb.transformed(&Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2));
+        assert!(rotated.min.abs_diff_eq(Vec3::new(-1.0, 0.0, 0.0), 1e-6));
+        assert!(rotated.max.abs_diff_eq(Vec3::new(1.0, 2.0, 3.0), 1e-6));
+        for corner in aabb.corners() {
+            let p = Mat4::from_rotation_z(0.3).transform_point3(corner);
+            let tilted = aabb.transformed(&Mat4::from_rotation_z(0.3));
+            assert!(p.cmpge(tilted.min - 1e-6).all() && p.cmple(tilted.max + 1e-6).all());
+        }
+    }
+}
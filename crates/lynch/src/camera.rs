@@ -118,6 +118,10 @@ impl Camera {
             .set_rotation_quat(rotation);
     }
 
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+    }
+
     pub(crate) fn get_near_plane(&self) -> f32 {
         self.z_near
     }
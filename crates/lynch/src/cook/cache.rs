@@ -0,0 +1,184 @@
+//! Incremental rebuilds: a cooked file is up to date when the hash of its
+//! source, every file the source references and the cook settings matches
+//! the one recorded when it was written.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use super::{source_kind, SourceKind};
+use crate::{
+    hash::ContentHash,
+    texture_processing::{compressed_sibling_paths, TextureSettings},
+};
+
+const CACHE_FILE: &str = "cook-cache.txt";
+
+/// Files besides `source` itself that its import reads: glTF buffers and
+/// images, OBJ material libraries and their textures, and pre-compressed
+/// copies of images that `settings` would pick up. Copies that do not exist
+/// yet are listed too, so adding one invalidates the cooked file.
+pub fn source_dependencies(source: &Path, settings: &TextureSettings) -> Vec<PathBuf> {
+    let dir = source.parent().unwrap_or_else(|| Path::new(""));
+    let mut dependencies = vec![];
+    let mut images = vec![];
+    match source_kind(source) {
+        Some(SourceKind::Model) if is_obj(source) => {
+            let Ok(obj) = fs::read_to_string(source) else {
+                return vec![];
+            };
+            let libraries = obj
+                .lines()
+                .filter_map(|line| line.trim().strip_prefix("mtllib "))
+                .flat_map(str::split_whitespace)
+                .map(|library| dir.join(library));
+            for library in libraries {
+                if let Ok((materials, _)) = tobj::load_mtl(&library) {
+                    for material in materials {
+                        for texture in [material.diffuse_texture, material.normal_texture] {
+                            if !texture.is_empty() {
+                                images.push(dir.join(texture));
+                            }
+                        }
+                    }
+                }
+                dependencies.push(library);
+            }
+        }
+        Some(SourceKind::Model) => {
+            let Ok(gltf) = gltf::Gltf::open(source) else {
+                return vec![];
+            };
+            for buffer in gltf.buffers() {
+                if let gltf::buffer::Source::Uri(uri) = buffer.source() {
+                    if !uri.starts_with("data:") {
+                        dependencies.push(dir.join(uri));
+                    }
+                }
+            }
+            for image in gltf.images() {
+                if let gltf::image::Source::Uri { uri, .. } = image.source() {
+                    if !uri.starts_with("data:") {
+                        images.push(dir.join(uri));
+                    }
+                }
+            }
+        }
+        Some(SourceKind::Image) => images.push(source.to_path_buf()),
+        None => {}
+    }
+    for image in images {
+        dependencies.extend(compressed_sibling_paths(&image, settings));
+        if image != source {
+            dependencies.push(image);
+        }
+    }
+    dependencies.sort();
+    dependencies.dedup();
+    dependencies
+}
+
+fn is_obj(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("obj"))
+}
+
+/// Hash of `source`, its dependencies and `settings`, the latter being
+/// anything whose `Debug` output changes with the cooked result. Missing
+/// dependencies hash as missing, so creating them triggers a rebuild.
+pub fn source_hash(source: &Path, dependencies: &[PathBuf], settings: &str) -> io::Result<u64> {
+    let mut hash = ContentHash::default();
+    hash.update(&super::format::COOKED_VERSION.to_le_bytes());
+    hash.update(settings.as_bytes());
+    hash.update_file(source)?;
+    for dependency in dependencies {
+        hash.update(dependency.to_string_lossy().as_bytes());
+        match dependency.is_file() {
+            true => hash.update_file(dependency)?,
+            false => hash.update(b"missing"),
+        }
+    }
+    Ok(hash.finish())
+}
+
+/// Hashes of the files in an output directory, stored next to them and
+/// keyed by their path relative to it.
+pub struct CookCache {
+    dir: PathBuf,
+    entries: BTreeMap<PathBuf, u64>,
+}
+
+impl CookCache {
+    /// Reads the cache of `output_dir`, empty if there is none yet.
+    pub fn load(output_dir: &Path) -> Self {
+        let entries = fs::read_to_string(output_dir.join(CACHE_FILE))
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let (hash, output) = line.split_once(' ')?;
+                Some((PathBuf::from(output), u64::from_str_radix(hash, 16).ok()?))
+            })
+            .collect();
+        CookCache {
+            dir: output_dir.to_path_buf(),
+            entries,
+        }
+    }
+
+    fn key<'a>(&self, output: &'a Path) -> &'a Path {
+        output.strip_prefix(&self.dir).unwrap_or(output)
+    }
+
+    /// Whether `output` exists and was cooked from sources hashing to `hash`.
+    pub fn is_fresh(&self, output: &Path, hash: u64) -> bool {
+        self.entries.get(self.key(output)) == Some(&hash) && output.is_file()
+    }
+
+    pub fn insert(&mut self, output: &Path, hash: u64) {
+        self.entries.insert(self.key(output).to_path_buf(), hash);
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let contents: String = self
+            .entries
+            .iter()
+            .map(|(output, hash)| format!("{:016x} {}\n", hash, output.display()))
+            .collect();
+        fs::write(self.dir.join(CACHE_FILE), contents)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::model_path;
+
+    #[test]
+    fn dependencies_follow_the_source_format() {
+        let settings = TextureSettings::default();
+        assert_eq!(source_dependencies(&model_path("quads.obj"), &settings), vec![model_path("quads.mtl")]);
+        // Everything in the glTF fixtures is embedded.
+        assert!(source_dependencies(&model_path("triangle.gltf"), &settings).is_empty());
+
+        // Compressed copies are listed before they exist.
+        let settings = TextureSettings {
+            bc_supported: true,
+            ..TextureSettings::default()
+        };
+        assert_eq!(
+            source_dependencies(&model_path("albedo.png"), &settings),
+            vec![model_path("albedo.dds"), model_path("albedo.ktx2")]
+        );
+    }
+
+    #[test]
+    fn hashes_change_with_contents_and_settings() {
+        let source = model_path("quads.obj");
+        let dependencies = source_dependencies(&source, &TextureSettings::default());
+        let hash = source_hash(&source, &dependencies, "a").unwrap();
+        assert_eq!(source_hash(&source, &dependencies, "a").unwrap(), hash);
+        assert_ne!(source_hash(&source, &dependencies, "b").unwrap(), hash);
+        assert_ne!(source_hash(&source, &[], "a").unwrap(), hash);
+    }
+}
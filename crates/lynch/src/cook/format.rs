@@ -0,0 +1,781 @@
+//! Layout of cooked files. Everything is little endian:
+//!
+//! ```text
+//! header   magic "COOPCOOK", version, kind, mesh, lod and image counts
+//! records  MESH_RECORD_SIZE per mesh, LOD_RECORD_SIZE per level of detail,
+//!          IMAGE_RECORD_SIZE per image
+//! blobs    vertex, index and pixel data, each 16 byte aligned
+//! ```
+//!
+//! Vertices are stored in the `#[repr(C)]` layout of [`Vertex`], so a mapped
+//! file can be read without decoding them.
+
+use std::{borrow::Cow, fs::File, ops::Range, path::Path};
+
+use glam::{Mat4, Vec2, Vec3, Vec4};
+use memmap2::Mmap;
+
+use super::CookError;
+use crate::{
+    animation::NodeTransform,
+    bounds::{Aabb, BoundingSphere},
+    gltf_loader::{
+        CpuLod, CpuMesh, CpuModel, CpuNode, Material, MaterialExtensions, SpecularGlossiness, TextureTransform,
+        DEFAULT_TEXTURE_MAP,
+    },
+    mesh::Vertex,
+    texture_processing::{ColorSpace, CpuImage, TextureFormat},
+};
+
+const MAGIC: &[u8; 8] = b"COOPCOOK";
+/// Bumped whenever the layout changes, older files are cooked again.
+pub const COOKED_VERSION: u32 = 2;
+
+const HEADER_SIZE: usize = 32;
+const MESH_RECORD_SIZE: usize = 288;
+const LOD_RECORD_SIZE: usize = 40;
+const IMAGE_RECORD_SIZE: usize = 40;
+const VERTEX_SIZE: usize = 80;
+const BLOB_ALIGNMENT: usize = 16;
+
+/// Order gives the stored format code.
+const FORMATS: [TextureFormat; 11] = [
+    TextureFormat::Rgba8,
+    TextureFormat::Rgba16,
+    TextureFormat::Rgba32Float,
+    TextureFormat::Bc1,
+    TextureFormat::Bc2,
+    TextureFormat::Bc3,
+    TextureFormat::Bc4,
+    TextureFormat::Bc5,
+    TextureFormat::Bc6hUfloat,
+    TextureFormat::Bc6hSfloat,
+    TextureFormat::Bc7,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CookedKind {
+    Model,
+    Texture,
+}
+
+/// Where a run of elements sits in the file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Blob {
+    pub offset: usize,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug)]
+pub struct CookedMesh {
+    pub vertices: Blob,
+    pub indices: Blob,
+    /// Indices into [`CookedFile::lods`].
+    pub lods: Range<usize>,
+    /// Model space transform of the mesh.
+    pub transform: Mat4,
+    pub material: Material,
+    pub material_extensions: MaterialExtensions,
+    pub bounds: BoundingSphere,
+    pub aabb: Aabb,
+}
+
+#[derive(Clone, Debug)]
+pub struct CookedLod {
+    pub vertices: Blob,
+    pub indices: Blob,
+    pub error: f32,
+}
+
+#[derive(Clone, Debug)]
+pub struct CookedImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    pub color_space: ColorSpace,
+    pub mip_levels: u32,
+    pub pixels: Blob,
+}
+
+/// Whether `[Vertex]` can be read straight out of the file.
+fn vertices_match_layout() -> bool {
+    cfg!(target_endian = "little")
+        && std::mem::size_of::<Vertex>() == VERTEX_SIZE
+        && memoffset::offset_of!(Vertex, normal) == 16
+        && memoffset::offset_of!(Vertex, uv) == 32
+        && memoffset::offset_of!(Vertex, color) == 48
+        && memoffset::offset_of!(Vertex, tangent) == 64
+}
+
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    fn f32s(&mut self, values: &[f32]) {
+        for value in values {
+            self.bytes.extend(value.to_le_bytes());
+        }
+    }
+
+    fn blob(&mut self, blob: Blob) {
+        self.u64(blob.offset as u64);
+        self.u64(blob.count as u64);
+    }
+
+    fn align(&mut self) {
+        self.bytes.resize(self.bytes.len().next_multiple_of(BLOB_ALIGNMENT), 0);
+    }
+}
+
+/// Blob data, written after the records once their size is known.
+struct Blobs {
+    start: usize,
+    data: Writer,
+}
+
+impl Blobs {
+    fn push(&mut self, write: impl FnOnce(&mut Writer), count: usize) -> Blob {
+        self.data.align();
+        let offset = self.start + self.data.bytes.len();
+        write(&mut self.data);
+        Blob { offset, count }
+    }
+
+    fn vertices(&mut self, vertices: &[Vertex]) -> Blob {
+        self.push(
+            |w| {
+                for v in vertices {
+                    w.f32s(&v.pos.to_array());
+                    w.f32s(&v.normal.to_array());
+                    w.f32s(&v.uv.extend(0.0).extend(0.0).to_array());
+                    w.f32s(&v.color.to_array());
+                    w.f32s(&v.tangent.to_array());
+                }
+            },
+            vertices.len(),
+        )
+    }
+
+    fn indices(&mut self, indices: &[u32]) -> Blob {
+        self.push(|w| indices.iter().for_each(|i| w.u32(*i)), indices.len())
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) -> Blob {
+        self.push(|w| w.bytes.extend_from_slice(bytes), bytes.len())
+    }
+}
+
+fn write_file(kind: CookedKind, meshes: &[CpuMesh], transforms: &[Mat4], images: &[CpuImage]) -> Vec<u8> {
+    let lod_count: usize = meshes.iter().map(|mesh| mesh.lods.len()).sum();
+    let records = HEADER_SIZE + meshes.len() * MESH_RECORD_SIZE + lod_count * LOD_RECORD_SIZE + images.len() * IMAGE_RECORD_SIZE;
+    let mut blobs = Blobs {
+        start: records.next_multiple_of(BLOB_ALIGNMENT),
+        data: Writer { bytes: vec![] },
+    };
+
+    let mut w = Writer {
+        bytes: Vec::with_capacity(records),
+    };
+    w.bytes.extend_from_slice(MAGIC);
+    w.u32(COOKED_VERSION);
+    w.u32(kind as u32);
+    w.u32(meshes.len() as u32);
+    w.u32(lod_count as u32);
+    w.u32(images.len() as u32);
+    w.u32(0);
+
+    let mut first_lod = 0;
+    for (mesh, transform) in meshes.iter().zip(transforms) {
+        w.blob(blobs.vertices(&mesh.vertices));
+        w.blob(blobs.indices(&mesh.indices));
+        w.u32(first_lod as u32);
+        w.u32(mesh.lods.len() as u32);
+        first_lod += mesh.lods.len();
+        w.f32s(&transform.to_cols_array());
+
+        let material = &mesh.material;
+        for map in [material.diffuse_map, material.normal_map, material.metallic_roughness_map, material.occlusion_map] {
+            w.u32(map);
+        }
+        w.f32s(&material.base_color_factor.to_array());
+        w.f32s(&[material.metallic_factor, material.roughness_factor]);
+        write_extensions(&mut w, &mesh.material_extensions);
+
+        let positions = || mesh.vertices.iter().map(|v| v.pos.truncate());
+        let bounds = BoundingSphere::from_points(positions());
+        let aabb = Aabb::from_points(positions());
+        w.f32s(&bounds.center.extend(bounds.radius).to_array());
+        w.f32s(&aabb.min.to_array());
+        w.f32s(&aabb.max.to_array());
+    }
+    for lod in meshes.iter().flat_map(|mesh| &mesh.lods) {
+        w.blob(blobs.vertices(&lod.vertices));
+        w.blob(blobs.indices(&lod.indices));
+        w.f32s(&[lod.error]);
+        w.u32(0);
+    }
+    for image in images {
+        let format = FORMATS.iter().position(|f| *f == image.format).unwrap();
+        for value in [image.width, image.height, format as u32, image.color_space as u32, image.mip_levels, 0] {
+            w.u32(value);
+        }
+        w.blob(blobs.bytes(&image.pixels));
+    }
+    debug_assert_eq!(w.bytes.len(), records);
+
+    w.align();
+    w.bytes.extend(blobs.data.bytes);
+    w.bytes
+}
+
+// Bits of the extension record saying which optional values are present.
+const UNLIT: u32 = 1;
+const IOR: u32 = 1 << 1;
+const TRANSMISSION_FACTOR: u32 = 1 << 2;
+const TRANSMISSION_MAP: u32 = 1 << 3;
+const SPECULAR_GLOSSINESS: u32 = 1 << 4;
+const DIFFUSE_TRANSFORM: u32 = 1 << 5;
+const METALLIC_ROUGHNESS_TRANSFORM: u32 = 1 << 6;
+const DIFFUSE_TEX_COORD: u32 = 1 << 7;
+const METALLIC_ROUGHNESS_TEX_COORD: u32 = 1 << 8;
+
+/// Writes `extensions` as 26 words, absent values as zero.
+fn write_extensions(w: &mut Writer, extensions: &MaterialExtensions) {
+    let flag = |present: bool, bit: u32| if present { bit } else { 0 };
+    let transforms = [extensions.diffuse_transform, extensions.metallic_roughness_transform];
+    w.u32(
+        flag(extensions.unlit, UNLIT)
+            | flag(extensions.ior.is_some(), IOR)
+            | flag(extensions.transmission_factor.is_some(), TRANSMISSION_FACTOR)
+            | flag(extensions.transmission_map.is_some(), TRANSMISSION_MAP)
+            | flag(extensions.specular_glossiness.is_some(), SPECULAR_GLOSSINESS)
+            | flag(transforms[0].is_some(), DIFFUSE_TRANSFORM)
+            | flag(transforms[1].is_some(), METALLIC_ROUGHNESS_TRANSFORM)
+            | flag(transforms[0].is_some_and(|t| t.tex_coord.is_some()), DIFFUSE_TEX_COORD)
+            | flag(transforms[1].is_some_and(|t| t.tex_coord.is_some()), METALLIC_ROUGHNESS_TEX_COORD),
+    );
+    w.f32s(&[extensions.ior.unwrap_or(0.0), extensions.transmission_factor.unwrap_or(0.0)]);
+    w.u32(extensions.transmission_map.unwrap_or(0));
+
+    let sg = extensions.specular_glossiness;
+    w.f32s(&sg.map_or(Vec4::ZERO, |sg| sg.diffuse_factor).to_array());
+    w.f32s(&sg.map_or(Vec3::ZERO, |sg| sg.specular_factor).to_array());
+    w.f32s(&[sg.map_or(0.0, |sg| sg.glossiness_factor)]);
+    w.u32(sg.map_or(0, |sg| sg.diffuse_map));
+    w.u32(sg.map_or(0, |sg| sg.specular_glossiness_map));
+
+    for transform in transforms {
+        let transform = transform.unwrap_or(TextureTransform {
+            offset: Vec2::ZERO,
+            rotation: 0.0,
+            scale: Vec2::ZERO,
+            tex_coord: None,
+        });
+        w.f32s(&transform.offset.to_array());
+        w.f32s(&[transform.rotation]);
+        w.f32s(&transform.scale.to_array());
+        w.u32(transform.tex_coord.unwrap_or(0));
+    }
+}
+
+fn read_extensions(r: &mut Reader) -> Result<MaterialExtensions, CookError> {
+    let flags = r.u32()?;
+    let present = |bit: u32| flags & bit != 0;
+    let [ior, transmission_factor] = r.f32s()?;
+    let transmission_map = r.u32()?;
+
+    let diffuse_factor = Vec4::from(r.f32s()?);
+    let specular_factor = Vec3::from(r.f32s()?);
+    let [glossiness_factor] = r.f32s()?;
+    let [diffuse_map, specular_glossiness_map] = [r.u32()?, r.u32()?];
+
+    let mut transforms = [None; 2];
+    let bits = [
+        (DIFFUSE_TRANSFORM, DIFFUSE_TEX_COORD),
+        (METALLIC_ROUGHNESS_TRANSFORM, METALLIC_ROUGHNESS_TEX_COORD),
+    ];
+    for (transform, (bit, tex_coord_bit)) in transforms.iter_mut().zip(bits) {
+        let offset = Vec2::from(r.f32s()?);
+        let [rotation] = r.f32s()?;
+        let scale = Vec2::from(r.f32s()?);
+        let tex_coord = r.u32()?;
+        *transform = present(bit).then_some(TextureTransform {
+            offset,
+            rotation,
+            scale,
+            tex_coord: present(tex_coord_bit).then_some(tex_coord),
+        });
+    }
+
+    Ok(MaterialExtensions {
+        unlit: present(UNLIT),
+        ior: present(IOR).then_some(ior),
+        transmission_factor: present(TRANSMISSION_FACTOR).then_some(transmission_factor),
+        transmission_map: present(TRANSMISSION_MAP).then_some(transmission_map),
+        specular_glossiness: present(SPECULAR_GLOSSINESS).then_some(SpecularGlossiness {
+            diffuse_factor,
+            specular_factor,
+            glossiness_factor,
+            diffuse_map,
+            specular_glossiness_map,
+        }),
+        diffuse_transform: transforms[0],
+        metallic_roughness_transform: transforms[1],
+    })
+}
+
+/// Texture slots of a material and its extensions, for checking them
+/// against the images of the file.
+fn texture_maps(material: &Material, extensions: &MaterialExtensions) -> Vec<u32> {
+    let mut maps = vec![material.diffuse_map, material.normal_map, material.metallic_roughness_map, material.occlusion_map];
+    maps.extend(extensions.transmission_map);
+    if let Some(sg) = extensions.specular_glossiness {
+        maps.extend([sg.diffuse_map, sg.specular_glossiness_map]);
+    }
+    maps
+}
+
+/// Serializes the static meshes, levels of detail and images of `model`.
+/// Node hierarchy, skins and animations are not stored, every mesh keeps the
+/// world transform of its node instead.
+pub fn write_model(model: &CpuModel) -> Vec<u8> {
+    let transforms: Vec<Mat4> = (0..model.meshes.len()).map(|mesh| model.transform(mesh)).collect();
+    write_file(CookedKind::Model, &model.meshes, &transforms, &model.images)
+}
+
+pub fn write_texture(image: &CpuImage) -> Vec<u8> {
+    write_file(CookedKind::Texture, &[], &[], std::slice::from_ref(image))
+}
+
+/// Reads little endian values, failing on truncated files.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, size: usize) -> Result<&'a [u8], CookError> {
+        let bytes = self
+            .bytes
+            .get(self.offset..self.offset + size)
+            .ok_or(CookError::InvalidFile("truncated records"))?;
+        self.offset += size;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, CookError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<usize, CookError> {
+        let value = u64::from_le_bytes(self.take(8)?.try_into().unwrap());
+        usize::try_from(value).map_err(|_| CookError::InvalidFile("offset out of range"))
+    }
+
+    fn f32s<const N: usize>(&mut self) -> Result<[f32; N], CookError> {
+        let mut values = [0.0; N];
+        for value in &mut values {
+            *value = f32::from_bits(self.u32()?);
+        }
+        Ok(values)
+    }
+
+    /// Reads a blob of `count` elements of `size` bytes, checking it lies
+    /// inside the file.
+    fn blob(&mut self, size: usize) -> Result<Blob, CookError> {
+        let blob = Blob {
+            offset: self.u64()?,
+            count: self.u64()?,
+        };
+        let end = blob
+            .count
+            .checked_mul(size)
+            .and_then(|length| length.checked_add(blob.offset));
+        match end {
+            Some(end) if end <= self.bytes.len() && blob.offset.is_multiple_of(BLOB_ALIGNMENT) => Ok(blob),
+            _ => Err(CookError::InvalidFile("data outside the file")),
+        }
+    }
+}
+
+/// A cooked model or texture, memory mapped. Records are read and indices
+/// checked against their vertex counts when the file is opened, vertex and
+/// pixel data only when asked for.
+pub struct CookedFile<B: AsRef<[u8]> = Mmap> {
+    bytes: B,
+    pub kind: CookedKind,
+    pub meshes: Vec<CookedMesh>,
+    pub lods: Vec<CookedLod>,
+    pub images: Vec<CookedImage>,
+}
+
+impl CookedFile {
+    pub fn open(path: &Path) -> Result<Self, CookError> {
+        let file = File::open(path)?;
+        // Safety: cooked files are only written whole by the cook tool, which
+        // replaces them rather than writing into them.
+        let bytes = unsafe { Mmap::map(&file)? };
+        CookedFile::from_bytes(bytes)
+    }
+}
+
+impl<B: AsRef<[u8]>> CookedFile<B> {
+    pub fn from_bytes(bytes: B) -> Result<Self, CookError> {
+        let data = bytes.as_ref();
+        if data.get(..8) != Some(&MAGIC[..]) {
+            return Err(CookError::InvalidFile("not a cooked file"));
+        }
+        let mut r = Reader { bytes: data, offset: 8 };
+        let version = r.u32()?;
+        if version != COOKED_VERSION {
+            return Err(CookError::Version(version));
+        }
+        let kind = match r.u32()? {
+            0 => CookedKind::Model,
+            1 => CookedKind::Texture,
+            _ => return Err(CookError::InvalidFile("unknown kind")),
+        };
+        let [mesh_count, lod_count, image_count, _] = [r.u32()?, r.u32()?, r.u32()?, r.u32()?].map(|n| n as usize);
+
+        let mut meshes = Vec::with_capacity(mesh_count.min(data.len() / MESH_RECORD_SIZE));
+        for _ in 0..mesh_count {
+            let vertices = r.blob(VERTEX_SIZE)?;
+            let indices = r.blob(4)?;
+            let first_lod = r.u32()? as usize;
+            let lods = first_lod..first_lod + r.u32()? as usize;
+            if lods.end > lod_count {
+                return Err(CookError::InvalidFile("level of detail out of range"));
+            }
+            let transform = Mat4::from_cols_array(&r.f32s()?);
+            let [diffuse_map, normal_map, metallic_roughness_map, occlusion_map] = [r.u32()?, r.u32()?, r.u32()?, r.u32()?];
+            let base_color_factor = Vec4::from(r.f32s()?);
+            let [metallic_factor, roughness_factor] = r.f32s()?;
+            let material = Material {
+                diffuse_map,
+                normal_map,
+                metallic_roughness_map,
+                occlusion_map,
+                base_color_factor,
+                metallic_factor,
+                roughness_factor,
+            };
+            let material_extensions = read_extensions(&mut r)?;
+            let in_range = |map: &u32| *map == DEFAULT_TEXTURE_MAP || (*map as usize) < image_count;
+            if !texture_maps(&material, &material_extensions).iter().all(in_range) {
+                return Err(CookError::InvalidFile("texture out of range"));
+            }
+            let [x, y, z, radius] = r.f32s()?;
+            let min = Vec3::from(r.f32s()?);
+            let max = Vec3::from(r.f32s()?);
+            meshes.push(CookedMesh {
+                vertices,
+                indices,
+                lods,
+                transform,
+                material,
+                material_extensions,
+                bounds: BoundingSphere {
+                    center: Vec3::new(x, y, z),
+                    radius,
+                },
+                aabb: Aabb { min, max },
+            });
+        }
+
+        let mut lods = Vec::with_capacity(lod_count.min(data.len() / LOD_RECORD_SIZE));
+        for _ in 0..lod_count {
+            let vertices = r.blob(VERTEX_SIZE)?;
+            let indices = r.blob(4)?;
+            let [error] = r.f32s()?;
+            r.u32()?;
+            lods.push(CookedLod { vertices, indices, error });
+        }
+
+        let mut images = Vec::with_capacity(image_count.min(data.len() / IMAGE_RECORD_SIZE));
+        for _ in 0..image_count {
+            let [width, height, format, color_space, mip_levels, _] =
+                [r.u32()?, r.u32()?, r.u32()?, r.u32()?, r.u32()?, r.u32()?];
+            let format = *FORMATS
+                .get(format as usize)
+                .ok_or(CookError::InvalidFile("unknown texture format"))?;
+            let color_space = match color_space {
+                0 => ColorSpace::Linear,
+                1 => ColorSpace::Srgb,
+                _ => return Err(CookError::InvalidFile("unknown colour space")),
+            };
+            let pixels = r.blob(1)?;
+            let image = CookedImage {
+                width,
+                height,
+                format,
+                color_space,
+                mip_levels,
+                pixels,
+            };
+            if mip_levels == 0 || image_size(&image) != Some(pixels.count) {
+                return Err(CookError::InvalidFile("pixel data does not match the image size"));
+            }
+            images.push(image);
+        }
+
+        let file = CookedFile {
+            bytes,
+            kind,
+            meshes,
+            lods,
+            images,
+        };
+        let streams = file.meshes.iter().map(|mesh| (mesh.vertices, mesh.indices));
+        let streams = streams.chain(file.lods.iter().map(|lod| (lod.vertices, lod.indices)));
+        for (vertices, indices) in streams {
+            if file.indices(indices).iter().any(|index| *index as usize >= vertices.count) {
+                return Err(CookError::InvalidFile("index out of range"));
+            }
+        }
+        Ok(file)
+    }
+
+    fn data(&self, blob: Blob, size: usize) -> &[u8] {
+        &self.bytes.as_ref()[blob.offset..blob.offset + blob.count * size]
+    }
+
+    /// Borrows the vertices straight from the file when the stored layout
+    /// matches `Vertex` in memory, decoding them otherwise.
+    pub fn vertices(&self, blob: Blob) -> Cow<'_, [Vertex]> {
+        let data = self.data(blob, VERTEX_SIZE);
+        if vertices_match_layout() && (data.as_ptr() as usize).is_multiple_of(std::mem::align_of::<Vertex>()) {
+            // Safety: the layout and alignment were checked above, and every
+            // bit pattern is a valid `f32`.
+            return Cow::Borrowed(unsafe { std::slice::from_raw_parts(data.as_ptr().cast(), blob.count) });
+        }
+        let f = |v: &[u8], i: usize| f32::from_le_bytes(v[i * 4..i * 4 + 4].try_into().unwrap());
+        let vec4 = |v: &[u8], i: usize| Vec4::new(f(v, i), f(v, i + 1), f(v, i + 2), f(v, i + 3));
+        Cow::Owned(
+            data.chunks_exact(VERTEX_SIZE)
+                .map(|v| Vertex {
+                    pos: vec4(v, 0),
+                    normal: vec4(v, 4),
+                    uv: Vec2::new(f(v, 8), f(v, 9)),
+                    color: vec4(v, 12),
+                    tangent: vec4(v, 16),
+                })
+                .collect(),
+        )
+    }
+
+    pub fn indices(&self, blob: Blob) -> Cow<'_, [u32]> {
+        let data = self.data(blob, 4);
+        // Safety: any four bytes are a valid `u32`, and `align_to` only
+        // returns an aligned middle.
+        match unsafe { data.align_to::<u32>() } {
+            ([], indices, []) if cfg!(target_endian = "little") => Cow::Borrowed(indices),
+            _ => Cow::Owned(
+                data.chunks_exact(4)
+                    .map(|i| u32::from_le_bytes(i.try_into().unwrap()))
+                    .collect(),
+            ),
+        }
+    }
+
+    pub fn pixels(&self, image: &CookedImage) -> &[u8] {
+        self.data(image.pixels, 1)
+    }
+
+    pub fn cpu_image(&self, image: &CookedImage) -> CpuImage {
+        CpuImage {
+            width: image.width,
+            height: image.height,
+            format: image.format,
+            color_space: image.color_space,
+            mip_levels: image.mip_levels,
+            pixels: self.pixels(image).to_vec(),
+            source: None,
+        }
+    }
+
+    /// Copies the file into a [`CpuModel`] with one root node per mesh.
+    pub fn to_cpu_model(&self) -> Result<CpuModel, CookError> {
+        if self.kind != CookedKind::Model {
+            return Err(CookError::InvalidFile("expected a cooked model"));
+        }
+        let mut model = CpuModel {
+            meshes: vec![],
+            nodes: vec![],
+            roots: vec![],
+            images: self.images.iter().map(|image| self.cpu_image(image)).collect(),
+            skins: vec![],
+            animations: vec![],
+        };
+        for (node, mesh) in self.meshes.iter().enumerate() {
+            let (scale, rotation, translation) = mesh.transform.to_scale_rotation_translation();
+            model.roots.push(node);
+            model.nodes.push(CpuNode {
+                name: None,
+                parent: None,
+                children: vec![],
+                transform: NodeTransform {
+                    translation,
+                    rotation,
+                    scale,
+                },
+                local_transform: mesh.transform,
+                world_transform: mesh.transform,
+                meshes: vec![node],
+                skin: None,
+                camera: None,
+                light: None,
+                morph_weights: vec![],
+            });
+            model.meshes.push(CpuMesh {
+                indices: self.indices(mesh.indices).into_owned(),
+                vertices: self.vertices(mesh.vertices).into_owned(),
+                material: mesh.material,
+                material_extensions: mesh.material_extensions,
+                joints: vec![],
+                joint_weights: vec![],
+                morph_targets: vec![],
+                node,
+                lods: self.lods[mesh.lods.clone()]
+                    .iter()
+                    .map(|lod| CpuLod {
+                        indices: self.indices(lod.indices).into_owned(),
+                        vertices: self.vertices(lod.vertices).into_owned(),
+                        error: lod.error,
+                    })
+                    .collect(),
+            });
+        }
+        Ok(model)
+    }
+
+    pub fn to_cpu_image(&self) -> Result<CpuImage, CookError> {
+        match (self.kind, self.images.first()) {
+            (CookedKind::Texture, Some(image)) => Ok(self.cpu_image(image)),
+            _ => Err(CookError::InvalidFile("expected a cooked texture")),
+        }
+    }
+}
+
+/// Bytes taken by every level of `image`, `None` on overflow.
+fn image_size(image: &CookedImage) -> Option<usize> {
+    (0..image.mip_levels).try_fold(0usize, |size, level| {
+        let width = image.width.checked_shr(level).unwrap_or(0).max(1);
+        let height = image.height.checked_shr(level).unwrap_or(0).max(1);
+        size.checked_add(image.format.level_size(width, height))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gltf_loader::{import_gltf, LodGeneration};
+    use crate::test_fixtures::model_path;
+    use crate::texture_processing::TextureSettings;
+
+    #[test]
+    fn models_round_trip() {
+        let mut model = import_gltf(model_path("triangle.gltf")).unwrap();
+        model.generate_lods(&LodGeneration::default());
+        model.process_images(&TextureSettings::default());
+        let vertices = model.meshes[0].vertices.clone();
+        model.meshes[0].lods.push(CpuLod {
+            indices: vec![0, 2, 1],
+            vertices,
+            error: 0.25,
+        });
+        model.meshes[0].material_extensions = MaterialExtensions {
+            unlit: true,
+            ior: Some(1.5),
+            transmission_map: Some(0),
+            specular_glossiness: Some(SpecularGlossiness {
+                diffuse_factor: Vec4::ONE,
+                specular_factor: Vec3::splat(0.5),
+                glossiness_factor: 0.75,
+                diffuse_map: 0,
+                specular_glossiness_map: DEFAULT_TEXTURE_MAP,
+            }),
+            metallic_roughness_transform: Some(TextureTransform {
+                offset: Vec2::new(0.25, 0.5),
+                rotation: 1.0,
+                scale: Vec2::splat(2.0),
+                tex_coord: Some(1),
+            }),
+            ..MaterialExtensions::default()
+        };
+
+        let cooked = CookedFile::from_bytes(write_model(&model)).unwrap();
+        assert_eq!(cooked.kind, CookedKind::Model);
+        let mesh = &cooked.meshes[0];
+        assert_eq!(mesh.transform, model.transform(0));
+        assert_eq!(mesh.bounds.radius, BoundingSphere::from_points(model.meshes[0].vertices.iter().map(|v| v.pos.truncate())).radius);
+        assert_eq!(mesh.aabb.max, Vec3::new(1.0, 1.0, 0.0));
+
+        let loaded = cooked.to_cpu_model().unwrap();
+        let (original, loaded_mesh) = (&model.meshes[0], &loaded.meshes[0]);
+        assert_eq!(loaded_mesh.indices, original.indices);
+        assert_eq!(loaded_mesh.vertices.len(), original.vertices.len());
+        for (a, b) in loaded_mesh.vertices.iter().zip(&original.vertices) {
+            assert_eq!((a.pos, a.normal, a.uv, a.color, a.tangent), (b.pos, b.normal, b.uv, b.color, b.tangent));
+        }
+        assert_eq!(loaded_mesh.material, original.material);
+        assert_eq!(loaded_mesh.material_extensions, original.material_extensions);
+        assert_eq!(loaded_mesh.lods[0].error, 0.25);
+        assert_eq!(loaded_mesh.lods[0].indices, vec![0, 2, 1]);
+        assert_eq!(loaded.transform(0), model.transform(0));
+
+        let (image, original) = (&loaded.images[0], &model.images[0]);
+        assert_eq!((image.width, image.mip_levels, image.color_space), (2, 2, ColorSpace::Srgb));
+        assert_eq!(image.pixels, original.pixels);
+    }
+
+    #[test]
+    fn damaged_files_are_rejected() {
+        let image = CpuImage::rgba8(2, 2, vec![7; 16]);
+        let bytes = write_texture(&image);
+        assert_eq!(CookedFile::from_bytes(bytes.clone()).unwrap().to_cpu_image().unwrap().pixels, vec![7; 16]);
+
+        let mut old = bytes.clone();
+        old[8] = 0;
+        assert!(matches!(CookedFile::from_bytes(old), Err(CookError::Version(0))));
+        assert!(matches!(
+            CookedFile::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(CookError::InvalidFile(_))
+        ));
+        assert!(CookedFile::from_bytes(&bytes[..40]).is_err());
+        assert!(CookedFile::from_bytes(bytes).unwrap().to_cpu_model().is_err());
+
+        let mut model = import_gltf(model_path("triangle.gltf")).unwrap();
+        model.meshes[0].material.normal_map = model.images.len() as u32;
+        assert!(matches!(
+            CookedFile::from_bytes(write_model(&model)),
+            Err(CookError::InvalidFile("texture out of range"))
+        ));
+
+        let mut model = import_gltf(model_path("triangle.gltf")).unwrap();
+        model.meshes[0].indices[2] = model.meshes[0].vertices.len() as u32;
+        assert!(matches!(
+            CookedFile::from_bytes(write_model(&model)),
+            Err(CookError::InvalidFile("index out of range"))
+        ));
+
+        let mut model = import_gltf(model_path("triangle.gltf")).unwrap();
+        model.meshes[0].lods.push(CpuLod {
+            indices: vec![0, 1, 3],
+            vertices: model.meshes[0].vertices.clone(),
+            error: 0.5,
+        });
+        assert!(matches!(
+            CookedFile::from_bytes(write_model(&model)),
+            Err(CookError::InvalidFile("index out of range"))
+        ));
+    }
+}
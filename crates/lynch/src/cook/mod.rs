@@ -0,0 +1,240 @@
+//! Offline cooking of glTF, OBJ and image sources into a binary format that
+//! loads without parsing, optimizing or mip mapping anything, see
+//! [`format`]. The `cooper-cook` tool drives [`cook_file`] over a directory,
+//! [`crate::assets`] loads the results like any other model or texture.
+
+mod cache;
+pub mod format;
+
+use std::{
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+pub use cache::{source_dependencies, source_hash, CookCache};
+pub use format::{write_model, write_texture, CookedFile, CookedKind, COOKED_VERSION};
+
+use crate::{
+    gltf_loader::{import_gltf, CpuModel, LodGeneration},
+    mesh_processing::{optimize_overdraw, optimize_vertex_cache, optimize_vertex_fetch},
+    obj_loader::import_obj,
+    texture_processing::{load_texture, TextureError, TextureSettings, TextureUsage},
+};
+
+pub const MODEL_EXTENSION: &str = "cmodel";
+pub const TEXTURE_EXTENSION: &str = "ctex";
+
+#[derive(Debug)]
+pub enum CookError {
+    Io(std::io::Error),
+    /// The source could not be imported.
+    Import(String),
+    Texture(TextureError),
+    /// The cooked file is truncated or malformed.
+    InvalidFile(&'static str),
+    /// The cooked file was written by another version of the format.
+    Version(u32),
+    /// Skinned, morphed and animated models are loaded from their source.
+    Unsupported(&'static str),
+}
+
+impl Display for CookError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            CookError::Io(e) => write!(f, "{}", e),
+            CookError::Import(e) => write!(f, "Could not import source: {}", e),
+            CookError::Texture(e) => write!(f, "{}", e),
+            CookError::InvalidFile(reason) => write!(f, "Invalid cooked file: {}", reason),
+            CookError::Version(version) => write!(
+                f,
+                "Cooked file has version {}, expected {}",
+                version, COOKED_VERSION
+            ),
+            CookError::Unsupported(reason) => write!(f, "Cannot cook {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CookError {}
+
+impl From<std::io::Error> for CookError {
+    fn from(e: std::io::Error) -> Self {
+        CookError::Io(e)
+    }
+}
+
+impl From<TextureError> for CookError {
+    fn from(e: TextureError) -> Self {
+        CookError::Texture(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SourceKind {
+    Model,
+    Image,
+}
+
+/// What `path` is cooked into, `None` for files that are not sources.
+pub fn source_kind(path: &Path) -> Option<SourceKind> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "gltf" | "glb" | "obj" => Some(SourceKind::Model),
+        "png" | "jpg" | "jpeg" | "tga" | "bmp" | "hdr" | "exr" => Some(SourceKind::Image),
+        _ => None,
+    }
+}
+
+/// Whether `path` is a cooked model or texture.
+pub fn is_cooked(path: &Path, kind: SourceKind) -> bool {
+    let extension = match kind {
+        SourceKind::Model => MODEL_EXTENSION,
+        SourceKind::Image => TEXTURE_EXTENSION,
+    };
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case(extension))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CookSettings {
+    pub lods: LodGeneration,
+    pub textures: TextureSettings,
+    /// What standalone images are sampled as, images of models follow their
+    /// material slot.
+    pub image_usage: TextureUsage,
+}
+
+impl Default for CookSettings {
+    fn default() -> Self {
+        CookSettings {
+            lods: LodGeneration::default(),
+            textures: TextureSettings::default(),
+            image_usage: TextureUsage::Data,
+        }
+    }
+}
+
+/// Imports a model and does all the work a runtime import would: vertex
+/// cache, overdraw and fetch ordering, levels of detail and texture mips.
+pub fn cook_model(source: &Path, settings: &CookSettings) -> Result<CpuModel, CookError> {
+    let mut model = match source.extension().is_some_and(|e| e.eq_ignore_ascii_case("obj")) {
+        true => import_obj(source).map_err(|err| CookError::Import(err.to_string()))?,
+        false => import_gltf(source).map_err(|err| CookError::Import(err.to_string()))?,
+    };
+    let deformed = model.meshes.iter().any(|mesh| !mesh.joints.is_empty() || !mesh.morph_targets.is_empty());
+    if deformed || !model.animations.is_empty() {
+        return Err(CookError::Unsupported("skinned, morphed or animated models"));
+    }
+    for mesh in &mut model.meshes {
+        optimize_vertex_cache(&mut mesh.indices, mesh.vertices.len());
+        optimize_overdraw(&mut mesh.indices, &mesh.vertices);
+        optimize_vertex_fetch(&mut mesh.vertices, &mut mesh.indices);
+    }
+    model.generate_lods(&settings.lods);
+    model.process_images(&settings.textures);
+    Ok(model)
+}
+
+/// Where `source`, found at `relative` below the source directory, is
+/// cooked to in `output_dir`.
+pub fn cooked_path(output_dir: &Path, relative: &Path) -> Option<PathBuf> {
+    let extension = match source_kind(relative)? {
+        SourceKind::Model => MODEL_EXTENSION,
+        SourceKind::Image => TEXTURE_EXTENSION,
+    };
+    Some(output_dir.join(relative).with_extension(extension))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CookOutcome {
+    Cooked,
+    /// The cache says `output` was cooked from the same sources.
+    UpToDate,
+}
+
+/// Cooks `source` to `output` unless `cache` has it up to date.
+pub fn cook_file(
+    source: &Path,
+    output: &Path,
+    settings: &CookSettings,
+    cache: &mut CookCache,
+) -> Result<CookOutcome, CookError> {
+    let kind = source_kind(source).ok_or(CookError::Unsupported("files that are not models or images"))?;
+    let dependencies = source_dependencies(source, &settings.textures);
+    let hash = source_hash(source, &dependencies, &format!("{:?}", settings))?;
+    if cache.is_fresh(output, hash) {
+        return Ok(CookOutcome::UpToDate);
+    }
+
+    let bytes = match kind {
+        SourceKind::Model => write_model(&cook_model(source, settings)?),
+        SourceKind::Image => write_texture(&load_texture(source, settings.image_usage, &settings.textures)?),
+    };
+    if let Some(dir) = output.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Written beside and renamed, so a loader mapping the old file never
+    // sees it change underneath.
+    let partial = output.with_extension("partial");
+    std::fs::write(&partial, bytes)?;
+    std::fs::rename(&partial, output)?;
+    cache.insert(output, hash);
+    Ok(CookOutcome::Cooked)
+}
+
+/// Loads a cooked model, failing on compressed images `textures` cannot
+/// sample.
+pub fn load_cooked_model(path: &Path, textures: &TextureSettings) -> Result<CpuModel, CookError> {
+    let model = CookedFile::open(path)?.to_cpu_model()?;
+    if !textures.bc_supported && model.images.iter().any(|image| image.format.is_compressed()) {
+        return Err(CookError::Unsupported("compressed textures on this device"));
+    }
+    Ok(model)
+}
+
+pub fn load_cooked_texture(
+    path: &Path,
+    textures: &TextureSettings,
+) -> Result<crate::texture_processing::CpuImage, CookError> {
+    let image = CookedFile::open(path)?.to_cpu_image()?;
+    if !textures.bc_supported && image.format.is_compressed() {
+        return Err(CookError::Unsupported("compressed textures on this device"));
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::model_path;
+
+    #[test]
+    fn sources_are_cooked_once_until_they_change() {
+        let dir = std::env::temp_dir().join(format!("lynch_cook_{}", std::process::id()));
+        let sources = dir.join("sources");
+        std::fs::create_dir_all(&sources).unwrap();
+        for file in ["quads.obj", "quads.mtl"] {
+            std::fs::copy(model_path(file), sources.join(file)).unwrap();
+        }
+        let source = sources.join("quads.obj");
+        let output_dir = dir.join("cooked");
+        let output = cooked_path(&output_dir, Path::new("quads.obj")).unwrap();
+        assert_eq!(output, output_dir.join("quads.cmodel"));
+
+        let settings = CookSettings::default();
+        let mut cache = CookCache::load(&output_dir);
+        assert_eq!(cook_file(&source, &output, &settings, &mut cache).unwrap(), CookOutcome::Cooked);
+        cache.save().unwrap();
+
+        let mut cache = CookCache::load(&output_dir);
+        assert_eq!(cook_file(&source, &output, &settings, &mut cache).unwrap(), CookOutcome::UpToDate);
+        // Editing a dependency cooks the model again.
+        std::fs::write(sources.join("quads.mtl"), "newmtl red\nKd 0 1 0\n").unwrap();
+        assert_eq!(cook_file(&source, &output, &settings, &mut cache).unwrap(), CookOutcome::Cooked);
+
+        let model = load_cooked_model(&output, &TextureSettings::default()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(model.meshes.len(), 2);
+        assert_eq!(model.meshes[0].material.base_color_factor, glam::Vec4::new(0.0, 1.0, 0.0, 1.0));
+        assert_eq!(model.meshes[0].indices.len(), 6);
+    }
+}
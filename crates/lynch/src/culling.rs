@@ -0,0 +1,353 @@
+//! CPU visibility tests run before draws are recorded: frustum culling of
+//! mesh bounds and an optional occlusion pass against a small software
+//! rasterized depth buffer of the largest meshes on screen.
+
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+
+use crate::{bounds::Aabb, mesh::Vertex};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CullingSettings {
+    pub frustum: bool,
+    pub occlusion: bool,
+    /// Meshes rasterized into the occlusion buffer, largest on screen first.
+    pub max_occluders: usize,
+    pub occlusion_width: u32,
+    pub occlusion_height: u32,
+}
+
+impl Default for CullingSettings {
+    fn default() -> Self {
+        CullingSettings {
+            frustum: true,
+            occlusion: false,
+            max_occluders: 16,
+            occlusion_width: 256,
+            occlusion_height: 128,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CullingStatistics {
+    pub meshes: usize,
+    pub frustum_culled: usize,
+    pub occlusion_culled: usize,
+    pub occluders: usize,
+    pub occluder_triangles: usize,
+}
+
+impl CullingStatistics {
+    pub fn visible(&self) -> usize {
+        self.meshes - self.frustum_culled - self.occlusion_culled
+    }
+}
+
+/// The six planes of a view projection, normals pointing inwards.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    /// Planes of a zero to one depth projection such as the one of
+    /// [`crate::Camera::get_projection`], after Gribb and Hartmann.
+    pub fn from_view_projection(view_projection: &Mat4) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|row| view_projection.row(row));
+        let planes = [w + x, w - x, w + y, w - y, z, w - z].map(|plane| plane / plane.xyz().length());
+        Frustum { planes }
+    }
+
+    pub fn intersects_sphere(&self, center: Vec3, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.xyz().dot(center) + plane.w >= -radius)
+    }
+
+    /// Whether any part of `aabb` may be inside. Boxes near a corner of the
+    /// frustum can pass without being inside, never the other way round.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            let normal = plane.xyz();
+            // The corner furthest along the plane's normal.
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+/// Low resolution depth buffer occluders are rasterized into, depths being
+/// in the projection's zero to one range, nearest kept.
+pub struct OcclusionBuffer {
+    width: u32,
+    height: u32,
+    depth: Vec<f32>,
+}
+
+impl OcclusionBuffer {
+    pub fn new(width: u32, height: u32) -> Self {
+        OcclusionBuffer {
+            width,
+            height,
+            depth: vec![1.0; (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn clear(&mut self) {
+        self.depth.fill(1.0);
+    }
+
+    /// Clip space position to buffer pixels and depth, `None` for points
+    /// behind the near plane.
+    fn project(&self, clip: Vec4) -> Option<Vec3> {
+        if clip.w <= f32::EPSILON || clip.z < 0.0 {
+            return None;
+        }
+        let ndc = clip.xyz() / clip.w;
+        Some(Vec3::new(
+            (ndc.x * 0.5 + 0.5) * self.width as f32,
+            (ndc.y * 0.5 + 0.5) * self.height as f32,
+            ndc.z,
+        ))
+    }
+
+    /// Rasterizes a mesh's triangles, both windings. Triangles crossing the
+    /// near plane are skipped, which can only let more through.
+    pub fn rasterize(&mut self, vertices: &[Vertex], indices: &[u32], model_view_projection: &Mat4) {
+        for triangle in indices.chunks_exact(3) {
+            let projected = [0, 1, 2].map(|i| {
+                let position = vertices[triangle[i] as usize].pos.truncate().extend(1.0);
+                self.project(*model_view_projection * position)
+            });
+            if let [Some(a), Some(b), Some(c)] = projected {
+                self.rasterize_triangle(a, b, c);
+            }
+        }
+    }
+
+    fn rasterize_triangle(&mut self, a: Vec3, mut b: Vec3, mut c: Vec3) {
+        let edge = |from: Vec3, to: Vec3, x: f32, y: f32| (to.x - from.x) * (y - from.y) - (to.y - from.y) * (x - from.x);
+        let area = edge(a, b, c.x, c.y);
+        if area == 0.0 {
+            return;
+        }
+        if area < 0.0 {
+            std::mem::swap(&mut b, &mut c);
+        }
+        let area = area.abs();
+
+        let low = a.min(b).min(c);
+        let high = a.max(b).max(c);
+        let x_range = (low.x.floor().max(0.0) as u32)..(high.x.ceil().min(self.width as f32) as u32);
+        let y_range = (low.y.floor().max(0.0) as u32)..(high.y.ceil().min(self.height as f32) as u32);
+        for y in y_range {
+            for x in x_range.clone() {
+                let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+                let wa = edge(b, c, px, py);
+                let wb = edge(c, a, px, py);
+                let wc = edge(a, b, px, py);
+                if wa < 0.0 || wb < 0.0 || wc < 0.0 {
+                    continue;
+                }
+                let depth = (wa * a.z + wb * b.z + wc * c.z) / area;
+                let stored = &mut self.depth[(y * self.width + x) as usize];
+                *stored = stored.min(depth);
+            }
+        }
+    }
+
+    /// Whether any pixel under the screen rectangle of `aabb` is further
+    /// away than the box's nearest point. Boxes reaching behind the near
+    /// plane are always visible.
+    pub fn is_visible(&self, aabb: &Aabb, model_view_projection: &Mat4) -> bool {
+        let mut low = Vec3::splat(f32::MAX);
+        let mut high = Vec3::splat(f32::MIN);
+        for corner in aabb.corners() {
+            let Some(p) = self.project(*model_view_projection * corner.extend(1.0)) else {
+                return true;
+            };
+            low = low.min(p);
+            high = high.max(p);
+        }
+        let x_range = (low.x.floor().max(0.0) as u32)..(high.x.ceil().min(self.width as f32) as u32);
+        let y_range = (low.y.floor().max(0.0) as u32)..(high.y.ceil().min(self.height as f32) as u32);
+        if x_range.is_empty() || y_range.is_empty() {
+            return true;
+        }
+        y_range.into_iter().any(|y| {
+            let row = (y * self.width) as usize;
+            self.depth[row + x_range.start as usize..row + x_range.end as usize]
+                .iter()
+                .any(|stored| *stored >= low.z)
+        })
+    }
+}
+
+/// A mesh to cull: bounds in model space, and its triangles if it may be
+/// used as an occluder.
+pub struct CullCandidate<'a> {
+    pub bounds: Aabb,
+    pub transform: Mat4,
+    pub occluder: Option<(&'a [Vertex], &'a [u32])>,
+}
+
+/// Visibility of every candidate as seen from `eye` through
+/// `view_projection`.
+pub fn cull_meshes(
+    candidates: &[CullCandidate],
+    view_projection: &Mat4,
+    eye: Vec3,
+    settings: &CullingSettings,
+    occlusion_buffer: &mut OcclusionBuffer,
+) -> (Vec<bool>, CullingStatistics) {
+    let mut statistics = CullingStatistics {
+        meshes: candidates.len(),
+        ..Default::default()
+    };
+    let frustum = Frustum::from_view_projection(view_projection);
+    let mut visible: Vec<bool> = candidates
+        .iter()
+        .map(|candidate| !settings.frustum || frustum.intersects_aabb(&candidate.bounds.transformed(&candidate.transform)))
+        .collect();
+    statistics.frustum_culled = visible.iter().filter(|visible| !**visible).count();
+    if !settings.occlusion {
+        return (visible, statistics);
+    }
+
+    // Largest on screen first, by bounding radius over distance.
+    let mut occluders: Vec<(f32, usize)> = candidates
+        .iter()
+        .enumerate()
+        .filter(|(i, candidate)| visible[*i] && candidate.occluder.is_some())
+        .map(|(i, candidate)| {
+            let world = candidate.bounds.transformed(&candidate.transform);
+            let radius = world.half_extents().length();
+            (radius / world.center().distance(eye).max(f32::EPSILON), i)
+        })
+        .collect();
+    occluders.sort_by(|a, b| b.0.total_cmp(&a.0));
+    occluders.truncate(settings.max_occluders);
+
+    occlusion_buffer.clear();
+    for (_, i) in &occluders {
+        let (vertices, indices) = candidates[*i].occluder.unwrap();
+        occlusion_buffer.rasterize(vertices, indices, &(*view_projection * candidates[*i].transform));
+        statistics.occluders += 1;
+        statistics.occluder_triangles += indices.len() / 3;
+    }
+
+    // Occluders are left visible, they are what the buffer holds.
+    for (i, (candidate, visible)) in candidates.iter().zip(visible.iter_mut()).enumerate() {
+        if occluders.iter().any(|(_, occluder)| *occluder == i) {
+            continue;
+        }
+        if *visible && !occlusion_buffer.is_visible(&candidate.bounds, &(*view_projection * candidate.transform)) {
+            *visible = false;
+            statistics.occlusion_culled += 1;
+        }
+    }
+    (visible, statistics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh_processing::fixtures::vertex;
+
+    fn view_projection() -> Mat4 {
+        let projection = Mat4::perspective_rh(90f32.to_radians(), 1.0, 0.1, 100.0);
+        projection * Mat4::look_at_rh(Vec3::ZERO, -Vec3::Z, Vec3::Y)
+    }
+
+    fn unit_box() -> Aabb {
+        Aabb {
+            min: Vec3::splat(-0.5),
+            max: Vec3::splat(0.5),
+        }
+    }
+
+    /// Two triangle quad in the XY plane, `size` wide around the origin.
+    fn quad(size: f32) -> (Vec<Vertex>, Vec<u32>) {
+        let h = size * 0.5;
+        let corners = [Vec3::new(-h, -h, 0.0), Vec3::new(h, -h, 0.0), Vec3::new(-h, h, 0.0), Vec3::new(h, h, 0.0)];
+        (corners.map(vertex).to_vec(), vec![0, 1, 2, 1, 3, 2])
+    }
+
+    #[test]
+    fn frustum_planes_bound_the_view() {
+        let frustum = Frustum::from_view_projection(&view_projection());
+        let at = |x, y, z| unit_box().transformed(&Mat4::from_translation(Vec3::new(x, y, z)));
+
+        assert!(frustum.intersects_aabb(&at(0.0, 0.0, -5.0)));
+        assert!(!frustum.intersects_aabb(&at(0.0, 0.0, 5.0)), "behind");
+        assert!(!frustum.intersects_aabb(&at(0.0, 0.0, -200.0)), "past the far plane");
+        assert!(!frustum.intersects_aabb(&at(10.0, 0.0, -5.0)), "left of the view");
+        assert!(frustum.intersects_aabb(&at(5.2, 0.0, -5.0)), "straddling the edge");
+        assert!(frustum.intersects_sphere(Vec3::new(0.0, 5.5, -5.0), 1.0));
+        assert!(!frustum.intersects_sphere(Vec3::new(0.0, 7.0, -5.0), 1.0));
+    }
+
+    #[test]
+    fn occluders_hide_what_is_behind_them() {
+        let mut buffer = OcclusionBuffer::new(64, 64);
+        let (vertices, indices) = quad(4.0);
+        let wall = Mat4::from_translation(Vec3::new(0.0, 0.0, -3.0));
+        buffer.rasterize(&vertices, &indices, &(view_projection() * wall));
+
+        let at = |x, z| view_projection() * Mat4::from_translation(Vec3::new(x, 0.0, z));
+        assert!(!buffer.is_visible(&unit_box(), &at(0.0, -10.0)), "behind the wall");
+        assert!(buffer.is_visible(&unit_box(), &at(0.0, -1.5)), "in front of the wall");
+        assert!(buffer.is_visible(&unit_box(), &at(8.0, -10.0)), "beside the wall");
+        assert!(buffer.is_visible(&unit_box(), &at(0.0, 0.0)), "around the eye");
+
+        buffer.clear();
+        assert!(buffer.is_visible(&unit_box(), &at(0.0, -10.0)));
+    }
+
+    #[test]
+    fn culling_counts_every_stage() {
+        let (vertices, indices) = quad(4.0);
+        let candidate = |x: f32, z: f32, occluder| CullCandidate {
+            bounds: unit_box(),
+            transform: Mat4::from_translation(Vec3::new(x, 0.0, z)),
+            occluder,
+        };
+        let candidates = [
+            CullCandidate {
+                bounds: Aabb::from_points(vertices.iter().map(|v| v.pos.truncate())),
+                ..candidate(0.0, -3.0, Some((vertices.as_slice(), indices.as_slice())))
+            },
+            candidate(0.0, -10.0, None),
+            candidate(0.0, 10.0, None),
+            candidate(8.0, -10.0, None),
+        ];
+        let mut buffer = OcclusionBuffer::new(64, 64);
+
+        let mut settings = CullingSettings::default();
+        let (visible, statistics) = cull_meshes(&candidates, &view_projection(), Vec3::ZERO, &settings, &mut buffer);
+        assert_eq!(visible, vec![true, true, false, true]);
+        assert_eq!(statistics.frustum_culled, 1);
+        assert_eq!(statistics.occluders, 0);
+
+        settings.occlusion = true;
+        let (visible, statistics) = cull_meshes(&candidates, &view_projection(), Vec3::ZERO, &settings, &mut buffer);
+        assert_eq!(visible, vec![true, false, false, true]);
+        assert_eq!(
+            statistics,
+            CullingStatistics {
+                meshes: 4,
+                frustum_culled: 1,
+                occlusion_culled: 1,
+                occluders: 1,
+                occluder_triangles: 2,
+            }
+        );
+        assert_eq!(statistics.visible(), 2);
+    }
+}
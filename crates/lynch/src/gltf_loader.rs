@@ -1,17 +1,34 @@
-use std::sync::Arc;
+use std::{
+    fmt::{Display, Formatter},
+    path::Path,
+    sync::Arc,
+};
 
 use ash::vk;
-use glam::{Mat4, Vec2, Vec3, Vec4};
+use glam::{Mat3, Mat4, Quat, Vec2, Vec3, Vec4};
 use uuid::Uuid;
 
 use crate::{
+    animation::{AnimationClip, AnimationChannel, Interpolation, Keyframes, NodeTransform, Pose},
+    assets::ModelImport,
+    bounds::{Aabb, BoundingSphere},
     mesh::{Primitive, Vertex},
-    vulkan::{Device, ImageDesc},
+    mesh_processing::{
+        build_lod_chain, compute_flat_normals, generate_tangents, optimize_vertex_fetch, remap_vertices,
+        surface_deviation,
+    },
+    texture_processing::{prepare_image, TextureFormat, TextureSettings, TextureUsage},
+    vulkan::Device,
     Texture,
 };
 
+pub use crate::texture_processing::CpuImage;
+
 pub const DEFAULT_TEXTURE_MAP: u32 = u32::MAX;
 
+/// Texture slots index into [`CpuModel::images`] / [`Model::textures`], or
+/// hold [`DEFAULT_TEXTURE_MAP`].
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Material {
     pub diffuse_map: u32,
     pub normal_map: u32,
@@ -26,190 +43,1345 @@ pub struct Mesh {
     pub primitive: Primitive,
     pub material: Material,
     pub gpu_mesh: u32,
+    /// glTF node the mesh hangs off.
+    pub node: usize,
+    pub deform: Option<MeshDeform>,
+    /// Coarser levels of detail, finest first.
+    pub lods: Vec<LodLevel>,
+    /// Error of every level in model units, starting with `0.0` for
+    /// `primitive` itself. See [`crate::lod::select_lod`].
+    pub lod_errors: Vec<f32>,
+    /// Bounds of `primitive` in the space of `node`.
+    pub bounds: BoundingSphere,
+    pub aabb: Aabb,
+}
+
+/// A coarser stand-in for a [`Mesh`], drawn with the mesh's material.
+pub struct LodLevel {
+    pub primitive: Primitive,
+    pub gpu_mesh: u32,
+}
+
+impl Mesh {
+    /// Primitive and bindless mesh index of level `lod`, `0` being the mesh
+    /// itself. Levels past the coarsest clamp to it.
+    pub fn level(&self, lod: usize) -> (&Primitive, u32) {
+        match lod.min(self.lods.len()) {
+            0 => (&self.primitive, self.gpu_mesh),
+            lod => (&self.lods[lod - 1].primitive, self.lods[lod - 1].gpu_mesh),
+        }
+    }
+}
+
+/// What a skinned or morphed mesh needs to be deformed on the CPU. The bind
+/// pose is kept in `Primitive::vertices`.
+pub struct MeshDeform {
+    pub joints: Vec<[u16; 4]>,
+    pub joint_weights: Vec<Vec4>,
+    pub morph_targets: Vec<MorphTarget>,
 }
 
 pub struct Model {
     pub meshes: Vec<Mesh>,
     pub textures: Vec<Texture>,
     pub transforms: Vec<Mat4>,
+    /// The imported model without its meshes and images, which are on the
+    /// GPU, to spawn entities from with [`crate::scene::spawn_gltf`].
+    pub scene: CpuModel,
+}
+
+#[derive(Debug)]
+pub enum AssetError {
+    Import(gltf::Error),
+    MissingAttribute {
+        mesh: usize,
+        primitive: usize,
+        attribute: &'static str,
+    },
+    /// A value of `attribute` refers past the `count` vertices or joints
+    /// it indexes.
+    IndexOutOfRange {
+        mesh: usize,
+        primitive: usize,
+        attribute: &'static str,
+        index: u32,
+        count: usize,
+    },
+    AttributeCountMismatch {
+        mesh: usize,
+        primitive: usize,
+        attribute: &'static str,
+        count: usize,
+        expected: usize,
+    },
+    InvalidImage {
+        image: usize,
+    },
+    InvalidAnimation {
+        animation: usize,
+        channel: usize,
+    },
+}
+
+impl Display for AssetError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            AssetError::Import(e) => write!(f, "Could not import glTF: {}", e),
+            AssetError::MissingAttribute {
+                mesh,
+                primitive,
+                attribute,
+            } => write!(
+                f,
+                "Primitive {} of mesh {} has no {} attribute.",
+                primitive, mesh, attribute
+            ),
+            AssetError::IndexOutOfRange {
+                mesh,
+                primitive,
+                attribute,
+                index,
+                count,
+            } => write!(
+                f,
+                "Primitive {} of mesh {} has {} value {} but only {} can be referenced.",
+                primitive, mesh, attribute, index, count
+            ),
+            AssetError::AttributeCountMismatch {
+                mesh,
+                primitive,
+                attribute,
+                count,
+                expected,
+            } => write!(
+                f,
+                "Primitive {} of mesh {} has {} {} values where {} are needed.",
+                primitive, mesh, count, attribute, expected
+            ),
+            AssetError::InvalidImage { image } => {
+                write!(f, "Image {} does not match its dimensions.", image)
+            }
+            AssetError::InvalidAnimation { animation, channel } => write!(
+                f,
+                "Channel {} of animation {} has missing or mismatched keyframes.",
+                channel, animation
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AssetError::Import(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<gltf::Error> for AssetError {
+    fn from(e: gltf::Error) -> Self {
+        AssetError::Import(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpuCamera {
+    Perspective {
+        yfov: f32,
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Directional,
+    Point,
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+/// `KHR_lights_punctual` light attached to a node.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CpuLight {
+    pub kind: LightKind,
+    pub color: Vec3,
+    pub intensity: f32,
+    pub range: Option<f32>,
 }
 
-pub fn load_node(
-    device: Arc<Device>,
+#[derive(Clone, Debug)]
+pub struct CpuNode {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub transform: NodeTransform,
+    pub local_transform: Mat4,
+    pub world_transform: Mat4,
+    /// Indices into [`CpuModel::meshes`] instanced by this node.
+    pub meshes: Vec<usize>,
+    pub skin: Option<usize>,
+    pub camera: Option<CpuCamera>,
+    pub light: Option<CpuLight>,
+    /// Morph weights of the node's mesh, empty without morph targets.
+    pub morph_weights: Vec<f32>,
+}
+
+/// Joints are node indices. Vertices reference joints by their position in
+/// [`CpuSkin::joints`].
+#[derive(Clone, Debug)]
+pub struct CpuSkin {
+    pub name: Option<String>,
+    pub joints: Vec<usize>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+    pub skeleton: Option<usize>,
+}
+
+/// Per vertex displacements, empty where the target leaves an attribute alone.
+#[derive(Clone, Debug, Default)]
+pub struct MorphTarget {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub tangents: Vec<Vec3>,
+}
+
+/// `KHR_texture_transform` of a texture slot.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureTransform {
+    pub offset: Vec2,
+    pub rotation: f32,
+    pub scale: Vec2,
+    pub tex_coord: Option<u32>,
+}
+
+/// `KHR_materials_pbrSpecularGlossiness` parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpecularGlossiness {
+    pub diffuse_factor: Vec4,
+    pub specular_factor: Vec3,
+    pub glossiness_factor: f32,
+    pub diffuse_map: u32,
+    pub specular_glossiness_map: u32,
+}
+
+/// Material extensions layered on top of [`Material`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MaterialExtensions {
+    pub unlit: bool,
+    pub ior: Option<f32>,
+    pub transmission_factor: Option<f32>,
+    pub transmission_map: Option<u32>,
+    pub specular_glossiness: Option<SpecularGlossiness>,
+    pub diffuse_transform: Option<TextureTransform>,
+    pub metallic_roughness_transform: Option<TextureTransform>,
+}
+
+/// One glTF primitive, flattened into its node's space.
+#[derive(Clone)]
+pub struct CpuMesh {
+    pub indices: Vec<u32>,
+    pub vertices: Vec<Vertex>,
+    pub material: Material,
+    pub material_extensions: MaterialExtensions,
+    /// `JOINTS_0`/`WEIGHTS_0`, empty for unskinned primitives.
+    pub joints: Vec<[u16; 4]>,
+    pub joint_weights: Vec<Vec4>,
+    pub morph_targets: Vec<MorphTarget>,
+    pub node: usize,
+    /// Coarser levels of detail, finest first. Authored as `<node>_LOD<n>`
+    /// nodes or built by [`CpuModel::generate_lods`].
+    pub lods: Vec<CpuLod>,
+}
+
+/// A simplified copy of a [`CpuMesh`] in the same node space.
+#[derive(Clone)]
+pub struct CpuLod {
+    pub indices: Vec<u32>,
+    pub vertices: Vec<Vertex>,
+    /// How far the level strays from the full mesh, in model units.
+    pub error: f32,
+}
+
+/// How [`CpuModel::generate_lods`] simplifies meshes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LodGeneration {
+    /// Levels per mesh, including the full mesh.
+    pub max_levels: usize,
+    /// Fraction of the previous level's triangles every level aims for.
+    pub reduction: f32,
+    /// Largest error a level may reach, relative to the mesh's bounding radius.
+    pub max_relative_error: f32,
+}
+
+impl Default for LodGeneration {
+    fn default() -> Self {
+        LodGeneration {
+            max_levels: 4,
+            reduction: 0.5,
+            max_relative_error: 0.05,
+        }
+    }
+}
+
+/// Everything read from a glTF file without touching the GPU. Nodes keep
+/// their glTF indices.
+#[derive(Clone)]
+pub struct CpuModel {
+    pub meshes: Vec<CpuMesh>,
+    pub nodes: Vec<CpuNode>,
+    pub roots: Vec<usize>,
+    pub images: Vec<CpuImage>,
+    pub skins: Vec<CpuSkin>,
+    pub animations: Vec<AnimationClip>,
+}
+
+impl CpuModel {
+    pub fn transform(&self, mesh: usize) -> Mat4 {
+        self.nodes[self.meshes[mesh].node].world_transform
+    }
+
+    pub fn parents(&self) -> Vec<Option<usize>> {
+        self.nodes.iter().map(|node| node.parent).collect()
+    }
+
+    /// Simplifies every mesh without authored levels of detail. Skinned and
+    /// morphed meshes are left alone, their deformation data only covers
+    /// the full mesh.
+    pub fn generate_lods(&mut self, settings: &LodGeneration) {
+        for mesh in &mut self.meshes {
+            if !mesh.lods.is_empty() || !mesh.joints.is_empty() || !mesh.morph_targets.is_empty() {
+                continue;
+            }
+            let radius = BoundingSphere::from_points(mesh.vertices.iter().map(|v| v.pos.truncate())).radius;
+            let chain = build_lod_chain(
+                &mesh.vertices,
+                &mesh.indices,
+                settings.max_levels,
+                settings.reduction,
+                radius * settings.max_relative_error,
+            );
+            mesh.lods = chain
+                .into_iter()
+                .skip(1)
+                .map(|mut lod| {
+                    let mut vertices = mesh.vertices.clone();
+                    optimize_vertex_fetch(&mut vertices, &mut lod.indices);
+                    CpuLod {
+                        indices: lod.indices,
+                        vertices,
+                        error: lod.error,
+                    }
+                })
+                .collect();
+        }
+    }
+
+    /// What each image is sampled as, colour winning over data when one
+    /// image fills several slots.
+    pub fn image_usages(&self) -> Vec<TextureUsage> {
+        let mut usages = vec![TextureUsage::Data; self.images.len()];
+        let mut set = |map: u32, usage: TextureUsage| {
+            if let Some(slot) = usages.get_mut(map as usize) {
+                if *slot != TextureUsage::Color {
+                    *slot = usage;
+                }
+            }
+        };
+        for mesh in &self.meshes {
+            set(mesh.material.diffuse_map, TextureUsage::Color);
+            set(mesh.material.normal_map, TextureUsage::Normal);
+            if let Some(sg) = &mesh.material_extensions.specular_glossiness {
+                set(sg.diffuse_map, TextureUsage::Color);
+            }
+        }
+        usages
+    }
+
+    /// Converts every image to the colour space of the slot using it and
+    /// builds its mips, or swaps in a pre-compressed copy, see
+    /// [`prepare_image`].
+    pub fn process_images(&mut self, settings: &TextureSettings) {
+        let usages = self.image_usages();
+        for (image, usage) in self.images.iter_mut().zip(usages) {
+            let source = std::mem::replace(image, CpuImage::rgba8(0, 0, vec![]));
+            *image = prepare_image(source, usage, settings);
+        }
+    }
+
+    /// Node transforms and morph weights as stored in the file.
+    pub fn rest_pose(&self) -> Pose {
+        Pose {
+            transforms: self.nodes.iter().map(|node| node.transform).collect(),
+            weights: self.nodes.iter().map(|node| node.morph_weights.clone()).collect(),
+        }
+    }
+}
+
+pub fn import_gltf<P: AsRef<Path>>(path: P) -> Result<CpuModel, AssetError> {
+    let path = path.as_ref();
+    let (gltf, buffers, images) = gltf::import(path)?;
+    let mut model = build_cpu_model(&gltf, &buffers, images)?;
+
+    // Remember which file each image came from, so compressed copies next
+    // to it can be found.
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    for (image, source) in model.images.iter_mut().zip(gltf.images()) {
+        if let gltf::image::Source::Uri { uri, .. } = source.source() {
+            if !uri.starts_with("data:") {
+                image.source = Some(dir.join(uri));
+            }
+        }
+    }
+    Ok(model)
+}
+
+fn build_cpu_model(
+    gltf: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+    images: Vec<gltf::image::Data>,
+) -> Result<CpuModel, AssetError> {
+    let mut model = CpuModel {
+        meshes: vec![],
+        nodes: gltf.nodes().map(|node| load_node(&node)).collect(),
+        roots: vec![],
+        images: vec![],
+        skins: gltf.skins().map(|skin| load_skin(&skin, buffers)).collect(),
+        animations: vec![],
+    };
+
+    for (i, image) in images.into_iter().enumerate() {
+        model.images.push(convert_image(i, image)?);
+    }
+
+    for node in gltf.nodes() {
+        for child in node.children() {
+            model.nodes[child.index()].parent = Some(node.index());
+        }
+    }
+
+    // A node listed by several scenes is still a single root.
+    let mut visited = vec![false; model.nodes.len()];
+    for node in gltf.scenes().flat_map(|scene| scene.nodes()) {
+        load_hierarchy(&node, &mut model, buffers, Mat4::IDENTITY, &mut visited)?;
+        if !model.roots.contains(&node.index()) {
+            model.roots.push(node.index());
+        }
+    }
+
+    for animation in gltf.animations() {
+        model.animations.push(load_animation(&animation, buffers)?);
+    }
+
+    attach_authored_lods(&mut model);
+
+    Ok(model)
+}
+
+/// Splits `name` into the base node's name and the level of a
+/// `<name>_LOD<n>` node.
+fn lod_suffix(name: &str) -> Option<(&str, usize)> {
+    let (base, level) = name.rsplit_once("_LOD")?;
+    Some((base, level.parse().ok()?))
+}
+
+/// Moves the meshes of `<name>_LOD<n>` nodes, `n` > 0, into the levels of
+/// detail of the matching primitives of node `<name>`, or `<name>_LOD0`.
+/// The level nodes keep their place in the hierarchy but lose their meshes.
+/// Levels whose primitive count differs from the base node are left as they
+/// are.
+fn attach_authored_lods(model: &mut CpuModel) {
+    let mut levels: Vec<(usize, usize, usize)> = vec![];
+    for (node_index, node) in model.nodes.iter().enumerate() {
+        let Some((base_name, level)) = node.name.as_deref().and_then(lod_suffix) else {
+            continue;
+        };
+        let lod0_name = format!("{base_name}_LOD0");
+        let base = model.nodes.iter().position(|n| {
+            !n.meshes.is_empty() && matches!(n.name.as_deref(), Some(name) if name == base_name || name == lod0_name)
+        });
+        let Some(base) = base.filter(|base| level > 0 && *base != node_index) else {
+            continue;
+        };
+        if model.nodes[base].meshes.len() != node.meshes.len() {
+            log::warn!(
+                "Not using {} as a level of detail, it has {} primitives where {} has {}",
+                node.name.as_deref().unwrap_or_default(),
+                node.meshes.len(),
+                base_name,
+                model.nodes[base].meshes.len()
+            );
+            continue;
+        }
+        levels.push((level, base, node_index));
+    }
+    if levels.is_empty() {
+        return;
+    }
+    levels.sort();
+
+    let mut removed = vec![false; model.meshes.len()];
+    for (_, base, node_index) in levels {
+        // Level vertices are brought into the base node's space.
+        let to_base = model.nodes[base].world_transform.inverse() * model.nodes[node_index].world_transform;
+        let normal_matrix = Mat3::from_mat4(to_base).inverse().transpose();
+        let lod_meshes = model.nodes[node_index].meshes.clone();
+        for (base_mesh, lod_mesh) in model.nodes[base].meshes.clone().into_iter().zip(lod_meshes) {
+            let mut vertices = model.meshes[lod_mesh].vertices.clone();
+            for vertex in &mut vertices {
+                vertex.pos = to_base.transform_point3(vertex.pos.truncate()).extend(vertex.pos.w);
+                vertex.normal = (normal_matrix * vertex.normal.truncate()).normalize_or_zero().extend(0.0);
+                vertex.tangent = to_base
+                    .transform_vector3(vertex.tangent.truncate())
+                    .normalize_or_zero()
+                    .extend(vertex.tangent.w);
+            }
+            let indices = model.meshes[lod_mesh].indices.clone();
+            let full = &model.meshes[base_mesh];
+            let error = surface_deviation(&full.vertices, &full.indices, &vertices, &indices);
+            // A level is never better than the one before it.
+            let error = full.lods.last().map_or(error, |previous| error.max(previous.error));
+            model.meshes[base_mesh].lods.push(CpuLod {
+                indices,
+                vertices,
+                error,
+            });
+            removed[lod_mesh] = true;
+        }
+        model.nodes[node_index].meshes.clear();
+    }
+
+    let new_index: Vec<usize> = removed
+        .iter()
+        .scan(0, |kept, removed| {
+            let index = *kept;
+            *kept += !removed as usize;
+            Some(index)
+        })
+        .collect();
+    let meshes = std::mem::take(&mut model.meshes);
+    model.meshes = meshes.into_iter().zip(&removed).filter(|(_, removed)| !**removed).map(|(mesh, _)| mesh).collect();
+    for node in &mut model.nodes {
+        for mesh in &mut node.meshes {
+            *mesh = new_index[*mesh];
+        }
+    }
+}
+
+fn load_node(node: &gltf::Node) -> CpuNode {
+    let (translation, rotation, scale) = node.transform().decomposed();
+    let local_transform = Mat4::from_cols_array_2d(&node.transform().matrix());
+    let morph_weights = node
+        .weights()
+        .or_else(|| node.mesh().and_then(|mesh| mesh.weights()))
+        .map(<[f32]>::to_vec)
+        .unwrap_or_default();
+
+    CpuNode {
+        name: node.name().map(String::from),
+        parent: None,
+        children: node.children().map(|child| child.index()).collect(),
+        transform: NodeTransform {
+            translation: Vec3::from(translation),
+            rotation: Quat::from_array(rotation),
+            scale: Vec3::from(scale),
+        },
+        local_transform,
+        world_transform: local_transform,
+        meshes: vec![],
+        skin: node.skin().map(|skin| skin.index()),
+        camera: node.camera().map(|camera| match camera.projection() {
+            gltf::camera::Projection::Perspective(p) => CpuCamera::Perspective {
+                yfov: p.yfov(),
+                aspect_ratio: p.aspect_ratio(),
+                znear: p.znear(),
+                zfar: p.zfar(),
+            },
+            gltf::camera::Projection::Orthographic(o) => CpuCamera::Orthographic {
+                xmag: o.xmag(),
+                ymag: o.ymag(),
+                znear: o.znear(),
+                zfar: o.zfar(),
+            },
+        }),
+        light: node.light().map(|light| CpuLight {
+            kind: match light.kind() {
+                gltf::khr_lights_punctual::Kind::Directional => LightKind::Directional,
+                gltf::khr_lights_punctual::Kind::Point => LightKind::Point,
+                gltf::khr_lights_punctual::Kind::Spot {
+                    inner_cone_angle,
+                    outer_cone_angle,
+                } => LightKind::Spot {
+                    inner_cone_angle,
+                    outer_cone_angle,
+                },
+            },
+            color: Vec3::from(light.color()),
+            intensity: light.intensity(),
+            range: light.range(),
+        }),
+        morph_weights,
+    }
+}
+
+fn load_hierarchy(
     node: &gltf::Node,
-    model: &mut Model,
+    model: &mut CpuModel,
     buffers: &[gltf::buffer::Data],
     parent_transform: Mat4,
-    path: &str,
-) {
-    let node_transform =
-        parent_transform * glam::Mat4::from_cols_array_2d(&node.transform().matrix());
+    visited: &mut [bool],
+) -> Result<(), AssetError> {
+    let node_index = node.index();
+    if std::mem::replace(&mut visited[node_index], true) {
+        return Ok(());
+    }
+    let world_transform = parent_transform * model.nodes[node_index].local_transform;
+    model.nodes[node_index].world_transform = world_transform;
 
     for child in node.children() {
-        load_node(device.clone(), &child, model, buffers, node_transform, path);
+        load_hierarchy(&child, model, buffers, world_transform, visited)?;
     }
 
     if let Some(mesh) = node.mesh() {
-        let primitives = mesh.primitives();
-
-        for primitive in primitives {
-            let reader = primitive.reader(|i| Some(&buffers[i.index()]));
-
-            let indices: Vec<_> = reader.read_indices().unwrap().into_u32().collect();
-            let positions: Vec<_> = reader.read_positions().unwrap().map(Vec3::from).collect();
-            let normals: Vec<_> = reader.read_normals().unwrap().map(Vec3::from).collect();
-            let tex_coords = if let Some(tex_coords) = reader.read_tex_coords(0) {
-                tex_coords.into_f32().map(Vec2::from).collect()
-            } else {
-                vec![Vec2::new(0.0, 0.0); positions.len()]
-            };
+        for primitive in mesh.primitives() {
+            let cpu_mesh = load_primitive(&mesh, &primitive, buffers, node_index)?;
+            if let Some(skin) = node.skin() {
+                check_skin(&cpu_mesh, &model.skins[skin.index()], &mesh, &primitive)?;
+            }
+            model.nodes[node_index].meshes.push(model.meshes.len());
+            model.meshes.push(cpu_mesh);
+        }
+    }
 
-            let tangents = if let Some(tangents) = reader.read_tangents() {
-                tangents.map(Vec4::from).collect()
-            } else {
-                vec![Vec4::new(0.0, 0.0, 0.0, 0.0); positions.len()]
-            };
+    Ok(())
+}
 
-            let colors: Vec<_> = if let Some(colors) = reader.read_colors(0) {
-                colors.into_rgba_f32().map(Vec4::from).collect()
-            } else {
-                vec![Vec4::new(1.0, 1.0, 1.0, 1.0); positions.len()]
-            };
+/// Checks a skinned primitive against the skin of its node, as skinning
+/// silently skips joints and matrices it can't find.
+fn check_skin(
+    cpu_mesh: &CpuMesh,
+    skin: &CpuSkin,
+    mesh: &gltf::Mesh,
+    primitive: &gltf::Primitive,
+) -> Result<(), AssetError> {
+    if skin.inverse_bind_matrices.len() != skin.joints.len() {
+        return Err(AssetError::AttributeCountMismatch {
+            mesh: mesh.index(),
+            primitive: primitive.index(),
+            attribute: "inverseBindMatrices",
+            count: skin.inverse_bind_matrices.len(),
+            expected: skin.joints.len(),
+        });
+    }
+    if let Some(&joint) = cpu_mesh.joints.iter().flatten().find(|&&joint| joint as usize >= skin.joints.len()) {
+        return Err(AssetError::IndexOutOfRange {
+            mesh: mesh.index(),
+            primitive: primitive.index(),
+            attribute: "JOINTS_0",
+            index: joint.into(),
+            count: skin.joints.len(),
+        });
+    }
+    Ok(())
+}
+
+fn load_skin(skin: &gltf::Skin, buffers: &[gltf::buffer::Data]) -> CpuSkin {
+    let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+    let inverse_bind_matrices = skin
+        .reader(|buffer| Some(&buffers[buffer.index()]))
+        .read_inverse_bind_matrices()
+        .map(|matrices| matrices.map(|m| Mat4::from_cols_array_2d(&m)).collect())
+        .unwrap_or_else(|| vec![Mat4::IDENTITY; joints.len()]);
+
+    CpuSkin {
+        name: skin.name().map(String::from),
+        joints,
+        inverse_bind_matrices,
+        skeleton: skin.skeleton().map(|node| node.index()),
+    }
+}
+
+fn load_animation(
+    animation: &gltf::Animation,
+    buffers: &[gltf::buffer::Data],
+) -> Result<AnimationClip, AssetError> {
+    use gltf::animation::util::ReadOutputs;
 
-            let mut vertices: Vec<Vertex> = vec![];
+    let mut clip = AnimationClip {
+        name: animation.name().map(String::from),
+        channels: vec![],
+        duration: 0.0,
+    };
 
-            for (i, _) in positions.iter().enumerate() {
-                vertices.push(Vertex {
-                    pos: positions[i].extend(0.0),
-                    normal: normals[i].extend(0.0),
-                    uv: tex_coords[i],
-                    tangent: tangents[i],
-                    color: colors[i],
-                });
+    for (channel_index, channel) in animation.channels().enumerate() {
+        let invalid = || AssetError::InvalidAnimation {
+            animation: animation.index(),
+            channel: channel_index,
+        };
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+        let times: Vec<f32> = reader.read_inputs().ok_or_else(invalid)?.collect();
+        let keyframes = match reader.read_outputs().ok_or_else(invalid)? {
+            ReadOutputs::Translations(values) => {
+                Keyframes::Translation(values.map(Vec3::from).collect())
+            }
+            ReadOutputs::Rotations(values) => {
+                Keyframes::Rotation(values.into_f32().map(Quat::from_array).collect())
             }
+            ReadOutputs::Scales(values) => Keyframes::Scale(values.map(Vec3::from).collect()),
+            ReadOutputs::MorphTargetWeights(values) => {
+                Keyframes::MorphWeights(values.into_f32().collect())
+            }
+        };
+        let interpolation = Interpolation::from(channel.sampler().interpolation());
 
-            let material = primitive.material();
-            let pbr = material.pbr_metallic_roughness();
-
-            let diffuse_index = pbr
-                .base_color_texture()
-                .map_or(DEFAULT_TEXTURE_MAP, |texture| {
-                    texture.texture().index() as u32
-                });
-
-            let normal_index = material
-                .normal_texture()
-                .map_or(DEFAULT_TEXTURE_MAP, |texture| {
-                    texture.texture().index() as u32
-                });
-
-            let metallic_roughness_index = pbr
-                .metallic_roughness_texture()
-                .map_or(DEFAULT_TEXTURE_MAP, |texture| {
-                    texture.texture().index() as u32
-                });
-
-            let occlusion_index = material
-                .occlusion_texture()
-                .map_or(DEFAULT_TEXTURE_MAP, |texture| {
-                    texture.texture().index() as u32
-                });
-
-            let base_color_factor = pbr.base_color_factor();
-            let metallic_factor = pbr.metallic_factor();
-            let roughness_factor = pbr.roughness_factor();
-
-            model.meshes.push(Mesh {
-                primitive: Primitive::new(device.clone(), indices, vertices),
-                material: Material {
-                    diffuse_map: diffuse_index,
-                    normal_map: normal_index,
-                    metallic_roughness_map: metallic_roughness_index,
-                    occlusion_map: occlusion_index,
-                    base_color_factor: Vec4::from(base_color_factor),
-                    metallic_factor,
-                    roughness_factor,
-                },
-                gpu_mesh: 0,
-            });
+        let outputs = match keyframes {
+            Keyframes::Translation(ref v) | Keyframes::Scale(ref v) => v.len(),
+            Keyframes::Rotation(ref v) => v.len(),
+            Keyframes::MorphWeights(ref v) => v.len(),
+        };
+        let per_time = match interpolation {
+            Interpolation::CubicSpline => times.len() * 3,
+            _ => times.len(),
+        };
+        let valid_count = match keyframes {
+            Keyframes::MorphWeights(_) => per_time > 0 && outputs % per_time == 0,
+            _ => outputs == per_time,
+        };
+        if times.is_empty() || !valid_count || times.windows(2).any(|t| t[0] > t[1]) {
+            return Err(invalid());
+        }
+
+        clip.duration = clip.duration.max(*times.last().unwrap());
+        clip.channels.push(AnimationChannel {
+            node: channel.target().node().index(),
+            interpolation,
+            times,
+            keyframes,
+        });
+    }
+
+    Ok(clip)
+}
+
+fn load_primitive(
+    mesh: &gltf::Mesh,
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    node: usize,
+) -> Result<CpuMesh, AssetError> {
+    let reader = primitive.reader(|i| Some(&buffers[i.index()]));
+    let missing = |attribute| AssetError::MissingAttribute {
+        mesh: mesh.index(),
+        primitive: primitive.index(),
+        attribute,
+    };
 
-            model
-                .meshes
-                .last_mut()
-                .unwrap()
-                .primitive
-                .vertex_buffer
-                .set_debug_name(
-                    format!("vertex_buffer {} {}", path, Uuid::new_v4().urn()).as_str(),
-                );
-            model
-                .meshes
-                .last_mut()
-                .unwrap()
-                .primitive
-                .index_buffer
-                .set_debug_name(format!("index_buffer {} {}", path, Uuid::new_v4().urn()).as_str());
+    let positions: Vec<_> = reader
+        .read_positions()
+        .ok_or_else(|| missing("POSITION"))?
+        .map(Vec3::from)
+        .collect();
+    let normals: Option<Vec<Vec3>> = reader.read_normals().map(|n| n.map(Vec3::from).collect());
+    let mut indices: Vec<_> = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    if let Some(&index) = indices.iter().find(|&&i| i as usize >= positions.len()) {
+        return Err(AssetError::IndexOutOfRange {
+            mesh: mesh.index(),
+            primitive: primitive.index(),
+            attribute: "indices",
+            index,
+            count: positions.len(),
+        });
+    }
 
-            model.transforms.push(node_transform);
+    let tex_coords: Option<Vec<Vec2>> = reader
+        .read_tex_coords(0)
+        .map(|tex_coords| tex_coords.into_f32().map(Vec2::from).collect());
+    let tangents: Option<Vec<Vec4>> = reader.read_tangents().map(|t| t.map(Vec4::from).collect());
+
+    let colors: Vec<_> = if let Some(colors) = reader.read_colors(0) {
+        colors.into_rgba_f32().map(Vec4::from).collect()
+    } else {
+        vec![Vec4::new(1.0, 1.0, 1.0, 1.0); positions.len()]
+    };
+
+    let mut vertices: Vec<Vertex> = vec![];
+
+    for (i, _) in positions.iter().enumerate() {
+        vertices.push(Vertex {
+            pos: positions[i].extend(0.0),
+            normal: normals.as_ref().and_then(|n| n.get(i)).copied().unwrap_or(Vec3::ZERO).extend(0.0),
+            uv: tex_coords.as_ref().and_then(|t| t.get(i)).copied().unwrap_or(Vec2::ZERO),
+            tangent: tangents.as_ref().and_then(|t| t.get(i)).copied().unwrap_or(Vec4::ZERO),
+            color: colors.get(i).copied().unwrap_or(Vec4::ONE),
+        });
+    }
+
+    let mut joints: Vec<[u16; 4]> = reader
+        .read_joints(0)
+        .map(|joints| joints.into_u16().collect())
+        .unwrap_or_default();
+    let mut joint_weights: Vec<Vec4> = reader
+        .read_weights(0)
+        .map(|weights| weights.into_f32().map(Vec4::from).collect())
+        .unwrap_or_default();
+    let mut morph_targets: Vec<MorphTarget> = reader
+        .read_morph_targets()
+        .map(|(positions, normals, tangents)| MorphTarget {
+            positions: positions.map_or(vec![], |p| p.map(Vec3::from).collect()),
+            normals: normals.map_or(vec![], |n| n.map(Vec3::from).collect()),
+            tangents: tangents.map_or(vec![], |t| t.map(Vec3::from).collect()),
+        })
+        .collect();
+
+    // Streams carried along through the vertex remaps must match POSITION.
+    let deform_streams = [("JOINTS_0", joints.len()), ("WEIGHTS_0", joint_weights.len())]
+        .into_iter()
+        .chain(morph_targets.iter().flat_map(|target| {
+            [
+                ("morph target POSITION", target.positions.len()),
+                ("morph target NORMAL", target.normals.len()),
+                ("morph target TANGENT", target.tangents.len()),
+            ]
+        }));
+    for (attribute, count) in deform_streams {
+        if count != 0 && count != positions.len() {
+            return Err(AssetError::AttributeCountMismatch {
+                mesh: mesh.index(),
+                primitive: primitive.index(),
+                attribute,
+                count,
+                expected: positions.len(),
+            });
+        }
+    }
+
+    // glTF asks for flat normals when they are missing, and for MikkTSpace
+    // tangents when a normal map needs them.
+    let material = load_material(&primitive.material());
+    let mut remaps = vec![];
+    if normals.is_none() {
+        remaps.push(compute_flat_normals(&mut vertices, &mut indices));
+    }
+    if tangents.is_none() && tex_coords.is_some() && material.normal_map != DEFAULT_TEXTURE_MAP {
+        remaps.extend(generate_tangents(&mut vertices, &mut indices));
+    }
+    for remap in remaps {
+        let remap_stream = |stream: &mut Vec<Vec3>| {
+            if !stream.is_empty() {
+                *stream = remap_vertices(stream, &remap);
+            }
+        };
+        for target in morph_targets.iter_mut() {
+            remap_stream(&mut target.positions);
+            remap_stream(&mut target.normals);
+            remap_stream(&mut target.tangents);
+        }
+        if !joints.is_empty() {
+            joints = remap_vertices(&joints, &remap);
+        }
+        if !joint_weights.is_empty() {
+            joint_weights = remap_vertices(&joint_weights, &remap);
         }
     }
+
+    Ok(CpuMesh {
+        indices,
+        vertices,
+        material,
+        material_extensions: load_material_extensions(&primitive.material()),
+        joints,
+        joint_weights,
+        morph_targets,
+        node,
+        lods: vec![],
+    })
+}
+
+fn load_material(material: &gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    // Models keep one texture per glTF image, so slots point at the image.
+    let image_index = |texture: gltf::Texture| texture.source().index() as u32;
+
+    Material {
+        diffuse_map: pbr
+            .base_color_texture()
+            .map_or(DEFAULT_TEXTURE_MAP, |info| image_index(info.texture())),
+        normal_map: material
+            .normal_texture()
+            .map_or(DEFAULT_TEXTURE_MAP, |info| image_index(info.texture())),
+        metallic_roughness_map: pbr
+            .metallic_roughness_texture()
+            .map_or(DEFAULT_TEXTURE_MAP, |info| image_index(info.texture())),
+        occlusion_map: material
+            .occlusion_texture()
+            .map_or(DEFAULT_TEXTURE_MAP, |info| image_index(info.texture())),
+        base_color_factor: Vec4::from(pbr.base_color_factor()),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+    }
+}
+
+fn load_texture_transform(info: &gltf::texture::Info) -> Option<TextureTransform> {
+    info.texture_transform().map(|transform| TextureTransform {
+        offset: Vec2::from(transform.offset()),
+        rotation: transform.rotation(),
+        scale: Vec2::from(transform.scale()),
+        tex_coord: transform.tex_coord(),
+    })
 }
 
-pub fn load_gltf(device: Arc<Device>, path: &str) -> Model {
-    let (gltf, buffers, mut images) = match gltf::import(path) {
-        Ok(result) => result,
-        Err(err) => panic!("Loading model {} failed with error: {}", path, err),
+fn load_material_extensions(material: &gltf::Material) -> MaterialExtensions {
+    let pbr = material.pbr_metallic_roughness();
+    let image_index = |info: gltf::texture::Info| info.texture().source().index() as u32;
+    let transmission = material.transmission();
+
+    MaterialExtensions {
+        unlit: material.unlit(),
+        ior: material.ior(),
+        transmission_factor: transmission.as_ref().map(|t| t.transmission_factor()),
+        transmission_map: transmission
+            .and_then(|t| t.transmission_texture())
+            .map(image_index),
+        specular_glossiness: material.pbr_specular_glossiness().map(|sg| SpecularGlossiness {
+            diffuse_factor: Vec4::from(sg.diffuse_factor()),
+            specular_factor: Vec3::from(sg.specular_factor()),
+            glossiness_factor: sg.glossiness_factor(),
+            diffuse_map: sg.diffuse_texture().map_or(DEFAULT_TEXTURE_MAP, image_index),
+            specular_glossiness_map: sg
+                .specular_glossiness_texture()
+                .map_or(DEFAULT_TEXTURE_MAP, image_index),
+        }),
+        diffuse_transform: pbr
+            .base_color_texture()
+            .and_then(|info| load_texture_transform(&info)),
+        metallic_roughness_transform: pbr
+            .metallic_roughness_texture()
+            .and_then(|info| load_texture_transform(&info)),
+    }
+}
+
+fn convert_image(index: usize, image: gltf::image::Data) -> Result<CpuImage, AssetError> {
+    use gltf::image::Format;
+
+    // (channels, bytes per channel, swap red and blue)
+    let (channels, channel_size, swizzle) = match image.format {
+        Format::R8 => (1, 1, false),
+        Format::R8G8 => (2, 1, false),
+        Format::R8G8B8 => (3, 1, false),
+        Format::R8G8B8A8 => (4, 1, false),
+        Format::B8G8R8 => (3, 1, true),
+        Format::B8G8R8A8 => (4, 1, true),
+        Format::R16 => (1, 2, false),
+        Format::R16G16 => (2, 2, false),
+        Format::R16G16B16 => (3, 2, false),
+        Format::R16G16B16A16 => (4, 2, false),
     };
+    let texel_count = image.width as usize * image.height as usize;
+    if image.pixels.len() != texel_count * channels * channel_size {
+        return Err(AssetError::InvalidImage { image: index });
+    }
 
+    if channel_size == 2 {
+        // Keep the precision of 16 bit images.
+        let mut pixels = Vec::with_capacity(texel_count * 8);
+        for texel in image.pixels.chunks_exact(channels * 2) {
+            // 16 bit channels are little endian.
+            let channel = |c: usize| u16::from_le_bytes([texel[c * 2], texel[c * 2 + 1]]);
+            let rgba = match channels {
+                1 => [channel(0), channel(0), channel(0), u16::MAX],
+                2 => [channel(0), channel(1), 0, u16::MAX],
+                3 => [channel(0), channel(1), channel(2), u16::MAX],
+                _ => [channel(0), channel(1), channel(2), channel(3)],
+            };
+            pixels.extend(rgba.iter().flat_map(|c| c.to_ne_bytes()));
+        }
+        return Ok(CpuImage {
+            format: TextureFormat::Rgba16,
+            ..CpuImage::rgba8(image.width, image.height, pixels)
+        });
+    }
+
+    let pixels = if channels == 4 && !swizzle {
+        image.pixels
+    } else {
+        let mut pixels = Vec::with_capacity(texel_count * 4);
+        for texel in image.pixels.chunks_exact(channels) {
+            let mut rgba = match channels {
+                1 => [texel[0], texel[0], texel[0], 255],
+                2 => [texel[0], texel[1], 0, 255],
+                3 => [texel[0], texel[1], texel[2], 255],
+                _ => [texel[0], texel[1], texel[2], texel[3]],
+            };
+            if swizzle {
+                rgba.swap(0, 2);
+            }
+            pixels.extend_from_slice(&rgba);
+        }
+        pixels
+    };
+
+    Ok(CpuImage::rgba8(image.width, image.height, pixels))
+}
+
+/// Uploads an imported model's buffers and images.
+pub fn upload_model(device: Arc<Device>, mut cpu_model: CpuModel, path: &str) -> Model {
+    let images = std::mem::take(&mut cpu_model.images);
+    let cpu_meshes = std::mem::take(&mut cpu_model.meshes);
     let mut model = Model {
         meshes: vec![],
         transforms: vec![],
         textures: vec![],
+        scene: cpu_model,
     };
+    let cpu_model = &model.scene;
 
-    for image in &mut images {
-        // Convert images from rgb8 to rgba8
-        if image.format == gltf::image::Format::R8G8B8 {
-            let dynamic_image = image::DynamicImage::ImageRgb8(
-                image::RgbImage::from_raw(
-                    image.width,
-                    image.height,
-                    std::mem::take(&mut image.pixels),
-                )
-                .unwrap(),
-            );
+    for image in &images {
+        model.textures.push(Texture::from_cpu_image(device.clone(), image, path));
+    }
 
-            let rgba8_image = dynamic_image.to_rgba();
-            image.format = gltf::image::Format::R8G8B8A8;
-            image.pixels = rgba8_image.into_raw();
-        }
+    for cpu_mesh in cpu_meshes {
+        let transform = cpu_model.nodes[cpu_mesh.node].world_transform;
+        let skinned = cpu_model.nodes[cpu_mesh.node].skin.is_some() && !cpu_mesh.joints.is_empty();
+        let deform = (skinned || !cpu_mesh.morph_targets.is_empty()).then_some(MeshDeform {
+            joints: cpu_mesh.joints,
+            joint_weights: cpu_mesh.joint_weights,
+            morph_targets: cpu_mesh.morph_targets,
+        });
+        // Deformation only covers the full mesh, so deformed meshes don't
+        // switch levels.
+        let cpu_lods = match deform {
+            Some(_) => vec![],
+            None => cpu_mesh.lods,
+        };
+        let lods = cpu_lods
+            .iter()
+            .map(|lod| LodLevel {
+                primitive: Primitive::new(device.clone(), lod.indices.clone(), lod.vertices.clone()),
+                gpu_mesh: 0,
+            })
+            .collect();
+        let lod_errors = std::iter::once(0.0).chain(cpu_lods.iter().map(|lod| lod.error)).collect();
+        let bounds = BoundingSphere::from_points(cpu_mesh.vertices.iter().map(|v| v.pos.truncate()));
+        let aabb = Aabb::from_points(cpu_mesh.vertices.iter().map(|v| v.pos.truncate()));
+        let mut mesh = Mesh {
+            primitive: Primitive::new(device.clone(), cpu_mesh.indices, cpu_mesh.vertices),
+            material: cpu_mesh.material,
+            gpu_mesh: 0,
+            node: cpu_mesh.node,
+            deform,
+            lods,
+            lod_errors,
+            bounds,
+            aabb,
+        };
 
-        if image.format != gltf::image::Format::R8G8B8A8 {
-        //    panic!("Unsupported image format!");
+        mesh.primitive.vertex_buffer.set_debug_name(
+            format!("vertex_buffer {} {}", path, Uuid::new_v4().urn()).as_str(),
+        );
+        mesh.primitive
+            .index_buffer
+            .set_debug_name(format!("index_buffer {} {}", path, Uuid::new_v4().urn()).as_str());
+        for (level, lod) in mesh.lods.iter_mut().enumerate() {
+            let name = format!("{} lod{} {}", path, level + 1, Uuid::new_v4().urn());
+            lod.primitive.vertex_buffer.set_debug_name(&format!("vertex_buffer {name}"));
+            lod.primitive.index_buffer.set_debug_name(&format!("index_buffer {name}"));
         }
 
-        let texture = Texture::create(
-            device.clone(),
-            Some(&image.pixels),
-            ImageDesc::new_2d(image.width, image.height, vk::Format::R8G8B8A8_UNORM),
-            path,
+        model.meshes.push(mesh);
+        model.transforms.push(transform);
+    }
+
+    model
+}
+
+/// Imports and uploads the glTF at `path`, generating levels of detail only
+/// when `settings` asks for them.
+pub fn load_gltf(device: Arc<Device>, path: &str, settings: &ModelImport) -> Result<Model, AssetError> {
+    let mut cpu_model = import_gltf(path)?;
+    if let Some(lods) = &settings.lods {
+        cpu_model.generate_lods(lods);
+    }
+    cpu_model.process_images(&TextureSettings {
+        bc_supported: device.features.texture_compression_bc == vk::TRUE,
+        ..settings.textures
+    });
+    Ok(upload_model(device, cpu_model, path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::model_path;
+
+    #[test]
+    fn roots_shared_by_scenes_are_imported_once() {
+        let model = import_gltf(model_path("shared_root.gltf")).unwrap();
+        assert_eq!(model.roots, vec![0]);
+        assert_eq!(model.meshes.len(), 1);
+    }
+
+    #[test]
+    fn imports_hierarchy_and_materials() {
+        let model = import_gltf(model_path("triangle.gltf")).unwrap();
+
+        assert_eq!(model.roots, vec![0]);
+        assert_eq!(model.nodes.len(), 2);
+        assert_eq!(model.nodes[0].name.as_deref(), Some("root"));
+        assert_eq!(model.nodes[0].children, vec![1]);
+        assert_eq!(model.nodes[1].parent, Some(0));
+        assert_eq!(model.nodes[1].meshes, vec![0]);
+        assert_eq!(
+            model.transform(0),
+            Mat4::from_translation(Vec3::new(1.0, 2.0, 0.0))
         );
 
-        model.textures.push(texture);
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.indices, vec![0, 1, 2]);
+        assert_eq!(mesh.vertices.len(), 3);
+        assert_eq!(mesh.vertices[1].pos, Vec4::new(1.0, 0.0, 0.0, 0.0));
+        assert_eq!(mesh.vertices[2].uv, Vec2::new(0.0, 1.0));
+        assert_eq!(mesh.vertices[0].normal, Vec4::new(0.0, 0.0, 1.0, 0.0));
+        assert_eq!(mesh.vertices[0].color, Vec4::ONE);
+
+        // Texture 1 samples image 0 and texture 0 samples image 1.
+        assert_eq!(mesh.material.diffuse_map, 0);
+        assert_eq!(mesh.material.metallic_roughness_map, 1);
+        assert_eq!(mesh.material.normal_map, DEFAULT_TEXTURE_MAP);
+        assert_eq!(mesh.material.base_color_factor, Vec4::new(1.0, 0.5, 0.25, 1.0));
+        assert_eq!(mesh.material.metallic_factor, 0.2);
+        assert_eq!(mesh.material.roughness_factor, 0.7);
     }
 
-    for scene in gltf.scenes() {
-        for node in scene.nodes() {
-            load_node(
-                device.clone(),
-                &node,
-                &mut model,
-                &buffers,
-                Mat4::IDENTITY,
-                path,
-            );
+    #[test]
+    fn images_are_expanded_to_rgba8() {
+        let model = import_gltf(model_path("triangle.gltf")).unwrap();
+
+        let rgb = &model.images[0];
+        assert_eq!((rgb.width, rgb.height), (2, 2));
+        assert_eq!(
+            rgb.pixels,
+            vec![255, 0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255, 255, 255, 255]
+        );
+        let grey = &model.images[1];
+        assert_eq!(grey.pixels, vec![128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn images_take_the_colour_space_of_their_slot() {
+        use crate::texture_processing::ColorSpace;
+
+        let mut model = import_gltf(model_path("triangle.gltf")).unwrap();
+        assert_eq!(model.image_usages(), vec![TextureUsage::Color, TextureUsage::Data]);
+        model.process_images(&TextureSettings::default());
+
+        let diffuse = &model.images[0];
+        assert_eq!((diffuse.color_space, diffuse.mip_levels), (ColorSpace::Srgb, 2));
+        assert_eq!(diffuse.vk_format(), vk::Format::R8G8B8A8_SRGB);
+        let metallic_roughness = &model.images[1];
+        assert_eq!(metallic_roughness.color_space, ColorSpace::Linear);
+        assert_eq!(metallic_roughness.vk_format(), vk::Format::R8G8B8A8_UNORM);
+    }
+
+    #[test]
+    fn unindexed_primitives_get_sequential_indices() {
+        let model = import_gltf(model_path("unindexed.gltf")).unwrap();
+        assert_eq!(model.meshes[0].indices, vec![0, 1, 2]);
+        assert_eq!(model.transform(0), Mat4::IDENTITY);
+        assert_eq!(model.meshes[0].material.diffuse_map, DEFAULT_TEXTURE_MAP);
+    }
+
+    #[test]
+    fn imports_skins_animations_and_extensions() {
+        use crate::animation::Keyframes;
+
+        let model = import_gltf(model_path("skinned.gltf")).unwrap();
+
+        assert_eq!(model.nodes.len(), 5);
+        assert_eq!(model.nodes[2].parent, Some(1));
+        assert_eq!(model.nodes[2].transform.translation, Vec3::Y);
+        assert_eq!(model.nodes[0].skin, Some(0));
+        assert_eq!(model.nodes[0].morph_weights, vec![0.5]);
+
+        let skin = &model.skins[0];
+        assert_eq!(skin.name.as_deref(), Some("leg"));
+        assert_eq!(skin.joints, vec![1, 2]);
+        assert_eq!(skin.skeleton, Some(1));
+        assert_eq!(skin.inverse_bind_matrices[1], Mat4::from_translation(-Vec3::Y));
+
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.joints[0], [0, 1, 0, 0]);
+        assert_eq!(mesh.joint_weights[0], Vec4::new(0.5, 0.5, 0.0, 0.0));
+        assert_eq!(mesh.morph_targets.len(), 1);
+        assert_eq!(mesh.morph_targets[0].positions, vec![Vec3::Z; 3]);
+        assert!(mesh.morph_targets[0].normals.is_empty());
+
+        let extensions = mesh.material_extensions;
+        assert!(extensions.unlit);
+        assert_eq!(extensions.ior, Some(1.4));
+        assert_eq!(extensions.transmission_factor, Some(0.3));
+        let transform = extensions.diffuse_transform.unwrap();
+        assert_eq!(transform.offset, Vec2::new(0.5, 0.0));
+        assert_eq!(transform.scale, Vec2::splat(2.0));
+        assert_eq!(transform.rotation, 0.25);
+
+        assert!(matches!(
+            model.nodes[3].camera,
+            Some(CpuCamera::Perspective { zfar: Some(z), .. }) if z == 100.0
+        ));
+        let light = model.nodes[4].light.unwrap();
+        assert_eq!(
+            light.kind,
+            LightKind::Spot {
+                inner_cone_angle: 0.2,
+                outer_cone_angle: 0.6
+            }
+        );
+        assert_eq!(light.range, Some(10.0));
+
+        let clip = &model.animations[0];
+        assert_eq!(clip.name.as_deref(), Some("wave"));
+        assert_eq!(clip.duration, 2.0);
+        assert_eq!(clip.channels.len(), 3);
+        assert_eq!(clip.channels[0].node, 1);
+        assert_eq!(clip.channels[1].interpolation, Interpolation::Step);
+        assert!(matches!(clip.channels[2].keyframes, Keyframes::MorphWeights(ref w) if w.len() == 6));
+
+        let mut transform = NodeTransform::default();
+        let mut weights = [0.0];
+        for channel in &clip.channels {
+            channel.sample(0.5, &mut transform, &mut weights);
         }
+        assert_eq!(transform.translation, Vec3::Y);
+        assert_eq!(transform.rotation, Quat::IDENTITY);
+        assert_eq!(weights, [0.5]);
     }
 
-    model
+    #[test]
+    fn malformed_files_are_errors() {
+        assert!(matches!(
+            import_gltf(model_path("truncated.gltf")),
+            Err(AssetError::Import(_))
+        ));
+        assert!(matches!(
+            import_gltf(model_path("missing_buffer.gltf")),
+            Err(AssetError::Import(_))
+        ));
+        assert!(matches!(
+            import_gltf(model_path("does_not_exist.gltf")),
+            Err(AssetError::Import(_))
+        ));
+        assert!(matches!(
+            import_gltf(model_path("bad_indices.gltf")),
+            Err(AssetError::IndexOutOfRange {
+                attribute: "indices",
+                index: 7,
+                count: 3,
+                ..
+            })
+        ));
+        assert!(matches!(
+            import_gltf(model_path("short_weights.gltf")),
+            Err(AssetError::AttributeCountMismatch {
+                attribute: "WEIGHTS_0",
+                count: 2,
+                expected: 3,
+                ..
+            })
+        ));
+        assert!(matches!(
+            import_gltf(model_path("short_inverse_binds.gltf")),
+            Err(AssetError::AttributeCountMismatch {
+                attribute: "inverseBindMatrices",
+                count: 1,
+                expected: 2,
+                ..
+            })
+        ));
+        assert!(matches!(
+            import_gltf(model_path("bad_joints.gltf")),
+            Err(AssetError::IndexOutOfRange {
+                attribute: "JOINTS_0",
+                index: 1,
+                count: 1,
+                ..
+            })
+        ));
+        assert!(matches!(
+            import_gltf(model_path("bad_animation.gltf")),
+            Err(AssetError::InvalidAnimation {
+                animation: 0,
+                channel: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn lod_nodes_become_levels_of_their_base_mesh() {
+        let model = import_gltf(model_path("lods.gltf")).unwrap();
+
+        assert_eq!(model.meshes.len(), 1);
+        assert!(model.nodes[0].meshes.is_empty());
+        assert_eq!(model.nodes[1].meshes, vec![0]);
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.vertices.len(), 9);
+        assert_eq!(mesh.lods.len(), 1);
+        assert_eq!(mesh.lods[0].indices.len(), 6);
+        // The raised centre of the full mesh is what the level flattens.
+        assert!((mesh.lods[0].error - 0.25).abs() < 1e-6, "{}", mesh.lods[0].error);
+
+        // A level with a primitive more than its base is not attached.
+        let model = import_gltf(model_path("lod_extra_primitive.gltf")).unwrap();
+        assert_eq!(model.meshes.len(), 3);
+        assert_eq!(model.nodes[0].meshes.len(), 2);
+        assert!(model.meshes.iter().all(|mesh| mesh.lods.is_empty()));
+
+        assert_eq!(lod_suffix("rock_LOD12"), Some(("rock", 12)));
+        assert_eq!(lod_suffix("rock_LODs"), None);
+    }
+
+    #[test]
+    fn generated_lods_simplify_static_meshes_only() {
+        let settings = LodGeneration {
+            max_relative_error: 1.0,
+            ..Default::default()
+        };
+        let mut model = import_gltf(model_path("lods.gltf")).unwrap();
+        model.generate_lods(&settings);
+        assert_eq!(model.meshes[0].lods.len(), 1, "authored levels are kept");
+
+        model.meshes[0].lods.clear();
+        model.generate_lods(&settings);
+        let lods = &model.meshes[0].lods;
+        assert!(!lods.is_empty());
+        assert!(lods[0].indices.len() < model.meshes[0].indices.len());
+        assert!(lods[0].error > 0.0);
+        // Unused vertices are dropped from every level.
+        assert!(lods[0].vertices.len() < model.meshes[0].vertices.len());
+
+        let mut skinned = import_gltf(model_path("skinned.gltf")).unwrap();
+        skinned.generate_lods(&settings);
+        assert!(skinned.meshes.iter().all(|mesh| mesh.lods.is_empty()));
+    }
+
+    #[test]
+    fn missing_normals_are_generated_flat() {
+        let model = import_gltf(model_path("missing_normals.gltf")).unwrap();
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.vertices.len(), 3);
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.normal, Vec4::new(0.0, 0.0, 1.0, 0.0));
+        }
+    }
 }
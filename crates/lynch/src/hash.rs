@@ -0,0 +1,55 @@
+//! Hashing of file contents and settings for the caches kept on disk.
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+/// 64 bit FNV-1a, stable across platforms and compiler versions.
+#[derive(Clone, Copy, Debug)]
+pub struct ContentHash(u64);
+
+impl Default for ContentHash {
+    fn default() -> Self {
+        ContentHash(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl ContentHash {
+    pub fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    pub fn update_file(&mut self, path: &Path) -> io::Result<()> {
+        let mut file = fs::File::open(path)?;
+        let mut buffer = vec![0; 1 << 16];
+        loop {
+            match file.read(&mut buffer)? {
+                0 => return Ok(()),
+                read => self.update(&buffer[..read]),
+            }
+        }
+    }
+
+    pub fn finish(self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_match_fnv_1a() {
+        let mut empty = ContentHash::default();
+        empty.update(b"");
+        assert_eq!(empty.finish(), 0xcbf2_9ce4_8422_2325);
+        let mut a = ContentHash::default();
+        a.update(b"a");
+        assert_eq!(a.finish(), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -0,0 +1,145 @@
+//! Watches the directories of loaded assets so files edited on disk can be
+//! imported again, see [`crate::assets::AssetManager::reload_changed`].
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+};
+
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchedFile {
+    /// A model file, reloaded itself.
+    Model,
+    /// Buffers and material libraries, reloading the models next to them.
+    ModelData,
+    /// Reloads the texture and its compressed copies, and the models next to
+    /// it that may embed it.
+    Image,
+    /// A declared render graph, see [`crate::render_graph::declared`].
+    RenderGraph,
+}
+
+/// What a change to `path` affects, if anything.
+pub fn watched_file(path: &Path) -> Option<WatchedFile> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "gltf" | "glb" | "obj" | "cmodel" => Some(WatchedFile::Model),
+        "bin" | "mtl" => Some(WatchedFile::ModelData),
+        "png" | "jpg" | "jpeg" | "tga" | "bmp" | "hdr" | "exr" | "ktx2" | "dds" | "ctex" => Some(WatchedFile::Image),
+        "ron" => Some(WatchedFile::RenderGraph),
+        _ => None,
+    }
+}
+
+/// Absolute form of `path`, resolving links if the file exists, so paths
+/// loaded relative to the working directory compare equal to the ones the
+/// watcher reports.
+pub fn absolute_path(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| match std::env::current_dir() {
+        Ok(dir) => dir.join(path),
+        Err(_) => path.to_path_buf(),
+    })
+}
+
+pub struct AssetWatcher {
+    watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<Event>>,
+    directories: HashSet<PathBuf>,
+}
+
+impl AssetWatcher {
+    pub fn new() -> notify::Result<Self> {
+        let (sender, events) = mpsc::channel();
+        Ok(AssetWatcher {
+            watcher: notify::recommended_watcher(sender)?,
+            events,
+            directories: HashSet::new(),
+        })
+    }
+
+    /// Starts watching the directory holding `path`, unless it already is.
+    pub fn watch_file(&mut self, path: &Path) -> notify::Result<()> {
+        let Some(directory) = absolute_path(path).parent().map(Path::to_path_buf) else {
+            return Ok(());
+        };
+        if self.directories.contains(&directory) {
+            return Ok(());
+        }
+        self.watcher.watch(&directory, RecursiveMode::NonRecursive)?;
+        self.directories.insert(directory);
+        Ok(())
+    }
+
+    /// Watched files created or written since the last call, each listed
+    /// once however many events a save produced.
+    pub fn changed(&self) -> Vec<PathBuf> {
+        let mut changed: Vec<PathBuf> = vec![];
+        for event in self.events.try_iter() {
+            let event = match event {
+                Ok(event) => event,
+                Err(err) => {
+                    log::warn!("Watching assets failed: {}", err);
+                    continue;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                continue;
+            }
+            for path in event.paths {
+                if watched_file(&path).is_some() && !changed.contains(&path) {
+                    changed.push(path);
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn files_are_classified_by_extension() {
+        assert_eq!(watched_file(Path::new("models/sphere.gltf")), Some(WatchedFile::Model));
+        assert_eq!(watched_file(Path::new("models/Sphere.OBJ")), Some(WatchedFile::Model));
+        assert_eq!(watched_file(Path::new("models/sphere.bin")), Some(WatchedFile::ModelData));
+        assert_eq!(watched_file(Path::new("textures/albedo.png")), Some(WatchedFile::Image));
+        assert_eq!(watched_file(Path::new("render_graph.ron")), Some(WatchedFile::RenderGraph));
+        assert_eq!(watched_file(Path::new("shaders/gbuffer.frag")), None);
+        assert_eq!(watched_file(Path::new("models/README")), None);
+    }
+
+    #[test]
+    fn writes_to_watched_directories_are_reported_once() {
+        let directory = std::env::temp_dir().join(format!("lynch_hot_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let model = directory.join("model.gltf");
+        std::fs::write(&model, "{}").unwrap();
+
+        let mut watcher = AssetWatcher::new().unwrap();
+        watcher.watch_file(&model).unwrap();
+        watcher.watch_file(&model).unwrap();
+        assert_eq!(watcher.directories.len(), 1);
+
+        std::fs::write(&model, "{ }").unwrap();
+        std::fs::write(directory.join("notes.txt"), "unwatched").unwrap();
+        std::fs::write(&model, "{  }").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut changed = vec![];
+        while changed.is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(50));
+            changed = watcher.changed();
+        }
+        let expected = absolute_path(&model);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(changed, vec![expected]);
+    }
+}
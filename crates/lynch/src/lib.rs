@@ -1,13 +1,44 @@
+pub mod animation;
+pub mod assets;
+pub mod bounds;
+pub mod cook;
+pub mod culling;
 mod camera;
 pub mod gltf_loader;
+pub mod hash;
+pub mod hot_reload;
+pub mod lights;
+pub mod lod;
 pub mod mesh;
+pub mod mesh_processing;
+pub mod obj_loader;
 pub mod render_graph;
 pub mod render_tools;
 
 pub mod renderer;
+pub mod scene;
+pub mod shadows;
+pub mod skinning;
 mod texture;
+pub mod texture_processing;
 pub mod vulkan;
 pub mod window;
+
+#[cfg(test)]
+mod test_fixtures {
+    use std::path::{Path, PathBuf};
+
+    /// Path of a file in `fixtures/models`.
+    pub fn model_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/models").join(name)
+    }
+
+    /// Path of a file in `fixtures/shaders`.
+    pub fn shader_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/shaders").join(name)
+    }
+}
+
 pub use camera::{
     Camera,
     CameraBuilder
@@ -0,0 +1,508 @@
+//! Lights gathered from the world every frame and assigned to the clusters
+//! of the view frustum they can reach, so shading a pixel only evaluates
+//! the lights of its cluster. Clusters split the screen into tiles and the
+//! depth between the near and far planes into exponentially thicker slices.
+//!
+//! [`assign_lights`] is the CPU reference of `light_clusters.comp`, which
+//! does the same per cluster on the GPU from the buffer [`encode_lights`]
+//! writes.
+
+use frost::{light_direction, DirectionalLight, PointLight, SearchIter, SpotLight, Transform, World};
+use glam::{Mat4, Vec3, Vec4Swizzles};
+
+use crate::Camera;
+
+/// Lights beyond this many are left out of the light buffer.
+pub const MAX_LIGHTS: usize = 1024;
+
+/// Header of the light buffer: the view, projection, counts and grid.
+const HEADER_WORDS: usize = 16 + 4 + 4 + 4;
+const LIGHT_WORDS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    Point,
+    /// Angles from the cone's axis in radians.
+    Spot { inner_angle: f32, outer_angle: f32 },
+    Directional,
+}
+
+impl LightKind {
+    fn index(&self) -> u32 {
+        match self {
+            LightKind::Point => 0,
+            LightKind::Spot { .. } => 1,
+            LightKind::Directional => 2,
+        }
+    }
+}
+
+/// A light placed in the world, see [`frost::PointLight`],
+/// [`frost::SpotLight`] and [`frost::DirectionalLight`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Unused by directional lights.
+    pub range: f32,
+    /// Whether a point or spot light wants a shadow map, see
+    /// [`crate::shadows::plan_light_shadows`].
+    pub casts_shadows: bool,
+}
+
+impl Light {
+    /// Sphere holding everything the light reaches, `None` for directional
+    /// lights which reach everything.
+    pub fn bounding_sphere(&self) -> Option<(Vec3, f32)> {
+        match self.kind {
+            LightKind::Point => Some((self.position, self.range)),
+            // The smallest sphere around the cone's apex and base.
+            LightKind::Spot { outer_angle, .. } if outer_angle < std::f32::consts::FRAC_PI_4 => {
+                let radius = self.range / (2.0 * outer_angle.cos());
+                Some((self.position + self.direction * radius, radius))
+            }
+            LightKind::Spot { outer_angle, .. } => Some((
+                self.position + self.direction * self.range * outer_angle.cos(),
+                self.range * outer_angle.sin(),
+            )),
+            LightKind::Directional => None,
+        }
+    }
+}
+
+/// Every light of `world` placed by a [`Transform`], directional lights
+/// first as [`encode_lights`] expects.
+pub fn gather_lights(world: &World) -> Vec<Light> {
+    let mut lights = vec![];
+    if let Ok(mut search) = world.search::<(&DirectionalLight, &Transform)>() {
+        lights.extend(search.iter().map(|(light, transform)| Light {
+            kind: LightKind::Directional,
+            position: transform.position,
+            direction: light_direction(transform),
+            color: light.color,
+            intensity: light.intensity,
+            range: 0.0,
+            casts_shadows: false,
+        }));
+    }
+    if let Ok(mut search) = world.search::<(&PointLight, &Transform)>() {
+        lights.extend(search.iter().map(|(light, transform)| Light {
+            kind: LightKind::Point,
+            position: transform.position,
+            direction: light_direction(transform),
+            color: light.color,
+            intensity: light.intensity,
+            range: light.range,
+            casts_shadows: light.casts_shadows,
+        }));
+    }
+    if let Ok(mut search) = world.search::<(&SpotLight, &Transform)>() {
+        lights.extend(search.iter().map(|(light, transform)| Light {
+            kind: LightKind::Spot {
+                inner_angle: light.inner_angle,
+                outer_angle: light.outer_angle,
+            },
+            position: transform.position,
+            direction: light_direction(transform),
+            color: light.color,
+            intensity: light.intensity,
+            range: light.range,
+            casts_shadows: light.casts_shadows,
+        }));
+    }
+    lights
+}
+
+/// How the view frustum is split into clusters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClusterGrid {
+    pub tiles_x: u32,
+    pub tiles_y: u32,
+    pub slices: u32,
+    /// Lights past this many in one cluster are left out of it.
+    pub max_lights_per_cluster: u32,
+}
+
+impl Default for ClusterGrid {
+    fn default() -> Self {
+        ClusterGrid {
+            tiles_x: 16,
+            tiles_y: 9,
+            slices: 24,
+            max_lights_per_cluster: 128,
+        }
+    }
+}
+
+impl ClusterGrid {
+    pub fn cluster_count(&self) -> usize {
+        (self.tiles_x * self.tiles_y * self.slices) as usize
+    }
+
+    /// Tiles are counted from the top left of the screen, slices from the
+    /// near plane.
+    pub fn cluster_index(&self, x: u32, y: u32, slice: u32) -> usize {
+        ((slice * self.tiles_y + y) * self.tiles_x + x) as usize
+    }
+
+    /// Distance from the camera slice `slice` starts at.
+    pub fn slice_depth(&self, view: &ClusterView, slice: u32) -> f32 {
+        view.near * (view.far / view.near).powf(slice as f32 / self.slices as f32)
+    }
+
+    /// The slice holding points `depth` in front of the camera.
+    pub fn slice(&self, view: &ClusterView, depth: f32) -> u32 {
+        let slice = (depth / view.near).ln() / (view.far / view.near).ln() * self.slices as f32;
+        (slice.floor().max(0.0) as u32).min(self.slices - 1)
+    }
+
+    /// Bytes of the buffer `light_clusters.comp` writes: the light count
+    /// of every cluster, then `max_lights_per_cluster` light indices for
+    /// each.
+    pub fn cluster_buffer_size(&self) -> u64 {
+        (self.cluster_count() * (1 + self.max_lights_per_cluster as usize) * 4) as u64
+    }
+
+    /// View space box around cluster `x`, `y`, `slice`.
+    fn bounds(&self, view: &ClusterView, x: u32, y: u32, slice: u32) -> (Vec3, Vec3) {
+        let near = self.slice_depth(view, slice);
+        let far = self.slice_depth(view, slice + 1);
+        // Tile rows count down from the top of the screen, where NDC y is 1.
+        let left = -1.0 + 2.0 * x as f32 / self.tiles_x as f32;
+        let right = -1.0 + 2.0 * (x + 1) as f32 / self.tiles_x as f32;
+        let bottom = 1.0 - 2.0 * (y + 1) as f32 / self.tiles_y as f32;
+        let top = 1.0 - 2.0 * y as f32 / self.tiles_y as f32;
+        let scale_x = 1.0 / view.projection.x_axis.x;
+        let scale_y = 1.0 / view.projection.y_axis.y;
+
+        let corners = [near, far].map(|depth| {
+            (
+                Vec3::new(left * scale_x * depth, bottom * scale_y * depth, -depth),
+                Vec3::new(right * scale_x * depth, top * scale_y * depth, -depth),
+            )
+        });
+        (corners[0].0.min(corners[1].0), corners[0].1.max(corners[1].1))
+    }
+}
+
+/// The camera the clusters are fitted to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClusterView {
+    pub view: Mat4,
+    pub projection: Mat4,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl ClusterView {
+    pub fn new(camera: &Camera) -> Self {
+        ClusterView {
+            view: camera.get_view(),
+            projection: camera.get_projection(),
+            near: camera.get_near_plane(),
+            far: camera.get_far_plane(),
+        }
+    }
+}
+
+/// The lights reaching each cluster, laid out like the buffer
+/// `light_clusters.comp` writes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LightClusters {
+    pub grid: ClusterGrid,
+    pub counts: Vec<u32>,
+    /// `max_lights_per_cluster` indices into the lights for every cluster.
+    pub indices: Vec<u32>,
+}
+
+impl LightClusters {
+    pub fn lights(&self, cluster: usize) -> &[u32] {
+        let start = cluster * self.grid.max_lights_per_cluster as usize;
+        &self.indices[start..start + self.counts[cluster] as usize]
+    }
+}
+
+/// Assigns every light whose bounding sphere touches a cluster to it, in
+/// the order of `lights`.
+pub fn assign_lights(lights: &[Light], view: &ClusterView, grid: &ClusterGrid) -> LightClusters {
+    let spheres: Vec<(usize, Vec3, f32)> = lights
+        .iter()
+        .enumerate()
+        .filter_map(|(index, light)| {
+            let (center, radius) = light.bounding_sphere()?;
+            Some((index, (view.view * center.extend(1.0)).xyz(), radius))
+        })
+        .collect();
+
+    let mut clusters = LightClusters {
+        grid: *grid,
+        counts: vec![0; grid.cluster_count()],
+        indices: vec![0; grid.cluster_count() * grid.max_lights_per_cluster as usize],
+    };
+    for slice in 0..grid.slices {
+        for y in 0..grid.tiles_y {
+            for x in 0..grid.tiles_x {
+                let cluster = grid.cluster_index(x, y, slice);
+                let (min, max) = grid.bounds(view, x, y, slice);
+                let reached = spheres.iter().filter(|(_, center, radius)| {
+                    center.clamp(min, max).distance_squared(*center) <= radius * radius
+                });
+                for (count, &(index, _, _)) in reached.take(grid.max_lights_per_cluster as usize).enumerate() {
+                    clusters.indices[cluster * grid.max_lights_per_cluster as usize + count] = index as u32;
+                    clusters.counts[cluster] += 1;
+                }
+            }
+        }
+    }
+    clusters
+}
+
+/// Bytes of the light buffer holding up to [`MAX_LIGHTS`] lights.
+pub fn light_buffer_size() -> u64 {
+    ((HEADER_WORDS + MAX_LIGHTS * LIGHT_WORDS) * 4) as u64
+}
+
+/// The light buffer `light_clusters.comp` and the deferred pass read, see
+/// `include/clustered_lights.glsl`. Directional lights have to come first.
+/// `shadow_faces` holds the first shadow face of each light with a shadow
+/// map, see [`crate::shadows::LightShadows`].
+pub fn encode_lights(
+    lights: &[Light],
+    shadow_faces: &[Option<u32>],
+    view: &ClusterView,
+    grid: &ClusterGrid,
+) -> Vec<u32> {
+    let lights = &lights[..lights.len().min(MAX_LIGHTS)];
+    let directional = lights
+        .iter()
+        .take_while(|light| light.kind == LightKind::Directional)
+        .count();
+    debug_assert!(lights[directional..]
+        .iter()
+        .all(|light| light.kind != LightKind::Directional));
+
+    let mut words = Vec::with_capacity(HEADER_WORDS + lights.len() * LIGHT_WORDS);
+    words.extend(view.view.to_cols_array().map(f32::to_bits));
+    words.extend(
+        [view.projection.x_axis.x, view.projection.y_axis.y, view.near, view.far].map(f32::to_bits),
+    );
+    words.extend([lights.len() as u32, directional as u32, 0, 0]);
+    words.extend([grid.tiles_x, grid.tiles_y, grid.slices, grid.max_lights_per_cluster]);
+    for (index, light) in lights.iter().enumerate() {
+        let shadow_face = match shadow_faces.get(index).copied().flatten() {
+            Some(face) => face as f32,
+            None => -1.0,
+        };
+        let (inner_angle, outer_angle) = match light.kind {
+            LightKind::Spot {
+                inner_angle,
+                outer_angle,
+            } => (inner_angle, outer_angle),
+            _ => (0.0, 0.0),
+        };
+        words.extend(light.position.extend(light.range).to_array().map(f32::to_bits));
+        words.extend(light.color.extend(light.intensity).to_array().map(f32::to_bits));
+        words.extend(light.direction.to_array().map(f32::to_bits));
+        words.push(light.kind.index());
+        words.extend([inner_angle.cos(), outer_angle.cos(), shadow_face, 0.0].map(f32::to_bits));
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view() -> ClusterView {
+        ClusterView {
+            view: Mat4::IDENTITY,
+            projection: Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 16.0 / 9.0, 0.1, 100.0),
+            near: 0.1,
+            far: 100.0,
+        }
+    }
+
+    fn point(position: Vec3, range: f32) -> Light {
+        Light {
+            kind: LightKind::Point,
+            position,
+            direction: -Vec3::Z,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range,
+            casts_shadows: false,
+        }
+    }
+
+    fn clusters_of(clusters: &LightClusters, light: u32) -> Vec<usize> {
+        (0..clusters.grid.cluster_count())
+            .filter(|&cluster| clusters.lights(cluster).contains(&light))
+            .collect()
+    }
+
+    #[test]
+    fn slices_grow_exponentially_from_near_to_far() {
+        let grid = ClusterGrid::default();
+        let view = view();
+        assert!((grid.slice_depth(&view, 0) - 0.1).abs() < 1e-6);
+        assert!((grid.slice_depth(&view, grid.slices) - 100.0).abs() < 1e-3);
+        let ratio = grid.slice_depth(&view, 2) / grid.slice_depth(&view, 1);
+        assert!((grid.slice_depth(&view, 11) / grid.slice_depth(&view, 10) - ratio).abs() < 1e-4);
+
+        for slice in 0..grid.slices {
+            let middle = (grid.slice_depth(&view, slice) + grid.slice_depth(&view, slice + 1)) / 2.0;
+            assert_eq!(grid.slice(&view, middle), slice);
+        }
+        assert_eq!(grid.slice(&view, 0.01), 0);
+        assert_eq!(grid.slice(&view, 1000.0), grid.slices - 1);
+    }
+
+    #[test]
+    fn lights_reach_the_clusters_around_them() {
+        let grid = ClusterGrid::default();
+        let view = view();
+        let lights = [point(Vec3::new(0.0, 0.0, -10.0), 1.0), point(Vec3::new(0.0, 0.0, 10.0), 1.0)];
+        let clusters = assign_lights(&lights, &view, &grid);
+
+        let reached = clusters_of(&clusters, 0);
+        let slices = grid.slice(&view, 9.0)..=grid.slice(&view, 11.0);
+        assert!(!reached.is_empty());
+        for slice in slices.clone() {
+            // The light is in the middle of the screen, between the two
+            // central columns of tiles.
+            for x in [7, 8] {
+                assert!(reached.contains(&grid.cluster_index(x, 4, slice)));
+            }
+        }
+        for &cluster in &reached {
+            let slice = cluster as u32 / (grid.tiles_x * grid.tiles_y);
+            let x = cluster as u32 % grid.tiles_x;
+            assert!(slices.contains(&slice));
+            assert!((6..=9).contains(&x));
+        }
+
+        // Behind the camera.
+        assert!(clusters_of(&clusters, 1).is_empty());
+    }
+
+    #[test]
+    fn clusters_follow_the_view() {
+        let grid = ClusterGrid::default();
+        let light = [point(Vec3::new(10.0, 0.0, 0.0), 1.0)];
+        let looking_away = assign_lights(&light, &view(), &grid);
+        assert!(clusters_of(&looking_away, 0).is_empty());
+
+        let looking_at = ClusterView {
+            view: Mat4::look_at_rh(Vec3::ZERO, Vec3::X, Vec3::Y),
+            ..view()
+        };
+        let clusters = assign_lights(&light, &looking_at, &grid);
+        let slice = grid.slice(&looking_at, 10.0);
+        assert!(clusters_of(&clusters, 0).contains(&grid.cluster_index(8, 4, slice)));
+    }
+
+    #[test]
+    fn tiles_count_down_from_the_top_of_the_screen() {
+        let grid = ClusterGrid::default();
+        let view = view();
+        let above = [point(Vec3::new(0.0, 8.0, -10.0), 0.5)];
+        let clusters = assign_lights(&above, &view, &grid);
+        let rows: Vec<u32> = clusters_of(&clusters, 0)
+            .into_iter()
+            .map(|cluster| cluster as u32 / grid.tiles_x % grid.tiles_y)
+            .collect();
+        assert!(!rows.is_empty());
+        assert!(rows.iter().all(|&row| row < 2));
+    }
+
+    #[test]
+    fn directional_lights_are_not_clustered_and_full_clusters_drop_lights() {
+        let grid = ClusterGrid {
+            max_lights_per_cluster: 2,
+            ..Default::default()
+        };
+        let sun = Light {
+            kind: LightKind::Directional,
+            ..point(Vec3::ZERO, 0.0)
+        };
+        let lights = [sun, point(Vec3::ZERO, 1000.0), point(Vec3::ZERO, 1000.0), point(Vec3::ZERO, 1000.0)];
+        let clusters = assign_lights(&lights, &view(), &grid);
+        for cluster in 0..grid.cluster_count() {
+            assert_eq!(clusters.lights(cluster), &[1, 2]);
+        }
+    }
+
+    #[test]
+    fn spot_light_spheres_hold_their_cones() {
+        for outer_angle in [0.2f32, 0.7, 1.2] {
+            let spot = Light {
+                kind: LightKind::Spot {
+                    inner_angle: 0.1,
+                    outer_angle,
+                },
+                direction: Vec3::X,
+                ..point(Vec3::new(1.0, 2.0, 3.0), 5.0)
+            };
+            let (center, radius) = spot.bounding_sphere().unwrap();
+            assert!(radius <= 5.0 + 1e-5);
+            let rim = Vec3::new(outer_angle.cos(), outer_angle.sin(), 0.0) * 5.0;
+            for point in [Vec3::ZERO, Vec3::X * 5.0, rim, rim * Vec3::new(1.0, -1.0, 1.0)] {
+                assert!((spot.position + point).distance(center) <= radius + 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn light_buffers_hold_the_view_grid_and_lights() {
+        let grid = ClusterGrid::default();
+        let view = view();
+        let sun = Light {
+            kind: LightKind::Directional,
+            ..point(Vec3::ZERO, 0.0)
+        };
+        let spot = Light {
+            kind: LightKind::Spot {
+                inner_angle: 0.0,
+                outer_angle: std::f32::consts::FRAC_PI_2,
+            },
+            ..point(Vec3::new(1.0, 2.0, 3.0), 4.0)
+        };
+        let words = encode_lights(&[sun, spot], &[None, Some(6)], &view, &grid);
+
+        assert_eq!(words.len(), HEADER_WORDS + 2 * LIGHT_WORDS);
+        assert_eq!(f32::from_bits(words[18]), 0.1);
+        assert_eq!(words[20..28], [2, 1, 0, 0, 16, 9, 24, 128]);
+        let spot_words = &words[HEADER_WORDS + LIGHT_WORDS..];
+        assert_eq!(spot_words[..4].iter().map(|&word| f32::from_bits(word)).collect::<Vec<_>>(), [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(spot_words[11], 1);
+        assert_eq!(f32::from_bits(spot_words[12]), 1.0);
+        assert!(f32::from_bits(spot_words[13]).abs() < 1e-6);
+        assert_eq!(f32::from_bits(spot_words[14]), 6.0);
+        assert_eq!(f32::from_bits(words[HEADER_WORDS + 14]), -1.0);
+        assert!((light_buffer_size() as usize) >= words.len() * 4);
+    }
+
+    #[test]
+    fn lights_are_gathered_with_directional_lights_first() {
+        let mut world = World::new();
+        world
+            .new_entity((PointLight::new(Vec3::X, 2.0, 3.0).with_shadows(), Transform::default()))
+            .unwrap();
+        world
+            .new_entity((DirectionalLight::new(Vec3::Y, 1.0), Transform::default()))
+            .unwrap();
+        // Without a transform there is nowhere to put it.
+        world.new_entity((SpotLight::default(),)).unwrap();
+
+        let lights = gather_lights(&world);
+        assert_eq!(lights.len(), 2);
+        assert_eq!(lights[0].kind, LightKind::Directional);
+        assert_eq!(lights[0].direction, -Vec3::Z);
+        assert_eq!(lights[1].kind, LightKind::Point);
+        assert_eq!((lights[1].color, lights[1].intensity, lights[1].range), (Vec3::X, 2.0, 3.0));
+        assert!(lights[1].casts_shadows && !lights[0].casts_shadows);
+    }
+}
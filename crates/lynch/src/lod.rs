@@ -0,0 +1,135 @@
+//! Level of detail selection by screen space error. Every level carries the
+//! distance it strays from the full mesh, in model units. Projected to the
+//! screen that gives an error in pixels, and the coarsest level under the
+//! threshold is drawn.
+
+use glam::{Mat4, Vec3};
+
+use crate::bounds::{max_scale, BoundingSphere};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LodSettings {
+    /// Largest error, in pixels, a level may show on screen.
+    pub max_pixel_error: f32,
+    /// Fraction of `max_pixel_error` a level has to clear before switching,
+    /// so levels don't pop back and forth around the threshold.
+    pub hysteresis: f32,
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        LodSettings {
+            max_pixel_error: 1.0,
+            hysteresis: 0.2,
+        }
+    }
+}
+
+/// Pixels covered by one unit at distance one, for a viewport
+/// `viewport_height` pixels high.
+pub fn projection_scale(projection: &Mat4, viewport_height: f32) -> f32 {
+    projection.y_axis.y * viewport_height * 0.5
+}
+
+/// Pixels an error of `error` units spans at `distance` from the eye. Within
+/// the error's reach of the eye everything is infinitely large.
+pub fn screen_space_error(error: f32, distance: f32, projection_scale: f32) -> f32 {
+    if error <= 0.0 {
+        return 0.0;
+    }
+    if distance <= 0.0 {
+        return f32::INFINITY;
+    }
+    error * projection_scale / distance
+}
+
+/// Distance from `eye` to the closest point of `bounds` after `transform`,
+/// divided by the transform's scale so it can be compared against errors in
+/// model units.
+pub fn model_distance(bounds: &BoundingSphere, transform: &Mat4, eye: Vec3) -> f32 {
+    let world = bounds.transformed(transform);
+    (world.center.distance(eye) - world.radius) / max_scale(transform).max(f32::EPSILON)
+}
+
+/// Picks a level given the error of every level, finest first with the full
+/// mesh at `0.0`, and the level drawn last frame. Switching to a coarser
+/// level waits until it is comfortably under the threshold, switching to a
+/// finer one until the current level is clearly over it.
+pub fn select_lod(errors: &[f32], distance: f32, projection_scale: f32, current: usize, settings: &LodSettings) -> usize {
+    if errors.is_empty() {
+        return 0;
+    }
+    let current = current.min(errors.len() - 1);
+    let pixels = |level: usize| screen_space_error(errors[level], distance, projection_scale);
+    let coarsest_under = |threshold: f32| (0..errors.len()).rev().find(|level| pixels(*level) <= threshold).unwrap_or(0);
+
+    let threshold = settings.max_pixel_error;
+    if pixels(current) > threshold * (1.0 + settings.hysteresis) {
+        return coarsest_under(threshold);
+    }
+    coarsest_under(threshold * (1.0 - settings.hysteresis)).max(current)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Camera;
+
+    const ERRORS: [f32; 3] = [0.0, 0.01, 0.1];
+
+    #[test]
+    fn projection_scale_matches_the_camera() {
+        let camera = Camera::builder().fov_degrees(90.0).aspect_ratio(16.0 / 9.0).build();
+        let scale = projection_scale(&camera.get_projection(), 1080.0);
+        // A 90 degree field of view spans two units at distance one.
+        assert!((scale - 540.0).abs() < 1e-3, "{scale}");
+        assert!((screen_space_error(0.01, 10.0, scale) - 0.54).abs() < 1e-4);
+        assert_eq!(screen_space_error(0.01, 0.0, scale), f32::INFINITY);
+        assert_eq!(screen_space_error(0.0, 0.0, scale), 0.0);
+    }
+
+    #[test]
+    fn levels_coarsen_with_distance() {
+        let settings = LodSettings::default();
+        let select = |distance| select_lod(&ERRORS, distance, 500.0, 0, &settings);
+        assert_eq!(select(1.0), 0);
+        assert_eq!(select(10.0), 1);
+        assert_eq!(select(100.0), 2);
+        assert_eq!(select(-1.0), 0);
+        assert_eq!(select_lod(&[], 10.0, 500.0, 3, &settings), 0);
+    }
+
+    #[test]
+    fn hysteresis_holds_the_level_around_the_threshold() {
+        let settings = LodSettings::default();
+        // Level 1 is exactly one pixel at distance 5.
+        let select = |distance, current| select_lod(&ERRORS, distance, 500.0, current, &settings);
+
+        // Just past the switching distance the finer level is kept...
+        assert_eq!(select(5.5, 0), 0);
+        assert_eq!(select(6.5, 0), 1);
+        // ...and just inside it the coarser one.
+        assert_eq!(select(4.5, 1), 1);
+        assert_eq!(select(4.0, 1), 0);
+
+        // Oscillating around the threshold never switches.
+        let mut level = 0;
+        for step in 0..20 {
+            let distance = 5.0 + if step % 2 == 0 { 0.4 } else { -0.4 };
+            level = select(distance, level);
+            assert_eq!(level, 0);
+        }
+    }
+
+    #[test]
+    fn distances_account_for_bounds_and_scale() {
+        let bounds = BoundingSphere {
+            center: Vec3::ZERO,
+            radius: 1.0,
+        };
+        let transform = Mat4::from_translation(Vec3::new(0.0, 0.0, -10.0)) * Mat4::from_scale(Vec3::splat(2.0));
+        // Eight units to the surface, four in model units.
+        assert!((model_distance(&bounds, &transform, Vec3::ZERO) - 4.0).abs() < 1e-5);
+        assert!(model_distance(&bounds, &Mat4::IDENTITY, Vec3::ZERO) < 0.0);
+    }
+}
@@ -179,7 +179,7 @@ pub fn load_gltf(device: Arc<Device>, path: &str) -> Model {
                 .unwrap(),
             );
 
-            let rgba_image = dynamic_image.to_rgba();
+            let rgba_image = dynamic_image.to_rgba8();
             image.format = gltf::image::Format::R8G8B8A8;
             image.pixels = rgba_image.into_raw();
         }
@@ -245,6 +245,21 @@ pub struct Primitive {
 
 impl Primitive {
     pub fn new(device: Arc<Device>, indices: Vec<u32>, vertices: Vec<Vertex>) -> Primitive {
+        Self::with_vertex_location(device, indices, vertices, gpu_allocator::MemoryLocation::GpuOnly)
+    }
+
+    /// Like [`Primitive::new`], but keeps the vertices in host visible memory
+    /// so they can be rewritten every frame without a transfer.
+    pub fn new_dynamic(device: Arc<Device>, indices: Vec<u32>, vertices: Vec<Vertex>) -> Primitive {
+        Self::with_vertex_location(device, indices, vertices, gpu_allocator::MemoryLocation::CpuToGpu)
+    }
+
+    fn with_vertex_location(
+        device: Arc<Device>,
+        indices: Vec<u32>,
+        vertices: Vec<Vertex>,
+        vertex_location: gpu_allocator::MemoryLocation,
+    ) -> Primitive {
         let index_buffer = Buffer::new(
             device.clone(),
             Some(indices.as_slice()),
@@ -265,7 +280,7 @@ impl Primitive {
                 | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
                 | vk::BufferUsageFlags::ACCELERATION_STRUCTURE_BUILD_INPUT_READ_ONLY_KHR
                 | vk::BufferUsageFlags::STORAGE_BUFFER,
-            gpu_allocator::MemoryLocation::GpuOnly,
+            vertex_location,
             Some(String::from("vertex_buffer")),
         );
 
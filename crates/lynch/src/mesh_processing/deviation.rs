@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use super::position;
+use crate::mesh::Vertex;
+
+/// Closest point to `p` on triangle `abc`, after Ericson's Real-Time
+/// Collision Detection.
+fn closest_point_on_triangle(p: Vec3, [a, b, c]: [Vec3; 3]) -> Vec3 {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denominator = 1.0 / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
+
+/// Uniform grid of the triangles of a mesh, each one listed in every cell
+/// its bounding box touches.
+struct TriangleGrid {
+    triangles: Vec<[Vec3; 3]>,
+    cells: HashMap<[i32; 3], Vec<usize>>,
+    cell_size: f32,
+    min: [i32; 3],
+    max: [i32; 3],
+}
+
+impl TriangleGrid {
+    fn new(vertices: &[Vertex], indices: &[u32]) -> Self {
+        let triangles: Vec<[Vec3; 3]> = indices
+            .chunks_exact(3)
+            .map(|t| [0, 1, 2].map(|i| position(&vertices[t[i] as usize])))
+            .collect();
+        let (low, high) = triangles
+            .iter()
+            .flatten()
+            .fold((Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)), |(low, high), p| (low.min(*p), high.max(*p)));
+        // Roughly one triangle per cell along the longest axis.
+        let extent = (high - low).max_element();
+        let cell_size = match extent > 0.0 {
+            true => extent / (triangles.len() as f32).cbrt().max(1.0),
+            false => 1.0,
+        };
+
+        let mut grid = TriangleGrid {
+            triangles,
+            cells: HashMap::new(),
+            cell_size,
+            min: [i32::MAX; 3],
+            max: [i32::MIN; 3],
+        };
+        for (index, triangle) in grid.triangles.iter().enumerate() {
+            let low = grid.cell(triangle[0].min(triangle[1]).min(triangle[2]));
+            let high = grid.cell(triangle[0].max(triangle[1]).max(triangle[2]));
+            for x in low[0]..=high[0] {
+                for y in low[1]..=high[1] {
+                    for z in low[2]..=high[2] {
+                        grid.cells.entry([x, y, z]).or_default().push(index);
+                    }
+                }
+            }
+            for axis in 0..3 {
+                grid.min[axis] = grid.min[axis].min(low[axis]);
+                grid.max[axis] = grid.max[axis].max(high[axis]);
+            }
+        }
+        grid
+    }
+
+    fn cell(&self, p: Vec3) -> [i32; 3] {
+        (p / self.cell_size).floor().to_array().map(|v| v as i32)
+    }
+
+    /// Distance from `p` to the closest triangle, searching rings of cells
+    /// outwards until no closer triangle can be left.
+    fn distance(&self, p: Vec3) -> f32 {
+        if self.triangles.is_empty() {
+            return 0.0;
+        }
+        let center = self.cell(p);
+        let last_ring = (0..3)
+            .map(|axis| (center[axis] - self.min[axis]).abs().max((center[axis] - self.max[axis]).abs()))
+            .max()
+            .unwrap_or(0);
+        let mut best = f32::MAX;
+        for ring in 0..=last_ring {
+            // Cells of this ring are at least `ring - 1` cells away.
+            if best <= (ring - 1).max(0) as f32 * self.cell_size {
+                break;
+            }
+            for x in -ring..=ring {
+                for y in -ring..=ring {
+                    for z in -ring..=ring {
+                        if x.abs().max(y.abs()).max(z.abs()) != ring {
+                            continue;
+                        }
+                        let cell = [center[0] + x, center[1] + y, center[2] + z];
+                        for triangle in self.cells.get(&cell).into_iter().flatten() {
+                            let closest = closest_point_on_triangle(p, self.triangles[*triangle]);
+                            best = best.min(closest.distance(p));
+                        }
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+/// Largest distance between the vertices of either mesh and the surface of
+/// the other, a cheap stand-in for the Hausdorff distance. Used to rate
+/// authored levels of detail against the mesh they replace.
+pub fn surface_deviation(vertices: &[Vertex], indices: &[u32], other_vertices: &[Vertex], other_indices: &[u32]) -> f32 {
+    let grid = TriangleGrid::new(vertices, indices);
+    let other_grid = TriangleGrid::new(other_vertices, other_indices);
+    let to_other = vertices.iter().map(|v| other_grid.distance(position(v)));
+    let to_self = other_vertices.iter().map(|v| grid.distance(position(v)));
+    to_other.chain(to_self).fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh_processing::fixtures::{grid, vertex};
+
+    #[test]
+    fn closest_points_cover_every_region() {
+        let triangle = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        assert_eq!(closest_point_on_triangle(Vec3::new(0.25, 0.25, 1.0), triangle), Vec3::new(0.25, 0.25, 0.0));
+        assert_eq!(closest_point_on_triangle(Vec3::new(-1.0, -1.0, 0.0), triangle), Vec3::ZERO);
+        assert_eq!(closest_point_on_triangle(Vec3::new(0.5, -1.0, 0.0), triangle), Vec3::new(0.5, 0.0, 0.0));
+        assert_eq!(closest_point_on_triangle(Vec3::new(1.0, 1.0, 0.0), triangle), Vec3::new(0.5, 0.5, 0.0));
+    }
+
+    #[test]
+    fn deviation_measures_the_furthest_vertex() {
+        let (vertices, indices) = grid(4);
+        assert_eq!(surface_deviation(&vertices, &indices, &vertices, &indices), 0.0);
+
+        let mut raised = vertices.clone();
+        raised[12].pos.z = 0.5;
+        assert!((surface_deviation(&raised, &indices, &vertices, &indices) - 0.5).abs() < 1e-6);
+
+        // A single triangle over the grid's corner is off by the distance
+        // from the far corner to its hypotenuse.
+        let corner = [Vec3::ZERO, Vec3::new(4.0, 0.0, 0.0), Vec3::new(0.0, 4.0, 0.0)].map(vertex);
+        let deviation = surface_deviation(&vertices, &indices, &corner, &[0, 1, 2]);
+        assert!((deviation - 8f32.sqrt()).abs() < 1e-5, "{deviation}");
+    }
+}
@@ -0,0 +1,136 @@
+//! CPU side processing of `Vertex`/index buffers, run before a mesh is
+//! uploaded. Operations that change the vertex count return a remap giving
+//! the original vertex of every new one, see [`remap_vertices`].
+
+mod deviation;
+mod normals;
+mod optimize;
+mod simplify;
+mod tangents;
+mod weld;
+
+pub use deviation::surface_deviation;
+pub use normals::{compute_flat_normals, compute_smooth_normals};
+pub use optimize::{cache_miss_ratio, optimize_overdraw, optimize_vertex_cache, optimize_vertex_fetch};
+pub use simplify::{build_lod_chain, simplify, MeshLod};
+pub use tangents::generate_tangents;
+pub use weld::{deduplicate_vertices, weld_vertices};
+
+use glam::Vec3;
+
+use crate::mesh::Vertex;
+
+/// Applies a remap returned by the functions of this module to another per
+/// vertex stream, e.g. joints or morph targets.
+pub fn remap_vertices<T: Clone>(values: &[T], remap: &[u32]) -> Vec<T> {
+    remap.iter().map(|old| values[*old as usize].clone()).collect()
+}
+
+fn position(vertex: &Vertex) -> Vec3 {
+    vertex.pos.truncate()
+}
+
+/// Bit pattern of every attribute, used to find identical vertices.
+fn vertex_bits(vertex: &Vertex) -> [u32; 18] {
+    let mut bits = [0; 18];
+    let values = vertex
+        .pos
+        .to_array()
+        .into_iter()
+        .chain(vertex.normal.to_array())
+        .chain(vertex.uv.to_array())
+        .chain(vertex.color.to_array())
+        .chain(vertex.tangent.to_array());
+    for (bits, value) in bits.iter_mut().zip(values) {
+        // +0.0 and -0.0 are the same vertex.
+        *bits = (value + 0.0).to_bits();
+    }
+    bits
+}
+
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use glam::{Vec2, Vec3, Vec4};
+
+    use crate::mesh::Vertex;
+
+    pub fn vertex(pos: Vec3) -> Vertex {
+        Vertex {
+            pos: pos.extend(0.0),
+            normal: Vec4::ZERO,
+            uv: Vec2::ZERO,
+            color: Vec4::ONE,
+            tangent: Vec4::ZERO,
+        }
+    }
+
+    /// Flat `size` x `size` quad grid in the XY plane with shared vertices.
+    pub fn grid(size: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let mut vertices = vec![];
+        for y in 0..=size {
+            for x in 0..=size {
+                let mut v = vertex(Vec3::new(x as f32, y as f32, 0.0));
+                v.uv = Vec2::new(x as f32, y as f32) / size as f32;
+                v.normal = Vec4::Z;
+                vertices.push(v);
+            }
+        }
+        let mut indices = vec![];
+        let row = size + 1;
+        for y in 0..size {
+            for x in 0..size {
+                let i = y * row + x;
+                indices.extend_from_slice(&[i, i + 1, i + row, i + 1, i + row + 1, i + row]);
+            }
+        }
+        (vertices, indices)
+    }
+
+    /// Closed unit sphere made by subdividing an octahedron `levels` times.
+    pub fn sphere(levels: u32) -> (Vec<Vertex>, Vec<u32>) {
+        let mut positions = vec![Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z];
+        let mut indices: Vec<u32> = vec![
+            0, 2, 4, 2, 1, 4, 1, 3, 4, 3, 0, 4, 2, 0, 5, 1, 2, 5, 3, 1, 5, 0, 3, 5,
+        ];
+        for _ in 0..levels {
+            let mut midpoints = std::collections::HashMap::new();
+            let mut midpoint = |a: u32, b: u32, positions: &mut Vec<Vec3>| {
+                *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                    positions.push((positions[a as usize] + positions[b as usize]).normalize());
+                    positions.len() as u32 - 1
+                })
+            };
+            let mut next = vec![];
+            for t in indices.chunks(3) {
+                let ab = midpoint(t[0], t[1], &mut positions);
+                let bc = midpoint(t[1], t[2], &mut positions);
+                let ca = midpoint(t[2], t[0], &mut positions);
+                next.extend_from_slice(&[t[0], ab, ca, ab, t[1], bc, ca, bc, t[2], ab, bc, ca]);
+            }
+            indices = next;
+        }
+        let vertices = positions
+            .into_iter()
+            .map(|p| {
+                let mut v = vertex(p);
+                v.normal = p.extend(0.0);
+                v
+            })
+            .collect();
+        (vertices, indices)
+    }
+
+    /// Triangles as sorted corner lists, to compare meshes regardless of order.
+    pub fn triangle_set(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = indices
+            .chunks(3)
+            .map(|t| {
+                let mut t = [t[0], t[1], t[2]];
+                t.sort();
+                t
+            })
+            .collect();
+        triangles.sort();
+        triangles
+    }
+}
@@ -0,0 +1,103 @@
+use std::collections::HashMap;
+
+use glam::Vec3;
+
+use super::position;
+use crate::mesh::Vertex;
+
+fn face_normal(vertices: &[Vertex], triangle: &[u32]) -> Vec3 {
+    let [a, b, c] = [0, 1, 2].map(|i| position(&vertices[triangle[i] as usize]));
+    (b - a).cross(c - a)
+}
+
+/// Area weighted vertex normals. Vertices sharing a position are smoothed
+/// together so UV seams don't show up in the shading.
+pub fn compute_smooth_normals(vertices: &mut [Vertex], indices: &[u32]) {
+    let key = |vertex: &Vertex| position(vertex).to_array().map(|v| (v + 0.0).to_bits());
+    let mut sums: HashMap<[u32; 3], Vec3> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        // The cross product's length is twice the triangle's area.
+        let normal = face_normal(vertices, triangle);
+        for index in triangle {
+            *sums.entry(key(&vertices[*index as usize])).or_insert(Vec3::ZERO) += normal;
+        }
+    }
+    for vertex in vertices.iter_mut() {
+        if let Some(sum) = sums.get(&key(vertex)) {
+            vertex.normal = sum.normalize_or_zero().extend(0.0);
+        }
+    }
+}
+
+/// Gives every triangle its face normal. A vertex stays shared only between
+/// triangles facing the same way, so the vertex count grows. Returns the
+/// original vertex of every new one.
+pub fn compute_flat_normals(vertices: &mut Vec<Vertex>, indices: &mut [u32]) -> Vec<u32> {
+    let mut corners: HashMap<(u32, [u32; 3]), u32> = HashMap::new();
+    let mut flat = vec![];
+    let mut remap = vec![];
+    for triangle in indices.chunks_exact_mut(3) {
+        let normal = face_normal(vertices, triangle).normalize_or_zero();
+        let bits = normal.to_array().map(|v| (v + 0.0).to_bits());
+        for index in triangle {
+            *index = *corners.entry((*index, bits)).or_insert_with(|| {
+                let mut vertex = vertices[*index as usize];
+                vertex.normal = normal.extend(0.0);
+                flat.push(vertex);
+                remap.push(*index);
+                flat.len() as u32 - 1
+            });
+        }
+    }
+    *vertices = flat;
+    remap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh_processing::fixtures::vertex;
+    use glam::Vec4;
+
+    /// Unit cube with one shared vertex per corner.
+    fn cube() -> (Vec<Vertex>, Vec<u32>) {
+        let vertices = (0..8)
+            .map(|i| vertex(Vec3::new((i & 1) as f32, ((i >> 1) & 1) as f32, (i >> 2) as f32)))
+            .collect();
+        let indices = vec![
+            0, 2, 1, 1, 2, 3, // -Z
+            4, 5, 6, 5, 7, 6, // +Z
+            0, 1, 4, 1, 5, 4, // -Y
+            2, 6, 3, 3, 6, 7, // +Y
+            0, 4, 2, 2, 4, 6, // -X
+            1, 3, 5, 3, 7, 5, // +X
+        ];
+        (vertices, indices)
+    }
+
+    #[test]
+    fn smooth_normals_average_the_corner() {
+        let (mut vertices, indices) = cube();
+        compute_smooth_normals(&mut vertices, &indices);
+        let expected = Vec3::new(1.0, 1.0, 1.0).normalize();
+        assert!(vertices[7].normal.truncate().abs_diff_eq(expected, 1e-6));
+        assert!(vertices[0].normal.truncate().abs_diff_eq(-expected, 1e-6));
+    }
+
+    #[test]
+    fn flat_normals_split_corners_per_face() {
+        let (mut vertices, mut indices) = cube();
+        let remap = compute_flat_normals(&mut vertices, &mut indices);
+
+        assert_eq!(vertices.len(), 24);
+        assert_eq!(remap.len(), 24);
+        for triangle in indices.chunks(3) {
+            let normal = face_normal(&vertices, triangle).normalize();
+            for index in triangle {
+                assert_eq!(vertices[*index as usize].normal, normal.extend(0.0));
+            }
+        }
+        assert_eq!(vertices[indices[0] as usize].normal, Vec4::new(0.0, 0.0, -1.0, 0.0));
+        assert_eq!(remap[indices[0] as usize], 0);
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::VecDeque;
+
+use glam::Vec3;
+
+use super::position;
+use crate::mesh::Vertex;
+
+/// Size of the simulated post-transform cache.
+const CACHE_SIZE: usize = 32;
+
+/// Forsyth's vertex score: recently used vertices and vertices with few
+/// triangles left score highest.
+fn vertex_score(cache_position: Option<usize>, remaining: u32) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache = match cache_position {
+        None => 0.0,
+        // The last triangle's vertices are scored lower so strips don't
+        // double back on themselves.
+        Some(position) if position < 3 => 0.75,
+        Some(position) => {
+            let scaled = 1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32;
+            scaled.powf(1.5)
+        }
+    };
+    cache + 2.0 * (remaining as f32).powf(-0.5)
+}
+
+/// Reorders triangles for the post-transform vertex cache using Tom
+/// Forsyth's linear-speed algorithm.
+pub fn optimize_vertex_cache(indices: &mut [u32], vertex_count: usize) {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return;
+    }
+
+    // Triangles of every vertex, packed into one array.
+    let mut remaining = vec![0u32; vertex_count];
+    for index in &indices[..triangle_count * 3] {
+        remaining[*index as usize] += 1;
+    }
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for vertex in 0..vertex_count {
+        offsets[vertex + 1] = offsets[vertex] + remaining[vertex] as usize;
+    }
+    let mut adjacency = vec![0u32; offsets[vertex_count]];
+    let mut fill = offsets.clone();
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        for index in corners {
+            adjacency[fill[*index as usize]] = triangle as u32;
+            fill[*index as usize] += 1;
+        }
+    }
+
+    let mut cache_position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut scores: Vec<f32> = (0..vertex_count).map(|v| vertex_score(None, remaining[v])).collect();
+    let triangle_score = |triangle: usize, scores: &[f32], indices: &[u32]| -> f32 {
+        indices[triangle * 3..triangle * 3 + 3].iter().map(|i| scores[*i as usize]).sum()
+    };
+    let mut triangle_scores: Vec<f32> = (0..triangle_count)
+        .map(|t| triangle_score(t, &scores, indices))
+        .collect();
+    let mut emitted = vec![false; triangle_count];
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut output = Vec::with_capacity(triangle_count * 3);
+    let mut best = (0..triangle_count).max_by(|a, b| triangle_scores[*a].total_cmp(&triangle_scores[*b]));
+    let mut cursor = 0;
+
+    while output.len() < triangle_count * 3 {
+        let triangle = match best {
+            Some(triangle) => triangle,
+            None => {
+                // Nothing left around the cache, continue with the next
+                // triangle in input order.
+                while emitted[cursor] {
+                    cursor += 1;
+                }
+                cursor
+            }
+        };
+        emitted[triangle] = true;
+        let corners = [indices[triangle * 3], indices[triangle * 3 + 1], indices[triangle * 3 + 2]];
+        output.extend_from_slice(&corners);
+
+        for vertex in corners {
+            let vertex = vertex as usize;
+            let list = &mut adjacency[offsets[vertex]..offsets[vertex] + remaining[vertex] as usize];
+            if let Some(slot) = list.iter().position(|t| *t as usize == triangle) {
+                list.swap(slot, remaining[vertex] as usize - 1);
+                remaining[vertex] -= 1;
+            }
+        }
+
+        // Most recently used first, the evicted tail keeps its entries
+        // until it has been rescored.
+        cache.retain(|v| !corners.contains(v));
+        for vertex in corners.iter().rev() {
+            cache.insert(0, *vertex);
+        }
+        for (position, vertex) in cache.iter().enumerate() {
+            let vertex = *vertex as usize;
+            cache_position[vertex] = (position < CACHE_SIZE).then_some(position);
+            scores[vertex] = vertex_score(cache_position[vertex], remaining[vertex]);
+        }
+
+        best = None;
+        let mut best_score = -1.0;
+        for vertex in &cache {
+            let vertex = *vertex as usize;
+            for t in &adjacency[offsets[vertex]..offsets[vertex] + remaining[vertex] as usize] {
+                let t = *t as usize;
+                triangle_scores[t] = triangle_score(t, &scores, indices);
+                if triangle_scores[t] > best_score {
+                    best_score = triangle_scores[t];
+                    best = Some(t);
+                }
+            }
+        }
+        cache.truncate(CACHE_SIZE);
+    }
+
+    indices[..output.len()].copy_from_slice(&output);
+}
+
+/// Average number of vertices transformed per triangle with a FIFO cache of
+/// `cache_size` entries, 3.0 being the worst and 0.5 about the best a
+/// regular mesh gets.
+pub fn cache_miss_ratio(indices: &[u32], vertex_count: usize, cache_size: usize) -> f32 {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return 0.0;
+    }
+    let mut cached = vec![false; vertex_count];
+    let mut fifo = VecDeque::with_capacity(cache_size + 1);
+    let mut misses = 0;
+    for index in indices {
+        let index = *index as usize;
+        if cached[index] {
+            continue;
+        }
+        misses += 1;
+        cached[index] = true;
+        fifo.push_back(index);
+        if fifo.len() > cache_size {
+            cached[fifo.pop_front().unwrap()] = false;
+        }
+    }
+    misses as f32 / triangle_count as f32
+}
+
+/// Reorders clusters of a cache optimized index buffer so outward facing
+/// parts of the mesh are drawn first, cutting overdraw without undoing
+/// [`optimize_vertex_cache`]. Clusters start where a triangle misses the
+/// cache on all three vertices.
+pub fn optimize_overdraw(indices: &mut [u32], vertices: &[Vertex]) {
+    let triangle_count = indices.len() / 3;
+    if triangle_count == 0 {
+        return;
+    }
+
+    let mut clusters = vec![0];
+    let mut cached = vec![false; vertices.len()];
+    let mut fifo = VecDeque::with_capacity(CACHE_SIZE + 1);
+    for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+        let mut misses = 0;
+        for index in corners {
+            let index = *index as usize;
+            if !cached[index] {
+                misses += 1;
+                cached[index] = true;
+                fifo.push_back(index);
+                if fifo.len() > CACHE_SIZE {
+                    cached[fifo.pop_front().unwrap()] = false;
+                }
+            }
+        }
+        if misses == 3 && triangle > 0 {
+            clusters.push(triangle);
+        }
+    }
+    clusters.push(triangle_count);
+
+    let mesh_centroid = vertices.iter().map(position).fold(Vec3::ZERO, |sum, p| sum + p)
+        / vertices.len().max(1) as f32;
+    let mut sorted: Vec<(f32, &[u32])> = clusters
+        .windows(2)
+        .map(|range| {
+            let cluster = &indices[range[0] * 3..range[1] * 3];
+            let mut centroid = Vec3::ZERO;
+            let mut normal = Vec3::ZERO;
+            let mut area = 0.0;
+            for triangle in cluster.chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| position(&vertices[triangle[i] as usize]));
+                let area_normal = (b - a).cross(c - a);
+                centroid += (a + b + c) / 3.0 * area_normal.length();
+                normal += area_normal;
+                area += area_normal.length();
+            }
+            let centroid = centroid / area.max(f32::EPSILON);
+            let facing = (centroid - mesh_centroid).dot(normal.normalize_or_zero());
+            // Clusters facing away from the centre go first.
+            (-facing, cluster)
+        })
+        .collect();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let reordered: Vec<u32> = sorted.iter().flat_map(|(_, cluster)| cluster.iter().copied()).collect();
+    indices[..reordered.len()].copy_from_slice(&reordered);
+}
+
+/// Reorders vertices in the order the indices first reach them, dropping
+/// unreferenced ones. Returns the original vertex of every new one.
+pub fn optimize_vertex_fetch(vertices: &mut Vec<Vertex>, indices: &mut [u32]) -> Vec<u32> {
+    let mut new_index = vec![u32::MAX; vertices.len()];
+    let mut remap = vec![];
+    for index in indices.iter_mut() {
+        if new_index[*index as usize] == u32::MAX {
+            new_index[*index as usize] = remap.len() as u32;
+            remap.push(*index);
+        }
+        *index = new_index[*index as usize];
+    }
+    *vertices = remap.iter().map(|old| vertices[*old as usize]).collect();
+    remap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh_processing::fixtures::{grid, sphere, triangle_set};
+
+    /// Deterministic shuffle of the triangles, keeping each one's winding.
+    fn shuffle_triangles(indices: &mut Vec<u32>) {
+        let mut triangles: Vec<[u32; 3]> = indices.chunks(3).map(|t| [t[0], t[1], t[2]]).collect();
+        let mut state = 12345u32;
+        for i in (1..triangles.len()).rev() {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            triangles.swap(i, state as usize % (i + 1));
+        }
+        *indices = triangles.concat();
+    }
+
+    #[test]
+    fn vertex_cache_order_keeps_triangles_and_cuts_misses() {
+        let (vertices, mut indices) = grid(32);
+        shuffle_triangles(&mut indices);
+        let before = cache_miss_ratio(&indices, vertices.len(), 16);
+        let triangles = triangle_set(&indices);
+
+        optimize_vertex_cache(&mut indices, vertices.len());
+        let after = cache_miss_ratio(&indices, vertices.len(), 16);
+
+        assert_eq!(triangle_set(&indices), triangles);
+        assert!(before > 2.0, "{before}");
+        assert!(after < 0.8, "{after}");
+    }
+
+    #[test]
+    fn overdraw_order_keeps_triangles() {
+        let (vertices, mut indices) = sphere(3);
+        shuffle_triangles(&mut indices);
+        optimize_vertex_cache(&mut indices, vertices.len());
+        let triangles = triangle_set(&indices);
+        let ratio = cache_miss_ratio(&indices, vertices.len(), CACHE_SIZE);
+
+        optimize_overdraw(&mut indices, &vertices);
+
+        assert_eq!(triangle_set(&indices), triangles);
+        assert!(cache_miss_ratio(&indices, vertices.len(), CACHE_SIZE) <= ratio * 1.05);
+    }
+
+    #[test]
+    fn vertex_fetch_follows_first_use() {
+        let (mut vertices, _) = grid(1);
+        let mut indices = vec![3, 1, 2];
+        let remap = optimize_vertex_fetch(&mut vertices, &mut indices);
+        assert_eq!(indices, vec![0, 1, 2]);
+        assert_eq!(remap, vec![3, 1, 2]);
+        assert_eq!(vertices.len(), 3);
+    }
+}
@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+
+use glam::DVec3;
+
+use crate::mesh::Vertex;
+
+/// Area weighted error quadric of Garland and Heckbert, storing the
+/// symmetric 4x4 matrix as `xx xy xz xw yy yz yw zz zw ww` followed by the
+/// total weight.
+#[derive(Clone, Copy, Default)]
+struct Quadric([f64; 11]);
+
+impl Quadric {
+    fn from_plane(normal: DVec3, distance: f64, weight: f64) -> Self {
+        let [a, b, c] = normal.to_array();
+        let d = distance;
+        Quadric([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d, 1.0].map(|v| v * weight))
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (a, b) in self.0.iter_mut().zip(other.0) {
+            *a += b;
+        }
+    }
+
+    /// Weighted mean of the squared distances from `p` to the quadric's planes.
+    fn error(&self, p: DVec3) -> f64 {
+        let [xx, xy, xz, xw, yy, yz, yw, zz, zw, ww, weight] = self.0;
+        if weight <= 0.0 {
+            return 0.0;
+        }
+        let (x, y, z) = (p.x, p.y, p.z);
+        let error = xx * x * x + 2.0 * xy * x * y + 2.0 * xz * x * z + 2.0 * xw * x
+            + yy * y * y + 2.0 * yz * y * z + 2.0 * yw * y
+            + zz * z * z + 2.0 * zw * z
+            + ww;
+        (error / weight).max(0.0)
+    }
+}
+
+/// One level of detail, all levels sharing the original vertex buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshLod {
+    pub indices: Vec<u32>,
+    /// How far the level strays from the original surface, in model units.
+    pub error: f32,
+}
+
+fn positions(vertices: &[Vertex]) -> Vec<DVec3> {
+    vertices.iter().map(|v| v.pos.truncate().as_dvec3()).collect()
+}
+
+/// Vertices that may not move: those on open borders and those sharing their
+/// position with another vertex, i.e. UV or normal seams.
+fn locked_vertices(positions: &[DVec3], indices: &[u32]) -> Vec<bool> {
+    let mut canonical: HashMap<[u64; 3], u32> = HashMap::new();
+    let mut wedges = vec![0u32; positions.len()];
+    let ids: Vec<u32> = positions
+        .iter()
+        .enumerate()
+        .map(|(i, p)| {
+            let id = *canonical.entry(p.to_array().map(|v| (v + 0.0).to_bits())).or_insert(i as u32);
+            wedges[id as usize] += 1;
+            id
+        })
+        .collect();
+
+    let mut edges: HashMap<(u32, u32), u32> = HashMap::new();
+    for triangle in indices.chunks_exact(3) {
+        for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+            let (a, b) = (ids[triangle[a] as usize], ids[triangle[b] as usize]);
+            *edges.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+        }
+    }
+
+    let mut locked: Vec<bool> = ids.iter().map(|id| wedges[*id as usize] > 1).collect();
+    let mut border = vec![false; positions.len()];
+    for ((a, b), count) in edges {
+        if count == 1 {
+            border[a as usize] = true;
+            border[b as usize] = true;
+        }
+    }
+    for (vertex, id) in ids.iter().enumerate() {
+        locked[vertex] |= border[*id as usize];
+    }
+    locked
+}
+
+/// Collapses edges in order of quadric error until at most
+/// `target_index_count` indices are left or every remaining collapse would
+/// stray more than `max_error` from the surface. Collapses keep one of the
+/// edge's vertices so attributes never need interpolating. Returns the new
+/// indices and the error reached, in model units.
+pub fn simplify(vertices: &[Vertex], indices: &[u32], target_index_count: usize, max_error: f32) -> (Vec<u32>, f32) {
+    let positions = positions(vertices);
+    let locked = locked_vertices(&positions, indices);
+    let mut indices = indices[..indices.len() / 3 * 3].to_vec();
+
+    let mut quadrics = vec![Quadric::default(); positions.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| positions[triangle[i] as usize]);
+        let normal = (b - a).cross(c - a);
+        let area = normal.length() * 0.5;
+        if area > 0.0 {
+            let normal = normal.normalize();
+            let quadric = Quadric::from_plane(normal, -normal.dot(a), area);
+            for index in triangle {
+                quadrics[*index as usize].add(&quadric);
+            }
+        }
+    }
+
+    let max_cost = (max_error as f64).powi(2);
+    let mut reached = 0.0f64;
+    let mut remap: Vec<u32> = (0..positions.len() as u32).collect();
+    while indices.len() > target_index_count {
+        let mut adjacency: Vec<Vec<usize>> = vec![vec![]; positions.len()];
+        for (triangle, corners) in indices.chunks_exact(3).enumerate() {
+            for index in corners {
+                adjacency[*index as usize].push(triangle);
+            }
+        }
+
+        let mut collapses = vec![];
+        for triangle in indices.chunks_exact(3) {
+            for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+                for (from, to) in [(triangle[a], triangle[b]), (triangle[b], triangle[a])] {
+                    if locked[from as usize] {
+                        continue;
+                    }
+                    let mut quadric = quadrics[from as usize];
+                    quadric.add(&quadrics[to as usize]);
+                    collapses.push((quadric.error(positions[to as usize]), from, to));
+                }
+            }
+        }
+        collapses.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut touched = vec![false; positions.len()];
+        let mut triangles_left = indices.len() / 3;
+        let mut collapsed = 0;
+        for (cost, from, to) in collapses {
+            if cost > max_cost || triangles_left * 3 <= target_index_count {
+                break;
+            }
+            if touched[from as usize] || touched[to as usize] {
+                continue;
+            }
+            if flips(&positions, &indices, &adjacency[from as usize], from, to) {
+                continue;
+            }
+
+            // Everything around `from` changes, so it sits out the rest of
+            // this pass.
+            for triangle in &adjacency[from as usize] {
+                let corners = &indices[triangle * 3..triangle * 3 + 3];
+                for index in corners {
+                    touched[*index as usize] = true;
+                }
+                if corners.contains(&to) {
+                    triangles_left -= 1;
+                }
+            }
+            remap[from as usize] = to;
+            let from_quadric = quadrics[from as usize];
+            quadrics[to as usize].add(&from_quadric);
+            reached = reached.max(cost);
+            collapsed += 1;
+        }
+        if collapsed == 0 {
+            break;
+        }
+
+        for index in indices.iter_mut() {
+            *index = remap[*index as usize];
+        }
+        for (vertex, target) in remap.iter_mut().enumerate() {
+            *target = vertex as u32;
+        }
+        indices = indices
+            .chunks_exact(3)
+            .filter(|t| t[0] != t[1] && t[1] != t[2] && t[2] != t[0])
+            .flatten()
+            .copied()
+            .collect();
+    }
+
+    (indices, reached.sqrt() as f32)
+}
+
+/// Whether moving `from` onto `to` turns any surviving triangle around.
+fn flips(positions: &[DVec3], indices: &[u32], triangles: &[usize], from: u32, to: u32) -> bool {
+    triangles.iter().any(|triangle| {
+        let corners = &indices[triangle * 3..triangle * 3 + 3];
+        if corners.contains(&to) {
+            return false;
+        }
+        let [a, b, c] = [0, 1, 2].map(|i| positions[corners[i] as usize]);
+        let moved = [0, 1, 2].map(|i| match corners[i] == from {
+            true => positions[to as usize],
+            false => positions[corners[i] as usize],
+        });
+        let before = (b - a).cross(c - a);
+        let after = (moved[1] - moved[0]).cross(moved[2] - moved[0]);
+        before.dot(after) <= 0.0
+    })
+}
+
+/// Simplifies `indices` level by level, each one aiming at `reduction`
+/// times the indices of the previous one. Stops after `max_levels` levels,
+/// including the original, once a level would exceed `max_error`, or once
+/// simplification stops making progress.
+pub fn build_lod_chain(
+    vertices: &[Vertex],
+    indices: &[u32],
+    max_levels: usize,
+    reduction: f32,
+    max_error: f32,
+) -> Vec<MeshLod> {
+    let mut lods = vec![MeshLod {
+        indices: indices.to_vec(),
+        error: 0.0,
+    }];
+    while lods.len() < max_levels {
+        let last = lods.last().unwrap();
+        let target = (last.indices.len() as f32 * reduction) as usize / 3 * 3;
+        let (indices, error) = simplify(vertices, &last.indices, target, max_error - last.error);
+        if indices.is_empty() || indices.len() as f32 > last.indices.len() as f32 * 0.95 {
+            break;
+        }
+        // Errors of successive levels add up at worst.
+        let error = last.error + error;
+        lods.push(MeshLod { indices, error });
+    }
+    lods
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh_processing::fixtures::{grid, sphere};
+
+    fn area(vertices: &[Vertex], indices: &[u32]) -> f32 {
+        indices
+            .chunks(3)
+            .map(|t| {
+                let [a, b, c] = [0, 1, 2].map(|i| vertices[t[i] as usize].pos.truncate());
+                (b - a).cross(c - a).length() * 0.5
+            })
+            .sum()
+    }
+
+    #[test]
+    fn flat_interiors_collapse_for_free() {
+        let (vertices, indices) = grid(8);
+        let (simplified, error) = simplify(&vertices, &indices, 0, 1e-4);
+
+        assert!(simplified.len() < indices.len() / 2, "{}", simplified.len());
+        assert!(error < 1e-4);
+        // The locked border keeps the outline, so the covered area is unchanged.
+        assert!((area(&vertices, &simplified) - 64.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn curved_surfaces_respect_the_error_bound() {
+        let (vertices, indices) = sphere(3);
+        let (coarse, error) = simplify(&vertices, &indices, indices.len() / 4, 1.0);
+        assert!(coarse.len() <= indices.len() / 4);
+        assert!(error > 0.0 && error < 0.5, "{error}");
+
+        let (bounded, bounded_error) = simplify(&vertices, &indices, 0, 0.01);
+        assert!(bounded_error <= 0.01);
+        assert!(bounded.len() > coarse.len());
+    }
+
+    #[test]
+    fn lod_chain_shrinks_and_accumulates_error() {
+        let (vertices, indices) = sphere(4);
+        let lods = build_lod_chain(&vertices, &indices, 4, 0.5, 1.0);
+
+        assert_eq!(lods.len(), 4);
+        assert_eq!(lods[0].indices, indices);
+        for pair in lods.windows(2) {
+            assert!(pair[1].indices.len() < pair[0].indices.len());
+            assert!(pair[1].error >= pair[0].error);
+        }
+        assert!(lods[3].indices.len() <= indices.len() / 8 + 3);
+    }
+}
@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+
+use glam::Vec4;
+
+use crate::mesh::Vertex;
+
+/// Unwelded view of a mesh for the MikkTSpace generator, one tangent per
+/// triangle corner.
+struct Corners<'a> {
+    vertices: &'a [Vertex],
+    indices: &'a [u32],
+    tangents: Vec<Vec4>,
+}
+
+impl Corners<'_> {
+    fn vertex(&self, face: usize, corner: usize) -> &Vertex {
+        &self.vertices[self.indices[face * 3 + corner] as usize]
+    }
+}
+
+impl bevy_mikktspace::Geometry for Corners<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).pos.truncate().to_array()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal.truncate().to_array()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).uv.to_array()
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = Vec4::from(tangent);
+    }
+}
+
+/// MikkTSpace tangents from positions, normals and UVs, `w` holding the
+/// bitangent sign. Vertices whose corners end up with different tangents,
+/// e.g. on mirrored UVs, are split. Returns the original vertex of every new
+/// one, or `None` with the mesh untouched if no tangents could be built.
+pub fn generate_tangents(vertices: &mut Vec<Vertex>, indices: &mut [u32]) -> Option<Vec<u32>> {
+    let mut corners = Corners {
+        vertices: vertices.as_slice(),
+        indices: &*indices,
+        tangents: vec![Vec4::ZERO; indices.len() / 3 * 3],
+    };
+    if !bevy_mikktspace::generate_tangents(&mut corners) {
+        return None;
+    }
+    let tangents = corners.tangents;
+
+    let mut split: HashMap<(u32, [u32; 4]), u32> = HashMap::new();
+    let mut tangent_vertices = vec![];
+    let mut remap = vec![];
+    for (index, tangent) in indices.iter_mut().zip(tangents) {
+        let bits = tangent.to_array().map(|v| (v + 0.0).to_bits());
+        *index = *split.entry((*index, bits)).or_insert_with(|| {
+            let mut vertex = vertices[*index as usize];
+            vertex.tangent = tangent;
+            tangent_vertices.push(vertex);
+            remap.push(*index);
+            tangent_vertices.len() as u32 - 1
+        });
+    }
+    *vertices = tangent_vertices;
+    Some(remap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh_processing::fixtures::grid;
+    use glam::Vec2;
+
+    #[test]
+    fn tangents_follow_the_u_direction() {
+        let (mut vertices, mut indices) = grid(2);
+        let remap = generate_tangents(&mut vertices, &mut indices).unwrap();
+
+        assert_eq!(vertices.len(), 9);
+        assert_eq!(remap, vec![0, 1, 3, 4, 2, 5, 6, 7, 8]);
+        for vertex in &vertices {
+            assert!(vertex.tangent.abs_diff_eq(Vec4::new(1.0, 0.0, 0.0, 1.0), 1e-5));
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_split_vertices() {
+        let (mut vertices, mut indices) = grid(2);
+        // Mirror U on the right column so the middle column is shared by
+        // both orientations.
+        for vertex in vertices.iter_mut() {
+            if vertex.pos.x > 1.5 {
+                vertex.uv = Vec2::new(0.0, vertex.uv.y);
+            }
+        }
+        generate_tangents(&mut vertices, &mut indices).unwrap();
+        assert!(vertices.len() > 9);
+        assert!(vertices.iter().any(|v| v.tangent.x < -0.5));
+    }
+}
@@ -0,0 +1,78 @@
+use std::collections::HashMap;
+
+use super::vertex_bits;
+use crate::mesh::Vertex;
+
+/// Merges vertices whose attributes are bit for bit identical.
+pub fn deduplicate_vertices(vertices: &mut Vec<Vertex>, indices: &mut [u32]) -> Vec<u32> {
+    merge_vertices(vertices, indices, vertex_bits)
+}
+
+/// Merges vertices whose attributes all lie within `tolerance` of each
+/// other, snapped to a grid of that size. The first vertex of a group is
+/// kept. Vertices no index refers to are dropped.
+pub fn weld_vertices(vertices: &mut Vec<Vertex>, indices: &mut [u32], tolerance: f32) -> Vec<u32> {
+    if tolerance <= 0.0 {
+        return deduplicate_vertices(vertices, indices);
+    }
+    merge_vertices(vertices, indices, |vertex| {
+        vertex_bits(vertex).map(|bits| (f32::from_bits(bits) / tolerance).round() as i32 as u32)
+    })
+}
+
+/// Keeps the first vertex per key, in the order the indices reach them.
+fn merge_vertices<K: std::hash::Hash + Eq>(
+    vertices: &mut Vec<Vertex>,
+    indices: &mut [u32],
+    key: impl Fn(&Vertex) -> K,
+) -> Vec<u32> {
+    let mut merged = vec![];
+    let mut remap = vec![];
+    let mut seen: HashMap<K, u32> = HashMap::new();
+    for index in indices.iter_mut() {
+        let vertex = &vertices[*index as usize];
+        *index = *seen.entry(key(vertex)).or_insert_with(|| {
+            merged.push(*vertex);
+            remap.push(*index);
+            merged.len() as u32 - 1
+        });
+    }
+    *vertices = merged;
+    remap
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh_processing::fixtures::vertex;
+    use glam::Vec3;
+
+    fn quad() -> (Vec<Vertex>, Vec<u32>) {
+        let corners = [Vec3::ZERO, Vec3::X, Vec3::Y, Vec3::X, Vec3::new(1.0, 1.0, 0.0), Vec3::Y];
+        (corners.map(vertex).to_vec(), (0..6).collect())
+    }
+
+    #[test]
+    fn identical_vertices_are_shared() {
+        let (mut vertices, mut indices) = quad();
+        let remap = deduplicate_vertices(&mut vertices, &mut indices);
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices, vec![0, 1, 2, 1, 3, 2]);
+        assert_eq!(remap, vec![0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn welding_tolerates_small_differences() {
+        let (mut vertices, mut indices) = quad();
+        vertices[3].pos.x += 1e-5;
+        vertices[5].uv.x = 1e-5;
+
+        let mut exact = (vertices.clone(), indices.clone());
+        deduplicate_vertices(&mut exact.0, &mut exact.1);
+        assert_eq!(exact.0.len(), 6);
+
+        weld_vertices(&mut vertices, &mut indices, 1e-3);
+        assert_eq!(vertices.len(), 4);
+        assert_eq!(indices, vec![0, 1, 2, 1, 3, 2]);
+    }
+}
@@ -0,0 +1,186 @@
+//! Wavefront OBJ import into the same [`CpuModel`] the glTF loader builds,
+//! one mesh and node per OBJ object. MTL materials are mapped onto the
+//! metallic-roughness [`Material`].
+
+use std::{collections::HashMap, path::Path};
+
+use glam::{Vec2, Vec3, Vec4};
+
+use crate::{
+    animation::NodeTransform,
+    gltf_loader::{CpuImage, CpuMesh, CpuModel, CpuNode, Material, MaterialExtensions, DEFAULT_TEXTURE_MAP},
+    mesh::Vertex,
+    mesh_processing::{compute_smooth_normals, generate_tangents},
+    texture_processing::decode_image,
+};
+
+/// Index of the image at `path` in `images`, loading it on first use. Maps
+/// that fail to load fall back to the default texture.
+fn load_map(
+    dir: &Path,
+    path: &str,
+    images: &mut Vec<CpuImage>,
+    loaded: &mut HashMap<String, u32>,
+) -> u32 {
+    if path.is_empty() {
+        return DEFAULT_TEXTURE_MAP;
+    }
+    if let Some(index) = loaded.get(path) {
+        return *index;
+    }
+    let index = match decode_image(&dir.join(path)) {
+        Ok(image) => {
+            images.push(image);
+            images.len() as u32 - 1
+        }
+        Err(err) => {
+            log::warn!("Loading texture {} failed: {}", path, err);
+            DEFAULT_TEXTURE_MAP
+        }
+    };
+    loaded.insert(path.to_owned(), index);
+    index
+}
+
+fn convert_material(material: &tobj::Material, diffuse_map: u32, normal_map: u32) -> Material {
+    Material {
+        diffuse_map,
+        normal_map,
+        metallic_roughness_map: DEFAULT_TEXTURE_MAP,
+        occlusion_map: DEFAULT_TEXTURE_MAP,
+        base_color_factor: Vec3::from(material.diffuse).extend(material.dissolve),
+        metallic_factor: 0.0,
+        // The usual Blinn-Phong exponent to GGX roughness conversion.
+        roughness_factor: (2.0 / (material.shininess + 2.0)).sqrt(),
+    }
+}
+
+fn default_material() -> Material {
+    Material {
+        diffuse_map: DEFAULT_TEXTURE_MAP,
+        normal_map: DEFAULT_TEXTURE_MAP,
+        metallic_roughness_map: DEFAULT_TEXTURE_MAP,
+        occlusion_map: DEFAULT_TEXTURE_MAP,
+        base_color_factor: Vec4::ONE,
+        metallic_factor: 0.0,
+        roughness_factor: 1.0,
+    }
+}
+
+pub fn import_obj<P: AsRef<Path>>(path: P) -> Result<CpuModel, tobj::LoadError> {
+    let path = path.as_ref();
+    let (objects, materials) = tobj::load_obj(path)?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut images = vec![];
+    let mut loaded = HashMap::new();
+    let materials: Vec<Material> = materials
+        .iter()
+        .map(|material| {
+            let diffuse_map = load_map(dir, &material.diffuse_texture, &mut images, &mut loaded);
+            let normal_map = load_map(dir, &material.normal_texture, &mut images, &mut loaded);
+            convert_material(material, diffuse_map, normal_map)
+        })
+        .collect();
+
+    let mut model = CpuModel {
+        meshes: vec![],
+        nodes: vec![],
+        roots: vec![],
+        images,
+        skins: vec![],
+        animations: vec![],
+    };
+
+    for object in objects {
+        let mesh = object.mesh;
+        let mut vertices: Vec<Vertex> = mesh
+            .positions
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(i, position)| Vertex {
+                pos: Vec3::from_slice(position).extend(0.0),
+                normal: mesh
+                    .normals
+                    .get(i * 3..i * 3 + 3)
+                    .map_or(Vec3::ZERO, Vec3::from_slice)
+                    .extend(0.0),
+                // OBJ puts the UV origin at the bottom left.
+                uv: mesh
+                    .texcoords
+                    .get(i * 2..i * 2 + 2)
+                    .map_or(Vec2::ZERO, |uv| Vec2::new(uv[0], 1.0 - uv[1])),
+                color: Vec4::ONE,
+                tangent: Vec4::ZERO,
+            })
+            .collect();
+        let mut indices = mesh.indices;
+
+        let material = mesh
+            .material_id
+            .and_then(|id| materials.get(id))
+            .copied()
+            .unwrap_or_else(default_material);
+        if mesh.normals.is_empty() {
+            compute_smooth_normals(&mut vertices, &indices);
+        }
+        if !mesh.texcoords.is_empty() && material.normal_map != DEFAULT_TEXTURE_MAP {
+            generate_tangents(&mut vertices, &mut indices);
+        }
+
+        let node = model.nodes.len();
+        model.roots.push(node);
+        model.nodes.push(CpuNode {
+            name: Some(object.name),
+            parent: None,
+            children: vec![],
+            transform: NodeTransform::default(),
+            local_transform: glam::Mat4::IDENTITY,
+            world_transform: glam::Mat4::IDENTITY,
+            meshes: vec![model.meshes.len()],
+            skin: None,
+            camera: None,
+            light: None,
+            morph_weights: vec![],
+        });
+        model.meshes.push(CpuMesh {
+            indices,
+            vertices,
+            material,
+            material_extensions: MaterialExtensions::default(),
+            joints: vec![],
+            joint_weights: vec![],
+            morph_targets: vec![],
+            node,
+            lods: vec![],
+        });
+    }
+
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::model_path;
+
+    #[test]
+    fn objects_become_meshes_with_their_materials() {
+        let model = import_obj(model_path("quads.obj")).unwrap();
+        assert_eq!(model.meshes.len(), 2);
+        assert_eq!(model.nodes[0].name.as_deref(), Some("front"));
+
+        let front = &model.meshes[0];
+        assert_eq!(front.indices.len(), 6);
+        assert_eq!(front.vertices.len(), 4);
+        assert_eq!(front.material.base_color_factor, Vec4::new(1.0, 0.0, 0.0, 1.0));
+        // Normals are computed when the file has none.
+        assert!(front.vertices.iter().all(|v| v.normal.truncate().abs_diff_eq(Vec3::Z, 1e-6)));
+        assert_eq!(front.vertices[0].uv, Vec2::new(0.0, 1.0));
+
+        let back = &model.meshes[1];
+        assert_eq!(back.material.diffuse_map, DEFAULT_TEXTURE_MAP);
+        assert_eq!(back.material.base_color_factor, Vec4::ONE);
+        assert!(import_obj(model_path("does_not_exist.obj")).is_err());
+    }
+}
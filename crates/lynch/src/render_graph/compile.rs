@@ -0,0 +1,626 @@
+//! Ahead of recording, a frame's passes are reduced to what they read and
+//! write, see [`GraphDesc`], and compiled into the order they run in, see
+//! [`CompiledGraph`]. Nothing here touches the device.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use ash::vk;
+use vk_sync::AccessType;
+
+use super::{BufferId, TextureId};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum GraphResource {
+    Texture(TextureId),
+    Buffer(BufferId),
+}
+
+/// Part of a resource a pass touches, `None` meaning every layer or level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Subresource {
+    pub layer: Option<u32>,
+    pub mip: Option<u32>,
+}
+
+impl Subresource {
+    pub const ALL: Subresource = Subresource {
+        layer: None,
+        mip: None,
+    };
+
+    pub fn layer(layer: u32) -> Self {
+        Subresource {
+            layer: Some(layer),
+            mip: None,
+        }
+    }
+
+    fn overlaps(&self, other: &Subresource) -> bool {
+        let overlap = |a: Option<u32>, b: Option<u32>| a.is_none() || b.is_none() || a == b;
+        overlap(self.layer, other.layer) && overlap(self.mip, other.mip)
+    }
+
+    fn covers(&self, other: &Subresource) -> bool {
+        let cover = |a: Option<u32>, b: Option<u32>| a.is_none() || a == b;
+        cover(self.layer, other.layer) && cover(self.mip, other.mip)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    /// Replaces the previous contents.
+    Write,
+    /// Keeps the previous contents, like attachments that are loaded.
+    ReadWrite,
+}
+
+impl AccessKind {
+    pub fn from_access_type(access_type: AccessType) -> Self {
+        match access_type {
+            AccessType::ColorAttachmentReadWrite | AccessType::General => AccessKind::ReadWrite,
+            AccessType::VertexShaderWrite
+            | AccessType::TessellationControlShaderWrite
+            | AccessType::TessellationEvaluationShaderWrite
+            | AccessType::GeometryShaderWrite
+            | AccessType::FragmentShaderWrite
+            | AccessType::ColorAttachmentWrite
+            | AccessType::DepthStencilAttachmentWrite
+            | AccessType::DepthAttachmentWriteStencilReadOnly
+            | AccessType::StencilAttachmentWriteDepthReadOnly
+            | AccessType::ComputeShaderWrite
+            | AccessType::AnyShaderWrite
+            | AccessType::TransferWrite
+            | AccessType::HostWrite => AccessKind::Write,
+            _ => AccessKind::Read,
+        }
+    }
+
+    pub fn from_load_op(load_op: vk::AttachmentLoadOp) -> Self {
+        match load_op {
+            vk::AttachmentLoadOp::LOAD => AccessKind::ReadWrite,
+            _ => AccessKind::Write,
+        }
+    }
+
+    fn reads(self) -> bool {
+        self != AccessKind::Write
+    }
+
+    fn writes(self) -> bool {
+        self != AccessKind::Read
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PassAccess {
+    pub resource: GraphResource,
+    pub subresource: Subresource,
+    pub kind: AccessKind,
+}
+
+impl PassAccess {
+    pub fn new(resource: GraphResource, kind: AccessKind) -> Self {
+        PassAccess {
+            resource,
+            subresource: Subresource::ALL,
+            kind,
+        }
+    }
+}
+
+/// Attachment formats of a pass, and of the pipeline it was created with.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct AttachmentFormats {
+    pub color: Vec<vk::Format>,
+    /// `vk::Format::UNDEFINED` without a depth attachment.
+    pub depth: vk::Format,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PassDesc {
+    pub name: String,
+    pub presentation: bool,
+    /// In the order the pass performs them.
+    pub accesses: Vec<PassAccess>,
+    /// What the pass renders to, the swapchain for presentation passes.
+    pub attachments: AttachmentFormats,
+    /// What the pass's pipeline expects, `None` for compute pipelines.
+    pub pipeline: Option<AttachmentFormats>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ResourceDesc {
+    pub name: String,
+    /// Holds contents from an earlier frame or the host, so reading it before
+    /// any pass writes it is fine.
+    pub initialized: bool,
+}
+
+/// A frame of a [`super::RenderGraph`] as plain data.
+#[derive(Clone, Debug, Default)]
+pub struct GraphDesc {
+    pub passes: Vec<PassDesc>,
+    pub textures: Vec<ResourceDesc>,
+    pub buffers: Vec<ResourceDesc>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GraphDiagnostic {
+    /// `pass` reads a resource no pass writes and nothing initialized.
+    ReadBeforeWrite { pass: usize, resource: GraphResource },
+    /// `second` replaces what `first` wrote before anything read it.
+    WriteWrite {
+        first: usize,
+        second: usize,
+        resource: GraphResource,
+    },
+    AttachmentCount {
+        pass: usize,
+        pipeline: usize,
+        attachments: usize,
+    },
+    /// `attachment` is `None` for the depth attachment.
+    FormatMismatch {
+        pass: usize,
+        attachment: Option<usize>,
+        pipeline: vk::Format,
+        attachments: vk::Format,
+    },
+    /// Without a presentation pass nothing is culled.
+    NoPresentationPass,
+}
+
+/// The passes of a frame in execution order, without those whose outputs
+/// never reach the presentation pass.
+#[derive(Clone, Debug, Default)]
+pub struct CompiledGraph {
+    /// Indices of the passes to record, in order.
+    pub order: Vec<usize>,
+    /// Indices of the passes left out, in insertion order.
+    pub culled: Vec<usize>,
+    pub diagnostics: Vec<GraphDiagnostic>,
+}
+
+struct LiveWrite {
+    pass: usize,
+    subresource: Subresource,
+    read: bool,
+}
+
+impl GraphDesc {
+    fn resource(&self, resource: GraphResource) -> Option<&ResourceDesc> {
+        match resource {
+            GraphResource::Texture(id) => self.textures.get(id),
+            GraphResource::Buffer(id) => self.buffers.get(id),
+        }
+    }
+
+    fn initialized(&self, resource: GraphResource) -> bool {
+        self.resource(resource).is_some_and(|resource| resource.initialized)
+    }
+
+    fn resource_name(&self, resource: GraphResource) -> String {
+        match (self.resource(resource), resource) {
+            (Some(desc), _) => desc.name.clone(),
+            (None, GraphResource::Texture(id)) => format!("texture {}", id),
+            (None, GraphResource::Buffer(id)) => format!("buffer {}", id),
+        }
+    }
+
+    pub fn compile(&self) -> CompiledGraph {
+        let order = self.sort();
+        let (producers, mut diagnostics) = self.hazards(&order);
+
+        let presentation: Vec<usize> = (0..self.passes.len()).filter(|&i| self.passes[i].presentation).collect();
+        let mut kept = vec![presentation.is_empty(); self.passes.len()];
+        let mut pending = presentation.clone();
+        while let Some(pass) = pending.pop() {
+            if !std::mem::replace(&mut kept[pass], true) {
+                pending.extend(&producers[pass]);
+            }
+        }
+        if presentation.is_empty() {
+            diagnostics.push(GraphDiagnostic::NoPresentationPass);
+        }
+        diagnostics.extend(self.format_mismatches());
+
+        CompiledGraph {
+            order: order.into_iter().filter(|&pass| kept[pass]).collect(),
+            culled: (0..self.passes.len()).filter(|&pass| !kept[pass]).collect(),
+            diagnostics,
+        }
+    }
+
+    /// Orders passes so every read comes after the write it depends on,
+    /// keeping the insertion order wherever the dependencies allow. A read
+    /// of an uninitialized resource with no earlier writer depends on the
+    /// first writer after it, so producers may be added after consumers.
+    fn sort(&self) -> Vec<usize> {
+        let count = self.passes.len();
+        let mut edges: Vec<HashSet<usize>> = vec![HashSet::new(); count];
+        let accesses: Vec<(usize, &PassAccess)> = self
+            .passes
+            .iter()
+            .enumerate()
+            .flat_map(|(pass, desc)| desc.accesses.iter().map(move |access| (pass, access)))
+            .collect();
+
+        for (i, &(pass, access)) in accesses.iter().enumerate() {
+            let conflicts = |&&(other, earlier): &&(usize, &PassAccess)| {
+                other != pass
+                    && earlier.resource == access.resource
+                    && earlier.subresource.overlaps(&access.subresource)
+                    && (earlier.kind.writes() || access.kind.writes())
+            };
+            let written_before = accesses[..i].iter().filter(conflicts).any(|(_, earlier)| earlier.kind.writes());
+            if access.kind.reads() && !written_before && !self.initialized(access.resource) {
+                let writer = accesses[i + 1..].iter().filter(conflicts).find(|(_, later)| later.kind.writes());
+                if let Some(&(writer, _)) = writer {
+                    edges[writer].insert(pass);
+                }
+            }
+            for &(earlier, _) in accesses[..i].iter().filter(conflicts) {
+                // Reads that wait for this pass already come after it.
+                if !edges[pass].contains(&earlier) {
+                    edges[earlier].insert(pass);
+                }
+            }
+        }
+
+        let mut incoming = vec![0; count];
+        for targets in &edges {
+            for &target in targets {
+                incoming[target] += 1;
+            }
+        }
+        let mut ready: BinaryHeap<Reverse<usize>> = (0..count).filter(|&pass| incoming[pass] == 0).map(Reverse).collect();
+        let mut order = Vec::with_capacity(count);
+        let mut scheduled = vec![false; count];
+        while order.len() < count {
+            // A cycle of passes waiting on each other runs in insertion
+            // order, the hazard check reports what it reads too early.
+            let Reverse(pass) = ready
+                .pop()
+                .unwrap_or_else(|| Reverse((0..count).find(|&pass| !scheduled[pass]).unwrap()));
+            if std::mem::replace(&mut scheduled[pass], true) {
+                continue;
+            }
+            order.push(pass);
+            for &target in &edges[pass] {
+                incoming[target] -= 1;
+                if incoming[target] == 0 && !scheduled[target] {
+                    ready.push(Reverse(target));
+                }
+            }
+        }
+        order
+    }
+
+    /// Walks `order` tracking what each resource holds, returning for every
+    /// pass the passes whose writes it reads.
+    fn hazards(&self, order: &[usize]) -> (Vec<Vec<usize>>, Vec<GraphDiagnostic>) {
+        let mut producers = vec![vec![]; self.passes.len()];
+        let mut diagnostics = vec![];
+        let mut live: HashMap<GraphResource, Vec<LiveWrite>> = HashMap::new();
+
+        for &pass in order {
+            for access in &self.passes[pass].accesses {
+                let writes = live.entry(access.resource).or_default();
+                if access.kind.reads() {
+                    let mut written = false;
+                    for write in writes.iter_mut().filter(|write| write.subresource.overlaps(&access.subresource)) {
+                        written = true;
+                        if write.pass != pass {
+                            write.read = true;
+                            if !producers[pass].contains(&write.pass) {
+                                producers[pass].push(write.pass);
+                            }
+                        }
+                    }
+                    let diagnostic = GraphDiagnostic::ReadBeforeWrite {
+                        pass,
+                        resource: access.resource,
+                    };
+                    if !written && !self.initialized(access.resource) && !diagnostics.contains(&diagnostic) {
+                        diagnostics.push(diagnostic);
+                    }
+                }
+                if access.kind.writes() {
+                    for write in writes.iter().filter(|write| access.subresource.covers(&write.subresource)) {
+                        if write.pass != pass && !write.read && access.kind == AccessKind::Write {
+                            diagnostics.push(GraphDiagnostic::WriteWrite {
+                                first: write.pass,
+                                second: pass,
+                                resource: access.resource,
+                            });
+                        }
+                    }
+                    writes.retain(|write| !access.subresource.covers(&write.subresource));
+                    writes.push(LiveWrite {
+                        pass,
+                        subresource: access.subresource,
+                        read: false,
+                    });
+                }
+            }
+        }
+        (producers, diagnostics)
+    }
+
+    fn format_mismatches(&self) -> Vec<GraphDiagnostic> {
+        let mut diagnostics = vec![];
+        for (pass, desc) in self.passes.iter().enumerate() {
+            let Some(pipeline) = &desc.pipeline else {
+                continue;
+            };
+            let attachments = &desc.attachments;
+            if pipeline.color.len() != attachments.color.len() {
+                diagnostics.push(GraphDiagnostic::AttachmentCount {
+                    pass,
+                    pipeline: pipeline.color.len(),
+                    attachments: attachments.color.len(),
+                });
+            }
+            let color = pipeline.color.iter().zip(&attachments.color).enumerate();
+            let depth = std::iter::once((None, (&pipeline.depth, &attachments.depth)));
+            for (attachment, (&expected, &found)) in color.map(|(i, formats)| (Some(i), formats)).chain(depth) {
+                if expected != found {
+                    diagnostics.push(GraphDiagnostic::FormatMismatch {
+                        pass,
+                        attachment,
+                        pipeline: expected,
+                        attachments: found,
+                    });
+                }
+            }
+        }
+        diagnostics
+    }
+}
+
+impl GraphDiagnostic {
+    /// Describes the diagnostic with the names in `graph`.
+    pub fn message(&self, graph: &GraphDesc) -> String {
+        let pass = |pass: usize| graph.passes.get(pass).map_or("?", |pass| pass.name.as_str());
+        match self {
+            GraphDiagnostic::ReadBeforeWrite { pass: reader, resource } => format!(
+                "Pass {} reads {} before anything writes it",
+                pass(*reader),
+                graph.resource_name(*resource)
+            ),
+            GraphDiagnostic::WriteWrite {
+                first,
+                second,
+                resource,
+            } => format!(
+                "Pass {} overwrites {} written by {} before anything reads it",
+                pass(*second),
+                graph.resource_name(*resource),
+                pass(*first)
+            ),
+            GraphDiagnostic::AttachmentCount {
+                pass: index,
+                pipeline,
+                attachments,
+            } => format!(
+                "Pass {} renders to {} colour attachments, its pipeline was created for {}",
+                pass(*index),
+                attachments,
+                pipeline
+            ),
+            GraphDiagnostic::FormatMismatch {
+                pass: index,
+                attachment,
+                pipeline,
+                attachments,
+            } => format!(
+                "Pass {} renders to {} as {:?}, its pipeline was created for {:?}",
+                pass(*index),
+                attachment.map_or("the depth attachment".to_owned(), |i| format!("colour attachment {}", i)),
+                attachments,
+                pipeline
+            ),
+            GraphDiagnostic::NoPresentationPass => "The graph has no presentation pass".to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T0: GraphResource = GraphResource::Texture(0);
+    const T1: GraphResource = GraphResource::Texture(1);
+    const T2: GraphResource = GraphResource::Texture(2);
+
+    fn pass(name: &str, accesses: &[(GraphResource, AccessKind)]) -> PassDesc {
+        PassDesc {
+            name: name.to_owned(),
+            accesses: accesses.iter().map(|&(resource, kind)| PassAccess::new(resource, kind)).collect(),
+            ..Default::default()
+        }
+    }
+
+    fn present(reads: GraphResource) -> PassDesc {
+        PassDesc {
+            presentation: true,
+            ..pass("present", &[(reads, AccessKind::Read)])
+        }
+    }
+
+    fn graph(passes: Vec<PassDesc>) -> GraphDesc {
+        let texture = |name: &str| ResourceDesc {
+            name: name.to_owned(),
+            initialized: false,
+        };
+        GraphDesc {
+            passes,
+            textures: vec![texture("t0"), texture("t1"), texture("t2")],
+            buffers: vec![],
+        }
+    }
+
+    #[test]
+    fn producers_run_before_consumers() {
+        use AccessKind::*;
+        let compiled = graph(vec![
+            present(T1),
+            pass("combine", &[(T0, Read), (T1, Write)]),
+            pass("produce", &[(T0, Write)]),
+        ])
+        .compile();
+        assert_eq!(compiled.order, vec![2, 1, 0]);
+        assert!(compiled.diagnostics.is_empty(), "{:?}", compiled.diagnostics);
+
+        // Independent passes keep the order they were added in.
+        let compiled = graph(vec![
+            pass("a", &[(T0, Write)]),
+            pass("b", &[(T1, Write)]),
+            pass("c", &[(T0, Read), (T1, Read), (T2, Write)]),
+            present(T2),
+        ])
+        .compile();
+        assert_eq!(compiled.order, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn writes_wait_for_earlier_reads() {
+        use AccessKind::*;
+        let compiled = graph(vec![
+            pass("first", &[(T0, Write)]),
+            pass("copy", &[(T0, Read), (T1, Write)]),
+            pass("second", &[(T0, Write)]),
+            pass("combine", &[(T0, Read), (T1, Read), (T2, Write)]),
+            present(T2),
+        ])
+        .compile();
+        assert_eq!(compiled.order, vec![0, 1, 2, 3, 4]);
+        assert!(compiled.diagnostics.is_empty(), "{:?}", compiled.diagnostics);
+    }
+
+    #[test]
+    fn passes_not_reaching_presentation_are_culled() {
+        use AccessKind::*;
+        let compiled = graph(vec![
+            pass("unused", &[(T2, Write)]),
+            pass("scene", &[(T0, Write)]),
+            pass("overlay", &[(T0, ReadWrite)]),
+            present(T0),
+            pass("too_late", &[(T0, ReadWrite)]),
+        ])
+        .compile();
+        assert_eq!(compiled.order, vec![1, 2, 3]);
+        assert_eq!(compiled.culled, vec![0, 4]);
+
+        let compiled = graph(vec![pass("offscreen", &[(T0, Write)])]).compile();
+        assert_eq!(compiled.order, vec![0]);
+        assert_eq!(compiled.diagnostics, vec![GraphDiagnostic::NoPresentationPass]);
+    }
+
+    #[test]
+    fn hazards_are_reported() {
+        use AccessKind::*;
+        let desc = graph(vec![
+            pass("a", &[(T0, Write)]),
+            pass("b", &[(T0, Write)]),
+            pass("c", &[(T1, Read), (T2, Write)]),
+            present(T0),
+        ]);
+        let compiled = desc.compile();
+        assert_eq!(
+            compiled.diagnostics,
+            vec![
+                GraphDiagnostic::WriteWrite {
+                    first: 0,
+                    second: 1,
+                    resource: T0
+                },
+                GraphDiagnostic::ReadBeforeWrite { pass: 2, resource: T1 },
+            ]
+        );
+        assert_eq!(compiled.culled, vec![0, 2]);
+        assert_eq!(
+            compiled.diagnostics[1].message(&desc),
+            "Pass c reads t1 before anything writes it"
+        );
+
+        // Contents from an earlier frame may be read.
+        let mut desc = desc;
+        desc.textures[1].initialized = true;
+        assert_eq!(desc.compile().diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn layers_are_tracked_separately() {
+        use AccessKind::*;
+        let layer = |pass: &str, layer: u32| PassDesc {
+            accesses: vec![PassAccess {
+                subresource: Subresource::layer(layer),
+                ..PassAccess::new(T0, Write)
+            }],
+            ..self::pass(pass, &[])
+        };
+        let compiled = graph(vec![layer("cascade_0", 0), layer("cascade_1", 1), present(T0)]).compile();
+        assert_eq!(compiled.order, vec![0, 1, 2]);
+        assert!(compiled.diagnostics.is_empty(), "{:?}", compiled.diagnostics);
+    }
+
+    #[test]
+    fn disabled_shadows_still_write_the_shadow_map() {
+        use AccessKind::*;
+        let clear = |layer: u32| PassDesc {
+            accesses: vec![PassAccess {
+                subresource: Subresource::layer(layer),
+                ..PassAccess::new(T0, Write)
+            }],
+            ..pass(&format!("shadow_clear_{layer}"), &[])
+        };
+        let deferred = || pass("deferred_pass", &[(T0, Read), (T1, Write)]);
+
+        let compiled = graph(vec![deferred(), present(T1)]).compile();
+        assert_eq!(
+            compiled.diagnostics,
+            vec![GraphDiagnostic::ReadBeforeWrite { pass: 0, resource: T0 }]
+        );
+
+        let compiled = graph(vec![clear(0), clear(1), deferred(), present(T1)]).compile();
+        assert_eq!(compiled.order, vec![0, 1, 2, 3]);
+        assert!(compiled.diagnostics.is_empty(), "{:?}", compiled.diagnostics);
+    }
+
+    #[test]
+    fn attachments_must_match_the_pipeline() {
+        let formats = |color: &[vk::Format], depth| AttachmentFormats {
+            color: color.to_vec(),
+            depth,
+        };
+        let mut gbuffer = pass("gbuffer", &[(T0, AccessKind::Write)]);
+        gbuffer.attachments = formats(&[vk::Format::R8G8B8A8_UNORM], vk::Format::D32_SFLOAT);
+        gbuffer.pipeline = Some(formats(&[vk::Format::R16G16B16A16_SFLOAT], vk::Format::D32_SFLOAT));
+        let mut present = present(T0);
+        present.attachments = formats(&[vk::Format::B8G8R8A8_UNORM], vk::Format::UNDEFINED);
+        present.pipeline = Some(formats(&[], vk::Format::UNDEFINED));
+
+        let desc = graph(vec![gbuffer, present]);
+        assert_eq!(
+            desc.compile().diagnostics,
+            vec![
+                GraphDiagnostic::FormatMismatch {
+                    pass: 0,
+                    attachment: Some(0),
+                    pipeline: vk::Format::R16G16B16A16_SFLOAT,
+                    attachments: vk::Format::R8G8B8A8_UNORM,
+                },
+                GraphDiagnostic::AttachmentCount {
+                    pass: 1,
+                    pipeline: 0,
+                    attachments: 1
+                },
+            ]
+        );
+    }
+}
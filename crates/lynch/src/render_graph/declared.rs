@@ -0,0 +1,473 @@
+//! Pipelines, resources and passes declared in a RON file, added to the
+//! graph after the built-in passes and reloaded when the file changes.
+//!
+//! ```ron
+//! (
+//!     pipelines: {
+//!         "tonemap": (
+//!             vertex: "assets/shaders/fullscreen.vert",
+//!             fragment: "assets/shaders/tonemap.frag",
+//!         ),
+//!     },
+//!     resources: {
+//!         "tonemapped": (format: "R8G8B8A8_UNORM", transient: true),
+//!     },
+//!     passes: [
+//!         (
+//!             name: "tonemap",
+//!             pipeline: "tonemap",
+//!             reads: ["deferred_output"],
+//!             writes: ["tonemapped"],
+//!             uniforms: { "params": [1.0, 2.2] },
+//!         ),
+//!     ],
+//!     output: "tonemapped",
+//! )
+//! ```
+//!
+//! Passes may read and write the built-in textures by name. Shader paths
+//! and omitted optional values may be written without `Some(..)`.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use ash::vk;
+use serde::Deserialize;
+
+use crate::hot_reload::{absolute_path, AssetWatcher};
+
+use super::{TextureSize, MAX_UNIFORMS_SIZE};
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GraphFile {
+    pub pipelines: BTreeMap<String, PipelineDecl>,
+    pub resources: BTreeMap<String, ResourceDecl>,
+    /// Recorded in this order, after the built-in passes.
+    pub passes: Vec<PassDecl>,
+    /// Texture presented instead of the deferred output.
+    pub output: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineDecl {
+    pub vertex: Option<String>,
+    pub fragment: Option<String>,
+    pub compute: Option<String>,
+    pub vertex_layout: VertexLayout,
+    /// Overwritten by the formats of the pass's writes when it is built.
+    pub color_formats: Vec<String>,
+    pub depth_format: Option<String>,
+    /// Preprocessor defines, each set compiling a separate variant.
+    pub defines: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum VertexLayout {
+    /// No vertex input, for fullscreen triangles.
+    #[default]
+    Empty,
+    /// The layout of [`crate::mesh::Vertex`], for drawing meshes.
+    Primitive,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResourceDecl {
+    pub format: String,
+    #[serde(default)]
+    pub size: SizeDecl,
+    #[serde(default)]
+    pub transient: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum SizeDecl {
+    #[default]
+    Full,
+    Half,
+    Fixed(u32, u32),
+}
+
+impl SizeDecl {
+    /// The graph size and the extent created before it is resolved.
+    pub fn texture_size(&self) -> (TextureSize, u32, u32) {
+        match *self {
+            SizeDecl::Full => (TextureSize::Full, 1, 1),
+            SizeDecl::Half => (TextureSize::Half, 1, 1),
+            SizeDecl::Fixed(width, height) => (TextureSize::Fixed, width, height),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PassDecl {
+    pub name: String,
+    pub pipeline: String,
+    /// Sampled textures.
+    #[serde(default)]
+    pub reads: Vec<String>,
+    /// Color attachments, cleared before the pass.
+    #[serde(default)]
+    pub writes: Vec<String>,
+    /// Storage images.
+    #[serde(default)]
+    pub storage_writes: Vec<String>,
+    #[serde(default)]
+    pub depth: Option<String>,
+    /// Uniform block name and its values. A pass binds one block.
+    #[serde(default)]
+    pub uniforms: BTreeMap<String, Vec<f32>>,
+    #[serde(default)]
+    pub draw: Draw,
+}
+
+impl PassDecl {
+    /// Every texture the pass reads or writes.
+    pub fn textures(&self) -> impl Iterator<Item = &str> {
+        self.reads
+            .iter()
+            .chain(&self.writes)
+            .chain(&self.storage_writes)
+            .chain(&self.depth)
+            .map(String::as_str)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+pub enum Draw {
+    /// A triangle covering the viewport.
+    #[default]
+    Fullscreen,
+    /// The scene's meshes, with the pipeline's layout for their materials.
+    Meshes,
+    /// A compute dispatch of this many work groups.
+    Dispatch(u32, u32, u32),
+}
+
+macro_rules! formats {
+    ($($format:ident),* $(,)?) => {
+        /// The format named like its `vk::Format` constant.
+        pub fn parse_format(name: &str) -> Option<vk::Format> {
+            match name {
+                $(stringify!($format) => Some(vk::Format::$format),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+formats!(
+    R8_UNORM,
+    R8G8_UNORM,
+    R8G8B8A8_UNORM,
+    R8G8B8A8_SRGB,
+    B8G8R8A8_UNORM,
+    B8G8R8A8_SRGB,
+    R16_UNORM,
+    R16_SFLOAT,
+    R16G16_SFLOAT,
+    R16G16B16A16_SFLOAT,
+    R32_SFLOAT,
+    R32G32_SFLOAT,
+    R32G32B32A32_SFLOAT,
+    A2B10G10R10_UNORM_PACK32,
+    B10G11R11_UFLOAT_PACK32,
+    D16_UNORM,
+    D32_SFLOAT,
+    D24_UNORM_S8_UINT,
+    D32_SFLOAT_S8_UINT,
+);
+
+/// `path` as the `&'static str` pipeline descs take. Each distinct path is
+/// leaked once, however often the file is reloaded.
+pub fn intern(path: &str) -> &'static str {
+    static PATHS: Mutex<BTreeSet<&'static str>> = Mutex::new(BTreeSet::new());
+    let mut paths = PATHS.lock().unwrap();
+    match paths.get(path) {
+        Some(interned) => interned,
+        None => {
+            let interned: &'static str = Box::leak(path.to_owned().into_boxed_str());
+            paths.insert(interned);
+            interned
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum GraphFileError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    UnknownPipeline { pass: String, pipeline: String },
+    /// Neither the file nor the built-in passes create the texture.
+    UnknownResource { pass: String, resource: String },
+    UnknownFormat(String),
+    /// A pipeline needs a vertex and a fragment shader, or only a compute
+    /// shader.
+    Shaders(String),
+    /// Compute pipelines dispatch, the others draw.
+    Draw(String),
+    Uniforms { pass: String, reason: &'static str },
+}
+
+impl Display for GraphFileError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            GraphFileError::Io(e) => write!(f, "{}", e),
+            GraphFileError::Parse(e) => write!(f, "Could not parse render graph: {}", e),
+            GraphFileError::UnknownPipeline { pass, pipeline } => {
+                write!(f, "Pass {} uses undeclared pipeline {}", pass, pipeline)
+            }
+            GraphFileError::UnknownResource { pass, resource } => {
+                write!(f, "Pass {} uses unknown texture {}", pass, resource)
+            }
+            GraphFileError::UnknownFormat(format) => write!(f, "Unknown format {}", format),
+            GraphFileError::Shaders(pipeline) => write!(
+                f,
+                "Pipeline {} needs a vertex and a fragment shader, or only a compute shader",
+                pipeline
+            ),
+            GraphFileError::Draw(pass) => write!(
+                f,
+                "Pass {} must dispatch a compute pipeline or draw with a graphics one",
+                pass
+            ),
+            GraphFileError::Uniforms { pass, reason } => {
+                write!(f, "Uniforms of pass {} {}", pass, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphFileError {}
+
+impl From<std::io::Error> for GraphFileError {
+    fn from(e: std::io::Error) -> Self {
+        GraphFileError::Io(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for GraphFileError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        GraphFileError::Parse(e)
+    }
+}
+
+impl GraphFile {
+    pub fn load(path: &Path) -> Result<Self, GraphFileError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses and checks everything but the names of built-in textures,
+    /// which only the graph knows.
+    pub fn parse(text: &str) -> Result<Self, GraphFileError> {
+        let file: GraphFile = ron::Options::default()
+            .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+            .from_str(text)?;
+        file.validate()?;
+        Ok(file)
+    }
+
+    fn validate(&self) -> Result<(), GraphFileError> {
+        let check_format = |format: &String| match parse_format(format) {
+            Some(_) => Ok(()),
+            None => Err(GraphFileError::UnknownFormat(format.clone())),
+        };
+        for (name, pipeline) in &self.pipelines {
+            let shaders = (&pipeline.vertex, &pipeline.fragment, &pipeline.compute);
+            if !matches!(shaders, (Some(_), Some(_), None) | (None, None, Some(_))) {
+                return Err(GraphFileError::Shaders(name.clone()));
+            }
+            pipeline.color_formats.iter().try_for_each(check_format)?;
+            pipeline.depth_format.iter().try_for_each(check_format)?;
+        }
+        for resource in self.resources.values() {
+            check_format(&resource.format)?;
+        }
+        for pass in &self.passes {
+            let Some(pipeline) = self.pipelines.get(&pass.pipeline) else {
+                return Err(GraphFileError::UnknownPipeline {
+                    pass: pass.name.clone(),
+                    pipeline: pass.pipeline.clone(),
+                });
+            };
+            if matches!(pass.draw, Draw::Dispatch(..)) != pipeline.compute.is_some() {
+                return Err(GraphFileError::Draw(pass.name.clone()));
+            }
+            let uniforms = |reason| GraphFileError::Uniforms {
+                pass: pass.name.clone(),
+                reason,
+            };
+            if pass.uniforms.len() > 1 {
+                return Err(uniforms("declare more than one block"));
+            }
+            if pass
+                .uniforms
+                .values()
+                .any(|values| values.len() * std::mem::size_of::<f32>() >= MAX_UNIFORMS_SIZE)
+            {
+                return Err(uniforms("are too large"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A [`GraphFile`] and the watcher reloading it.
+pub struct DeclaredGraph {
+    pub path: PathBuf,
+    pub file: GraphFile,
+    watcher: Option<AssetWatcher>,
+    reported_errors: Vec<String>,
+}
+
+impl DeclaredGraph {
+    pub fn load(path: &Path) -> Result<Self, GraphFileError> {
+        let file = GraphFile::load(path)?;
+        let watcher = AssetWatcher::new().and_then(|mut watcher| {
+            watcher.watch_file(path)?;
+            Ok(watcher)
+        });
+        let watcher = match watcher {
+            Ok(watcher) => Some(watcher),
+            Err(err) => {
+                log::warn!("Render graph {} will not be reloaded: {}", path.display(), err);
+                None
+            }
+        };
+        Ok(DeclaredGraph {
+            path: path.to_path_buf(),
+            file,
+            watcher,
+            reported_errors: vec![],
+        })
+    }
+
+    /// Loads the file again if it changed on disk, keeping the previous
+    /// passes while it has errors.
+    pub fn reload_changed(&mut self) -> bool {
+        let Some(watcher) = &self.watcher else {
+            return false;
+        };
+        let path = absolute_path(&self.path);
+        if !watcher.changed().contains(&path) {
+            return false;
+        }
+        match GraphFile::load(&self.path) {
+            Ok(file) => {
+                log::info!("Reloaded render graph {}", self.path.display());
+                self.file = file;
+                self.reported_errors.clear();
+                true
+            }
+            Err(err) => {
+                log::error!("{}: {}", self.path.display(), err);
+                false
+            }
+        }
+    }
+
+    /// Logs the errors of adding the passes to the graph whenever they
+    /// change, rather than every frame.
+    pub fn report(&mut self, errors: &[GraphFileError]) {
+        let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+        if messages != self.reported_errors {
+            for message in &messages {
+                log::error!("{}: {}", self.path.display(), message);
+            }
+            self.reported_errors = messages;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAPH: &str = r#"(
+        pipelines: {
+            "blur": (compute: "assets/shaders/blur.comp"),
+            "tonemap": (
+                vertex: "assets/shaders/fullscreen.vert",
+                fragment: "assets/shaders/tonemap.frag",
+                color_formats: ["R8G8B8A8_UNORM"],
+                defines: {"ACES": "1"},
+            ),
+        },
+        resources: {
+            "blurred": (format: "R16G16B16A16_SFLOAT", size: Half, transient: true),
+            "tonemapped": (format: "R8G8B8A8_UNORM"),
+        },
+        passes: [
+            (name: "blur", pipeline: "blur", reads: ["deferred_output"], storage_writes: ["blurred"], draw: Dispatch(8, 8, 1)),
+            (name: "tonemap", pipeline: "tonemap", reads: ["blurred"], writes: ["tonemapped"], uniforms: {"params": [1.0, 2.2]}),
+        ],
+        output: "tonemapped",
+    )"#;
+
+    #[test]
+    fn graphs_are_parsed() {
+        let file = GraphFile::parse(GRAPH).unwrap();
+        assert_eq!(file.pipelines["blur"].compute.as_deref(), Some("assets/shaders/blur.comp"));
+        assert_eq!(file.pipelines["tonemap"].vertex_layout, VertexLayout::Empty);
+        assert_eq!(file.pipelines["tonemap"].defines["ACES"], "1");
+        assert_eq!(file.resources["blurred"].size, SizeDecl::Half);
+        assert_eq!(file.resources["tonemapped"].size, SizeDecl::Full);
+        assert!(!file.resources["tonemapped"].transient);
+        assert_eq!(file.passes[0].draw, Draw::Dispatch(8, 8, 1));
+        assert_eq!(file.passes[1].draw, Draw::Fullscreen);
+        assert_eq!(file.passes[1].uniforms["params"], vec![1.0, 2.2]);
+        assert_eq!(
+            file.passes[0].textures().collect::<Vec<_>>(),
+            vec!["deferred_output", "blurred"]
+        );
+        assert_eq!(file.output.as_deref(), Some("tonemapped"));
+        assert_eq!(parse_format("D32_SFLOAT"), Some(vk::Format::D32_SFLOAT));
+        assert_eq!(GraphFile::parse("()").unwrap(), GraphFile::default());
+    }
+
+    #[test]
+    fn invalid_graphs_are_rejected() {
+        let error = |text: &str| GraphFile::parse(text).unwrap_err().to_string();
+
+        assert!(error("(passes: [(name: \"a\")])").contains("1:"));
+        assert!(error("(pass: [])").contains("pass"));
+        assert_eq!(
+            error(&GRAPH.replace("\"R8G8B8A8_UNORM\"]", "\"RGBA8\"]")),
+            "Unknown format RGBA8"
+        );
+        assert_eq!(
+            error(&GRAPH.replace("pipeline: \"blur\"", "pipeline: \"sharpen\"")),
+            "Pass blur uses undeclared pipeline sharpen"
+        );
+        assert_eq!(
+            error(&GRAPH.replace("(compute:", "(vertex: \"a.vert\", compute:")),
+            "Pipeline blur needs a vertex and a fragment shader, or only a compute shader"
+        );
+        assert_eq!(
+            error(&GRAPH.replace("Dispatch(8, 8, 1)", "Fullscreen")),
+            "Pass blur must dispatch a compute pipeline or draw with a graphics one"
+        );
+        assert_eq!(
+            error(&GRAPH.replace("{\"params\": [1.0, 2.2]}", "{\"a\": [1.0], \"b\": [2.0]}")),
+            "Uniforms of pass tonemap declare more than one block"
+        );
+    }
+
+    #[test]
+    fn shipped_graph_parses() {
+        GraphFile::parse(include_str!("../../../../assets/render_graph.ron")).unwrap();
+    }
+
+    #[test]
+    fn paths_are_interned_once() {
+        let path = String::from("assets/shaders/interned.frag");
+        assert!(std::ptr::eq(intern(&path), intern("assets/shaders/interned.frag")));
+    }
+}
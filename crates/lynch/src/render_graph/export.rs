@@ -0,0 +1,436 @@
+//! A snapshot of a frame's graph for tools: Graphviz DOT, JSON and the
+//! render graph view of the debug UI.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Write};
+
+use ash::vk;
+use serde::{Serialize, Serializer};
+use vk_sync::AccessType;
+
+use super::compile::{AccessKind, CompiledGraph, GraphDesc, GraphResource};
+use super::memory::MemoryPlan;
+use super::PipelineId;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct ExportedPipeline {
+    #[serde(rename = "vertex")]
+    pub vertex_path: Option<String>,
+    #[serde(rename = "fragment")]
+    pub fragment_path: Option<String>,
+    #[serde(rename = "compute")]
+    pub compute_path: Option<String>,
+    #[serde(serialize_with = "debug_names")]
+    pub color_formats: Vec<vk::Format>,
+    #[serde(serialize_with = "debug_name")]
+    pub depth_format: vk::Format,
+}
+
+impl ExportedPipeline {
+    pub fn shader_paths(&self) -> impl Iterator<Item = &str> {
+        [&self.vertex_path, &self.fragment_path, &self.compute_path]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ExportedPass {
+    pub name: String,
+    pub pipeline: PipelineId,
+    pub presentation: bool,
+    /// Position in the compiled order, `None` when culled.
+    pub position: Option<usize>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportedResourceKind {
+    Texture {
+        #[serde(serialize_with = "debug_name")]
+        format: vk::Format,
+        width: u32,
+        height: u32,
+        layers: u32,
+        mips: u32,
+    },
+    Buffer {
+        #[serde(serialize_with = "debug_name")]
+        usage: vk::BufferUsageFlags,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ExportedResource {
+    #[serde(rename = "id", serialize_with = "resource_id")]
+    pub resource: GraphResource,
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: ExportedResourceKind,
+    /// Bytes of memory the resource needs.
+    pub size: u64,
+    pub transient: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct ExportedEdge {
+    pub pass: usize,
+    #[serde(serialize_with = "resource_id")]
+    pub resource: GraphResource,
+    #[serde(rename = "access", serialize_with = "access_kind")]
+    pub kind: AccessKind,
+}
+
+/// A barrier `render` records before or after `pass`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Transition {
+    pub pass: usize,
+    #[serde(serialize_with = "resource_id")]
+    pub resource: GraphResource,
+    #[serde(serialize_with = "debug_name")]
+    pub from: AccessType,
+    #[serde(serialize_with = "debug_name")]
+    pub to: AccessType,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct GraphExport {
+    pub passes: Vec<ExportedPass>,
+    pub pipelines: Vec<ExportedPipeline>,
+    pub resources: Vec<ExportedResource>,
+    pub edges: Vec<ExportedEdge>,
+    pub transitions: Vec<Transition>,
+}
+
+/// Replays the barriers of every pass in `compiled` order the way `render`
+/// records them: each resource starts from `initial` and transients start
+/// over from nothing when their lifetime begins.
+pub fn transitions(
+    compiled: &CompiledGraph,
+    barriers: &[Vec<(GraphResource, AccessType)>],
+    initial: &HashMap<GraphResource, AccessType>,
+    plan: &MemoryPlan,
+) -> Vec<Transition> {
+    let mut accesses = initial.clone();
+    let mut transitions = vec![];
+    for (position, &pass) in compiled.order.iter().enumerate() {
+        for resource in plan.acquired_at(position) {
+            accesses.insert(resource, AccessType::Nothing);
+        }
+        for &(resource, to) in &barriers[pass] {
+            let from = accesses.insert(resource, to).unwrap_or(AccessType::Nothing);
+            transitions.push(Transition {
+                pass,
+                resource,
+                from,
+                to,
+            });
+        }
+    }
+    transitions
+}
+
+/// Edges between passes and the resources they access, once per access kind.
+pub fn edges(desc: &GraphDesc) -> Vec<ExportedEdge> {
+    let mut edges: Vec<ExportedEdge> = vec![];
+    for (pass, pass_desc) in desc.passes.iter().enumerate() {
+        for access in &pass_desc.accesses {
+            let edge = ExportedEdge {
+                pass,
+                resource: access.resource,
+                kind: access.kind,
+            };
+            if !edges.contains(&edge) {
+                edges.push(edge);
+            }
+        }
+    }
+    edges
+}
+
+fn node_id(resource: GraphResource) -> String {
+    match resource {
+        GraphResource::Texture(id) => format!("texture_{id}"),
+        GraphResource::Buffer(id) => format!("buffer_{id}"),
+    }
+}
+
+// Vulkan and vk-sync types are exported by their `Debug` names.
+fn debug_name<T: Debug, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&format_args!("{value:?}"))
+}
+
+fn debug_names<T: Debug, S: Serializer>(values: &[T], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(values.iter().map(|value| format!("{value:?}")))
+}
+
+fn resource_id<S: Serializer>(resource: &GraphResource, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&node_id(*resource))
+}
+
+fn access_kind<S: Serializer>(kind: &AccessKind, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(match kind {
+        AccessKind::Read => "read",
+        AccessKind::Write => "write",
+        AccessKind::ReadWrite => "read_write",
+    })
+}
+
+/// Quoted and escaped for DOT.
+fn quoted(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn mib(bytes: u64) -> f64 {
+    bytes as f64 / (1024.0 * 1024.0)
+}
+
+impl ExportedResource {
+    /// Format and extent of a texture, usage of a buffer.
+    pub fn summary(&self) -> String {
+        match self.kind {
+            ExportedResourceKind::Texture {
+                format,
+                width,
+                height,
+                layers,
+                mips,
+            } => {
+                let mut summary = format!("{format:?} {width}x{height}");
+                if layers > 1 {
+                    let _ = write!(summary, " [{layers}]");
+                }
+                if mips > 1 {
+                    let _ = write!(summary, " {mips} mips");
+                }
+                summary
+            }
+            ExportedResourceKind::Buffer { usage } => format!("{usage:?}"),
+        }
+    }
+}
+
+impl GraphExport {
+    pub fn resource(&self, resource: GraphResource) -> Option<&ExportedResource> {
+        self.resources.iter().find(|exported| exported.resource == resource)
+    }
+
+    /// Transitions of `resource` recorded around `pass`.
+    pub fn transitions_of(&self, pass: usize, resource: GraphResource) -> impl Iterator<Item = &Transition> {
+        self.transitions
+            .iter()
+            .filter(move |transition| transition.pass == pass && transition.resource == resource)
+    }
+
+    /// Passes are boxes with their shaders, resources are ellipses, culled
+    /// passes are dashed and transient resources are filled. Edges are
+    /// labelled with the transitions `render` records.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph render_graph {\n    rankdir=LR;\n    node [fontname=\"monospace\"];\n");
+        for (index, pass) in self.passes.iter().enumerate() {
+            let mut label = pass.name.clone();
+            match pass.position {
+                Some(position) => {
+                    let _ = write!(label, " (#{position})");
+                }
+                None => label.push_str(" (culled)"),
+            }
+            if let Some(pipeline) = self.pipelines.get(pass.pipeline) {
+                for path in pipeline.shader_paths() {
+                    let _ = write!(label, "\n{path}");
+                }
+            }
+            let style = match (pass.position, pass.presentation) {
+                (None, _) => ", style=dashed, color=gray",
+                (_, true) => ", penwidth=2",
+                _ => "",
+            };
+            let _ = writeln!(dot, "    pass_{index} [shape=box, label={}{style}];", quoted(&label));
+        }
+        for resource in &self.resources {
+            let label = format!("{}\n{}\n{:.2} MiB", resource.name, resource.summary(), mib(resource.size));
+            let style = match resource.transient {
+                true => ", style=filled, fillcolor=lightgray",
+                false => "",
+            };
+            let _ = writeln!(
+                dot,
+                "    {} [shape=ellipse, label={}{style}];",
+                node_id(resource.resource),
+                quoted(&label)
+            );
+        }
+        for edge in &self.edges {
+            let label = self
+                .transitions_of(edge.pass, edge.resource)
+                .map(|transition| format!("{:?} -> {:?}", transition.from, transition.to))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let (from, to, direction) = match edge.kind {
+                AccessKind::Read => (node_id(edge.resource), format!("pass_{}", edge.pass), ""),
+                AccessKind::Write => (format!("pass_{}", edge.pass), node_id(edge.resource), ""),
+                AccessKind::ReadWrite => (format!("pass_{}", edge.pass), node_id(edge.resource), ", dir=both"),
+            };
+            let _ = writeln!(dot, "    {from} -> {to} [label={}{direction}];", quoted(&label));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_json(&self) -> String {
+        // Every field serializes to a string, number, bool or list of them.
+        serde_json::to_string_pretty(self).expect("Render graph export is always valid JSON")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::compile::{PassAccess, PassDesc};
+    use super::super::memory::{Lifetime, MemoryRequest};
+    use super::*;
+
+    fn graph() -> GraphDesc {
+        let pass = |name: &str, accesses: &[(usize, AccessKind)]| PassDesc {
+            name: name.to_owned(),
+            presentation: name == "present",
+            accesses: accesses
+                .iter()
+                .map(|&(id, kind)| PassAccess::new(GraphResource::Texture(id), kind))
+                .collect(),
+            ..Default::default()
+        };
+        GraphDesc {
+            passes: vec![
+                pass("scene", &[(0, AccessKind::Write)]),
+                pass("present", &[(0, AccessKind::Read)]),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn scene_barriers() -> Vec<Vec<(GraphResource, AccessType)>> {
+        vec![
+            vec![(GraphResource::Texture(0), AccessType::ColorAttachmentWrite)],
+            vec![(
+                GraphResource::Texture(0),
+                AccessType::FragmentShaderReadSampledImageOrUniformTexelBuffer,
+            )],
+        ]
+    }
+
+    #[test]
+    fn transitions_follow_render_order() {
+        let desc = graph();
+        let compiled = desc.compile();
+        let initial = HashMap::from([(
+            GraphResource::Texture(0),
+            AccessType::FragmentShaderReadSampledImageOrUniformTexelBuffer,
+        )]);
+        let transitions = transitions(&compiled, &scene_barriers(), &initial, &MemoryPlan::default());
+        assert_eq!(transitions.len(), 2);
+        assert_eq!(transitions[0].pass, 0);
+        assert_eq!(transitions[0].from, AccessType::FragmentShaderReadSampledImageOrUniformTexelBuffer);
+        assert_eq!(transitions[0].to, AccessType::ColorAttachmentWrite);
+        assert_eq!(transitions[1].from, AccessType::ColorAttachmentWrite);
+    }
+
+    #[test]
+    fn transients_start_from_nothing() {
+        let desc = graph();
+        let compiled = desc.compile();
+        let request = MemoryRequest {
+            resource: GraphResource::Texture(0),
+            size: 256,
+            alignment: 256,
+            memory_type_bits: 1,
+            linear: false,
+        };
+        let lifetimes = HashMap::from([(GraphResource::Texture(0), Lifetime { first: 0, last: 1 })]);
+        let plan = MemoryPlan::new(&[request], &lifetimes);
+        let initial = HashMap::from([(
+            GraphResource::Texture(0),
+            AccessType::FragmentShaderReadSampledImageOrUniformTexelBuffer,
+        )]);
+        let transitions = transitions(&compiled, &scene_barriers(), &initial, &plan);
+        assert_eq!(transitions[0].from, AccessType::Nothing);
+    }
+
+    #[test]
+    fn dot_and_json_describe_the_graph() {
+        let desc = graph();
+        let compiled = desc.compile();
+        let export = GraphExport {
+            passes: vec![
+                ExportedPass {
+                    name: "scene".to_owned(),
+                    pipeline: 0,
+                    presentation: false,
+                    position: Some(0),
+                },
+                ExportedPass {
+                    name: "present \"final\"".to_owned(),
+                    pipeline: 0,
+                    presentation: true,
+                    position: Some(1),
+                },
+            ],
+            pipelines: vec![ExportedPipeline {
+                vertex_path: Some("assets/shaders/scene.vert".to_owned()),
+                fragment_path: Some("assets/shaders/scene.frag".to_owned()),
+                color_formats: vec![vk::Format::R8G8B8A8_UNORM],
+                ..Default::default()
+            }],
+            resources: vec![ExportedResource {
+                resource: GraphResource::Texture(0),
+                name: "scene_color".to_owned(),
+                kind: ExportedResourceKind::Texture {
+                    format: vk::Format::R8G8B8A8_UNORM,
+                    width: 1920,
+                    height: 1080,
+                    layers: 1,
+                    mips: 1,
+                },
+                size: 1920 * 1080 * 4,
+                transient: true,
+            }],
+            edges: edges(&desc),
+            transitions: transitions(&compiled, &scene_barriers(), &HashMap::new(), &MemoryPlan::default()),
+        };
+
+        let dot = export.to_dot();
+        assert!(dot.starts_with("digraph render_graph {"));
+        assert!(dot.contains("pass_0 -> texture_0 [label=\"Nothing -> ColorAttachmentWrite\"];"));
+        assert!(dot.contains("texture_0 -> pass_1"));
+        assert!(dot.contains("present \\\"final\\\" (#1)\\nassets/shaders/scene.vert"));
+        assert!(dot.contains("R8G8B8A8_UNORM 1920x1080\\n7.91 MiB"));
+
+        let json: serde_json::Value = serde_json::from_str(&export.to_json()).unwrap();
+        assert_eq!(json["passes"][1]["name"], "present \"final\"");
+        let pipeline = &json["pipelines"][0];
+        assert_eq!(pipeline["vertex"], "assets/shaders/scene.vert");
+        assert_eq!(pipeline["fragment"], "assets/shaders/scene.frag");
+        assert!(pipeline["compute"].is_null());
+        assert_eq!(pipeline["color_formats"][0], "R8G8B8A8_UNORM");
+        let resource = &json["resources"][0];
+        assert_eq!(resource["id"], "texture_0");
+        assert_eq!(resource["type"], "texture");
+        assert_eq!((resource["format"].as_str(), resource["width"].as_u64()), (Some("R8G8B8A8_UNORM"), Some(1920)));
+        assert_eq!(json["edges"][1], serde_json::json!({"pass": 1, "resource": "texture_0", "access": "read"}));
+        assert_eq!((json["transitions"][0]["from"].as_str(), json["transitions"][0]["to"].as_str()), (Some("Nothing"), Some("ColorAttachmentWrite")));
+    }
+}
@@ -0,0 +1,327 @@
+//! Transient resources only hold data between the first and the last pass
+//! using them in a frame, so those whose lifetimes never overlap can share
+//! memory. [`MemoryPlan`] places them in as few blocks as it can, without
+//! touching the device.
+
+use std::collections::HashMap;
+
+use super::compile::{CompiledGraph, GraphDesc, GraphResource};
+
+/// Positions in [`CompiledGraph::order`] of the first and the last pass
+/// using a resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Lifetime {
+    pub first: usize,
+    pub last: usize,
+}
+
+impl Lifetime {
+    fn overlaps(&self, other: &Lifetime) -> bool {
+        self.first <= other.last && other.first <= self.last
+    }
+}
+
+/// Lifetimes of every resource the compiled passes use.
+pub fn lifetimes(desc: &GraphDesc, compiled: &CompiledGraph) -> HashMap<GraphResource, Lifetime> {
+    let mut lifetimes = HashMap::new();
+    for (position, &pass) in compiled.order.iter().enumerate() {
+        for access in &desc.passes[pass].accesses {
+            lifetimes
+                .entry(access.resource)
+                .and_modify(|lifetime: &mut Lifetime| lifetime.last = position)
+                .or_insert(Lifetime {
+                    first: position,
+                    last: position,
+                });
+        }
+    }
+    lifetimes
+}
+
+/// Memory requirements of a transient resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryRequest {
+    pub resource: GraphResource,
+    pub size: u64,
+    pub alignment: u64,
+    pub memory_type_bits: u32,
+    /// Buffers, kept apart from images so `bufferImageGranularity` never
+    /// matters.
+    pub linear: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryBlock {
+    pub size: u64,
+    pub alignment: u64,
+    pub memory_type_bits: u32,
+    pub linear: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub resource: GraphResource,
+    pub block: usize,
+    pub offset: u64,
+    pub size: u64,
+    /// `None` for resources no pass uses this frame, they may share memory
+    /// with anything.
+    pub lifetime: Option<Lifetime>,
+}
+
+impl Placement {
+    fn conflicts(&self, other: &Placement) -> bool {
+        let lifetimes = match (self.lifetime, other.lifetime) {
+            (Some(a), Some(b)) => a.overlaps(&b),
+            _ => false,
+        };
+        lifetimes && self.block == other.block && self.offset < other.offset + other.size && other.offset < self.offset + self.size
+    }
+}
+
+/// Totals of a [`MemoryPlan`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub resources: usize,
+    pub blocks: usize,
+    /// Bytes the resources would take in allocations of their own.
+    pub requested: u64,
+    pub allocated: u64,
+}
+
+/// Where every transient resource of a frame lives.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryPlan {
+    pub blocks: Vec<MemoryBlock>,
+    pub placements: Vec<Placement>,
+}
+
+fn align_up(offset: u64, alignment: u64) -> u64 {
+    offset.div_ceil(alignment.max(1)) * alignment.max(1)
+}
+
+impl MemoryPlan {
+    /// Places the largest resources first, each at the lowest offset of the
+    /// first compatible block where it overlaps nothing alive at the same
+    /// time, opening a new block when none has room.
+    pub fn new(requests: &[MemoryRequest], lifetimes: &HashMap<GraphResource, Lifetime>) -> Self {
+        let mut requests: Vec<&MemoryRequest> = requests.iter().collect();
+        requests.sort_by_key(|request| (std::cmp::Reverse(request.size), request.resource));
+
+        let mut plan = MemoryPlan::default();
+        for request in requests {
+            let mut placement = Placement {
+                resource: request.resource,
+                block: 0,
+                offset: 0,
+                size: request.size,
+                lifetime: lifetimes.get(&request.resource).copied(),
+            };
+            let fits = plan.blocks.iter().enumerate().find_map(|(block, desc)| {
+                let compatible = desc.linear == request.linear && desc.memory_type_bits & request.memory_type_bits != 0;
+                if !compatible {
+                    return None;
+                }
+                let candidate = |offset| Placement {
+                    block,
+                    offset,
+                    ..placement
+                };
+                let mut offsets: Vec<u64> = plan
+                    .placements
+                    .iter()
+                    .filter(|other| other.block == block)
+                    .map(|other| align_up(other.offset + other.size, request.alignment))
+                    .chain([0])
+                    .collect();
+                offsets.sort_unstable();
+                offsets.into_iter().map(candidate).find(|candidate| {
+                    candidate.offset + candidate.size <= desc.size
+                        && plan.placements.iter().all(|other| !candidate.conflicts(other))
+                })
+            });
+
+            match fits {
+                Some(fits) => {
+                    let block = &mut plan.blocks[fits.block];
+                    block.alignment = block.alignment.max(request.alignment);
+                    block.memory_type_bits &= request.memory_type_bits;
+                    placement = fits;
+                }
+                None => {
+                    placement.block = plan.blocks.len();
+                    plan.blocks.push(MemoryBlock {
+                        size: request.size,
+                        alignment: request.alignment,
+                        memory_type_bits: request.memory_type_bits,
+                        linear: request.linear,
+                    });
+                }
+            }
+            plan.placements.push(placement);
+        }
+        plan
+    }
+
+    /// Whether both plans put every resource in the same place, whatever
+    /// their lifetimes.
+    pub fn same_layout(&self, other: &MemoryPlan) -> bool {
+        let layout = |plan: &MemoryPlan| {
+            let mut layout: Vec<_> = plan
+                .placements
+                .iter()
+                .map(|placement| (placement.resource, placement.block, placement.offset))
+                .collect();
+            layout.sort_unstable();
+            layout
+        };
+        self.blocks == other.blocks && layout(self) == layout(other)
+    }
+
+    pub fn placement(&self, resource: GraphResource) -> Option<&Placement> {
+        self.placements.iter().find(|placement| placement.resource == resource)
+    }
+
+    /// Resources whose memory overlaps `resource`'s.
+    pub fn aliases(&self, resource: GraphResource) -> impl Iterator<Item = GraphResource> + '_ {
+        let placement = self.placement(resource).copied();
+        self.placements
+            .iter()
+            .filter(move |other| {
+                placement.is_some_and(|placement| {
+                    other.resource != resource
+                        && other.block == placement.block
+                        && other.offset < placement.offset + placement.size
+                        && placement.offset < other.offset + other.size
+                })
+            })
+            .map(|other| other.resource)
+    }
+
+    /// Resources whose lifetime starts at `position` of the compiled order.
+    pub fn acquired_at(&self, position: usize) -> impl Iterator<Item = GraphResource> + '_ {
+        self.placements
+            .iter()
+            .filter(move |placement| placement.lifetime.is_some_and(|lifetime| lifetime.first == position))
+            .map(|placement| placement.resource)
+    }
+
+    pub fn usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            resources: self.placements.len(),
+            blocks: self.blocks.len(),
+            requested: self.placements.iter().map(|placement| placement.size).sum(),
+            allocated: self.blocks.iter().map(|block| block.size).sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::compile::{AccessKind, PassAccess, PassDesc};
+    use super::*;
+
+    fn texture(id: usize, size: u64) -> MemoryRequest {
+        MemoryRequest {
+            resource: GraphResource::Texture(id),
+            size,
+            alignment: 256,
+            memory_type_bits: 0b11,
+            linear: false,
+        }
+    }
+
+    fn lifetimes(lifetimes: &[(usize, usize, usize)]) -> HashMap<GraphResource, Lifetime> {
+        lifetimes
+            .iter()
+            .map(|&(id, first, last)| (GraphResource::Texture(id), Lifetime { first, last }))
+            .collect()
+    }
+
+    #[test]
+    fn resources_alive_at_different_times_share_memory() {
+        let requests = [texture(0, 1024), texture(1, 1024), texture(2, 512)];
+        let plan = MemoryPlan::new(&requests, &lifetimes(&[(0, 0, 1), (1, 2, 3), (2, 1, 2)]));
+        let placement = |id| *plan.placement(GraphResource::Texture(id)).unwrap();
+        assert_eq!((placement(0).block, placement(0).offset), (0, 0));
+        assert_eq!((placement(1).block, placement(1).offset), (0, 0));
+        // Alive alongside both, so it got a block of its own.
+        assert_eq!(placement(2).block, 1);
+        assert_eq!(
+            plan.usage(),
+            MemoryUsage {
+                resources: 3,
+                blocks: 2,
+                requested: 2560,
+                allocated: 1536,
+            }
+        );
+    }
+
+    #[test]
+    fn overlapping_lifetimes_get_separate_ranges() {
+        let requests = [texture(0, 1024), texture(1, 300), texture(2, 300)];
+        let plan = MemoryPlan::new(&requests, &lifetimes(&[(0, 0, 0), (1, 1, 2), (2, 2, 3)]));
+        let offset = |id| plan.placement(GraphResource::Texture(id)).unwrap().offset;
+        assert_eq!(plan.blocks.len(), 1);
+        assert_eq!(offset(1), 0);
+        // Aligned past the end of the other one.
+        assert_eq!(offset(2), 512);
+        assert_eq!(plan.aliases(GraphResource::Texture(2)).collect::<Vec<_>>(), vec![GraphResource::Texture(0)]);
+        assert_eq!(plan.acquired_at(2).collect::<Vec<_>>(), vec![GraphResource::Texture(2)]);
+    }
+
+    #[test]
+    fn incompatible_memory_is_not_shared() {
+        let buffer = MemoryRequest {
+            resource: GraphResource::Buffer(0),
+            linear: true,
+            ..texture(0, 512)
+        };
+        let other_type = MemoryRequest {
+            memory_type_bits: 0b100,
+            ..texture(1, 512)
+        };
+        let requests = [texture(0, 1024), buffer, other_type];
+        let mut lifetimes = lifetimes(&[(0, 0, 0), (1, 1, 1)]);
+        lifetimes.insert(GraphResource::Buffer(0), Lifetime { first: 2, last: 2 });
+        let plan = MemoryPlan::new(&requests, &lifetimes);
+        assert_eq!(plan.blocks.len(), 3);
+
+        let mut reordered = plan.clone();
+        reordered.placements.reverse();
+        assert!(plan.same_layout(&reordered));
+    }
+
+    #[test]
+    fn lifetimes_follow_the_compiled_order() {
+        let pass = |name: &str, accesses: &[(usize, AccessKind)]| PassDesc {
+            name: name.to_owned(),
+            presentation: name == "present",
+            accesses: accesses
+                .iter()
+                .map(|&(id, kind)| PassAccess::new(GraphResource::Texture(id), kind))
+                .collect(),
+            ..Default::default()
+        };
+        let desc = GraphDesc {
+            passes: vec![
+                pass("present", &[(2, AccessKind::Read)]),
+                pass("blur", &[(1, AccessKind::Read), (2, AccessKind::Write)]),
+                pass("scene", &[(0, AccessKind::Write)]),
+                pass("tonemap", &[(0, AccessKind::Read), (1, AccessKind::Write)]),
+            ],
+            ..Default::default()
+        };
+        let compiled = desc.compile();
+        assert_eq!(compiled.order, vec![2, 3, 1, 0]);
+        let lifetimes = super::lifetimes(&desc, &compiled);
+        assert_eq!(lifetimes[&GraphResource::Texture(0)], Lifetime { first: 0, last: 1 });
+        assert_eq!(lifetimes[&GraphResource::Texture(2)], Lifetime { first: 2, last: 3 });
+
+        // The scene and the blurred result are never alive together.
+        let requests = [texture(0, 1024), texture(1, 1024), texture(2, 1024)];
+        let plan = MemoryPlan::new(&requests, &lifetimes);
+        assert_eq!(plan.usage().allocated, 2048);
+    }
+}
@@ -0,0 +1,1200 @@
+pub mod compile;
+pub mod declared;
+pub mod export;
+pub mod memory;
+pub mod size;
+pub mod validate;
+
+use std::collections::HashMap;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use ash::vk::{self, DescriptorSetLayoutBinding, DescriptorSetLayoutCreateInfo, DescriptorType, ShaderStageFlags};
+use gpu_allocator::vulkan::{Allocation, AllocationCreateDesc, AllocationScheme};
+use gpu_allocator::MemoryLocation;
+use rspirv_reflect::{BindingCount, DescriptorInfo};
+use vk_sync::AccessType;
+
+use crate::renderer::Renderer;
+use crate::vulkan::render_pass::RenderPassBuilder;
+use crate::vulkan::renderer::{
+    VulkanRenderer, DESCRIPTOR_SET_INDEX_BINDLESS, DESCRIPTOR_SET_INDEX_INPUT_TEXTURES,
+    DESCRIPTOR_SET_INDEX_VIEW,
+};
+use crate::vulkan::shader::Binding;
+use crate::vulkan::{
+    image_pipeline_barrier, Buffer, DescriptorSet, Device, Image, ImageDesc, Pipeline, PipelineDesc, PipelineDescBuilder, PipelineType, RenderPass
+};
+use crate::{vulkan, Texture};
+
+pub use compile::{CompiledGraph, GraphDesc, GraphDiagnostic};
+pub use declared::{DeclaredGraph, GraphFile, GraphFileError};
+pub use export::GraphExport;
+pub use memory::{MemoryPlan, MemoryUsage};
+pub use size::TextureSize;
+pub use validate::{PassBindings, PassMismatch};
+use compile::{AccessKind, AttachmentFormats, GraphResource, PassAccess, PassDesc, ResourceDesc, Subresource};
+use export::{ExportedPass, ExportedPipeline, ExportedResource, ExportedResourceKind};
+use memory::MemoryRequest;
+
+pub type TextureId = usize;
+pub type BufferId = usize;
+pub type PipelineId = usize;
+
+/// Resources a pass waits on and the access it waits for them to be in.
+type Barriers = Vec<(GraphResource, AccessType)>;
+
+
+pub struct GraphTexture {
+    pub texture: Texture,
+    pub prev_access: AccessType,
+    pub size: TextureSize,
+    /// Only holds data within a frame, so its memory may be shared, see
+    /// [`MemoryPlan`].
+    pub transient: bool,
+    /// Last accesses of the resources sharing its memory, waited on by the
+    /// next barrier.
+    pub aliased_accesses: Vec<AccessType>,
+}
+pub struct GraphBuffer {
+    pub buffer: Buffer,
+    pub prev_access: AccessType,
+    pub transient: bool,
+    pub aliased_accesses: Vec<AccessType>,
+}
+impl GraphTexture {
+    fn barrier(&mut self, device: &Device, command_buffer: vk::CommandBuffer, next_access: AccessType) {
+        self.prev_access = match self.aliased_accesses.is_empty() {
+            true => image_pipeline_barrier(
+                device,
+                command_buffer,
+                &self.texture.image,
+                self.prev_access,
+                next_access,
+                false,
+            ),
+            false => vulkan::image_aliasing_barrier(
+                device,
+                command_buffer,
+                &self.texture.image,
+                &std::mem::take(&mut self.aliased_accesses),
+                next_access,
+            ),
+        };
+    }
+}
+impl GraphBuffer {
+    pub fn nothing(buffer: Buffer) -> Self {
+        Self {
+            buffer,
+            prev_access:AccessType::Nothing,
+            transient: false,
+            aliased_accesses: vec![],
+        }
+    }
+
+    fn barrier(&mut self, device: &Device, command_buffer: vk::CommandBuffer, next_access: AccessType) {
+        for aliased_access in std::mem::take(&mut self.aliased_accesses) {
+            vulkan::global_pipeline_barrier(device, command_buffer, aliased_access, next_access);
+        }
+        self.prev_access =
+            vulkan::global_pipeline_barrier(device, command_buffer, self.prev_access, next_access);
+    }
+}
+
+// TODO These need to be destroyed on release
+pub struct GraphResources {
+    pub buffers: Vec<GraphBuffer>,
+    pub textures: Vec<GraphTexture>,
+    pub pipelines: Vec<Pipeline>,
+    /// Where transient textures and buffers live this frame.
+    pub memory_plan: MemoryPlan,
+    transient_memory: Vec<Allocation>,
+    /// A transient was recreated, so it has no memory even if the plan
+    /// stays the same.
+    transients_unbound: bool,
+}
+
+pub enum DepthAttachment {
+    GraphHandle(Attachment),
+    External(Image, vk::AttachmentLoadOp),
+}
+
+#[derive(Copy, Clone)]
+pub enum ViewType {
+    Full(),
+    Layer(u32),
+}
+
+#[derive(Copy, Clone)]
+pub struct Attachment {
+    pub texture: TextureId,
+    pub view: ViewType,
+    pub load_op: vk::AttachmentLoadOp,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum TextureResourceType {
+    CombinedImageSampler,
+    StorageImage,
+}
+
+#[derive(Copy, Clone)]
+pub struct TextureResource {
+    pub texture: TextureId,
+    pub input_type: TextureResourceType,
+    pub access_type: AccessType,
+}
+
+#[derive(Copy, Clone)]
+pub struct BufferResource {
+    pub buffer: BufferId,
+    pub access_type: AccessType,
+}
+
+#[derive(Copy, Clone)]
+pub enum Resource {
+    Texture(TextureResource),
+    Buffer(BufferResource),
+}
+pub struct TextureCopy {
+    pub src: TextureId,
+    pub dst: TextureId,
+    pub copy_desc: vk::ImageCopy,
+}
+pub struct RenderGraph {
+    pub passes: Vec<Vec<RenderPass>>,
+    pub resources: GraphResources,
+    pub descriptor_set_camera: Vec<DescriptorSet>,
+    pub pipeline_descs: Vec<PipelineDesc>,
+    pub current_frame: usize,
+    pub device: Arc<Device>,
+    /// Format presentation passes render to.
+    pub present_format: vk::Format,
+    /// Resolution [`TextureSize::Full`] textures are created at.
+    pub swapchain_extent: vk::Extent2D,
+    /// Order `render` records the current frame's passes in, see
+    /// [`RenderGraph::compile`].
+    pub compiled: Option<CompiledGraph>,
+    /// Passes loaded from a file, see [`RenderGraph::load_declared`].
+    pub declared: Option<DeclaredGraph>,
+    reported_diagnostics: Vec<String>,
+    reported_mismatches: Vec<String>,
+}
+// TODO REMOVE THIS CONSTANT
+pub const MAX_UNIFORMS_SIZE: usize = 2048;
+
+// TODO DYNAMIC DESCRIPTOR SETS
+#[derive(Copy, Clone)]
+pub struct UniformData {
+    pub data: [MaybeUninit<u8>; MAX_UNIFORMS_SIZE],
+    pub size: u64,
+}
+
+
+impl GraphResources {
+    fn new() -> GraphResources {
+        GraphResources {
+            buffers: vec![],
+            textures: vec![],
+            pipelines: vec![],
+            memory_plan: MemoryPlan::default(),
+            transient_memory: vec![],
+            transients_unbound: false,
+        }
+    }
+
+    pub fn buffer(&self, id: BufferId) -> &GraphBuffer {
+        &self.buffers[id]
+    }
+
+    pub fn texture(&self, id: TextureId) -> &GraphTexture {
+        &self.textures[id]
+    }
+
+    pub fn pipeline(&self, id: PipelineId) -> &Pipeline {
+        &self.pipelines[id]
+    }
+
+    /// Accesses `render` waits for around `pass`, in order: before it records
+    /// and after, for its copy.
+    fn pass_barriers(&self, pass: &RenderPass) -> (Barriers, Barriers) {
+        let mut before = vec![];
+        for read in &pass.reads {
+            before.push(match read {
+                Resource::Texture(read) => (GraphResource::Texture(read.texture), read.access_type),
+                Resource::Buffer(read) => (GraphResource::Buffer(read.buffer), read.access_type),
+            });
+        }
+        for (buffer, access_type) in pass.extra_barriers.iter().flatten() {
+            before.push((GraphResource::Buffer(*buffer), *access_type));
+        }
+        let depth = match &pass.depth_attachment {
+            Some(DepthAttachment::GraphHandle(depth)) => Some(depth),
+            _ => None,
+        };
+        for write in pass.writes.iter().chain(depth) {
+            let access_type = match Image::is_depth_image_fmt(self.textures[write.texture].texture.image.desc.format) {
+                true => AccessType::DepthStencilAttachmentWrite,
+                false => AccessType::ColorAttachmentWrite,
+            };
+            before.push((GraphResource::Texture(write.texture), access_type));
+        }
+
+        let after = match &pass.copy_command {
+            Some(copy) => vec![
+                (GraphResource::Texture(copy.src), AccessType::TransferRead),
+                (GraphResource::Texture(copy.dst), AccessType::TransferWrite),
+            ],
+            None => vec![],
+        };
+        (before, after)
+    }
+
+    fn barrier(&mut self, device: &Device, command_buffer: vk::CommandBuffer, resource: GraphResource, next_access: AccessType) {
+        match resource {
+            GraphResource::Texture(id) => self.textures[id].barrier(device, command_buffer, next_access),
+            GraphResource::Buffer(id) => self.buffers[id].barrier(device, command_buffer, next_access),
+        }
+    }
+
+    /// Makes the transients first used at `position` of the compiled order
+    /// wait for the last accesses of the resources they share memory with.
+    fn acquire_transients(&mut self, position: usize) {
+        let plan = &self.memory_plan;
+        let acquired: Vec<(GraphResource, Vec<AccessType>)> = plan
+            .acquired_at(position)
+            .map(|resource| {
+                let accesses = plan
+                    .aliases(resource)
+                    .map(|alias| match alias {
+                        GraphResource::Texture(id) => self.textures[id].prev_access,
+                        GraphResource::Buffer(id) => self.buffers[id].prev_access,
+                    })
+                    .filter(|access| !matches!(access, AccessType::Nothing))
+                    .collect();
+                (resource, accesses)
+            })
+            .collect();
+
+        for (resource, accesses) in acquired {
+            match resource {
+                GraphResource::Texture(id) => {
+                    let texture = &mut self.textures[id];
+                    texture.prev_access = AccessType::Nothing;
+                    texture.aliased_accesses = accesses;
+                }
+                GraphResource::Buffer(id) => {
+                    let buffer = &mut self.buffers[id];
+                    buffer.prev_access = AccessType::Nothing;
+                    buffer.aliased_accesses = accesses;
+                }
+            }
+        }
+    }
+}
+impl Drop for RenderGraph {
+    fn drop(&mut self) {
+        self.descriptor_set_camera
+            .iter()
+            .for_each(|ds| ds.clean_vk_resources());
+        self.resources.buffers.iter().for_each(|b| unsafe {
+            self.device.ash_device.destroy_buffer(b.buffer.vk_buffer, None);
+        });
+        self.resources.pipelines.iter().for_each(|b| unsafe {
+            self.device
+                .ash_device
+                .destroy_pipeline_layout(b.pipeline_layout, None);
+        });
+        self.resources.textures.iter().for_each(|b| {
+            b.texture.clean_vk_resources();
+        });
+        let mut allocator = self.device.gpu_allocator.lock().unwrap();
+        for allocation in self.resources.transient_memory.drain(..) {
+            allocator.free(allocation).expect("Failed to free transient memory");
+        }
+    }
+}
+impl RenderGraph {
+    pub fn new(
+        device: Arc<Device>,
+        camera_uniform_buffer: &Vec<Buffer>,
+        num_frames_in_flight: u32,
+    ) -> Self {
+        RenderGraph {
+            passes: (0..num_frames_in_flight).map(|_| vec![]).collect(),
+            resources: GraphResources::new(),
+            descriptor_set_camera: (*camera_uniform_buffer)
+                .iter()
+                .map(|buffer| Self::create_camera_descriptor_set(device.clone(), buffer))
+                .collect(),
+            pipeline_descs: vec![],
+            current_frame: 0,
+            device: device,
+            present_format: vk::Format::UNDEFINED,
+            swapchain_extent: vk::Extent2D::default(),
+            compiled: None,
+            declared: None,
+            reported_diagnostics: vec![],
+            reported_mismatches: vec![],
+        }
+    }
+
+    /// Adds the passes declared in the RON file at `path` to every frame,
+    /// reloading them when it changes.
+    pub fn load_declared(&mut self, path: impl AsRef<std::path::Path>) -> Result<(), GraphFileError> {
+        self.declared = Some(DeclaredGraph::load(path.as_ref())?);
+        Ok(())
+    }
+
+    pub fn new_frame(&mut self, current_frame: usize) {
+        self.current_frame = current_frame;
+    }
+
+    pub fn clear(&mut self) {
+        for pass in &self.passes[self.current_frame] {
+            if let Some(descriptor_set) = &pass.uniform_descriptor_set {
+                unsafe {
+                    self.device
+                        .ash_device
+                        .destroy_descriptor_pool(descriptor_set.pool, None)
+                };
+            }
+            if let Some(descriptor_set) = &pass.read_resources_descriptor_set {
+                unsafe {
+                    self.device
+                        .ash_device
+                        .destroy_descriptor_pool(descriptor_set.pool, None)
+                };
+            }
+        }
+
+        self.passes[self.current_frame].clear();
+        self.compiled = None;
+    }
+
+    pub fn create_camera_descriptor_set(
+        device: Arc<Device>,
+        camera_uniform_buffer: &Buffer,
+    ) -> DescriptorSet {
+        let descriptor_set_layout_binding = DescriptorSetLayoutBinding::builder()
+            .binding(0)
+            .descriptor_type(DescriptorType::UNIFORM_BUFFER)
+            .descriptor_count(1)
+            .stage_flags(ShaderStageFlags::ALL)
+            .build();
+
+        let descriptor_sets_layout_info = DescriptorSetLayoutCreateInfo::builder()
+            .bindings(&[descriptor_set_layout_binding])
+            .build();
+
+        let descriptor_set_layout = unsafe {
+            device
+                .device()
+                .create_descriptor_set_layout(&descriptor_sets_layout_info, None)
+                .expect("Error creating descriptor set layout")
+        };
+
+        let mut binding_map: vulkan::shader::BindingMap = std::collections::BTreeMap::new();
+        binding_map.insert(
+            "view".to_string(),
+            Binding {
+                set: DESCRIPTOR_SET_INDEX_VIEW,
+                binding: 0,
+                info: DescriptorInfo {
+                    ty: rspirv_reflect::DescriptorType::UNIFORM_BUFFER,
+                    binding_count: BindingCount::One,
+                    name: "view".to_string(),
+                },
+            },
+        );
+
+        let descriptor_set_camera =
+            DescriptorSet::new(device.clone(), descriptor_set_layout, binding_map);
+
+        descriptor_set_camera.write_uniform_buffer("view".to_string(), camera_uniform_buffer);
+
+        descriptor_set_camera
+    }
+
+    fn add_pass(&mut self, name: String, pipeline_handle: PipelineId) -> RenderPassBuilder {
+        RenderPassBuilder {
+            name,
+            pipeline_handle,
+            reads: vec![],
+            writes: vec![],
+            render_func: None,
+            depth_attachment: None,
+            presentation_pass: false,
+            uniforms: HashMap::new(),
+            copy_command: None,
+            extra_barriers: None,
+            device: self.device.clone(),
+        }
+    }
+
+    pub fn add_pass_from_desc(
+        &mut self,
+        name: &str,
+        desc_builder: PipelineDescBuilder,
+    ) -> RenderPassBuilder {
+        let pipeline_handle = self.get_or_create_pipeline(desc_builder.build());
+        self.add_pass(name.to_string(), pipeline_handle)
+    }
+
+    /// Creates a texture of `size` relative to the swapchain, or returns the
+    /// one called `debug_name`, recreating it when its size changed.
+    pub fn get_or_create_texture(
+        &mut self,
+        debug_name: &str,
+        device: Arc<Device>,
+        size: TextureSize,
+        image_desc: ImageDesc,
+    ) -> TextureId {
+        let image_desc = size.resolve(image_desc, self.swapchain_extent);
+        match self.texture_id(debug_name) {
+            Some(id) => {
+                self.resize_texture(id, size, image_desc);
+                id
+            }
+            None => {
+                self.resources.textures.push(GraphTexture {
+                    texture: Texture::create(device, None, image_desc, debug_name),
+                    prev_access: AccessType::Nothing,
+                    size,
+                    transient: false,
+                    aliased_accesses: vec![],
+                });
+
+                self.resources.textures.len() - 1
+            }
+        }
+    }
+
+    /// Like [`RenderGraph::get_or_create_texture`], for textures that no
+    /// pass reads before another writes them in the same frame. They get
+    /// memory when the graph is compiled and may share it.
+    pub fn get_or_create_transient_texture(
+        &mut self,
+        debug_name: &str,
+        device: Arc<Device>,
+        size: TextureSize,
+        image_desc: ImageDesc,
+    ) -> TextureId {
+        let image_desc = size.resolve(image_desc, self.swapchain_extent);
+        match self.texture_id(debug_name) {
+            Some(id) => {
+                self.resize_texture(id, size, image_desc);
+                id
+            }
+            None => {
+                self.resources.textures.push(GraphTexture {
+                    texture: Texture::new_unbound(device, image_desc, debug_name),
+                    prev_access: AccessType::Nothing,
+                    size,
+                    transient: true,
+                    aliased_accesses: vec![],
+                });
+
+                self.resources.textures.len() - 1
+            }
+        }
+    }
+
+    /// The texture created as `debug_name`, if any.
+    pub fn texture_id(&self, debug_name: &str) -> Option<TextureId> {
+        self.resources
+            .textures
+            .iter()
+            .position(|iter| iter.texture.image.debug_name == debug_name)
+    }
+
+    /// Recreates texture `id` if `image_desc` has another extent or layer
+    /// count. Passes are built every frame, so their descriptor sets pick
+    /// up the new views.
+    fn resize_texture(&mut self, id: TextureId, size: TextureSize, image_desc: ImageDesc) {
+        let texture = &mut self.resources.textures[id];
+        texture.size = size;
+        let desc = texture.texture.image.desc;
+        if (desc.width, desc.height, desc.array_layers)
+            == (image_desc.width, image_desc.height, image_desc.array_layers)
+        {
+            return;
+        }
+
+        // Besides swapchain recreation, a reloaded graph file can resize a
+        // texture while earlier frames still read it.
+        unsafe { self.device.ash_device.device_wait_idle().unwrap() };
+        let debug_name = texture.texture.image.debug_name.clone();
+        if texture.transient {
+            texture.texture.image.desc = image_desc;
+            texture.texture.unbind();
+            self.resources.transients_unbound = true;
+        } else {
+            texture.texture.clean_vk_resources();
+            unsafe { self.device.ash_device.free_memory(texture.texture.image.device_memory, None) };
+            texture.texture = Texture::create(self.device.clone(), None, image_desc, &debug_name);
+        }
+        texture.prev_access = AccessType::Nothing;
+    }
+
+    pub fn get_or_create_buffer(
+        &mut self,
+        debug_name: &str,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+        memory_location: MemoryLocation,
+    ) -> BufferId {
+        self.resources
+            .buffers
+            .iter()
+            .position(|iter| iter.buffer.debug_name == debug_name)
+            .unwrap_or_else(|| {
+                let mut buffer = Buffer::new::<u8>(
+                    self.device.clone(),
+                    None,
+                    size,
+                    usage,
+                    memory_location,
+                    Some(String::from(debug_name)),
+                );
+
+                buffer.set_debug_name(debug_name);
+
+                self.resources.buffers.push(GraphBuffer::nothing(buffer));
+
+                self.resources.buffers.len() - 1
+            })
+    }
+
+    /// A device local buffer that only holds data within a frame, see
+    /// [`RenderGraph::get_or_create_transient_texture`].
+    pub fn get_or_create_transient_buffer(
+        &mut self,
+        debug_name: &str,
+        size: u64,
+        usage: vk::BufferUsageFlags,
+    ) -> BufferId {
+        self.resources
+            .buffers
+            .iter()
+            .position(|iter| iter.buffer.debug_name == debug_name)
+            .unwrap_or_else(|| {
+                let buffer = Buffer::new_unbound(self.device.clone(), size, usage, debug_name);
+                self.resources.buffers.push(GraphBuffer {
+                    transient: true,
+                    ..GraphBuffer::nothing(buffer)
+                });
+
+                self.resources.buffers.len() - 1
+            })
+    }
+
+    /// Returns the handle of the pipeline with the
+    /// [`crate::vulkan::PipelineKey`] of `pipeline_desc`, creating it when
+    /// there is none yet.
+    pub fn get_or_create_pipeline(&mut self, pipeline_desc: PipelineDesc) -> PipelineId {
+        let key = pipeline_desc.key();
+        if let Some(existing_pipeline_id) = self
+            .pipeline_descs
+            .iter()
+            .position(|desc| desc.key() == key)
+        {
+            existing_pipeline_id
+        } else {
+            self.pipeline_descs.push(pipeline_desc);
+            self.pipeline_descs.len() - 1
+        }
+    }
+
+    /// The current frame's passes as plain data, with the attachment formats
+    /// of pipelines that were already created.
+    pub fn describe(&self) -> GraphDesc {
+        let texture_format = |texture: TextureId| self.resources.textures[texture].texture.image.format();
+        let subresource = |view: ViewType| match view {
+            ViewType::Full() => Subresource::ALL,
+            ViewType::Layer(layer) => Subresource::layer(layer),
+        };
+
+        let passes = self.passes[self.current_frame]
+            .iter()
+            .map(|pass| {
+                let mut accesses = vec![];
+                for read in &pass.reads {
+                    accesses.push(match read {
+                        Resource::Texture(read) => PassAccess::new(
+                            GraphResource::Texture(read.texture),
+                            AccessKind::from_access_type(read.access_type),
+                        ),
+                        Resource::Buffer(read) => PassAccess::new(
+                            GraphResource::Buffer(read.buffer),
+                            AccessKind::from_access_type(read.access_type),
+                        ),
+                    });
+                }
+                for (buffer, access_type) in pass.extra_barriers.iter().flatten() {
+                    accesses.push(PassAccess::new(
+                        GraphResource::Buffer(*buffer),
+                        AccessKind::from_access_type(*access_type),
+                    ));
+                }
+                let depth = match &pass.depth_attachment {
+                    Some(DepthAttachment::GraphHandle(depth)) => Some(depth),
+                    _ => None,
+                };
+                for attachment in pass.writes.iter().chain(depth) {
+                    accesses.push(PassAccess {
+                        resource: GraphResource::Texture(attachment.texture),
+                        subresource: subresource(attachment.view),
+                        kind: AccessKind::from_load_op(attachment.load_op),
+                    });
+                }
+                // Copies happen after the pass has rendered.
+                if let Some(copy) = &pass.copy_command {
+                    let dst = copy.copy_desc.dst_subresource;
+                    accesses.push(PassAccess::new(GraphResource::Texture(copy.src), AccessKind::Read));
+                    accesses.push(PassAccess {
+                        resource: GraphResource::Texture(copy.dst),
+                        subresource: Subresource {
+                            layer: (dst.layer_count == 1).then_some(dst.base_array_layer),
+                            mip: Some(dst.mip_level),
+                        },
+                        kind: AccessKind::Write,
+                    });
+                }
+
+                let attachments = AttachmentFormats {
+                    color: match pass.is_pres_pass {
+                        true => vec![self.present_format],
+                        false => pass.writes.iter().map(|write| texture_format(write.texture)).collect(),
+                    },
+                    depth: match &pass.depth_attachment {
+                        Some(DepthAttachment::GraphHandle(depth)) => texture_format(depth.texture),
+                        Some(DepthAttachment::External(image, _)) => image.format(),
+                        None => vk::Format::UNDEFINED,
+                    },
+                };
+                let pipeline = self
+                    .resources
+                    .pipelines
+                    .get(pass.pipeline_handle)
+                    .map_or(&self.pipeline_descs[pass.pipeline_handle], |pipeline| &pipeline.pipeline_desc);
+
+                PassDesc {
+                    name: pass.name.clone(),
+                    presentation: pass.is_pres_pass,
+                    accesses,
+                    attachments,
+                    pipeline: pipeline.compute_path.is_none().then(|| AttachmentFormats {
+                        color: pipeline.color_attachment_formats.clone(),
+                        depth: pipeline.depth_stencil_attachment_format,
+                    }),
+                }
+            })
+            .collect();
+
+        GraphDesc {
+            passes,
+            textures: self
+                .resources
+                .textures
+                .iter()
+                .map(|texture| ResourceDesc {
+                    name: texture.texture.image.debug_name.clone(),
+                    initialized: !texture.transient && !matches!(texture.prev_access, AccessType::Nothing),
+                })
+                .collect(),
+            // Other buffers are filled from the host.
+            buffers: self
+                .resources
+                .buffers
+                .iter()
+                .map(|buffer| ResourceDesc {
+                    name: buffer.buffer.debug_name.clone(),
+                    initialized: !buffer.transient,
+                })
+                .collect(),
+        }
+    }
+
+    /// Sorts and culls the current frame's passes for `render`, warning about
+    /// hazards and mismatched attachments whenever they change, and places
+    /// transient resources in memory.
+    pub fn compile(&mut self) -> &CompiledGraph {
+        let desc = self.describe();
+        let compiled = desc.compile();
+        self.plan_transient_memory(&desc, &compiled);
+
+        let messages: Vec<String> = compiled
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.message(&desc))
+            .collect();
+        if messages != self.reported_diagnostics {
+            for message in &messages {
+                log::warn!("{}", message);
+            }
+            self.reported_diagnostics = messages;
+        }
+        self.compiled.insert(compiled)
+    }
+
+    /// The current frame's passes, pipelines and resources, with the
+    /// transitions `render` records for them.
+    pub fn export(&self) -> GraphExport {
+        let desc = self.describe();
+        let fallback;
+        let compiled = match &self.compiled {
+            Some(compiled) => compiled,
+            None => {
+                fallback = desc.compile();
+                &fallback
+            }
+        };
+        let passes = &self.passes[self.current_frame];
+
+        let barriers: Vec<Barriers> = passes
+            .iter()
+            .map(|pass| {
+                let (before, after) = self.resources.pass_barriers(pass);
+                before.into_iter().chain(after).collect()
+            })
+            .collect();
+        let textures = self.resources.textures.iter().enumerate();
+        let buffers = self.resources.buffers.iter().enumerate();
+        let initial = textures
+            .clone()
+            .map(|(id, texture)| (GraphResource::Texture(id), texture.prev_access))
+            .chain(buffers.clone().map(|(id, buffer)| (GraphResource::Buffer(id), buffer.prev_access)))
+            .collect();
+
+        GraphExport {
+            passes: passes
+                .iter()
+                .enumerate()
+                .map(|(index, pass)| ExportedPass {
+                    name: pass.name.clone(),
+                    pipeline: pass.pipeline_handle,
+                    presentation: pass.is_pres_pass,
+                    position: compiled.order.iter().position(|&pass| pass == index),
+                })
+                .collect(),
+            pipelines: self
+                .pipeline_descs
+                .iter()
+                .enumerate()
+                .map(|(id, desc)| {
+                    let desc = self.resources.pipelines.get(id).map_or(desc, |pipeline| &pipeline.pipeline_desc);
+                    ExportedPipeline {
+                        vertex_path: desc.vertex_path.map(str::to_owned),
+                        fragment_path: desc.fragment_path.map(str::to_owned),
+                        compute_path: desc.compute_path.map(str::to_owned),
+                        color_formats: desc.color_attachment_formats.clone(),
+                        depth_format: desc.depth_stencil_attachment_format,
+                    }
+                })
+                .collect(),
+            resources: textures
+                .map(|(id, texture)| {
+                    let image = &texture.texture.image;
+                    ExportedResource {
+                        resource: GraphResource::Texture(id),
+                        name: image.debug_name.clone(),
+                        kind: ExportedResourceKind::Texture {
+                            format: image.desc.format,
+                            width: image.desc.width,
+                            height: image.desc.height,
+                            layers: image.desc.array_layers,
+                            mips: image.desc.mip_levels,
+                        },
+                        size: image.memory_requirements().size,
+                        transient: texture.transient,
+                    }
+                })
+                .chain(buffers.map(|(id, buffer)| ExportedResource {
+                    resource: GraphResource::Buffer(id),
+                    name: buffer.buffer.debug_name.clone(),
+                    kind: ExportedResourceKind::Buffer {
+                        usage: buffer.buffer.usage,
+                    },
+                    size: buffer.buffer.memory_req.size,
+                    transient: buffer.transient,
+                }))
+                .collect(),
+            edges: export::edges(&desc),
+            transitions: export::transitions(compiled, &barriers, &initial, &self.resources.memory_plan),
+        }
+    }
+
+    /// Plans where transient resources live over `compiled`. Images and
+    /// buffers cannot be bound twice, so they are recreated whenever the
+    /// plan moves anything.
+    fn plan_transient_memory(&mut self, desc: &GraphDesc, compiled: &CompiledGraph) {
+        let textures = self.resources.textures.iter().enumerate().filter(|(_, texture)| texture.transient);
+        let textures = textures.map(|(id, texture)| (GraphResource::Texture(id), texture.texture.image.memory_requirements(), false));
+        let buffers = self.resources.buffers.iter().enumerate().filter(|(_, buffer)| buffer.transient);
+        let buffers = buffers.map(|(id, buffer)| (GraphResource::Buffer(id), buffer.buffer.memory_req, true));
+        let requests: Vec<MemoryRequest> = textures
+            .chain(buffers)
+            .map(|(resource, requirements, linear)| MemoryRequest {
+                resource,
+                size: requirements.size,
+                alignment: requirements.alignment,
+                memory_type_bits: requirements.memory_type_bits,
+                linear,
+            })
+            .collect();
+
+        let plan = MemoryPlan::new(&requests, &memory::lifetimes(desc, compiled));
+        if self.resources.transients_unbound || !plan.same_layout(&self.resources.memory_plan) {
+            self.place_transients(&plan);
+            self.resources.transients_unbound = false;
+        }
+        self.resources.memory_plan = plan;
+    }
+
+    fn place_transients(&mut self, plan: &MemoryPlan) {
+        unsafe { self.device.ash_device.device_wait_idle().unwrap() };
+        for placement in &self.resources.memory_plan.placements {
+            match placement.resource {
+                GraphResource::Texture(id) => self.resources.textures[id].texture.unbind(),
+                GraphResource::Buffer(id) => self.resources.buffers[id].buffer.unbind(),
+            }
+        }
+
+        let mut allocator = self.device.gpu_allocator.lock().unwrap();
+        for allocation in self.resources.transient_memory.drain(..) {
+            allocator.free(allocation).expect("Failed to free transient memory");
+        }
+        self.resources.transient_memory = plan
+            .blocks
+            .iter()
+            .map(|block| {
+                allocator
+                    .allocate(&AllocationCreateDesc {
+                        name: "render graph transients",
+                        requirements: vk::MemoryRequirements {
+                            size: block.size,
+                            alignment: block.alignment,
+                            memory_type_bits: block.memory_type_bits,
+                        },
+                        location: MemoryLocation::GpuOnly,
+                        linear: block.linear,
+                        allocation_scheme: AllocationScheme::GpuAllocatorManaged,
+                    })
+                    .expect("Failed to allocate transient memory")
+            })
+            .collect();
+        drop(allocator);
+
+        for placement in &plan.placements {
+            let allocation = &self.resources.transient_memory[placement.block];
+            let memory = unsafe { allocation.memory() };
+            let offset = allocation.offset() + placement.offset;
+            match placement.resource {
+                GraphResource::Texture(id) => {
+                    let texture = &mut self.resources.textures[id];
+                    texture.texture.bind_memory(memory, offset);
+                    texture.prev_access = AccessType::Nothing;
+                }
+                GraphResource::Buffer(id) => {
+                    let buffer = &mut self.resources.buffers[id];
+                    buffer.buffer.bind_memory(memory, offset);
+                    buffer.prev_access = AccessType::Nothing;
+                }
+            }
+        }
+    }
+
+    pub fn prepare(&mut self, renderer: &VulkanRenderer) {
+        let device = renderer.device();
+
+        for (i, desc) in self.pipeline_descs.iter().enumerate() {
+            if self.resources.pipelines.len() <= i {
+                self.resources.pipelines.push(Pipeline::new(
+                    device,
+                    desc.clone(),
+                    Some(renderer.internal_renderer.bindless_descriptor_set_layout),
+                ));
+            }
+        }
+        let mut mismatches = vec![];
+        for pass in &mut self.passes[self.current_frame] {
+            let pass_mismatches =
+                PassBindings::of(pass).check(&self.resources.pipelines[pass.pipeline_handle].reflection);
+            pass.skipped = pass_mismatches.iter().any(PassMismatch::is_error);
+            mismatches.extend(
+                pass_mismatches
+                    .iter()
+                    .map(|mismatch| (mismatch.is_error(), mismatch.message(&pass.name))),
+            );
+            if pass.skipped {
+                continue;
+            }
+
+            pass.try_create_read_resources_descriptor_set(
+                &self.resources.pipelines,
+                &self.resources.textures,
+                &self.resources.buffers,
+            );
+            pass.try_create_uniform_buffer_descriptor_set(
+                &self.resources.pipelines,
+                &self.resources.buffers,
+            );
+
+            pass.update_uniform_buffer_memory(&mut self.resources.buffers);
+        }
+        self.report_mismatches(mismatches);
+    }
+
+    /// Logs passes that do not fit their shaders whenever that changes.
+    /// Passes with errors are skipped until they do.
+    fn report_mismatches(&mut self, mismatches: Vec<(bool, String)>) {
+        let messages: Vec<String> = mismatches.iter().map(|(_, message)| message.clone()).collect();
+        if messages == self.reported_mismatches {
+            return;
+        }
+        for (is_error, message) in &mismatches {
+            if *is_error {
+                log::error!("{}, skipping it", message);
+            } else {
+                log::warn!("{}", message);
+            }
+        }
+        self.reported_mismatches = messages;
+    }
+
+    pub fn recompile_all_shaders(
+        &mut self,
+        bindless_descriptor_set_layout: Option<vk::DescriptorSetLayout>,
+    ) {
+        for pipeline in &mut self.resources.pipelines {
+            pipeline.recreate_pipeline(&self.device, bindless_descriptor_set_layout);
+        }
+    }
+
+    pub fn recompile_shader(
+        &mut self,
+        device: &Device,
+        bindless_descriptor_set_layout: Option<vk::DescriptorSetLayout>,
+        path: std::path::PathBuf,
+    ) {
+        for pipeline in &mut self.resources.pipelines {
+            if pipeline.shader_files.iter().any(|file| path.ends_with(file)) {
+                pipeline.recreate_pipeline(device, bindless_descriptor_set_layout);
+            }
+        }
+    }
+
+    pub fn render(
+        &mut self,
+        command_buffer: &vk::CommandBuffer,
+        renderer: &VulkanRenderer,
+        present_image: &Image,
+    ) {
+        let device = renderer.device();
+        let passes = &self.passes[self.current_frame];
+        let order = match &self.compiled {
+            Some(compiled) => compiled.order.clone(),
+            None => (0..passes.len()).collect(),
+        };
+
+        for (position, pass) in order.iter().map(|&index| &passes[index]).enumerate() {
+            self.resources.acquire_transients(position);
+            if pass.skipped {
+                continue;
+            }
+
+            let (barriers_before, barriers_after) = self.resources.pass_barriers(pass);
+            for (resource, access_type) in barriers_before {
+                self.resources.barrier(device, *command_buffer, resource, access_type);
+            }
+            let pass_pipeline = &self.resources.pipelines[pass.pipeline_handle];
+
+            if pass.is_pres_pass {
+                image_pipeline_barrier(
+                    device,
+                    *command_buffer,
+                    present_image,
+                    AccessType::Present,
+                    AccessType::ColorAttachmentWrite,
+                    false,
+                );
+            }
+
+            let write_attachments: Vec<(Image, ViewType, vk::AttachmentLoadOp)> = pass
+                .writes
+                .iter()
+                .map(|write| {
+                    (
+                        self.resources.textures[write.texture].texture.image.clone(),
+                        write.view,
+                        write.load_op,
+                    )
+                })
+                .collect();
+            let extent = if !pass.writes.is_empty() {
+                vk::Extent2D {
+                    width: self.resources.textures[pass.writes[0].texture]
+                        .texture
+                        .image
+                        .width(),
+                    height: self.resources.textures[pass.writes[0].texture]
+                        .texture
+                        .image
+                        .height(),
+                }
+            } else if pass.depth_attachment.is_some() {
+                match pass.depth_attachment.as_ref().unwrap() {
+                    DepthAttachment::GraphHandle(depth_attachment) => vk::Extent2D {
+                        width: self.resources.textures[depth_attachment.texture]
+                            .texture
+                            .image
+                            .width(),
+                        height: self.resources.textures[depth_attachment.texture]
+                            .texture
+                            .image
+                            .height(),
+                    },
+                    DepthAttachment::External(depth_attachment, _) => vk::Extent2D {
+                        width: depth_attachment.width(),
+                        height: depth_attachment.height(),
+                    },
+                }
+            } else {
+                vk::Extent2D {
+                    width: 1,
+                    height: 1,
+                }
+            };
+
+            let present_image = [(
+                present_image.clone(),
+                ViewType::Full(),
+                vk::AttachmentLoadOp::CLEAR,
+            )];
+
+            pass.prepare_render(
+                command_buffer,
+                if !pass.is_pres_pass {
+                    write_attachments.as_slice()
+                } else {
+                    &present_image
+                },
+                if pass.depth_attachment.is_some() {
+                    match pass.depth_attachment.as_ref().unwrap() {
+                        DepthAttachment::GraphHandle(depth_attachment) => Some((
+                            self.resources.textures[depth_attachment.texture]
+                                .texture
+                                .image
+                                .clone(),
+                            depth_attachment.view,
+                            depth_attachment.load_op,
+                        )),
+                        DepthAttachment::External(depth_attachment, load_op) => {
+                            Some((depth_attachment.clone(), ViewType::Full(), *load_op))
+                        }
+                    }
+                } else {
+                    None
+                },
+                if !pass.is_pres_pass {
+                    extent
+                } else {
+                    vk::Extent2D {
+                        width: present_image[0].0.width(),
+                        height: present_image[0].0.height(),
+                    }
+                },
+                &self.resources.pipelines,
+            );
+            unsafe {
+                let bind_point = match pass_pipeline.pipeline_type {
+                    PipelineType::Graphics => vk::PipelineBindPoint::GRAPHICS,
+                    PipelineType::Compute => vk::PipelineBindPoint::COMPUTE,
+                };
+
+                device.device().cmd_bind_descriptor_sets(
+                    *command_buffer,
+                    bind_point,
+                    pass_pipeline.pipeline_layout,
+                    DESCRIPTOR_SET_INDEX_BINDLESS,
+                    &[renderer.internal_renderer.bindless_descriptor_set],
+                    &[],
+                );
+
+                device.device().cmd_bind_descriptor_sets(
+                    *command_buffer,
+                    bind_point,
+                    pass_pipeline.pipeline_layout,
+                    DESCRIPTOR_SET_INDEX_VIEW,
+                    &[self.descriptor_set_camera[self.current_frame].handle],
+                    &[],
+                );
+
+                if let Some(read_textures_descriptor_set) = &pass.read_resources_descriptor_set {
+                    device.device().cmd_bind_descriptor_sets(
+                        *command_buffer,
+                        bind_point,
+                        pass_pipeline.pipeline_layout,
+                        DESCRIPTOR_SET_INDEX_INPUT_TEXTURES,
+                        &[read_textures_descriptor_set.handle],
+                        &[],
+                    )
+                }
+
+                let uniform_binding = pass
+                    .uniforms
+                    .values()
+                    .next()
+                    .and_then(|(name, _)| pass_pipeline.reflection.get_binding(name).ok());
+                if let (Some(uniforms_descriptor_set), Some(binding)) = (&pass.uniform_descriptor_set, uniform_binding) {
+                    device.device().cmd_bind_descriptor_sets(
+                        *command_buffer,
+                        bind_point,
+                        pass_pipeline.pipeline_layout,
+                        binding.set,
+                        &[uniforms_descriptor_set.handle],
+                        &[],
+                    )
+                }
+            };
+
+            if let Some(render_func) = &pass.render_func {
+                render_func(device, command_buffer, renderer, pass, &self.resources);
+            }
+
+            if pass_pipeline.pipeline_type == PipelineType::Graphics {
+                unsafe { device.device().cmd_end_rendering(*command_buffer) };
+            }
+
+            if let Some(copy_command) = &pass.copy_command {
+                let src = copy_command.src;
+                let dst = copy_command.dst;
+
+                for (resource, access_type) in barriers_after {
+                    self.resources.barrier(device, *command_buffer, resource, access_type);
+                }
+
+                let src = &self.resources.textures[src].texture.image;
+                let dst = &self.resources.textures[dst].texture.image;
+
+                let mut copy_desc = copy_command.copy_desc;
+                copy_desc.src_subresource.aspect_mask = src.desc.aspect_flags;
+                copy_desc.dst_subresource.aspect_mask = dst.desc.aspect_flags;
+
+                unsafe {
+                    device.device().cmd_copy_image(
+                        *command_buffer,
+                        src.image,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        dst.image,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &[copy_desc],
+                    )
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! Graph textures sized relative to the swapchain follow it when it is
+//! recreated.
+
+use ash::vk;
+
+use crate::vulkan::ImageDesc;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureSize {
+    /// The swapchain's resolution.
+    Full,
+    /// Half the swapchain's resolution, rounded up.
+    Half,
+    /// The width and height of the texture's [`ImageDesc`].
+    Fixed,
+}
+
+impl TextureSize {
+    /// `desc` with its width and height replaced by this size.
+    pub fn resolve(&self, desc: ImageDesc, swapchain: vk::Extent2D) -> ImageDesc {
+        let (width, height) = match self {
+            TextureSize::Full => (swapchain.width, swapchain.height),
+            TextureSize::Half => (swapchain.width.div_ceil(2), swapchain.height.div_ceil(2)),
+            TextureSize::Fixed => return desc,
+        };
+        ImageDesc {
+            width: width.max(1),
+            height: height.max(1),
+            ..desc
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_follow_the_swapchain() {
+        let swapchain = vk::Extent2D {
+            width: 1921,
+            height: 1080,
+        };
+        let desc = ImageDesc::new_2d(4096, 2048, vk::Format::R16_UNORM);
+        let extent = |size: TextureSize, swapchain| {
+            let desc = size.resolve(desc, swapchain);
+            (desc.width, desc.height)
+        };
+        assert_eq!(extent(TextureSize::Full, swapchain), (1921, 1080));
+        assert_eq!(extent(TextureSize::Half, swapchain), (961, 540));
+        assert_eq!(extent(TextureSize::Fixed, swapchain), (4096, 2048));
+        assert_eq!(TextureSize::Half.resolve(desc, swapchain).format, vk::Format::R16_UNORM);
+
+        let minimized = vk::Extent2D { width: 0, height: 0 };
+        assert_eq!(extent(TextureSize::Half, minimized), (1, 1));
+    }
+}
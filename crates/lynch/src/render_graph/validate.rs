@@ -0,0 +1,367 @@
+//! Checks the reads, uniforms and colour attachments of a pass against what
+//! the shaders of its pipeline declare, before anything is bound.
+
+use rspirv_reflect::DescriptorType;
+
+use crate::vulkan::renderer::{DESCRIPTOR_SET_INDEX_BINDLESS, DESCRIPTOR_SET_INDEX_INPUT_TEXTURES, DESCRIPTOR_SET_INDEX_VIEW};
+use crate::vulkan::shader::ShaderReflect;
+use crate::vulkan::RenderPass;
+
+use super::{Resource, TextureResourceType};
+
+/// A way a pass does not fit its pipeline.
+#[derive(Debug, PartialEq)]
+pub enum PassMismatch {
+    /// Read `index` is bound to a binding declared as another descriptor
+    /// type.
+    ReadType {
+        index: usize,
+        name: String,
+        declared: DescriptorType,
+        bound: DescriptorType,
+    },
+    /// Read `index` has no binding in the input set to go to.
+    UndeclaredRead { index: usize },
+    /// The shaders declare input `binding` but the pass reads nothing into
+    /// it.
+    MissingRead { binding: u32, name: String },
+    /// The shaders declare no uniform buffer called `name`.
+    UnknownUniform { name: String },
+    /// Uniform `name` holds fewer bytes than its block.
+    UniformSize { name: String, declared: u32, provided: u64 },
+    /// The shaders declare uniform buffer `name` but the pass provides no
+    /// data for it.
+    MissingUniform { name: String, set: u32 },
+    /// Only the first uniform of a pass is bound, these are not.
+    UnboundUniforms { names: Vec<String> },
+    /// The fragment shader writes colours to `outputs` but the pass renders
+    /// to `attachments` colour attachments.
+    ColorOutputs { outputs: Vec<u32>, attachments: usize },
+}
+
+impl PassMismatch {
+    /// Whether recording the pass would bind descriptors the shaders do not
+    /// declare or leave declared ones unbound. The others only mean output
+    /// is lost.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, PassMismatch::UnboundUniforms { .. } | PassMismatch::ColorOutputs { .. })
+    }
+
+    pub fn message(&self, pass: &str) -> String {
+        match self {
+            PassMismatch::ReadType {
+                index,
+                name,
+                declared,
+                bound,
+            } => format!(
+                "Pass {} binds read {} as {:?}, its shaders declare {} as {:?}",
+                pass, index, bound, name, declared
+            ),
+            PassMismatch::UndeclaredRead { index } => format!(
+                "Pass {} binds read {}, its shaders declare no input binding {}",
+                pass, index, index
+            ),
+            PassMismatch::MissingRead { binding, name } => format!(
+                "Pass {} reads nothing into {}, input binding {} of its shaders",
+                pass, name, binding
+            ),
+            PassMismatch::UnknownUniform { name } => {
+                format!("Pass {} sets uniform {}, its shaders declare no such uniform buffer", pass, name)
+            }
+            PassMismatch::UniformSize {
+                name,
+                declared,
+                provided,
+            } => format!(
+                "Pass {} sets {} bytes of uniform {}, its shaders declare {}",
+                pass, provided, name, declared
+            ),
+            PassMismatch::MissingUniform { name, set } => format!(
+                "Pass {} sets no uniform {}, declared in set {} of its shaders",
+                pass, name, set
+            ),
+            PassMismatch::UnboundUniforms { names } => format!(
+                "Pass {} sets uniforms {}, only one uniform buffer is bound per pass",
+                pass,
+                names.join(", ")
+            ),
+            PassMismatch::ColorOutputs { outputs, attachments } => format!(
+                "Pass {} renders to {} colour attachments, its fragment shader writes locations {:?}",
+                pass, attachments, outputs
+            ),
+        }
+    }
+}
+
+/// What a pass binds, as far as the shaders of its pipeline can tell.
+pub struct PassBindings<'a> {
+    pub reads: &'a [Resource],
+    /// Name and size in bytes of each uniform.
+    pub uniforms: Vec<(&'a str, u64)>,
+    pub color_attachments: usize,
+}
+
+impl<'a> PassBindings<'a> {
+    pub fn of(pass: &'a RenderPass) -> Self {
+        let mut uniforms: Vec<(&str, u64)> = pass
+            .uniforms
+            .values()
+            .map(|(name, data)| (name.as_str(), data.size))
+            .collect();
+        uniforms.sort();
+        PassBindings {
+            reads: &pass.reads,
+            uniforms,
+            color_attachments: if pass.is_pres_pass { 1 } else { pass.writes.len() },
+        }
+    }
+
+    pub fn check(&self, reflection: &ShaderReflect) -> Vec<PassMismatch> {
+        let mut mismatches = vec![];
+
+        let inputs = reflection.descriptor_set_reflections.get(&DESCRIPTOR_SET_INDEX_INPUT_TEXTURES);
+        for (index, read) in self.reads.iter().enumerate() {
+            let bound = match read {
+                Resource::Texture(texture) if texture.input_type == TextureResourceType::StorageImage => {
+                    DescriptorType::STORAGE_IMAGE
+                }
+                Resource::Texture(_) => DescriptorType::COMBINED_IMAGE_SAMPLER,
+                Resource::Buffer(_) => DescriptorType::STORAGE_BUFFER,
+            };
+            match inputs.and_then(|inputs| inputs.get(&(index as u32))) {
+                Some(declared) if declared.ty != bound => mismatches.push(PassMismatch::ReadType {
+                    index,
+                    name: declared.name.clone(),
+                    declared: declared.ty,
+                    bound,
+                }),
+                Some(_) => {}
+                None => mismatches.push(PassMismatch::UndeclaredRead { index }),
+            }
+        }
+        for (&binding, declared) in inputs.into_iter().flatten() {
+            if binding as usize >= self.reads.len() {
+                mismatches.push(PassMismatch::MissingRead {
+                    binding,
+                    name: declared.name.clone(),
+                });
+            }
+        }
+
+        for &(name, provided) in &self.uniforms {
+            match reflection.get_binding(name) {
+                Ok(binding) if binding.ty == DescriptorType::UNIFORM_BUFFER => {
+                    let declared = reflection.uniform_block_sizes.get(&(binding.set, binding.binding));
+                    if let Some(&declared) = declared.filter(|&&declared| provided < declared as u64) {
+                        mismatches.push(PassMismatch::UniformSize {
+                            name: name.to_owned(),
+                            declared,
+                            provided,
+                        });
+                    }
+                }
+                _ => mismatches.push(PassMismatch::UnknownUniform { name: name.to_owned() }),
+            }
+        }
+        let shared_sets = [
+            DESCRIPTOR_SET_INDEX_BINDLESS,
+            DESCRIPTOR_SET_INDEX_VIEW,
+            DESCRIPTOR_SET_INDEX_INPUT_TEXTURES,
+        ];
+        for (&set, descriptors) in &reflection.descriptor_set_reflections {
+            if shared_sets.contains(&set) {
+                continue;
+            }
+            for declared in descriptors.values() {
+                if declared.ty == DescriptorType::UNIFORM_BUFFER
+                    && !self.uniforms.iter().any(|&(name, _)| name == declared.name)
+                {
+                    mismatches.push(PassMismatch::MissingUniform {
+                        name: declared.name.clone(),
+                        set,
+                    });
+                }
+            }
+        }
+        if self.uniforms.len() > 1 {
+            mismatches.push(PassMismatch::UnboundUniforms {
+                names: self.uniforms.iter().map(|&(name, _)| name.to_owned()).collect(),
+            });
+        }
+
+        let outputs: Vec<u32> = reflection.fragment_outputs.iter().copied().collect();
+        if !outputs.iter().copied().eq(0..self.color_attachments as u32) {
+            mismatches.push(PassMismatch::ColorOutputs {
+                outputs,
+                attachments: self.color_attachments,
+            });
+        }
+
+        mismatches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use vk_sync::AccessType;
+
+    use super::*;
+    use crate::test_fixtures::shader_path;
+    use crate::render_graph::{BufferResource, TextureResource};
+
+    fn reflect(stages: &[&str]) -> ShaderReflect {
+        let spirv: Vec<Vec<u8>> = stages.iter().map(|stage| std::fs::read(shader_path(stage)).unwrap()).collect();
+        let stages: Vec<&[u8]> = spirv.iter().map(Vec::as_slice).collect();
+        ShaderReflect::new(&stages).unwrap()
+    }
+
+    fn sampled(texture: usize) -> Resource {
+        Resource::Texture(TextureResource {
+            texture,
+            input_type: TextureResourceType::CombinedImageSampler,
+            access_type: AccessType::AnyShaderReadSampledImageOrUniformTexelBuffer,
+        })
+    }
+
+    fn storage_image(texture: usize) -> Resource {
+        Resource::Texture(TextureResource {
+            texture,
+            input_type: TextureResourceType::StorageImage,
+            access_type: AccessType::AnyShaderWrite,
+        })
+    }
+
+    fn buffer(buffer: usize) -> Resource {
+        Resource::Buffer(BufferResource {
+            buffer,
+            access_type: AccessType::AnyShaderWrite,
+        })
+    }
+
+    #[test]
+    fn passes_matching_their_shaders_pass() {
+        let blur = reflect(&["fullscreen.vert.spv", "blur.frag.spv"]);
+        let reads = [sampled(0), sampled(1)];
+        let pass = PassBindings {
+            reads: &reads,
+            uniforms: vec![("blur_settings", 112)],
+            color_attachments: 2,
+        };
+        assert_eq!(pass.check(&blur), vec![]);
+
+        let histogram = reflect(&["histogram.comp.spv"]);
+        let reads = [storage_image(0), buffer(0)];
+        let pass = PassBindings {
+            reads: &reads,
+            uniforms: vec![("params", 16)],
+            color_attachments: 0,
+        };
+        assert_eq!(pass.check(&histogram), vec![]);
+    }
+
+    #[test]
+    fn reads_are_checked_against_the_input_set() {
+        let blur = reflect(&["fullscreen.vert.spv", "blur.frag.spv"]);
+        let reads = [storage_image(0)];
+        let pass = PassBindings {
+            reads: &reads,
+            uniforms: vec![("blur_settings", 112)],
+            color_attachments: 2,
+        };
+        assert_eq!(
+            pass.check(&blur),
+            vec![
+                PassMismatch::ReadType {
+                    index: 0,
+                    name: "in_color".to_owned(),
+                    declared: DescriptorType::COMBINED_IMAGE_SAMPLER,
+                    bound: DescriptorType::STORAGE_IMAGE,
+                },
+                PassMismatch::MissingRead {
+                    binding: 1,
+                    name: "in_depth".to_owned()
+                },
+            ]
+        );
+
+        let histogram = reflect(&["histogram.comp.spv"]);
+        let reads = [storage_image(0), buffer(0), buffer(1)];
+        let pass = PassBindings {
+            reads: &reads,
+            uniforms: vec![("params", 8)],
+            color_attachments: 0,
+        };
+        assert_eq!(pass.check(&histogram), vec![PassMismatch::UndeclaredRead { index: 2 }]);
+    }
+
+    #[test]
+    fn uniforms_are_checked_by_name_and_size() {
+        let blur = reflect(&["fullscreen.vert.spv", "blur.frag.spv"]);
+        let reads = [sampled(0), sampled(1)];
+        let pass = PassBindings {
+            reads: &reads,
+            uniforms: vec![("blur_settings", 80), ("in_color", 16), ("settings", 16)],
+            color_attachments: 2,
+        };
+        let mismatches = pass.check(&blur);
+        assert_eq!(
+            mismatches,
+            vec![
+                PassMismatch::UniformSize {
+                    name: "blur_settings".to_owned(),
+                    declared: 112,
+                    provided: 80,
+                },
+                PassMismatch::UnknownUniform {
+                    name: "in_color".to_owned()
+                },
+                PassMismatch::UnknownUniform {
+                    name: "settings".to_owned()
+                },
+                PassMismatch::UnboundUniforms {
+                    names: vec!["blur_settings".to_owned(), "in_color".to_owned(), "settings".to_owned()]
+                },
+            ]
+        );
+        assert!(mismatches[0].is_error());
+        assert!(!mismatches[3].is_error());
+
+        let pass = PassBindings {
+            reads: &reads,
+            uniforms: vec![],
+            color_attachments: 2,
+        };
+        assert_eq!(
+            pass.check(&blur),
+            vec![PassMismatch::MissingUniform {
+                name: "blur_settings".to_owned(),
+                set: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn colour_attachments_match_fragment_outputs() {
+        let blur = reflect(&["fullscreen.vert.spv", "blur.frag.spv"]);
+        let reads = [sampled(0), sampled(1)];
+        let pass = PassBindings {
+            reads: &reads,
+            uniforms: vec![("blur_settings", 112)],
+            color_attachments: 1,
+        };
+        let mismatches = pass.check(&blur);
+        assert_eq!(
+            mismatches,
+            vec![PassMismatch::ColorOutputs {
+                outputs: vec![0, 1],
+                attachments: 1
+            }]
+        );
+        assert!(!mismatches[0].is_error());
+        assert_eq!(
+            mismatches[0].message("blur"),
+            "Pass blur renders to 1 colour attachments, its fragment shader writes locations [0, 1]"
+        );
+    }
+}
@@ -29,31 +29,25 @@ pub fn setup_atmosphere_pass(
         .external_depth_attachment(renderer.depth_image.clone(), vk::AttachmentLoadOp::LOAD)
         .record_render(
             move |device, command_buffer, renderer, _pass, _resources| unsafe {
-                if renderer.internal_renderer.instances.len() > 0 { // we should not rely on an instance existing for this
+                let internal = &renderer.internal_renderer;
+                // we should not rely on an instance existing for this
+                let model = internal.instances.first().and_then(|instance| internal.assets.models.get(&instance.model));
+                if let Some(mesh) = model.and_then(|model| model.meshes.first()) {
                     device.device().cmd_bind_vertex_buffers(
                         *command_buffer,
                         0,
-                        &[renderer.internal_renderer.instances[0].model.meshes[0]
-                            .primitive
-                            .vertex_buffer
-                            .vk_buffer],
+                        &[mesh.primitive.vertex_buffer.vk_buffer],
                         &[0],
                     );
                     device.device().cmd_bind_index_buffer(
                         *command_buffer,
-                        renderer.internal_renderer.instances[0].model.meshes[0]
-                            .primitive
-                            .index_buffer
-                            .vk_buffer,
+                        mesh.primitive.index_buffer.vk_buffer,
                         0,
                         vk::IndexType::UINT32,
                     );
                     device.device().cmd_draw_indexed(
                         *command_buffer,
-                        renderer.internal_renderer.instances[0].model.meshes[0]
-                            .primitive
-                            .indices
-                            .len() as u32,
+                        mesh.primitive.indices.len() as u32,
                         1,
                         0,
                         0,
@@ -0,0 +1,170 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ash::vk;
+
+use crate::{
+    render_graph::{
+        declared::{intern, parse_format, Draw, GraphFile, PassDecl, VertexLayout},
+        GraphFileError, RenderGraph, TextureId,
+    },
+    vulkan::{Device, Image, ImageDesc, PipelineDesc},
+};
+
+/// Adds the passes of the graph's declared file, reloading it first if it
+/// changed, and returns the texture to present instead of `output`.
+pub fn setup_declared_passes(graph: &mut RenderGraph, device: Arc<Device>, output: TextureId) -> TextureId {
+    let Some(mut declared) = graph.declared.take() else {
+        return output;
+    };
+    declared.reload_changed();
+
+    let mut errors = vec![];
+    let textures = create_textures(graph, device, &declared.file);
+    for pass in &declared.file.passes {
+        match PassTextures::resolve(graph, &textures, pass) {
+            Ok(pass_textures) => add_pass(graph, &declared.file, pass, pass_textures),
+            Err(err) => errors.push(err),
+        }
+    }
+    let output = match &declared.file.output {
+        Some(name) => texture(graph, &textures, "present_pass", name).unwrap_or_else(|err| {
+            errors.push(err);
+            output
+        }),
+        None => output,
+    };
+
+    declared.report(&errors);
+    graph.declared = Some(declared);
+    output
+}
+
+/// Texture `name`, created by the file or by the built-in passes.
+fn texture(
+    graph: &RenderGraph,
+    textures: &HashMap<String, TextureId>,
+    pass: &str,
+    name: &str,
+) -> Result<TextureId, GraphFileError> {
+    textures
+        .get(name)
+        .copied()
+        .or_else(|| graph.texture_id(name))
+        .ok_or_else(|| GraphFileError::UnknownResource {
+            pass: pass.to_string(),
+            resource: name.to_string(),
+        })
+}
+
+struct PassTextures {
+    reads: Vec<TextureId>,
+    writes: Vec<TextureId>,
+    storage_writes: Vec<TextureId>,
+    depth: Option<TextureId>,
+}
+
+impl PassTextures {
+    fn resolve(
+        graph: &RenderGraph,
+        textures: &HashMap<String, TextureId>,
+        pass: &PassDecl,
+    ) -> Result<Self, GraphFileError> {
+        let resolve = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| texture(graph, textures, &pass.name, name))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(PassTextures {
+            reads: resolve(&pass.reads)?,
+            writes: resolve(&pass.writes)?,
+            storage_writes: resolve(&pass.storage_writes)?,
+            depth: resolve(pass.depth.as_slice())?.pop(),
+        })
+    }
+}
+
+fn create_textures(graph: &mut RenderGraph, device: Arc<Device>, file: &GraphFile) -> HashMap<String, TextureId> {
+    file.resources
+        .iter()
+        .map(|(name, resource)| {
+            // Formats were checked when the file was parsed.
+            let format = parse_format(&resource.format).unwrap();
+            let (size, width, height) = resource.size.texture_size();
+            let mut desc = ImageDesc::new_2d(width, height, format);
+            if Image::is_depth_image_fmt(format) {
+                desc = desc
+                    .aspect(vk::ImageAspectFlags::DEPTH)
+                    .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED);
+            }
+            let id = if resource.transient {
+                graph.get_or_create_transient_texture(name, device.clone(), size, desc)
+            } else {
+                graph.get_or_create_texture(name, device.clone(), size, desc)
+            };
+            (name.clone(), id)
+        })
+        .collect()
+}
+
+fn add_pass(graph: &mut RenderGraph, file: &GraphFile, pass: &PassDecl, textures: PassTextures) {
+    let pipeline = &file.pipelines[&pass.pipeline];
+    let mut desc = PipelineDesc::builder();
+    if let Some(path) = &pipeline.vertex {
+        desc = desc.vertex_path(intern(path));
+    }
+    if let Some(path) = &pipeline.fragment {
+        desc = desc.fragment_path(intern(path));
+    }
+    if let Some(path) = &pipeline.compute {
+        desc = desc.compute_path(intern(path));
+    }
+    for (name, value) in &pipeline.defines {
+        desc = desc.define(name, value);
+    }
+    if pipeline.vertex_layout == VertexLayout::Primitive {
+        desc = desc
+            .default_primitive_vertex_bindings()
+            .default_primitive_vertex_attributes();
+    }
+    desc = desc.color_attachment_formats(
+        pipeline
+            .color_formats
+            .iter()
+            .filter_map(|format| parse_format(format))
+            .collect(),
+    );
+    if let Some(format) = pipeline.depth_format.as_deref().and_then(parse_format) {
+        desc = desc.depth_stencil_attachment_format(format);
+    }
+
+    let mut builder = graph.add_pass_from_desc(&pass.name, desc);
+    for read in textures.reads {
+        builder = builder.layout_in(read);
+    }
+    for write in textures.writes {
+        builder = builder.layout_out(write);
+    }
+    for write in textures.storage_writes {
+        builder = builder.image_write(write);
+    }
+    if let Some(depth) = textures.depth {
+        builder = builder.depth_attachment(depth);
+    }
+    for (name, values) in &pass.uniforms {
+        builder = builder.uniform_floats(name, values);
+    }
+    builder = match pass.draw {
+        Draw::Fullscreen => builder.record_render(|device, command_buffer, _, _, _| unsafe {
+            device.device().cmd_draw(*command_buffer, 3, 1, 0, 0);
+        }),
+        Draw::Meshes => builder.record_render(|device, command_buffer, renderer, pass, resources| {
+            let pipeline = resources.pipeline(pass.pipeline_handle);
+            renderer
+                .internal_renderer
+                .draw_meshes(device, *command_buffer, pipeline.pipeline_layout);
+        }),
+        Draw::Dispatch(x, y, z) => builder.dispatch_compute(x, y, z),
+    };
+    builder.build(graph);
+}
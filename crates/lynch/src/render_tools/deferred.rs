@@ -1,8 +1,11 @@
 use crate::{
     render_graph::{RenderGraph, TextureId},
+    shadows::{ShadowFilter, ShadowUniforms},
     vulkan::PipelineDesc,
 };
 
+use super::{lights::LightClusterBuffers, shadow::LightShadowMaps};
+
 #[allow(clippy::too_many_arguments)]
 pub fn setup_deferred_pass(
     graph: &mut RenderGraph,
@@ -15,16 +18,29 @@ pub fn setup_deferred_pass(
     irradiance_map: TextureId,
     specular_map: TextureId,
     brdf_lut: TextureId,
-    cascade_data: ([glam::Mat4; 4], [f32; 4]),
+    shadow_params: ShadowUniforms,
+    shadow_filter: ShadowFilter,
+    sun_shadows: bool,
+    light_clusters: Option<LightClusterBuffers>,
+    light_shadow_maps: Option<LightShadowMaps>,
     deferred_output: TextureId,
 ) {
-    graph
-        .add_pass_from_desc(
-            "deferred_pass",
-            PipelineDesc::builder()
-                .vertex_path("assets/shaders/fullscreen.vert")
-                .fragment_path("assets/shaders/deferred.frag"),
-        )
+    let mut desc = PipelineDesc::builder()
+        .vertex_path("assets/shaders/fullscreen.vert")
+        .fragment_path("assets/shaders/deferred.frag")
+        .define("SHADOW_FILTER", shadow_filter.index())
+        .define("SUN_SHADOWS", sun_shadows as u32);
+    // Shades with include/clustered_lights.glsl and include/shadows.glsl,
+    // reading the lights, clusters, shadow atlas and shadow faces after the
+    // textures.
+    if light_clusters.is_some() {
+        desc = desc.define("CLUSTERED_LIGHTS", "1");
+    }
+    if light_shadow_maps.is_some() {
+        desc = desc.define("LIGHT_SHADOWS", "1");
+    }
+    let mut pass = graph
+        .add_pass_from_desc("deferred_pass", desc)
         .layout_in(gbuffer_position)
         .layout_in(gbuffer_normal)
         .layout_in(gbuffer_albedo)
@@ -33,9 +49,20 @@ pub fn setup_deferred_pass(
         .layout_in(ssao_output)
         .layout_in(irradiance_map)
         .layout_in(specular_map)
-        .layout_in(brdf_lut)
-        .layout_out(deferred_output)
-        .uniforms("shadowmapParams", &(cascade_data))
+        .layout_in(brdf_lut);
+    if let Some(light_clusters) = light_clusters {
+        pass = pass
+            .read_buffer(light_clusters.lights)
+            .read_buffer(light_clusters.clusters);
+    }
+    // Only lights cast shadows, so these always follow the clusters.
+    if let Some(light_shadow_maps) = light_shadow_maps {
+        pass = pass
+            .layout_in(light_shadow_maps.atlas)
+            .read_buffer(light_shadow_maps.faces);
+    }
+    pass.layout_out(deferred_output)
+        .uniforms("shadowmapParams", &shadow_params)
         .record_render(
             move |device, command_buffer, _renderer, _pass, _resources| unsafe {
                 device.device().cmd_draw(*command_buffer, 3, 1, 0, 0);
@@ -28,7 +28,7 @@ pub fn setup_forward_pass(
         .record_render(move |device, command_buffer, renderer, pass, resources| {
             let pipeline = resources.pipeline(pass.pipeline_handle);
 
-            renderer.internal_renderer.draw_meshes(
+            renderer.internal_renderer.draw_visible_meshes(
                 device,
                 *command_buffer,
                 pipeline.pipeline_layout,
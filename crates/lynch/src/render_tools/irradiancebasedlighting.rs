@@ -4,7 +4,7 @@ use ash::vk;
 use glam::{Mat4, Vec3};
 
 use crate::{
-    render_graph::{RenderGraph, TextureId},
+    render_graph::{RenderGraph, TextureId, TextureSize},
     vulkan::{renderer::VulkanRenderer, Device, ImageCopyDescBuilder, ImageDesc, PipelineDesc},
 };
 
@@ -32,30 +32,35 @@ pub fn  setup_cubemap_pass(
     let environment_map = graph.get_or_create_texture(
         "environment_map",
         device.clone(),
+        TextureSize::Fixed,
         ImageDesc::new_cubemap(mip0_size, mip0_size, rgba32_fmt).mip_levels(num_mips),
     );
 
     let irradiance_map = graph.get_or_create_texture(
         "irradiance_map",
         device.clone(),
+        TextureSize::Fixed,
         ImageDesc::new_cubemap(mip0_size, mip0_size, rgba32_fmt),
     );
 
     let specular_map = graph.get_or_create_texture(
         "specular_map",
         device.clone(),
+        TextureSize::Fixed,
         ImageDesc::new_cubemap(mip0_size, mip0_size, rgba32_fmt).mip_levels(num_mips),
     );
 
-    let offscreen = graph.get_or_create_texture(
+    let offscreen = graph.get_or_create_transient_texture(
         "cubemap_offscreen",
         device.clone(),
+        TextureSize::Fixed,
         ImageDesc::new_2d(mip0_size, mip0_size, rgba32_fmt),
     );
 
     let brdf_lut = graph.get_or_create_texture(
         "brdf_lut",
         device.clone(),
+        TextureSize::Fixed,
         ImageDesc::new_2d(LUT_TEXTURE_SIZE, LUT_TEXTURE_SIZE, vk::Format::R16G16_SFLOAT),
     );
 
@@ -0,0 +1,65 @@
+use ash::vk;
+
+use crate::{
+    lights::{encode_lights, light_buffer_size, ClusterGrid, ClusterView, Light},
+    render_graph::{BufferId, RenderGraph},
+    vulkan::PipelineDesc,
+    Camera,
+};
+
+/// Invocations of a `light_clusters.comp` workgroup, one per cluster.
+const CLUSTERS_PER_WORKGROUP: u32 = 64;
+
+/// What the deferred pass reads clustered lights from.
+#[derive(Clone, Copy, Debug)]
+pub struct LightClusterBuffers {
+    pub lights: BufferId,
+    pub clusters: BufferId,
+}
+
+/// Uploads `lights` and assigns them to the clusters of `grid` on the GPU.
+/// Without lights there is nothing to assign and the deferred pass only
+/// shades with the sun and image based lighting. `shadow_faces` is
+/// [`crate::shadows::LightShadows::first_faces`].
+pub fn setup_light_cluster_pass(
+    graph: &mut RenderGraph,
+    lights: &[Light],
+    shadow_faces: &[Option<u32>],
+    camera: &Camera,
+    grid: ClusterGrid,
+) -> Option<LightClusterBuffers> {
+    if lights.is_empty() {
+        return None;
+    }
+
+    let light_buffer = graph.get_or_create_buffer(
+        &format!("light_buffer_frame_{}", graph.current_frame),
+        light_buffer_size(),
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        gpu_allocator::MemoryLocation::CpuToGpu,
+    );
+    graph.resources.buffers[light_buffer]
+        .buffer
+        .update_memory(&encode_lights(lights, shadow_faces, &ClusterView::new(camera), &grid));
+    let clusters = graph.get_or_create_transient_buffer(
+        "light_clusters",
+        grid.cluster_buffer_size(),
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+    );
+
+    let workgroups = (grid.cluster_count() as u32).div_ceil(CLUSTERS_PER_WORKGROUP);
+    graph
+        .add_pass_from_desc(
+            "light_cluster_pass",
+            PipelineDesc::builder().compute_path("assets/shaders/light_clusters.comp"),
+        )
+        .read_buffer(light_buffer)
+        .write_buffer(clusters)
+        .dispatch_compute(workgroups, 1, 1)
+        .build(graph);
+
+    Some(LightClusterBuffers {
+        lights: light_buffer,
+        clusters,
+    })
+}
@@ -3,20 +3,24 @@ use std::sync::Arc;
 use ash::vk;
 
 use crate::{
-    render_graph::{RenderGraph, TextureId},
+    lights::ClusterGrid,
+    render_graph::{RenderGraph, TextureId, TextureSize},
+    shadows::{plan_light_shadows, ShadowSettings},
     vulkan::{renderer::VulkanRenderer, Device, ImageDesc},
     Camera, ViewUniformData,
 };
 
 use self::{
-    atmosphere::setup_atmosphere_pass, deferred::setup_deferred_pass, gbuffer::setup_gbuffer_pass, irradiancebasedlighting::setup_cubemap_pass, present::setup_present_pass, ssao::setup_ssao_pass
+    atmosphere::setup_atmosphere_pass, declared::setup_declared_passes, deferred::setup_deferred_pass, gbuffer::setup_gbuffer_pass, irradiancebasedlighting::setup_cubemap_pass, lights::setup_light_cluster_pass, present::setup_present_pass, ssao::setup_ssao_pass
 };
 
 pub mod atmosphere;
+pub mod declared;
 pub mod deferred;
 pub mod forward;
 pub mod gbuffer;
 pub mod irradiancebasedlighting;
+pub mod lights;
 pub mod present;
 pub mod shadow;
 pub mod ssao;
@@ -38,23 +42,34 @@ pub fn create_gbuffer_textures(
     let textures: Vec<TextureId> = GBUFFER_MAP
         .into_iter()
         .map(|(texture_name, format)| -> usize {
-            graph.get_or_create_texture(
+            graph.get_or_create_transient_texture(
                 texture_name,
                 device.clone(),
+                TextureSize::Full,
                 ImageDesc::new_2d(width, height, *format),
             )
         })
         .collect();
     (textures[0], textures[1], textures[2], textures[3])
 }
-pub fn create_shadowmap_texture(graph: &mut RenderGraph, device: Arc<Device>) -> TextureId {
+pub fn create_shadowmap_texture(
+    graph: &mut RenderGraph,
+    device: Arc<Device>,
+    settings: &ShadowSettings,
+) -> TextureId {
     let image_usage_flags = vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
         | vk::ImageUsageFlags::TRANSFER_DST
         | vk::ImageUsageFlags::SAMPLED;
-    graph.get_or_create_texture(
+    graph.get_or_create_transient_texture(
         "shadow_map",
         device,
-        ImageDesc::new_2d_array(4096, 4096, shadow::SHADOW_TEXTURE_CASCADE_NO as u32, vk::Format::D32_SFLOAT)
+        TextureSize::Fixed,
+        ImageDesc::new_2d_array(
+            settings.cascade_resolution,
+            settings.cascade_resolution,
+            settings.cascades(),
+            vk::Format::D32_SFLOAT,
+        )
             .aspect(vk::ImageAspectFlags::DEPTH)
             .usage(image_usage_flags),
     )
@@ -74,18 +89,25 @@ pub fn build_render_graph(
     let (gbuffer_position, gbuffer_normal, gbuffer_albedo, gbuffer_pbr) =
         create_gbuffer_textures(graph, device.clone(), width, height);
 
-    let shadow_map = create_shadowmap_texture(graph, device.clone());
-    let (cascade_matrices, cascade_depths) = shadow::setup_shadow_pass(
+    let shadow_settings = &base.internal_renderer.shadow_settings;
+    let shadow_map = create_shadowmap_texture(graph, device.clone(), shadow_settings);
+    let shadow_params = shadow::setup_shadow_pass(
         graph,
         shadow_map,
         view_data.sun_dir,
         camera,
+        shadow_settings,
         view_data.shadows_enabled,
     );
+    let light_shadows = match view_data.shadows_enabled {
+        true => plan_light_shadows(&base.internal_renderer.lights, camera.get_position(), shadow_settings),
+        false => Default::default(),
+    };
+    let light_shadow_maps = shadow::setup_light_shadow_pass(graph, device.clone(), &light_shadows);
 
     let image_desc = ImageDesc::new_2d(width, height, vk::Format::R32G32B32A32_SFLOAT);
 
-    let deferred_output = graph.get_or_create_texture("deferred_output", device.clone(), image_desc);
+    let deferred_output = graph.get_or_create_transient_texture("deferred_output", device.clone(), TextureSize::Full, image_desc);
 
     let image_desc = ImageDesc::new_2d(width, height, vk::Format::R16_UNORM);
     setup_gbuffer_pass(
@@ -98,7 +120,7 @@ pub fn build_render_graph(
     );
     let (environment_map, irradiance_map, specular_map, brdf_lut) =
         setup_cubemap_pass(device.clone(), graph, &base);
-    let ssao_output = graph.get_or_create_texture("ssao_output", device.clone(), image_desc);
+    let ssao_output = graph.get_or_create_transient_texture("ssao_output", device.clone(), TextureSize::Full, image_desc);
     
     setup_ssao_pass(
         graph,
@@ -107,6 +129,13 @@ pub fn build_render_graph(
         ssao_output,
         true,
     );
+    let light_clusters = setup_light_cluster_pass(
+        graph,
+        &base.internal_renderer.lights,
+        &light_shadows.first_faces,
+        camera,
+        ClusterGrid::default(),
+    );
     setup_deferred_pass(
         graph,
         gbuffer_position,
@@ -118,11 +147,16 @@ pub fn build_render_graph(
         irradiance_map,
         specular_map,
         brdf_lut,
-        (cascade_matrices, cascade_depths),
+        shadow_params,
+        shadow_settings.filter,
+        view_data.shadows_enabled,
+        light_clusters,
+        light_shadow_maps,
         deferred_output,
     );
     setup_atmosphere_pass(graph, base, deferred_output, environment_map, camera);
-    setup_present_pass(graph, deferred_output);
+    let output = setup_declared_passes(graph, device, deferred_output);
+    setup_present_pass(graph, output);
 }
 
 
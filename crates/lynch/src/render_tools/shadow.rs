@@ -1,144 +1,150 @@
+use std::sync::Arc;
+
+use ash::vk;
+
 use crate::{
     camera,
-    render_graph::{RenderGraph, TextureId},
-    vulkan::PipelineDesc,
+    render_graph::{BufferId, RenderGraph, TextureId, TextureSize},
+    shadows::{encode_shadow_faces, fit_cascades, shadow_face_buffer_size, LightShadows, ShadowSettings, ShadowUniforms},
+    vulkan::{Device, ImageDesc, PipelineDesc, PipelineDescBuilder},
 };
-use glam::{Mat4, Vec3, Vec4Swizzles};
 
-pub const SHADOW_TEXTURE_CASCADE_NO: usize = 4;
+/// What the deferred pass reads point and spot light shadows from.
+#[derive(Clone, Copy, Debug)]
+pub struct LightShadowMaps {
+    pub atlas: TextureId,
+    pub faces: BufferId,
+}
+
+fn shadow_pipeline_desc() -> PipelineDescBuilder {
+    PipelineDesc::builder()
+        .vertex_path("assets/shaders/shadow.vert")
+        .fragment_path("assets/shaders/shadow.frag")
+        .default_primitive_vertex_bindings()
+        .default_primitive_vertex_attributes()
+}
+
+/// Renders the sun's shadow cascades into the layers of `shadow_texture`.
+/// With shadows disabled the layers are only cleared, the deferred pass
+/// still binds the texture and would otherwise read memory another transient
+/// texture shares.
 pub fn setup_shadow_pass(
     graph: &mut RenderGraph,
     shadow_texture: TextureId,
     sun_dir: glam::Vec3,
     camera: &camera::Camera,
+    settings: &ShadowSettings,
     enabled: bool,
-) -> (
-    [glam::Mat4; SHADOW_TEXTURE_CASCADE_NO],
-    [f32; SHADOW_TEXTURE_CASCADE_NO],
-) {
-    let mut out_cascade_matrices: [Mat4; SHADOW_TEXTURE_CASCADE_NO] =
-        [glam::Mat4::IDENTITY; SHADOW_TEXTURE_CASCADE_NO];
-    let mut out_split_depths: [f32; SHADOW_TEXTURE_CASCADE_NO] = [0.0; SHADOW_TEXTURE_CASCADE_NO];
-
+) -> ShadowUniforms {
     if !enabled {
-        return (out_cascade_matrices, out_split_depths);
-    }
-
-    let mut cascade_splits: [f32; SHADOW_TEXTURE_CASCADE_NO] = [0.0; SHADOW_TEXTURE_CASCADE_NO];
-
-    let near_clip: f32 = camera.get_near_plane();
-    let far_clip: f32 = camera.get_far_plane();
-    let clip_range: f32 = far_clip - near_clip;
-
-    let min_z: f32 = near_clip;
-    let max_z: f32 = near_clip + clip_range;
-
-    let cascade_split_lambda: f32 = 0.927;
-    for i in 0..SHADOW_TEXTURE_CASCADE_NO {
-        let p: f32 = (i + 1) as f32 / SHADOW_TEXTURE_CASCADE_NO as f32;
-        let log: f32 = min_z * (max_z / min_z).powf(p);
-        let uniform: f32 = min_z + (max_z - min_z) * p;
-        let d: f32 = cascade_split_lambda * (log - uniform) + uniform;
-        cascade_splits[i] = (d - near_clip) / clip_range;
-    }
-    let mut last_split_dist = 0.0;
-    for i in 0..SHADOW_TEXTURE_CASCADE_NO {
-        let split_dist = cascade_splits[i];
-
-        let mut frustum_corners: [Vec3; 8] = [
-            Vec3::new(-1.0, 1.0, 0.0),
-            Vec3::new(1.0, 1.0, 0.0),
-            Vec3::new(1.0, -1.0, 0.0),
-            Vec3::new(-1.0, -1.0, 0.0),
-            Vec3::new(-1.0, 1.0, 1.0),
-            Vec3::new(1.0, 1.0, 1.0),
-            Vec3::new(1.0, -1.0, 1.0),
-            Vec3::new(-1.0, -1.0, 1.0),
-        ];
-
-        let invert_cam: Mat4 = (camera.get_projection() * camera.get_view()).inverse();
-        for corner in &mut frustum_corners {
-            let inv_corner: glam::Vec4 = invert_cam * corner.extend(1.0);
-            *corner = inv_corner.xyz() / inv_corner.w;
-        }
-
-        for i in 0..SHADOW_TEXTURE_CASCADE_NO {
-            let dist: Vec3 = frustum_corners[i + 4] - frustum_corners[i];
-            frustum_corners[i + 4] = frustum_corners[i] + (dist * split_dist);
-            frustum_corners[i] += dist * last_split_dist;
+        for layer in 0..settings.cascades() {
+            graph
+                .add_pass_from_desc(format!("shadow_clear_{layer}").as_str(), shadow_pipeline_desc())
+                .depth_attachment_layer(shadow_texture, layer)
+                .build(graph);
         }
+        return ShadowUniforms::new(&[], settings);
+    }
 
-        let frustum_center: Vec3 = frustum_corners.iter().sum::<Vec3>() / 8.0;
-
-        let mut radius: f32 = 0.0;
-        for corner in &frustum_corners {
-            let distance: f32 = (*corner - frustum_center).length();
-            radius = radius.max(distance);
-        }
-        radius = f32::ceil(radius * 16.0) / 16.0;
-
-        let max_extents: Vec3 = Vec3::new(radius, radius, radius);
-        let min_extents: Vec3 = -max_extents;
-
-        let light_view_matrix: Mat4 = Mat4::look_at_rh(
-            frustum_center - sun_dir * min_extents.z,
-            frustum_center,
-            Vec3::Y,
-        );
-
-        let light_ortho_matrix = Mat4::orthographic_rh(
-            min_extents.x,
-            max_extents.x,
-            min_extents.y,
-            max_extents.y,
-            -(max_extents.z - min_extents.z),
-            max_extents.z - min_extents.z,
-        );
-
-        let view_projection_matrix = light_ortho_matrix * light_view_matrix;
-        out_cascade_matrices[i] = view_projection_matrix;
-        out_split_depths[i] = near_clip + split_dist * clip_range;
-
-        last_split_dist = split_dist;
-
+    let cascades = fit_cascades(
+        camera.get_view(),
+        camera.get_projection(),
+        camera.get_near_plane(),
+        camera.get_far_plane(),
+        sun_dir,
+        settings,
+    );
+    for (i, cascade) in cascades.iter().enumerate() {
         graph
-            .add_pass_from_desc(
-                format!("shadow_pass_{i}").as_str(),
-                PipelineDesc::builder()
-                    .vertex_path("assets/shaders/shadow.vert")
-                    .fragment_path("assets/shaders/shadow.frag")
-                    .default_primitive_vertex_bindings()
-                    .default_primitive_vertex_attributes(),
-            )
-            .uniforms("cascade_view_projection", &view_projection_matrix)
+            .add_pass_from_desc(format!("shadow_pass_{i}").as_str(), shadow_pipeline_desc())
+            .uniforms("cascade_view_projection", &cascade.view_projection)
             .depth_attachment_layer(shadow_texture, i as u32)
             .record_render(move |device, command_buffer, renderer, pass, resources| {
-                if enabled {
-                    let pipeline = resources.pipeline(pass.pipeline_handle);
-                    renderer.internal_renderer.draw_meshes(
-                        device,
-                        *command_buffer,
-                        pipeline.pipeline_layout,
-                    );
-                }
+                let pipeline = resources.pipeline(pass.pipeline_handle);
+                renderer.internal_renderer.draw_meshes(
+                    device,
+                    *command_buffer,
+                    pipeline.pipeline_layout,
+                );
             })
             .build(graph);
     }
 
-    (out_cascade_matrices, out_split_depths)
+    ShadowUniforms::new(&cascades, settings)
 }
 
+/// Renders every face of `shadows` into its tile of the shadow atlas.
+/// Without faces nothing is rendered and the deferred pass leaves point and
+/// spot lights unshadowed.
+pub fn setup_light_shadow_pass(
+    graph: &mut RenderGraph,
+    device: Arc<Device>,
+    shadows: &LightShadows,
+) -> Option<LightShadowMaps> {
+    if shadows.faces.is_empty() {
+        return None;
+    }
 
-pub fn calculate_cascade_splits(near_clip: f32, far_clip: f32) -> [f32; SHADOW_TEXTURE_CASCADE_NO] {
-    let mut cascade_splits = [0.0; SHADOW_TEXTURE_CASCADE_NO];
-    let clip_range = far_clip - near_clip;
-    let cascade_split_lambda = 0.927;
-
-    for i in 0..SHADOW_TEXTURE_CASCADE_NO {
-        let p = (i + 1) as f32 / SHADOW_TEXTURE_CASCADE_NO as f32;
-        let log = near_clip * (far_clip / near_clip).powf(p);
-        let uniform = near_clip + clip_range * p;
-        cascade_splits[i] = (cascade_split_lambda * (log - uniform) + uniform - near_clip) / clip_range;
+    let atlas = graph.get_or_create_transient_texture(
+        "shadow_atlas",
+        device,
+        TextureSize::Fixed,
+        ImageDesc::new_2d(shadows.atlas_size, shadows.atlas_size, vk::Format::D32_SFLOAT)
+            .aspect(vk::ImageAspectFlags::DEPTH)
+            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED),
+    );
+    let faces = graph.get_or_create_buffer(
+        &format!("shadow_faces_frame_{}", graph.current_frame),
+        shadow_face_buffer_size(),
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        gpu_allocator::MemoryLocation::CpuToGpu,
+    );
+    graph.resources.buffers[faces]
+        .buffer
+        .update_memory(&encode_shadow_faces(&shadows.faces, shadows.atlas_size));
+
+    for (i, face) in shadows.faces.iter().enumerate() {
+        let rect = face.rect;
+        // The first face clears the whole atlas, the others keep it.
+        let load_op = match i {
+            0 => vk::AttachmentLoadOp::CLEAR,
+            _ => vk::AttachmentLoadOp::LOAD,
+        };
+        graph
+            .add_pass_from_desc(format!("light_shadow_pass_{i}").as_str(), shadow_pipeline_desc())
+            .uniforms("cascade_view_projection", &face.view_projection)
+            .depth_attachment_load_op(atlas, load_op)
+            .record_render(move |device, command_buffer, renderer, pass, resources| unsafe {
+                let viewport = [vk::Viewport {
+                    x: rect.x as f32,
+                    y: (rect.y + rect.size) as f32,
+                    width: rect.size as f32,
+                    height: -(rect.size as f32),
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                }];
+                let scissor = [vk::Rect2D {
+                    offset: vk::Offset2D {
+                        x: rect.x as i32,
+                        y: rect.y as i32,
+                    },
+                    extent: vk::Extent2D {
+                        width: rect.size,
+                        height: rect.size,
+                    },
+                }];
+                device.device().cmd_set_viewport(*command_buffer, 0, &viewport);
+                device.device().cmd_set_scissor(*command_buffer, 0, &scissor);
+
+                let pipeline = resources.pipeline(pass.pipeline_handle);
+                renderer.internal_renderer.draw_meshes(
+                    device,
+                    *command_buffer,
+                    pipeline.pipeline_layout,
+                );
+            })
+            .build(graph);
     }
-    cascade_splits
-}
\ No newline at end of file
+
+    Some(LightShadowMaps { atlas, faces })
+}
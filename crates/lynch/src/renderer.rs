@@ -3,10 +3,10 @@ pub trait Renderer {
     fn create(window: &Window, camera: &Camera, gui: &mut imgui::Context) -> Self
     where
         Self: Sized;
-    fn begin_frame(self: &mut Self) -> usize;
+    fn begin_frame(self: &mut Self) -> Option<usize>;
     fn update_view_to_camera(self: &mut Self, camera: &Camera);
     fn end_frame(self: &mut Self);
-    fn add_model(self: &mut Self, model: crate::gltf_loader::Model, transform: glam::Mat4);
+    fn add_model(self: &mut Self, model: crate::assets::Handle<crate::gltf_loader::Model>, transform: glam::Mat4);
     fn draw_meshes(self: &Self);
     fn draw_frame(self: &mut Self, frame_count: u32);
     fn wait_gpu_idle(self: &Self);
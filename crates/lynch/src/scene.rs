@@ -0,0 +1,239 @@
+use std::sync::Arc;
+
+use frost::{Entity, Search, SearchIter, SearchLookup, World, WorldFull};
+use glam::Mat4;
+
+use crate::{
+    animation::{AnimationClip, AnimationPlayer, AnimationStateMachine, NodeTransform, Pose},
+    gltf_loader::{CpuCamera, CpuLight, CpuModel},
+    skinning::joint_palette,
+};
+
+/// Relation kind pointing a node entity at its parent, e.g. `Related<ChildOf>`.
+/// Root nodes point at the scene entity returned by [`spawn_gltf`].
+pub struct ChildOf;
+
+/// Node of a spawned glTF, `index` being its glTF node index.
+pub struct GltfNode {
+    pub index: usize,
+    pub name: Option<String>,
+}
+
+pub struct GlobalTransform(pub Mat4);
+
+/// Joint entities in the order vertex `JOINTS_0` refer to them.
+pub struct Skin {
+    pub joints: Vec<Entity>,
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+pub struct MorphWeights(pub Vec<f32>);
+
+pub struct GltfCamera(pub CpuCamera);
+
+pub struct GltfLight(pub CpuLight);
+
+/// Animation clips of a spawned glTF, stored on its scene entity.
+/// `nodes[i]` is the entity spawned for glTF node `i`.
+pub struct AnimationLibrary {
+    pub clips: Arc<[AnimationClip]>,
+    pub nodes: Vec<Entity>,
+    pub parents: Vec<Option<usize>>,
+    pub rest: Pose,
+    pub scene: Entity,
+}
+
+/// Evaluated pose of a spawned glTF, kept on its scene entity for the
+/// renderer. Indexed by glTF node, `globals` being relative to the scene
+/// entity and `palettes` empty for nodes without a skin.
+pub struct ScenePose {
+    pub globals: Vec<Mat4>,
+    pub palettes: Vec<Vec<Mat4>>,
+    pub weights: Vec<Vec<f32>>,
+}
+
+/// Spawns one entity per node of `model` below a new scene entity placed at
+/// `transform`, and returns the scene entity.
+pub fn spawn_gltf(world: &mut World, model: &CpuModel, transform: Mat4) -> Result<Entity, WorldFull> {
+    let scene = world.new_entity((GlobalTransform(transform),))?;
+
+    let mut nodes = Vec::with_capacity(model.nodes.len());
+    for (index, node) in model.nodes.iter().enumerate() {
+        nodes.push(world.new_entity((
+            GltfNode {
+                index,
+                name: node.name.clone(),
+            },
+            node.transform,
+            GlobalTransform(transform * node.world_transform),
+        ))?);
+    }
+
+    for (node, &entity) in model.nodes.iter().zip(nodes.iter()) {
+        let parent = node.parent.map_or(scene, |parent| nodes[parent]);
+        world.relate::<ChildOf>(entity, parent).unwrap();
+
+        if let Some(skin) = node.skin {
+            let skin = &model.skins[skin];
+            let component = Skin {
+                joints: skin.joints.iter().map(|joint| nodes[*joint]).collect(),
+                inverse_bind_matrices: skin.inverse_bind_matrices.clone(),
+            };
+            world.add_component(entity, component).unwrap();
+        }
+        if !node.morph_weights.is_empty() {
+            world
+                .add_component(entity, MorphWeights(node.morph_weights.clone()))
+                .unwrap();
+        }
+        if let Some(camera) = node.camera {
+            world.add_component(entity, GltfCamera(camera)).unwrap();
+        }
+        if let Some(light) = node.light {
+            world.add_component(entity, GltfLight(light)).unwrap();
+        }
+    }
+
+    let rest = model.rest_pose();
+    let pose = ScenePose {
+        globals: model.nodes.iter().map(|node| node.world_transform).collect(),
+        palettes: vec![vec![]; model.nodes.len()],
+        weights: rest.weights.clone(),
+    };
+    world.add_component(scene, pose).unwrap();
+    if !model.animations.is_empty() {
+        world.add_component(scene, AnimationPlayer::new()).unwrap();
+    }
+    world
+        .add_component(
+            scene,
+            AnimationLibrary {
+                clips: model.animations.clone().into(),
+                nodes,
+                parents: model.parents(),
+                rest,
+                scene,
+            },
+        )
+        .unwrap();
+
+    Ok(scene)
+}
+
+/// Lets every `AnimationStateMachine` switch its player's clips. Runs before
+/// [`animation_system`].
+pub fn animation_state_system(
+    mut machines: Search<(&mut AnimationStateMachine, &mut AnimationPlayer, &AnimationLibrary)>,
+    _delta_time: f32,
+) {
+    for (machine, player, library) in machines.iter() {
+        machine.update(player, &library.clips);
+    }
+}
+
+/// Advances every `AnimationPlayer` and writes the blended pose to the node
+/// entities and the scene's [`ScenePose`], joint palettes included.
+pub fn animation_system(
+    mut scenes: Search<(&mut AnimationPlayer, &AnimationLibrary, &mut ScenePose)>,
+    mut locals: SearchLookup<&mut NodeTransform>,
+    mut morph_weights: SearchLookup<&mut MorphWeights>,
+    mut globals: SearchLookup<&mut GlobalTransform>,
+    skins: SearchLookup<&Skin>,
+    delta_time: f32,
+) {
+    let mut pose = Pose::default();
+    for (player, library, scene_pose) in scenes.iter() {
+        player.advance(delta_time, &library.clips);
+        player.evaluate(&library.clips, &library.rest, &mut pose);
+        scene_pose.globals = pose.global_transforms(&library.parents);
+
+        let scene_transform = globals.get(library.scene).map_or(Mat4::IDENTITY, |g| g.0);
+        for (index, &entity) in library.nodes.iter().enumerate() {
+            if let Some(local) = locals.get_mut(entity) {
+                *local = pose.transforms[index];
+            }
+            if let Some(weights) = morph_weights.get_mut(entity) {
+                weights.0.clone_from(&pose.weights[index]);
+            }
+            if let Some(global) = globals.get_mut(entity) {
+                global.0 = scene_transform * scene_pose.globals[index];
+            }
+        }
+
+        // `Skin` refers to joints by entity, so palettes are built from world
+        // transforms; the scene transform cancels out.
+        for (index, &entity) in library.nodes.iter().enumerate() {
+            let (Some(skin), Some(mesh)) = (skins.get(entity), globals.get(entity)) else {
+                continue;
+            };
+            let joints: Vec<Mat4> = skin
+                .joints
+                .iter()
+                .map(|joint| globals.get(*joint).map_or(Mat4::IDENTITY, |g| g.0))
+                .collect();
+            scene_pose.palettes[index] = joint_palette(mesh.0, &joints, &skin.inverse_bind_matrices);
+        }
+        scene_pose.weights.clone_from(&pose.weights);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gltf_loader::import_gltf;
+    use crate::test_fixtures::model_path;
+    use frost::{Related, System};
+
+    #[test]
+    fn spawns_node_hierarchy_with_components() {
+        let model = import_gltf(model_path("skinned.gltf")).unwrap();
+        let mut world = World::new();
+        let scene = spawn_gltf(&mut world, &model, Mat4::IDENTITY).unwrap();
+
+        let library = world.get::<AnimationLibrary>(scene).unwrap();
+        let nodes = library.nodes.clone();
+        assert_eq!(library.clips.len(), 1);
+        drop(library);
+
+        let mut roots: Vec<Entity> = world.related_to::<ChildOf>(scene).to_vec();
+        roots.sort_by_key(|e| nodes.iter().position(|n| n == e));
+        assert_eq!(roots, vec![nodes[0], nodes[1], nodes[3], nodes[4]]);
+        assert_eq!(world.related_to::<ChildOf>(nodes[1]), &[nodes[2]]);
+        assert_eq!(world.get::<Related<ChildOf>>(nodes[2]).unwrap().target(), nodes[1]);
+
+        let skin = world.get::<Skin>(nodes[0]).unwrap();
+        assert_eq!(skin.joints, vec![nodes[1], nodes[2]]);
+        drop(skin);
+        assert_eq!(world.get::<MorphWeights>(nodes[0]).unwrap().0, vec![0.5]);
+        assert!(world.get::<GltfCamera>(nodes[3]).is_ok());
+        assert!(world.get::<GltfLight>(nodes[4]).is_ok());
+        assert!(world.get::<GltfLight>(nodes[3]).is_err());
+
+        let mut search = world.search::<(&GltfNode, &GlobalTransform)>().unwrap();
+        let knee = search.iter().find(|(node, _)| node.index == 2).unwrap();
+        assert_eq!(knee.1 .0, Mat4::from_translation(glam::Vec3::Y));
+    }
+
+    #[test]
+    fn animation_system_poses_nodes_and_palettes() {
+        let model = import_gltf(model_path("skinned.gltf")).unwrap();
+        let mut world = World::new();
+        let offset = Mat4::from_translation(glam::Vec3::X * 10.0);
+        let scene = spawn_gltf(&mut world, &model, offset).unwrap();
+        let nodes = world.get::<AnimationLibrary>(scene).unwrap().nodes.clone();
+        world.get_mut::<AnimationPlayer>(scene).unwrap().play(0);
+
+        animation_system.run(&world, 1.0).unwrap();
+
+        let raised = Mat4::from_translation(glam::Vec3::new(0.0, 3.0, 0.0));
+        assert_eq!(world.get::<NodeTransform>(nodes[1]).unwrap().translation.y, 2.0);
+        assert_eq!(world.get::<GlobalTransform>(nodes[2]).unwrap().0, offset * raised);
+        let pose = world.get::<ScenePose>(scene).unwrap();
+        assert_eq!(pose.globals[2], raised);
+        assert!(pose.palettes[1].is_empty());
+        let moved = Mat4::from_translation(glam::Vec3::Y * 2.0);
+        for joint in &pose.palettes[0] {
+            assert!(joint.abs_diff_eq(moved, 1e-5));
+        }
+    }
+}
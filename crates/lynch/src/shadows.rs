@@ -0,0 +1,588 @@
+//! Shadow maps: cascades of the sun's shadow fitted to slices of the view
+//! frustum, and shadow maps of point and spot lights packed into an atlas.
+//!
+//! Each cascade is fitted to the bounding sphere of its slice, so its size
+//! does not change as the camera turns, and only moves in whole texels, so
+//! its shadows do not swim as the camera moves.
+
+use glam::{Mat4, UVec4, Vec3, Vec4, Vec4Swizzles};
+
+use crate::lights::{Light, LightKind};
+
+/// Cascades beyond this many are left out.
+pub const MAX_CASCADES: usize = 8;
+/// Point and spot light shadow maps beyond this many faces are left out.
+pub const MAX_SHADOW_FACES: usize = 256;
+/// Atlas tiles are not made smaller than this to fit in more lights.
+pub const MIN_TILE_SIZE: u32 = 64;
+
+const FACE_WORDS: usize = 16 + 4;
+/// Near plane of point and spot light shadow maps, relative to the range.
+const NEAR_PLANE_FRACTION: f32 = 0.01;
+/// Spot light cones wider than this are cut down to fit a perspective.
+const MAX_SPOT_FOV: f32 = 3.0;
+
+/// How shadow maps are sampled, see `include/shadows.glsl`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ShadowFilter {
+    /// A single comparison.
+    Hard,
+    /// The average of comparisons over a square `2 * radius + 1` texels
+    /// wide.
+    Pcf { radius: u32 },
+    /// Percentage closer soft shadows, with penumbrae widening as the
+    /// receiver gets further from its blockers. `light_size` is the width
+    /// of the light in shadow map UVs, the sample counts are at most 16.
+    Pcss {
+        light_size: f32,
+        blocker_samples: u32,
+        filter_samples: u32,
+    },
+}
+
+impl ShadowFilter {
+    /// Value of the `SHADOW_FILTER` define of the deferred pass.
+    pub fn index(&self) -> u32 {
+        match self {
+            ShadowFilter::Hard => 0,
+            ShadowFilter::Pcf { .. } => 1,
+            ShadowFilter::Pcss { .. } => 2,
+        }
+    }
+
+    /// Parameters of the filter as `include/shadows.glsl` reads them.
+    pub fn params(&self) -> Vec4 {
+        match *self {
+            ShadowFilter::Hard => Vec4::ZERO,
+            ShadowFilter::Pcf { radius } => Vec4::new(radius as f32, 0.0, 0.0, 0.0),
+            ShadowFilter::Pcss {
+                light_size,
+                blocker_samples,
+                filter_samples,
+            } => Vec4::new(light_size, blocker_samples as f32, filter_samples as f32, 0.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowSettings {
+    /// Cascades of the sun's shadow, up to [`MAX_CASCADES`].
+    pub cascade_count: u32,
+    /// Blends the cascade splits from uniform at 0 to logarithmic at 1.
+    pub cascade_split_lambda: f32,
+    pub cascade_resolution: u32,
+    /// Width of the atlas point and spot light shadow maps are packed in.
+    pub atlas_size: u32,
+    pub spot_resolution: u32,
+    /// Width of each of the six faces of a point light's shadow cube.
+    pub point_resolution: u32,
+    pub filter: ShadowFilter,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            cascade_count: 4,
+            cascade_split_lambda: 0.927,
+            cascade_resolution: 4096,
+            atlas_size: 4096,
+            spot_resolution: 1024,
+            point_resolution: 512,
+            filter: ShadowFilter::Pcf { radius: 1 },
+        }
+    }
+}
+
+impl ShadowSettings {
+    /// [`ShadowSettings::cascade_count`] kept to at least one and at most
+    /// [`MAX_CASCADES`].
+    pub fn cascades(&self) -> u32 {
+        self.cascade_count.clamp(1, MAX_CASCADES as u32)
+    }
+}
+
+/// Distances from the camera each of `count` cascades ends at.
+pub fn cascade_splits(near: f32, far: f32, count: u32, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let p = i as f32 / count as f32;
+            let log = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            lambda * (log - uniform) + uniform
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cascade {
+    pub view_projection: Mat4,
+    /// Distance from the camera the cascade ends at.
+    pub split_depth: f32,
+}
+
+/// Fits the cascades of `settings` to the frustum of a camera seen through
+/// `view` and `projection`, for a sun in direction `sun_dir`.
+pub fn fit_cascades(
+    view: Mat4,
+    projection: Mat4,
+    near: f32,
+    far: f32,
+    sun_dir: Vec3,
+    settings: &ShadowSettings,
+) -> Vec<Cascade> {
+    let inverse_view_projection = (projection * view).inverse();
+    let frustum_corners = [
+        Vec3::new(-1.0, 1.0, 0.0),
+        Vec3::new(1.0, 1.0, 0.0),
+        Vec3::new(1.0, -1.0, 0.0),
+        Vec3::new(-1.0, -1.0, 0.0),
+        Vec3::new(-1.0, 1.0, 1.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        Vec3::new(1.0, -1.0, 1.0),
+        Vec3::new(-1.0, -1.0, 1.0),
+    ]
+    .map(|corner| {
+        let corner = inverse_view_projection * corner.extend(1.0);
+        corner.xyz() / corner.w
+    });
+
+    let mut last_split = 0.0;
+    cascade_splits(near, far, settings.cascades(), settings.cascade_split_lambda)
+        .into_iter()
+        .map(|split_depth| {
+            let split = (split_depth - near) / (far - near);
+            let mut corners = frustum_corners;
+            for i in 0..4 {
+                let ray = frustum_corners[i + 4] - frustum_corners[i];
+                corners[i] = frustum_corners[i] + ray * last_split;
+                corners[i + 4] = frustum_corners[i] + ray * split;
+            }
+            last_split = split;
+
+            Cascade {
+                view_projection: stabilized_projection(&corners, sun_dir, settings.cascade_resolution),
+                split_depth,
+            }
+        })
+        .collect()
+}
+
+/// An orthographic projection along `sun_dir` around the bounding sphere of
+/// `corners`, moved to put the world's origin on a texel.
+fn stabilized_projection(corners: &[Vec3; 8], sun_dir: Vec3, resolution: u32) -> Mat4 {
+    let center = corners.iter().sum::<Vec3>() / 8.0;
+    let radius = corners
+        .iter()
+        .map(|corner| corner.distance(center))
+        .fold(0.0f32, f32::max);
+    // Rounded up so the sphere keeps its size through rounding errors.
+    let radius = (radius * 16.0).ceil() / 16.0;
+
+    let view = Mat4::look_at_rh(center + sun_dir * radius, center, up_for(sun_dir));
+    let mut projection = Mat4::orthographic_rh(-radius, radius, -radius, radius, -2.0 * radius, 2.0 * radius);
+
+    let texels = resolution as f32 / 2.0;
+    let origin = (projection * view * Vec4::W).xy() * texels;
+    let offset = (origin.round() - origin) / texels;
+    projection.w_axis.x += offset.x;
+    projection.w_axis.y += offset.y;
+    projection * view
+}
+
+fn up_for(direction: Vec3) -> Vec3 {
+    if direction.normalize().y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    }
+}
+
+/// What the deferred pass reads the cascades from.
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct ShadowUniforms {
+    pub cascade_matrices: [Mat4; MAX_CASCADES],
+    /// Split depth of every cascade, four to a vector.
+    pub cascade_splits: [Vec4; MAX_CASCADES / 4],
+    /// Cascades, cascade resolution, atlas size.
+    pub counts: UVec4,
+    /// See [`ShadowFilter::params`].
+    pub filter_params: Vec4,
+}
+
+impl ShadowUniforms {
+    pub fn new(cascades: &[Cascade], settings: &ShadowSettings) -> Self {
+        let mut uniforms = ShadowUniforms {
+            cascade_matrices: [Mat4::IDENTITY; MAX_CASCADES],
+            cascade_splits: [Vec4::ZERO; MAX_CASCADES / 4],
+            counts: UVec4::new(
+                cascades.len().min(MAX_CASCADES) as u32,
+                settings.cascade_resolution,
+                settings.atlas_size,
+                0,
+            ),
+            filter_params: settings.filter.params(),
+        };
+        for (i, cascade) in cascades.iter().take(MAX_CASCADES).enumerate() {
+            uniforms.cascade_matrices[i] = cascade.view_projection;
+            uniforms.cascade_splits[i / 4][i % 4] = cascade.split_depth;
+        }
+        uniforms
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtlasRect {
+    pub x: u32,
+    pub y: u32,
+    pub size: u32,
+}
+
+impl AtlasRect {
+    /// Offset and scale of the rect in the UVs of an atlas `atlas_size`
+    /// wide.
+    pub fn uv_rect(&self, atlas_size: u32) -> Vec4 {
+        let atlas_size = atlas_size as f32;
+        Vec4::new(
+            self.x as f32 / atlas_size,
+            self.y as f32 / atlas_size,
+            self.size as f32 / atlas_size,
+            self.size as f32 / atlas_size,
+        )
+    }
+}
+
+/// Packs square tiles a power of two wide into a square atlas, splitting
+/// free squares into quarters until they fit as a quadtree would.
+#[derive(Clone, Debug)]
+pub struct ShadowAtlas {
+    size: u32,
+    free: Vec<AtlasRect>,
+}
+
+impl ShadowAtlas {
+    /// `size` is rounded up to a power of two.
+    pub fn new(size: u32) -> Self {
+        let mut atlas = ShadowAtlas { size: size.next_power_of_two(), free: vec![] };
+        atlas.clear();
+        atlas
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Frees every tile.
+    pub fn clear(&mut self) {
+        self.free = vec![AtlasRect { x: 0, y: 0, size: self.size }];
+    }
+
+    /// A tile at least `size` texels wide, taken from the smallest free
+    /// square it fits in.
+    pub fn allocate(&mut self, size: u32) -> Option<AtlasRect> {
+        let size = size.max(1).next_power_of_two();
+        let (index, _) = self
+            .free
+            .iter()
+            .enumerate()
+            .filter(|(_, rect)| rect.size >= size)
+            .min_by_key(|(_, rect)| (rect.size, rect.y, rect.x))?;
+        let mut rect = self.free.swap_remove(index);
+        while rect.size > size {
+            rect.size /= 2;
+            let half = rect.size;
+            self.free.extend([
+                AtlasRect { x: rect.x + half, ..rect },
+                AtlasRect { y: rect.y + half, ..rect },
+                AtlasRect {
+                    x: rect.x + half,
+                    y: rect.y + half,
+                    ..rect
+                },
+            ]);
+        }
+        Some(rect)
+    }
+}
+
+/// A shadow map of a point or spot light in the atlas.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShadowFace {
+    pub view_projection: Mat4,
+    pub rect: AtlasRect,
+}
+
+/// The shadow maps of point and spot lights.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LightShadows {
+    /// Width of the atlas the faces are packed in.
+    pub atlas_size: u32,
+    pub faces: Vec<ShadowFace>,
+    /// First face of every light with shadows, indexed like the lights.
+    /// Point lights have six faces in the order of [`point_light_faces`],
+    /// spot lights one.
+    pub first_faces: Vec<Option<u32>>,
+}
+
+/// View projections of the faces of a point light's shadow cube, looking
+/// along +X, -X, +Y, -Y, +Z and -Z.
+pub fn point_light_faces(position: Vec3, range: f32) -> [Mat4; 6] {
+    let projection = Mat4::perspective_rh(std::f32::consts::FRAC_PI_2, 1.0, range * NEAR_PLANE_FRACTION, range);
+    [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z]
+        .map(|direction| projection * Mat4::look_at_rh(position, position + direction, up_for(direction)))
+}
+
+/// View projection of a spot light's shadow map, covering its outer cone.
+pub fn spot_light_view_projection(light: &Light, outer_angle: f32) -> Mat4 {
+    let fov = (2.0 * outer_angle).min(MAX_SPOT_FOV);
+    let projection = Mat4::perspective_rh(fov, 1.0, light.range * NEAR_PLANE_FRACTION, light.range);
+    projection * Mat4::look_at_rh(light.position, light.position + light.direction, up_for(light.direction))
+}
+
+/// Packs the shadow maps of the lights casting shadows into an atlas, those
+/// reaching closest to `eye` first. Lights that do not fit at their
+/// resolution make do with smaller tiles, down to [`MIN_TILE_SIZE`], before
+/// going without.
+pub fn plan_light_shadows(lights: &[Light], eye: Vec3, settings: &ShadowSettings) -> LightShadows {
+    let distance = |light: &Light| (light.position.distance(eye) - light.range).max(0.0);
+    let mut casting: Vec<usize> = (0..lights.len())
+        .filter(|&index| lights[index].casts_shadows && lights[index].kind != LightKind::Directional)
+        .collect();
+    casting.sort_by(|&a, &b| distance(&lights[a]).total_cmp(&distance(&lights[b])));
+
+    let mut atlas = ShadowAtlas::new(settings.atlas_size);
+    let mut shadows = LightShadows {
+        atlas_size: atlas.size(),
+        faces: vec![],
+        first_faces: vec![None; lights.len()],
+    };
+    for index in casting {
+        let light = &lights[index];
+        let (matrices, resolution) = match light.kind {
+            LightKind::Point => (point_light_faces(light.position, light.range).to_vec(), settings.point_resolution),
+            LightKind::Spot { outer_angle, .. } => {
+                (vec![spot_light_view_projection(light, outer_angle)], settings.spot_resolution)
+            }
+            LightKind::Directional => continue,
+        };
+        if shadows.faces.len() + matrices.len() > MAX_SHADOW_FACES {
+            continue;
+        }
+
+        let mut size = resolution.next_power_of_two();
+        let rects = loop {
+            let mut attempt = atlas.clone();
+            let rects: Option<Vec<AtlasRect>> = matrices.iter().map(|_| attempt.allocate(size)).collect();
+            if rects.is_some() {
+                atlas = attempt;
+                break rects;
+            }
+            if size / 2 < MIN_TILE_SIZE {
+                break None;
+            }
+            size /= 2;
+        };
+        if let Some(rects) = rects {
+            shadows.first_faces[index] = Some(shadows.faces.len() as u32);
+            shadows.faces.extend(
+                matrices
+                    .into_iter()
+                    .zip(rects)
+                    .map(|(view_projection, rect)| ShadowFace { view_projection, rect }),
+            );
+        }
+    }
+    shadows
+}
+
+/// Bytes of the buffer holding up to [`MAX_SHADOW_FACES`] faces.
+pub fn shadow_face_buffer_size() -> u64 {
+    (MAX_SHADOW_FACES * FACE_WORDS * 4) as u64
+}
+
+/// The face buffer the deferred pass reads, see `include/shadows.glsl`: the
+/// view projection and atlas UV rect of every face.
+pub fn encode_shadow_faces(faces: &[ShadowFace], atlas_size: u32) -> Vec<u32> {
+    let faces = &faces[..faces.len().min(MAX_SHADOW_FACES)];
+    let mut words = Vec::with_capacity(faces.len() * FACE_WORDS);
+    for face in faces {
+        words.extend(face.view_projection.to_cols_array().map(f32::to_bits));
+        words.extend(face.rect.uv_rect(atlas_size).to_array().map(f32::to_bits));
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn projection() -> Mat4 {
+        Mat4::perspective_rh(std::f32::consts::FRAC_PI_3, 16.0 / 9.0, 0.1, 100.0)
+    }
+
+    fn cascades(eye: Vec3, target: Vec3) -> Vec<Cascade> {
+        let view = Mat4::look_at_rh(eye, target, Vec3::Y);
+        let sun_dir = Vec3::new(-1.0, 1.0, 0.15).normalize();
+        fit_cascades(view, projection(), 0.1, 100.0, sun_dir, &ShadowSettings::default())
+    }
+
+    fn overlaps(a: &AtlasRect, b: &AtlasRect) -> bool {
+        a.x < b.x + b.size && b.x < a.x + a.size && a.y < b.y + b.size && b.y < a.y + a.size
+    }
+
+    fn point(position: Vec3, range: f32) -> Light {
+        Light {
+            kind: LightKind::Point,
+            position,
+            direction: -Vec3::Z,
+            color: Vec3::ONE,
+            intensity: 1.0,
+            range,
+            casts_shadows: true,
+        }
+    }
+
+    #[test]
+    fn splits_blend_uniform_and_logarithmic() {
+        assert_eq!(cascade_splits(1.0, 100.0, 4, 0.0), [25.75, 50.5, 75.25, 100.0]);
+        let log = cascade_splits(1.0, 100.0, 2, 1.0);
+        assert!((log[0] - 10.0).abs() < 1e-4 && (log[1] - 100.0).abs() < 1e-3);
+
+        let splits = cascade_splits(0.1, 100.0, 6, 0.5);
+        assert_eq!(splits.len(), 6);
+        assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn cascade_count_is_configurable() {
+        let settings = ShadowSettings {
+            cascade_count: 6,
+            ..Default::default()
+        };
+        let cascades = fit_cascades(Mat4::IDENTITY, projection(), 0.1, 100.0, Vec3::Y, &settings);
+        assert_eq!(cascades.len(), 6);
+        assert!((cascades[5].split_depth - 100.0).abs() < 1e-3);
+
+        let uniforms = ShadowUniforms::new(&cascades, &settings);
+        assert_eq!(uniforms.counts.x, 6);
+        assert_eq!(uniforms.cascade_splits[1].y, cascades[5].split_depth);
+        assert_eq!(uniforms.cascade_matrices[6], Mat4::IDENTITY);
+        assert_eq!(std::mem::size_of::<ShadowUniforms>(), MAX_CASCADES * 64 + 2 * 16 + 32);
+
+        let too_many = ShadowSettings {
+            cascade_count: 100,
+            ..Default::default()
+        };
+        assert_eq!(too_many.cascades() as usize, MAX_CASCADES);
+    }
+
+    #[test]
+    fn cascades_keep_their_size_as_the_camera_turns() {
+        let ahead = cascades(Vec3::ZERO, -Vec3::Z);
+        let aside = cascades(Vec3::ZERO, Vec3::new(0.7, 0.2, 0.3));
+        for (ahead, aside) in ahead.iter().zip(&aside) {
+            // The scale of an orthographic projection is its size.
+            let scale = |cascade: &Cascade| cascade.view_projection.x_axis.truncate().length();
+            assert!((scale(ahead) - scale(aside)).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn cascades_move_in_whole_texels() {
+        let resolution = ShadowSettings::default().cascade_resolution as f32;
+        let texel = |cascade: &Cascade, point: Vec3| {
+            let clip = cascade.view_projection * point.extend(1.0);
+            clip.xy() * resolution / 2.0
+        };
+        let before = cascades(Vec3::new(1.0, 2.0, 3.0), Vec3::new(1.0, 2.0, 0.0));
+        let after = cascades(Vec3::new(1.37, 2.05, 2.61), Vec3::new(1.37, 2.05, -0.39));
+        for (before, after) in before.iter().zip(&after) {
+            for point in [Vec3::ZERO, Vec3::new(5.3, -2.1, 7.7), Vec3::new(-40.0, 3.0, 12.5)] {
+                let moved = texel(after, point) - texel(before, point);
+                assert!((moved - moved.round()).abs().max_element() < 1e-2, "{:?}", moved);
+            }
+        }
+    }
+
+    #[test]
+    fn atlas_tiles_do_not_overlap_and_run_out() {
+        let mut atlas = ShadowAtlas::new(1024);
+        let mut tiles = vec![atlas.allocate(512).unwrap()];
+        tiles.extend((0..4).map(|_| atlas.allocate(256).unwrap()));
+        // Rounded up to a power of two.
+        tiles.push(atlas.allocate(200).unwrap());
+        assert_eq!(tiles[5].size, 256);
+        tiles.extend((0..4).map(|_| atlas.allocate(128).unwrap()));
+
+        for (i, a) in tiles.iter().enumerate() {
+            assert!(a.x + a.size <= 1024 && a.y + a.size <= 1024);
+            for b in &tiles[i + 1..] {
+                assert!(!overlaps(a, b), "{:?} overlaps {:?}", a, b);
+            }
+        }
+        // Small tiles were split off the smallest free squares, leaving the
+        // last quarter whole.
+        assert_eq!(atlas.allocate(1024), None);
+        assert_eq!(atlas.allocate(512), Some(AtlasRect { x: 512, y: 512, size: 512 }));
+        assert!(atlas.allocate(256).is_some() && atlas.allocate(256).is_some());
+        assert_eq!(atlas.allocate(128), None);
+
+        atlas.clear();
+        assert_eq!(atlas.allocate(1024), Some(AtlasRect { x: 0, y: 0, size: 1024 }));
+        assert_eq!(AtlasRect { x: 256, y: 512, size: 256 }.uv_rect(1024), Vec4::new(0.25, 0.5, 0.25, 0.25));
+    }
+
+    #[test]
+    fn point_light_faces_look_along_each_axis() {
+        let position = Vec3::new(1.0, 2.0, 3.0);
+        let faces = point_light_faces(position, 10.0);
+        for (face, direction) in faces.iter().zip([Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z]) {
+            let clip = *face * (position + direction * 5.0).extend(1.0);
+            let ndc = clip.xyz() / clip.w;
+            assert!(ndc.x.abs() < 1e-5 && ndc.y.abs() < 1e-5);
+            assert!(ndc.z > 0.0 && ndc.z < 1.0);
+        }
+    }
+
+    #[test]
+    fn closest_lights_get_shadows_first_and_shrink_to_fit() {
+        let settings = ShadowSettings {
+            atlas_size: 1024,
+            spot_resolution: 512,
+            point_resolution: 512,
+            ..Default::default()
+        };
+        let spot = Light {
+            kind: LightKind::Spot {
+                inner_angle: 0.3,
+                outer_angle: 0.5,
+            },
+            ..point(Vec3::new(0.0, 0.0, -4.0), 5.0)
+        };
+        let unshadowed = Light {
+            casts_shadows: false,
+            ..point(Vec3::ZERO, 5.0)
+        };
+        let lights = [
+            point(Vec3::new(100.0, 0.0, 0.0), 5.0),
+            spot,
+            unshadowed,
+            point(Vec3::new(20.0, 0.0, 0.0), 5.0),
+            point(Vec3::new(200.0, 0.0, 0.0), 5.0),
+        ];
+        let shadows = plan_light_shadows(&lights, Vec3::ZERO, &settings);
+
+        // The furthest light finds the atlas full.
+        assert_eq!(shadows.first_faces, [Some(7), Some(0), None, Some(1), None]);
+        assert_eq!(shadows.faces.len(), 13);
+        assert_eq!(shadows.faces[0].rect.size, 512);
+        // Six 512 wide faces do not fit next to the spot light's.
+        assert!(shadows.faces[1..].iter().all(|face| face.rect.size == 256));
+        assert_eq!(shadows.faces[0].view_projection, spot_light_view_projection(&spot, 0.5));
+
+        let words = encode_shadow_faces(&shadows.faces, shadows.atlas_size);
+        assert_eq!(words.len(), 13 * FACE_WORDS);
+        assert_eq!(f32::from_bits(words[18]), 0.5);
+        assert!((shadow_face_buffer_size() as usize) >= words.len() * 4);
+    }
+}
@@ -0,0 +1,175 @@
+use glam::{Mat3, Mat4, Vec4};
+
+use crate::{gltf_loader::MorphTarget, mesh::Vertex};
+
+/// Joint matrices of a skin, `inverse(mesh) * joint * inverse_bind` per joint.
+/// Skinned vertices stay in the mesh node's space, so the mesh keeps being
+/// drawn with its node transform.
+pub fn joint_palette(mesh_global: Mat4, joint_globals: &[Mat4], inverse_bind_matrices: &[Mat4]) -> Vec<Mat4> {
+    let inverse_mesh = mesh_global.inverse();
+    joint_globals
+        .iter()
+        .zip(inverse_bind_matrices)
+        .map(|(joint, inverse_bind)| inverse_mesh * *joint * *inverse_bind)
+        .collect()
+}
+
+/// Offsets positions, normals and tangents by the weighted morph targets.
+pub fn apply_morph_targets(vertices: &mut [Vertex], targets: &[MorphTarget], weights: &[f32]) {
+    for (target, &weight) in targets.iter().zip(weights) {
+        if weight == 0.0 {
+            continue;
+        }
+        for (i, vertex) in vertices.iter_mut().enumerate() {
+            if let Some(position) = target.positions.get(i) {
+                vertex.pos += (*position * weight).extend(0.0);
+            }
+            if let Some(normal) = target.normals.get(i) {
+                vertex.normal += (*normal * weight).extend(0.0);
+            }
+            if let Some(tangent) = target.tangents.get(i) {
+                vertex.tangent += (*tangent * weight).extend(0.0);
+            }
+        }
+    }
+}
+
+/// Linear blend skinning of `vertices` in place. Vertices without joints or
+/// with all-zero weights are left untouched.
+pub fn skin_vertices(vertices: &mut [Vertex], joints: &[[u16; 4]], weights: &[Vec4], palette: &[Mat4]) {
+    for ((vertex, joints), weights) in vertices.iter_mut().zip(joints).zip(weights) {
+        let mut skin = Mat4::ZERO;
+        for (joint, weight) in joints.iter().zip(weights.to_array()) {
+            if let (true, Some(matrix)) = (weight != 0.0, palette.get(*joint as usize)) {
+                skin += *matrix * weight;
+            }
+        }
+        if skin == Mat4::ZERO {
+            continue;
+        }
+
+        let linear = Mat3::from_mat4(skin);
+        let direction = |v: Vec4| (linear * v.truncate()).normalize_or_zero().extend(v.w);
+        vertex.pos = skin.transform_point3(vertex.pos.truncate()).extend(vertex.pos.w);
+        vertex.normal = direction(vertex.normal);
+        vertex.tangent = direction(vertex.tangent);
+    }
+}
+
+/// Skinned copy of a mesh's bind pose vertices, with morph targets applied
+/// first. An empty `palette` only applies the morph targets.
+pub fn deform_vertices(
+    bind_pose: &[Vertex],
+    joints: &[[u16; 4]],
+    weights: &[Vec4],
+    targets: &[MorphTarget],
+    palette: &[Mat4],
+    morph_weights: &[f32],
+) -> Vec<Vertex> {
+    let mut vertices = bind_pose.to_vec();
+    apply_morph_targets(&mut vertices, targets, morph_weights);
+    if !palette.is_empty() {
+        skin_vertices(&mut vertices, joints, weights, palette);
+    }
+    vertices
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        animation::{AnimationPlayer, PlayingClip, Pose},
+        gltf_loader::{import_gltf, CpuModel},
+        test_fixtures::model_path,
+    };
+    use glam::{Quat, Vec3};
+
+    fn skinned() -> CpuModel {
+        import_gltf(model_path("skinned.gltf")).unwrap()
+    }
+
+    /// Skinned positions of the fixture's mesh after playing its clip for `time`.
+    fn posed_positions(model: &CpuModel, time: f32) -> Vec<Vec3> {
+        let mut player = AnimationPlayer::new();
+        player.play_on(0, PlayingClip::new(0).looping(false));
+        player.advance(time, &model.animations);
+        let mut pose = Pose::default();
+        player.evaluate(&model.animations, &model.rest_pose(), &mut pose);
+
+        let globals = pose.global_transforms(&model.parents());
+        let mesh = &model.meshes[0];
+        let skin = &model.skins[model.nodes[mesh.node].skin.unwrap()];
+        let joints: Vec<Mat4> = skin.joints.iter().map(|joint| globals[*joint]).collect();
+        let palette = joint_palette(globals[mesh.node], &joints, &skin.inverse_bind_matrices);
+
+        let mut vertices = mesh.vertices.clone();
+        skin_vertices(&mut vertices, &mesh.joints, &mesh.joint_weights, &palette);
+        vertices.iter().map(|v| v.pos.truncate()).collect()
+    }
+
+    fn assert_positions(actual: &[Vec3], expected: &[Vec3]) {
+        assert_eq!(actual.len(), expected.len());
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!(actual.abs_diff_eq(*expected, 1e-5), "{actual} != {expected}");
+        }
+    }
+
+    #[test]
+    fn rest_pose_leaves_bind_pose_untouched() {
+        let model = skinned();
+        let bind: Vec<Vec3> = model.meshes[0].vertices.iter().map(|v| v.pos.truncate()).collect();
+        assert_positions(&posed_positions(&model, 0.0), &bind);
+    }
+
+    #[test]
+    fn matches_reference_poses() {
+        let model = skinned();
+
+        // Hip raised to y = 2, knee still unrotated: everything moves up by 2.
+        assert_positions(
+            &posed_positions(&model, 1.0),
+            &[Vec3::new(0.0, 2.0, 0.0), Vec3::new(1.0, 2.0, 0.0), Vec3::new(0.0, 3.0, 0.0)],
+        );
+
+        // The knee turns a quarter about Y around its joint at y = 3. The first
+        // vertex is split evenly between hip and knee.
+        let knee = Mat4::from_translation(Vec3::new(0.0, 3.0, 0.0))
+            * Mat4::from_quat(Quat::from_rotation_y(std::f32::consts::FRAC_PI_2))
+            * Mat4::from_translation(Vec3::new(0.0, -1.0, 0.0));
+        let hip = Mat4::from_translation(Vec3::new(0.0, 2.0, 0.0));
+        let split = (hip + knee) * 0.5;
+        assert_positions(
+            &posed_positions(&model, 2.0),
+            &[
+                split.transform_point3(Vec3::ZERO),
+                Vec3::new(0.0, 2.0, -1.0),
+                Vec3::new(0.0, 3.0, 0.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn palette_cancels_the_mesh_transform() {
+        let mesh = Mat4::from_translation(Vec3::new(5.0, 0.0, 0.0));
+        let joint = Mat4::from_translation(Vec3::new(5.0, 1.0, 0.0));
+        let palette = joint_palette(mesh, &[joint], &[Mat4::IDENTITY]);
+        assert_eq!(palette, vec![Mat4::from_translation(Vec3::Y)]);
+    }
+
+    #[test]
+    fn morph_targets_are_weighted() {
+        let model = skinned();
+        let mesh = &model.meshes[0];
+        let vertices = deform_vertices(
+            &mesh.vertices,
+            &mesh.joints,
+            &mesh.joint_weights,
+            &mesh.morph_targets,
+            &[],
+            &[0.25],
+        );
+        for (morphed, bind) in vertices.iter().zip(&mesh.vertices) {
+            assert_eq!(morphed.pos - bind.pos, Vec4::new(0.0, 0.0, 0.25, 0.0));
+        }
+    }
+}
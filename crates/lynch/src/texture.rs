@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use ash::vk;
 
+use crate::texture_processing::{load_texture, CpuImage, TextureSettings, TextureUsage};
 use crate::vulkan::Buffer;
 use crate::vulkan::Device;
 use crate::vulkan::{Image, ImageDesc};
@@ -21,26 +22,31 @@ impl Texture {
             self.device.ash_device.destroy_sampler(self.sampler, None);
         }
     }
-    pub fn load(device: Arc<Device>, path: &str) -> Texture {
-        let image = match image::open(path) {
+    /// Loads an image, or a KTX2/DDS file if the device samples BC formats,
+    /// to be sampled as `usage`.
+    pub fn load(device: Arc<Device>, path: &str, usage: TextureUsage) -> Texture {
+        let settings = TextureSettings {
+            bc_supported: device.features.texture_compression_bc == vk::TRUE,
+            ..Default::default()
+        };
+        let image = match load_texture(std::path::Path::new(path), usage, &settings) {
             Ok(image) => image,
-            Err(_err) => panic!("Unable to load \"{}\"", path),
+            Err(err) => panic!("Unable to load \"{}\": {}", path, err),
         };
 
-        let image = image.to_rgba();
-        let (width, height) = (image.width(), image.height());
-        let image_data = image.into_raw();
-
-        let mut texture = Texture::create(
-            device,
-            Some(&image_data),
-            ImageDesc::new_2d(width, height, vk::Format::R8G8B8A8_UNORM),
-            path,
-        );
-
-        texture.image.set_debug_name(path);
+        Texture::from_cpu_image(device, &image, path)
+    }
 
-        texture
+    /// Uploads every mip level of `image` in its own format.
+    pub fn from_cpu_image(device: Arc<Device>, image: &CpuImage, debug_name: &str) -> Texture {
+        let image_desc = ImageDesc::new_2d(image.width, image.height, image.vk_format())
+            .usage(
+                vk::ImageUsageFlags::TRANSFER_DST
+                    | vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::SAMPLED,
+            )
+            .mip_levels(image.mip_levels);
+        Texture::upload(device, Some((&image.pixels, &image.level_offsets())), image_desc, debug_name)
     }
 
     pub fn create(
@@ -48,6 +54,17 @@ impl Texture {
         pixels: Option<&[u8]>,
         image_desc: ImageDesc,
         debug_name: &str,
+    ) -> Texture {
+        Texture::upload(device, pixels.map(|pixels| (pixels, &[0][..])), image_desc, debug_name)
+    }
+
+    /// Creates the image and copies `pixels` into it, level `i` starting at
+    /// byte `level_offsets[i]`.
+    fn upload(
+        device: Arc<Device>,
+        pixels: Option<(&[u8], &[usize])>,
+        image_desc: ImageDesc,
+        debug_name: &str,
     ) -> Texture {
         let mut image = Image::new_from_desc(device.clone(), image_desc);
 
@@ -63,7 +80,7 @@ impl Texture {
                 true,
             );
 
-            if let Some(pixels) = pixels {
+            if let Some((pixels, level_offsets)) = pixels {
                 let staging_buffer = Buffer::new(
                     device.clone(),
                     Some(pixels),
@@ -73,7 +90,7 @@ impl Texture {
                     Some(String::from("staging_buffer")),
                 );
 
-                staging_buffer.copy_to_image(cb, &image);
+                staging_buffer.copy_levels_to_image(cb, &image, level_offsets);
                 buffer_to_destroy.push(staging_buffer);
             }
 
@@ -95,27 +112,7 @@ impl Texture {
             }
         });
 
-        let sampler_info = vk::SamplerCreateInfo {
-            mag_filter: vk::Filter::LINEAR,
-            min_filter: vk::Filter::LINEAR,
-            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
-            address_mode_u: vk::SamplerAddressMode::MIRRORED_REPEAT,
-            address_mode_v: vk::SamplerAddressMode::MIRRORED_REPEAT,
-            address_mode_w: vk::SamplerAddressMode::MIRRORED_REPEAT,
-            max_anisotropy: 1.0,
-            border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
-            compare_op: vk::CompareOp::NEVER,
-            min_lod: 0.0,
-            max_lod: image_desc.mip_levels as f32,
-            ..Default::default()
-        };
-
-        let sampler = unsafe {
-            device
-                .ash_device
-                .create_sampler(&sampler_info, None)
-                .expect("Unable to create sampler")
-        };
+        let sampler = Texture::create_sampler(&device, image_desc.mip_levels);
 
         let descriptor_info = vk::DescriptorImageInfo {
             image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
@@ -137,4 +134,62 @@ impl Texture {
             descriptor_info,
         }
     }
+
+    /// A texture whose image gets memory later, see [`Texture::bind_memory`].
+    pub fn new_unbound(device: Arc<Device>, image_desc: ImageDesc, debug_name: &str) -> Texture {
+        let mut image = Image::new_unbound(device.clone(), image_desc);
+        image.debug_name = debug_name.to_string();
+        let sampler = Texture::create_sampler(&device, image_desc.mip_levels);
+        Texture {
+            device,
+            image,
+            sampler,
+            descriptor_info: vk::DescriptorImageInfo {
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                image_view: vk::ImageView::null(),
+                sampler,
+            },
+        }
+    }
+
+    pub fn bind_memory(&mut self, memory: vk::DeviceMemory, offset: u64) {
+        self.image.bind_memory(memory, offset);
+        let debug_name = self.image.debug_name.clone();
+        self.image.set_debug_name(&debug_name);
+        self.descriptor_info.image_view = self.image.image_view;
+    }
+
+    /// Replaces the image with an unbound copy, as images cannot be bound to
+    /// memory twice.
+    pub fn unbind(&mut self) {
+        self.image.clean_vk_resources();
+        let debug_name = std::mem::take(&mut self.image.debug_name);
+        self.image = Image::new_unbound(self.device.clone(), self.image.desc);
+        self.image.debug_name = debug_name;
+        self.descriptor_info.image_view = vk::ImageView::null();
+    }
+
+    fn create_sampler(device: &Device, mip_levels: u32) -> vk::Sampler {
+        let sampler_info = vk::SamplerCreateInfo {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::MIRRORED_REPEAT,
+            address_mode_v: vk::SamplerAddressMode::MIRRORED_REPEAT,
+            address_mode_w: vk::SamplerAddressMode::MIRRORED_REPEAT,
+            max_anisotropy: 1.0,
+            border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            compare_op: vk::CompareOp::NEVER,
+            min_lod: 0.0,
+            max_lod: mip_levels as f32,
+            ..Default::default()
+        };
+
+        unsafe {
+            device
+                .ash_device
+                .create_sampler(&sampler_info, None)
+                .expect("Unable to create sampler")
+        }
+    }
 }
@@ -0,0 +1,317 @@
+//! Readers for the KTX2 and DDS containers, limited to single 2D images with
+//! their mip chain stored uncompressed or in a BC format.
+
+use std::path::Path;
+
+use super::{mips::mip_count, ColorSpace, CpuImage, TextureError, TextureFormat};
+
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+
+fn read_u32(bytes: &[u8], offset: usize) -> Result<u32, TextureError> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(TextureError::InvalidContainer("truncated header"))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> Result<u64, TextureError> {
+    bytes
+        .get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().unwrap()))
+        .ok_or(TextureError::InvalidContainer("truncated header"))
+}
+
+/// The level count of a `width` x `height` container, past the smallest
+/// level being dropped.
+fn checked_levels(width: u32, height: u32, levels: u32) -> Result<u32, TextureError> {
+    if width == 0 || height == 0 {
+        return Err(TextureError::InvalidContainer("zero width or height"));
+    }
+    if levels >= 32 {
+        return Err(TextureError::InvalidContainer("more than 31 mip levels"));
+    }
+    Ok(levels.min(mip_count(width, height)))
+}
+
+/// Format and colour space of a `VkFormat` value.
+fn ktx2_format(vk_format: u32) -> Option<(TextureFormat, ColorSpace)> {
+    use ColorSpace::*;
+    Some(match vk_format {
+        37 => (TextureFormat::Rgba8, Linear),
+        43 => (TextureFormat::Rgba8, Srgb),
+        91 => (TextureFormat::Rgba16, Linear),
+        109 => (TextureFormat::Rgba32Float, Linear),
+        131 | 133 => (TextureFormat::Bc1, Linear),
+        132 | 134 => (TextureFormat::Bc1, Srgb),
+        135 => (TextureFormat::Bc2, Linear),
+        136 => (TextureFormat::Bc2, Srgb),
+        137 => (TextureFormat::Bc3, Linear),
+        138 => (TextureFormat::Bc3, Srgb),
+        139 => (TextureFormat::Bc4, Linear),
+        141 => (TextureFormat::Bc5, Linear),
+        143 => (TextureFormat::Bc6hUfloat, Linear),
+        144 => (TextureFormat::Bc6hSfloat, Linear),
+        145 => (TextureFormat::Bc7, Linear),
+        146 => (TextureFormat::Bc7, Srgb),
+        _ => return None,
+    })
+}
+
+pub fn parse_ktx2(bytes: &[u8]) -> Result<CpuImage, TextureError> {
+    if bytes.get(..12) != Some(&KTX2_IDENTIFIER[..]) {
+        return Err(TextureError::InvalidContainer("missing KTX2 identifier"));
+    }
+    let vk_format = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 20)?;
+    let height = read_u32(bytes, 24)?;
+    let depth = read_u32(bytes, 28)?;
+    let layers = read_u32(bytes, 32)?;
+    let faces = read_u32(bytes, 36)?;
+    let levels = checked_levels(width, height, read_u32(bytes, 40)?.max(1))?;
+    let supercompression = read_u32(bytes, 44)?;
+
+    if supercompression != 0 {
+        return Err(TextureError::UnsupportedFormat(format!("KTX2 supercompression {}", supercompression)));
+    }
+    if depth > 1 || layers > 1 || faces != 1 {
+        return Err(TextureError::UnsupportedFormat("KTX2 array, cube or 3D texture".to_owned()));
+    }
+    let (format, color_space) =
+        ktx2_format(vk_format).ok_or_else(|| TextureError::UnsupportedFormat(format!("VkFormat {}", vk_format)))?;
+
+    let mut image = CpuImage {
+        width,
+        height,
+        format,
+        color_space,
+        mip_levels: levels,
+        pixels: vec![],
+        source: None,
+    };
+    // The level index lists the smallest level last, each with its own offset.
+    for level in 0..levels {
+        let entry = 80 + level as usize * 24;
+        let offset = read_u64(bytes, entry)? as usize;
+        let length = read_u64(bytes, entry + 8)? as usize;
+        let (level_width, level_height) = image.level_extent(level);
+        if length != format.level_size(level_width, level_height) {
+            return Err(TextureError::InvalidContainer("KTX2 level size does not match its extent"));
+        }
+        let data = offset
+            .checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or(TextureError::InvalidContainer("KTX2 level outside the file"))?;
+        image.pixels.extend_from_slice(data);
+    }
+    Ok(image)
+}
+
+pub fn load_ktx2(path: &Path) -> Result<CpuImage, TextureError> {
+    let mut image = parse_ktx2(&std::fs::read(path)?)?;
+    image.source = Some(path.to_path_buf());
+    Ok(image)
+}
+
+fn dxgi_format(dxgi: u32) -> Option<(TextureFormat, ColorSpace)> {
+    use ColorSpace::*;
+    Some(match dxgi {
+        2 => (TextureFormat::Rgba32Float, Linear),
+        11 => (TextureFormat::Rgba16, Linear),
+        28 => (TextureFormat::Rgba8, Linear),
+        29 => (TextureFormat::Rgba8, Srgb),
+        71 => (TextureFormat::Bc1, Linear),
+        72 => (TextureFormat::Bc1, Srgb),
+        74 => (TextureFormat::Bc2, Linear),
+        75 => (TextureFormat::Bc2, Srgb),
+        77 => (TextureFormat::Bc3, Linear),
+        78 => (TextureFormat::Bc3, Srgb),
+        80 => (TextureFormat::Bc4, Linear),
+        83 => (TextureFormat::Bc5, Linear),
+        95 => (TextureFormat::Bc6hUfloat, Linear),
+        96 => (TextureFormat::Bc6hSfloat, Linear),
+        98 => (TextureFormat::Bc7, Linear),
+        99 => (TextureFormat::Bc7, Srgb),
+        _ => return None,
+    })
+}
+
+pub fn parse_dds(bytes: &[u8]) -> Result<CpuImage, TextureError> {
+    const MIPMAP_COUNT: u32 = 0x20000;
+    const FOURCC: u32 = 0x4;
+    const CUBEMAP: u32 = 0x200;
+
+    if bytes.get(..4) != Some(&DDS_MAGIC[..]) || read_u32(bytes, 4)? != 124 {
+        return Err(TextureError::InvalidContainer("missing DDS header"));
+    }
+    let flags = read_u32(bytes, 8)?;
+    let height = read_u32(bytes, 12)?;
+    let width = read_u32(bytes, 16)?;
+    let levels = match flags & MIPMAP_COUNT {
+        0 => 1,
+        _ => read_u32(bytes, 28)?.max(1),
+    };
+    let levels = checked_levels(width, height, levels)?;
+    let pixel_flags = read_u32(bytes, 80)?;
+    let four_cc = read_u32(bytes, 84)?.to_le_bytes();
+    if read_u32(bytes, 112)? & CUBEMAP != 0 {
+        return Err(TextureError::UnsupportedFormat("DDS cubemap".to_owned()));
+    }
+    if pixel_flags & FOURCC == 0 {
+        return Err(TextureError::UnsupportedFormat("DDS without a FourCC".to_owned()));
+    }
+
+    let (format, color_space, mut data) = match &four_cc[..] {
+        b"DXT1" => (TextureFormat::Bc1, ColorSpace::Linear, 128),
+        b"DXT2" | b"DXT3" => (TextureFormat::Bc2, ColorSpace::Linear, 128),
+        b"DXT4" | b"DXT5" => (TextureFormat::Bc3, ColorSpace::Linear, 128),
+        b"ATI1" | b"BC4U" => (TextureFormat::Bc4, ColorSpace::Linear, 128),
+        b"ATI2" | b"BC5U" => (TextureFormat::Bc5, ColorSpace::Linear, 128),
+        b"DX10" => {
+            let dxgi = read_u32(bytes, 128)?;
+            let (format, color_space) =
+                dxgi_format(dxgi).ok_or_else(|| TextureError::UnsupportedFormat(format!("DXGI format {}", dxgi)))?;
+            // Resource dimension 3 is a 2D texture, misc flag 0x4 a cubemap.
+            if read_u32(bytes, 132)? != 3 || read_u32(bytes, 136)? & 0x4 != 0 || read_u32(bytes, 140)? > 1 {
+                return Err(TextureError::UnsupportedFormat("DDS array or non 2D texture".to_owned()));
+            }
+            (format, color_space, 148)
+        }
+        other => {
+            return Err(TextureError::UnsupportedFormat(format!(
+                "DDS FourCC {}",
+                String::from_utf8_lossy(other)
+            )))
+        }
+    };
+
+    let mut image = CpuImage {
+        width,
+        height,
+        format,
+        color_space,
+        mip_levels: levels,
+        pixels: vec![],
+        source: None,
+    };
+    for level in 0..levels {
+        let (level_width, level_height) = image.level_extent(level);
+        let length = format.level_size(level_width, level_height);
+        let level = bytes
+            .get(data..data + length)
+            .ok_or(TextureError::InvalidContainer("DDS shorter than its mip chain"))?;
+        image.pixels.extend_from_slice(level);
+        data += length;
+    }
+    Ok(image)
+}
+
+pub fn load_dds(path: &Path) -> Result<CpuImage, TextureError> {
+    let mut image = parse_dds(&std::fs::read(path)?)?;
+    image.source = Some(path.to_path_buf());
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ktx2(vk_format: u32, width: u32, height: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = KTX2_IDENTIFIER.to_vec();
+        for value in [vk_format, 1, width, height, 0, 0, 1, levels.len() as u32, 0] {
+            bytes.extend(value.to_le_bytes());
+        }
+        bytes.resize(80, 0);
+        let mut offset = 80 + levels.len() * 24;
+        for level in levels {
+            for value in [offset as u64, level.len() as u64, level.len() as u64] {
+                bytes.extend(value.to_le_bytes());
+            }
+            offset += level.len();
+        }
+        for level in levels {
+            bytes.extend(level);
+        }
+        bytes
+    }
+
+    fn dds(four_cc: &[u8; 4], dx10: Option<u32>, width: u32, height: u32, levels: u32, data: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0; 128];
+        bytes[..4].copy_from_slice(DDS_MAGIC);
+        bytes[4..8].copy_from_slice(&124u32.to_le_bytes());
+        bytes[8..12].copy_from_slice(&0x20000u32.to_le_bytes());
+        bytes[12..16].copy_from_slice(&height.to_le_bytes());
+        bytes[16..20].copy_from_slice(&width.to_le_bytes());
+        bytes[28..32].copy_from_slice(&levels.to_le_bytes());
+        bytes[80..84].copy_from_slice(&0x4u32.to_le_bytes());
+        bytes[84..88].copy_from_slice(four_cc);
+        if let Some(dxgi) = dx10 {
+            for value in [dxgi, 3, 0, 1, 0] {
+                bytes.extend(value.to_le_bytes());
+            }
+        }
+        bytes.extend(data);
+        bytes
+    }
+
+    #[test]
+    fn ktx2_levels_are_read_in_order() {
+        let bytes = ktx2(146, 8, 4, &[vec![1; 32], vec![2; 16], vec![3; 16], vec![4; 16]]);
+        let image = parse_ktx2(&bytes).unwrap();
+        assert_eq!((image.format, image.color_space), (TextureFormat::Bc7, ColorSpace::Srgb));
+        assert_eq!((image.width, image.height, image.mip_levels), (8, 4, 4));
+        assert_eq!(image.level(0), &[1; 32][..]);
+        assert_eq!(image.level(3), &[4; 16][..]);
+
+        assert!(matches!(
+            parse_ktx2(&ktx2(146, 8, 4, &[vec![1; 16]])),
+            Err(TextureError::InvalidContainer(_))
+        ));
+        assert!(matches!(
+            parse_ktx2(&ktx2(1000156000, 8, 4, &[vec![1; 32]])),
+            Err(TextureError::UnsupportedFormat(_))
+        ));
+        assert!(parse_ktx2(&bytes[..100]).is_err());
+    }
+
+    #[test]
+    fn level_counts_are_checked_against_the_extent() {
+        // Levels past 1x1 are dropped.
+        let levels = vec![vec![1; 8], vec![2; 4], vec![3; 4]];
+        let image = parse_ktx2(&ktx2(37, 2, 1, &levels)).unwrap();
+        assert_eq!(image.mip_levels, 2);
+        assert_eq!(image.pixels.len(), 12);
+        let image = parse_dds(&dds(b"DXT1", None, 4, 4, 5, &[7; 24])).unwrap();
+        assert_eq!(image.mip_levels, 3);
+
+        assert!(matches!(
+            parse_ktx2(&ktx2(37, 0, 4, &[vec![]])),
+            Err(TextureError::InvalidContainer(_))
+        ));
+        assert!(matches!(
+            parse_dds(&dds(b"DXT1", None, 4, 0, 1, &[])),
+            Err(TextureError::InvalidContainer(_))
+        ));
+        assert!(matches!(
+            parse_dds(&dds(b"DXT1", None, 4, 4, 32, &[7; 24])),
+            Err(TextureError::InvalidContainer(_))
+        ));
+    }
+
+    #[test]
+    fn dds_four_cc_and_dx10_headers_are_read() {
+        let image = parse_dds(&dds(b"DXT1", None, 4, 4, 3, &[7; 24])).unwrap();
+        assert_eq!((image.format, image.mip_levels), (TextureFormat::Bc1, 3));
+        assert_eq!(image.pixels.len(), 24);
+
+        let image = parse_dds(&dds(b"DX10", Some(83), 8, 8, 1, &[5; 64])).unwrap();
+        assert_eq!((image.format, image.color_space), (TextureFormat::Bc5, ColorSpace::Linear));
+
+        assert!(parse_dds(&dds(b"DXT5", None, 4, 4, 2, &[0; 16])).is_err());
+        assert!(matches!(
+            parse_dds(&dds(b"DX10", Some(999), 4, 4, 1, &[0; 16])),
+            Err(TextureError::UnsupportedFormat(_))
+        ));
+        assert!(parse_dds(b"DDS ").is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use std::path::Path;
+
+use image::DynamicImage;
+
+use super::{ColorSpace, CpuImage, TextureError, TextureFormat};
+
+pub fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Expands a decoded image to RGBA, keeping 16 bit and floating point
+/// precision. Floating point images hold linear radiance, everything else is
+/// taken as it was authored.
+pub fn from_dynamic_image(image: DynamicImage) -> CpuImage {
+    let (width, height) = (image.width(), image.height());
+    let (format, color_space, pixels) = match image {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+            let pixels = image.to_rgba32f().into_raw().iter().flat_map(|v| v.to_ne_bytes()).collect();
+            (TextureFormat::Rgba32Float, ColorSpace::Linear, pixels)
+        }
+        DynamicImage::ImageLuma16(_)
+        | DynamicImage::ImageLumaA16(_)
+        | DynamicImage::ImageRgb16(_)
+        | DynamicImage::ImageRgba16(_) => {
+            let pixels = image.to_rgba16().into_raw().iter().flat_map(|v| v.to_ne_bytes()).collect();
+            (TextureFormat::Rgba16, ColorSpace::Srgb, pixels)
+        }
+        _ => (TextureFormat::Rgba8, ColorSpace::Srgb, image.to_rgba8().into_raw()),
+    };
+    CpuImage {
+        width,
+        height,
+        format,
+        color_space,
+        mip_levels: 1,
+        pixels,
+        source: None,
+    }
+}
+
+/// Decodes PNG, JPEG, TGA, BMP, Radiance HDR, OpenEXR and the other formats
+/// the `image` crate reads.
+pub fn decode_image(path: &Path) -> Result<CpuImage, TextureError> {
+    let mut image = from_dynamic_image(image::open(path)?);
+    image.source = Some(path.to_path_buf());
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{ImageBuffer, Rgb, Rgba};
+
+    use super::*;
+
+    #[test]
+    fn srgb_round_trips() {
+        for i in 0..=255 {
+            let value = i as f32 / 255.0;
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-5);
+        }
+        assert!((srgb_to_linear(0.5) - 0.2140).abs() < 1e-4);
+    }
+
+    #[test]
+    fn wide_images_keep_their_precision() {
+        let hdr = ImageBuffer::from_pixel(1, 1, Rgb([4.0f32, 0.5, 0.0]));
+        let image = from_dynamic_image(DynamicImage::ImageRgb32F(hdr));
+        assert_eq!((image.format, image.color_space), (TextureFormat::Rgba32Float, ColorSpace::Linear));
+        let values: Vec<f32> = image
+            .pixels
+            .chunks_exact(4)
+            .map(|v| f32::from_ne_bytes(v.try_into().unwrap()))
+            .collect();
+        assert_eq!(values, vec![4.0, 0.5, 0.0, 1.0]);
+
+        let deep = ImageBuffer::from_pixel(1, 1, Rgba([1u16, 2, 3, 65535]));
+        let image = from_dynamic_image(DynamicImage::ImageRgba16(deep));
+        assert_eq!(image.format, TextureFormat::Rgba16);
+        assert_eq!(&image.pixels[..2], &1u16.to_ne_bytes());
+        assert_eq!(image.pixels.len(), 8);
+    }
+}
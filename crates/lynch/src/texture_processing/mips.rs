@@ -0,0 +1,228 @@
+use glam::{Vec3, Vec4};
+
+use super::{
+    decode::{linear_to_srgb, srgb_to_linear},
+    ColorSpace, CpuImage, TextureFormat, TextureSettings, TextureUsage,
+};
+
+/// Levels in a full chain down to 1x1.
+pub fn mip_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Source texels covered by each of `dst` texels when shrinking a row of
+/// `src` texels, as `(first, weights)`. Odd sizes give fractional
+/// footprints, so every source texel contributes the same total weight.
+fn footprints(src: usize, dst: usize) -> Vec<(usize, Vec<f32>)> {
+    let scale = src as f32 / dst as f32;
+    (0..dst)
+        .map(|i| {
+            let start = i as f32 * scale;
+            let end = start + scale;
+            let first = start.floor() as usize;
+            let last = (end.ceil() as usize).min(src);
+            let weights = (first..last)
+                .map(|texel| (end.min(texel as f32 + 1.0) - start.max(texel as f32)) / scale)
+                .collect();
+            (first, weights)
+        })
+        .collect()
+}
+
+/// Box filters `texels` from `width` x `height` to half size, one axis at a
+/// time.
+fn downsample(texels: &[Vec4], width: usize, height: usize) -> (Vec<Vec4>, usize, usize) {
+    let (dst_width, dst_height) = ((width / 2).max(1), (height / 2).max(1));
+
+    let columns = footprints(width, dst_width);
+    let mut horizontal = Vec::with_capacity(dst_width * height);
+    for row in texels.chunks_exact(width) {
+        for (first, weights) in &columns {
+            let texel = weights.iter().enumerate().fold(Vec4::ZERO, |sum, (j, w)| sum + row[first + j] * *w);
+            horizontal.push(texel);
+        }
+    }
+
+    let rows = footprints(height, dst_height);
+    let mut result = Vec::with_capacity(dst_width * dst_height);
+    for (first, weights) in &rows {
+        for x in 0..dst_width {
+            let texel = weights
+                .iter()
+                .enumerate()
+                .fold(Vec4::ZERO, |sum, (j, w)| sum + horizontal[(first + j) * dst_width + x] * *w);
+            result.push(texel);
+        }
+    }
+    (result, dst_width, dst_height)
+}
+
+/// Builds every level below the linear `base` image, the base included.
+/// Colours are filtered with premultiplied alpha so transparent texels do not
+/// bleed into their neighbours, and normals are renormalized per level.
+pub fn generate_mips(base: Vec<Vec4>, width: u32, height: u32, usage: TextureUsage) -> Vec<Vec<Vec4>> {
+    let premultiply = usage == TextureUsage::Color;
+    let mut current: Vec<Vec4> = match premultiply {
+        true => base.iter().map(|t| (t.truncate() * t.w).extend(t.w)).collect(),
+        false => base.clone(),
+    };
+    let (mut width, mut height) = (width as usize, height as usize);
+
+    let mut levels = vec![base];
+    for _ in 1..mip_count(width as u32, height as u32) {
+        let (next, next_width, next_height) = downsample(&current, width, height);
+        let level = next
+            .iter()
+            .map(|t| match usage {
+                TextureUsage::Color if t.w > 0.0 => (t.truncate() / t.w).extend(t.w),
+                TextureUsage::Color => Vec4::ZERO,
+                TextureUsage::Normal => {
+                    let normal = (t.truncate() * 2.0 - Vec3::ONE).normalize_or_zero();
+                    (normal * 0.5 + Vec3::splat(0.5)).extend(t.w)
+                }
+                TextureUsage::Data => *t,
+            })
+            .collect();
+        levels.push(level);
+        (current, width, height) = (next, next_width, next_height);
+    }
+    levels
+}
+
+fn decode_level(image: &CpuImage, level: u32, srgb: bool) -> Vec<Vec4> {
+    let to_linear = |v: Vec4| match srgb {
+        true => Vec4::new(srgb_to_linear(v.x), srgb_to_linear(v.y), srgb_to_linear(v.z), v.w),
+        false => v,
+    };
+    let bytes = image.level(level);
+    match image.format {
+        TextureFormat::Rgba8 => bytes
+            .chunks_exact(4)
+            .map(|t| to_linear(Vec4::new(t[0] as f32, t[1] as f32, t[2] as f32, t[3] as f32) / 255.0))
+            .collect(),
+        TextureFormat::Rgba16 => bytes
+            .chunks_exact(8)
+            .map(|t| {
+                let channel = |i: usize| u16::from_ne_bytes([t[i * 2], t[i * 2 + 1]]) as f32 / 65535.0;
+                to_linear(Vec4::new(channel(0), channel(1), channel(2), channel(3)))
+            })
+            .collect(),
+        _ => bytes
+            .chunks_exact(16)
+            .map(|t| {
+                let channel = |i: usize| f32::from_ne_bytes(t[i * 4..i * 4 + 4].try_into().unwrap());
+                Vec4::new(channel(0), channel(1), channel(2), channel(3))
+            })
+            .collect(),
+    }
+}
+
+fn encode_level(texels: &[Vec4], format: TextureFormat, srgb: bool, pixels: &mut Vec<u8>) {
+    for texel in texels {
+        let texel = match srgb {
+            true => Vec4::new(linear_to_srgb(texel.x), linear_to_srgb(texel.y), linear_to_srgb(texel.z), texel.w),
+            false => *texel,
+        };
+        match format {
+            TextureFormat::Rgba8 => {
+                let texel = (texel.clamp(Vec4::ZERO, Vec4::ONE) * 255.0).round();
+                pixels.extend(texel.to_array().map(|c| c as u8));
+            }
+            TextureFormat::Rgba16 => {
+                let texel = (texel.clamp(Vec4::ZERO, Vec4::ONE) * 65535.0).round();
+                pixels.extend(texel.to_array().iter().flat_map(|c| (*c as u16).to_ne_bytes()));
+            }
+            _ => pixels.extend(texel.to_array().iter().flat_map(|c| c.to_ne_bytes())),
+        }
+    }
+}
+
+/// Stores an uncompressed image in the colour space `usage` samples it in,
+/// generating its mip chain first if `settings` ask for one. 8 bit colour
+/// stays sRGB encoded, 16 bit colour has no sRGB format and is stored linear.
+/// Floating point images are taken to be linear already.
+pub fn process_image(image: CpuImage, usage: TextureUsage, settings: &TextureSettings) -> CpuImage {
+    if image.format.is_compressed() {
+        return image;
+    }
+    let authored_srgb = usage == TextureUsage::Color && image.format != TextureFormat::Rgba32Float;
+    let color_space = match image.format {
+        TextureFormat::Rgba8 => usage.color_space(),
+        _ => ColorSpace::Linear,
+    };
+    let generate = settings.generate_mips && image.mip_levels == 1;
+    if !generate && authored_srgb == (color_space == ColorSpace::Srgb) {
+        return CpuImage { color_space, ..image };
+    }
+
+    let levels = match generate {
+        true => generate_mips(decode_level(&image, 0, authored_srgb), image.width, image.height, usage),
+        false => (0..image.mip_levels)
+            .map(|level| decode_level(&image, level, authored_srgb))
+            .collect(),
+    };
+    let mut pixels = Vec::with_capacity(image.pixels.len() * 4 / 3 + image.format.block_size());
+    for level in &levels {
+        encode_level(level, image.format, color_space == ColorSpace::Srgb, &mut pixels);
+    }
+    CpuImage {
+        color_space,
+        mip_levels: levels.len() as u32,
+        pixels,
+        ..image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chains_go_down_to_one_texel() {
+        assert_eq!(mip_count(1, 1), 1);
+        assert_eq!(mip_count(256, 256), 9);
+        assert_eq!(mip_count(300, 17), 9);
+
+        let levels = generate_mips(vec![Vec4::ONE; 5 * 3], 5, 3, TextureUsage::Data);
+        let sizes: Vec<usize> = levels.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![15, 2, 1]);
+        assert!(levels.iter().flatten().all(|t| t.abs_diff_eq(Vec4::ONE, 1e-6)));
+    }
+
+    #[test]
+    fn odd_sizes_weigh_every_texel_equally() {
+        let row = vec![Vec4::splat(0.0), Vec4::splat(3.0), Vec4::splat(6.0)];
+        let levels = generate_mips(row, 3, 1, TextureUsage::Data);
+        // Averaging all three texels, not just the first two.
+        assert!(levels[1][0].abs_diff_eq(Vec4::splat(3.0), 1e-5));
+    }
+
+    #[test]
+    fn colour_is_filtered_in_linear_space() {
+        // Black and white averaged in linear space is brighter than sRGB 128.
+        let image = CpuImage::rgba8(2, 1, vec![0, 0, 0, 255, 255, 255, 255, 255]);
+        let color = process_image(image.clone(), TextureUsage::Color, &TextureSettings::default());
+        assert_eq!((color.mip_levels, color.color_space), (2, ColorSpace::Srgb));
+        assert_eq!(color.level(1), &[188, 188, 188, 255]);
+
+        let data = process_image(image, TextureUsage::Data, &TextureSettings::default());
+        assert_eq!(data.color_space, ColorSpace::Linear);
+        assert_eq!(data.level(1), &[128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn transparent_texels_do_not_bleed() {
+        let image = CpuImage::rgba8(2, 1, vec![255, 0, 0, 255, 0, 255, 0, 0]);
+        let color = process_image(image, TextureUsage::Color, &TextureSettings::default());
+        assert_eq!(color.level(1), &[255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn normals_stay_unit_length() {
+        let image = CpuImage::rgba8(2, 1, vec![255, 128, 128, 255, 128, 255, 128, 255]);
+        let normal = process_image(image, TextureUsage::Normal, &TextureSettings::default());
+        let texel = normal.level(1);
+        let n = Vec3::new(texel[0] as f32, texel[1] as f32, texel[2] as f32) / 255.0 * 2.0 - Vec3::ONE;
+        assert!((n.length() - 1.0).abs() < 0.02);
+    }
+}
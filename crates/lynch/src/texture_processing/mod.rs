@@ -0,0 +1,331 @@
+//! CPU side preparation of images before they are uploaded: decoding 8 bit,
+//! 16 bit and floating point files, mip chain generation, and picking up
+//! pre-compressed KTX2/DDS copies. Which colour space an image is stored in
+//! depends on the material slot it is used for, see [`TextureUsage`].
+
+mod container;
+mod decode;
+mod mips;
+
+use std::{
+    fmt::{Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+use ash::vk;
+
+pub use container::{load_dds, load_ktx2, parse_dds, parse_ktx2};
+pub use decode::{decode_image, linear_to_srgb, srgb_to_linear};
+pub use mips::{generate_mips, mip_count, process_image};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureFormat {
+    Rgba8,
+    Rgba16,
+    Rgba32Float,
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc6hUfloat,
+    Bc6hSfloat,
+    Bc7,
+}
+
+impl TextureFormat {
+    pub fn is_compressed(self) -> bool {
+        !matches!(self, TextureFormat::Rgba8 | TextureFormat::Rgba16 | TextureFormat::Rgba32Float)
+    }
+
+    /// Bytes per texel, or per 4x4 block for compressed formats.
+    pub fn block_size(self) -> usize {
+        match self {
+            TextureFormat::Rgba8 => 4,
+            TextureFormat::Rgba16 | TextureFormat::Bc1 | TextureFormat::Bc4 => 8,
+            _ => 16,
+        }
+    }
+
+    /// Bytes taken by a `width` x `height` level.
+    pub fn level_size(self, width: u32, height: u32) -> usize {
+        let (width, height) = match self.is_compressed() {
+            true => ((width as usize).div_ceil(4), (height as usize).div_ceil(4)),
+            false => (width as usize, height as usize),
+        };
+        width * height * self.block_size()
+    }
+
+    /// Whether the GPU can decode sRGB when sampling the format.
+    pub fn has_srgb(self) -> bool {
+        matches!(
+            self,
+            TextureFormat::Rgba8 | TextureFormat::Bc1 | TextureFormat::Bc2 | TextureFormat::Bc3 | TextureFormat::Bc7
+        )
+    }
+
+    pub fn vk_format(self, color_space: ColorSpace) -> vk::Format {
+        let srgb = color_space == ColorSpace::Srgb && self.has_srgb();
+        match (self, srgb) {
+            (TextureFormat::Rgba8, false) => vk::Format::R8G8B8A8_UNORM,
+            (TextureFormat::Rgba8, true) => vk::Format::R8G8B8A8_SRGB,
+            (TextureFormat::Rgba16, _) => vk::Format::R16G16B16A16_UNORM,
+            (TextureFormat::Rgba32Float, _) => vk::Format::R32G32B32A32_SFLOAT,
+            (TextureFormat::Bc1, false) => vk::Format::BC1_RGBA_UNORM_BLOCK,
+            (TextureFormat::Bc1, true) => vk::Format::BC1_RGBA_SRGB_BLOCK,
+            (TextureFormat::Bc2, false) => vk::Format::BC2_UNORM_BLOCK,
+            (TextureFormat::Bc2, true) => vk::Format::BC2_SRGB_BLOCK,
+            (TextureFormat::Bc3, false) => vk::Format::BC3_UNORM_BLOCK,
+            (TextureFormat::Bc3, true) => vk::Format::BC3_SRGB_BLOCK,
+            (TextureFormat::Bc4, _) => vk::Format::BC4_UNORM_BLOCK,
+            (TextureFormat::Bc5, _) => vk::Format::BC5_UNORM_BLOCK,
+            (TextureFormat::Bc6hUfloat, _) => vk::Format::BC6H_UFLOAT_BLOCK,
+            (TextureFormat::Bc6hSfloat, _) => vk::Format::BC6H_SFLOAT_BLOCK,
+            (TextureFormat::Bc7, false) => vk::Format::BC7_UNORM_BLOCK,
+            (TextureFormat::Bc7, true) => vk::Format::BC7_SRGB_BLOCK,
+        }
+    }
+}
+
+/// How the stored values of an image are encoded.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    Linear,
+    Srgb,
+}
+
+/// What a texture is sampled as, deciding its colour space and how its
+/// mips are filtered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureUsage {
+    /// Base colour and other authored colours, stored as sRGB.
+    Color,
+    /// Roughness, occlusion, masks and other linear values.
+    Data,
+    /// Tangent space normals, renormalized in every mip.
+    Normal,
+}
+
+impl TextureUsage {
+    pub fn color_space(self) -> ColorSpace {
+        match self {
+            TextureUsage::Color => ColorSpace::Srgb,
+            TextureUsage::Data | TextureUsage::Normal => ColorSpace::Linear,
+        }
+    }
+}
+
+/// When to load a pre-compressed copy instead of the image itself.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompressionPolicy {
+    /// Always decode the source image.
+    Never,
+    /// Use a `.ktx2` or `.dds` file with the same name next to the source
+    /// image if there is one and the device samples BC formats.
+    PreferCompressed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TextureSettings {
+    /// Builds a full mip chain for images that come without one.
+    pub generate_mips: bool,
+    pub compression: CompressionPolicy,
+    /// Whether the device samples BC formats, compressed files are skipped
+    /// without.
+    pub bc_supported: bool,
+}
+
+impl Default for TextureSettings {
+    fn default() -> Self {
+        TextureSettings {
+            generate_mips: true,
+            compression: CompressionPolicy::PreferCompressed,
+            bc_supported: false,
+        }
+    }
+}
+
+/// Decoded image with every mip level, largest first, packed one after
+/// another in `pixels`.
+#[derive(Clone, Debug)]
+pub struct CpuImage {
+    pub width: u32,
+    pub height: u32,
+    pub format: TextureFormat,
+    pub color_space: ColorSpace,
+    pub mip_levels: u32,
+    pub pixels: Vec<u8>,
+    /// File the image was read from, `None` for embedded images.
+    pub source: Option<PathBuf>,
+}
+
+impl CpuImage {
+    /// Single level RGBA8 image holding linear values.
+    pub fn rgba8(width: u32, height: u32, pixels: Vec<u8>) -> Self {
+        CpuImage {
+            width,
+            height,
+            format: TextureFormat::Rgba8,
+            color_space: ColorSpace::Linear,
+            mip_levels: 1,
+            pixels,
+            source: None,
+        }
+    }
+
+    pub fn level_extent(&self, level: u32) -> (u32, u32) {
+        let shrink = |size: u32| size.checked_shr(level).unwrap_or(0).max(1);
+        (shrink(self.width), shrink(self.height))
+    }
+
+    /// Byte offset of every level in `pixels`.
+    pub fn level_offsets(&self) -> Vec<usize> {
+        let mut offset = 0;
+        (0..self.mip_levels)
+            .map(|level| {
+                let (width, height) = self.level_extent(level);
+                let start = offset;
+                offset += self.format.level_size(width, height);
+                start
+            })
+            .collect()
+    }
+
+    pub fn level(&self, level: u32) -> &[u8] {
+        let start = self.level_offsets()[level as usize];
+        let (width, height) = self.level_extent(level);
+        &self.pixels[start..start + self.format.level_size(width, height)]
+    }
+
+    pub fn vk_format(&self) -> vk::Format {
+        self.format.vk_format(self.color_space)
+    }
+}
+
+#[derive(Debug)]
+pub enum TextureError {
+    Io(std::io::Error),
+    Decode(image::ImageError),
+    /// The KTX2/DDS file is truncated or malformed.
+    InvalidContainer(&'static str),
+    UnsupportedFormat(String),
+}
+
+impl Display for TextureError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            TextureError::Io(e) => write!(f, "Could not read texture: {}", e),
+            TextureError::Decode(e) => write!(f, "Could not decode texture: {}", e),
+            TextureError::InvalidContainer(reason) => write!(f, "Invalid texture container: {}", reason),
+            TextureError::UnsupportedFormat(format) => write!(f, "Unsupported texture format {}", format),
+        }
+    }
+}
+
+impl std::error::Error for TextureError {}
+
+impl From<std::io::Error> for TextureError {
+    fn from(e: std::io::Error) -> Self {
+        TextureError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for TextureError {
+    fn from(e: image::ImageError) -> Self {
+        TextureError::Decode(e)
+    }
+}
+
+/// Paths `settings` would look for a pre-compressed copy of `source` at,
+/// whether or not they exist.
+pub fn compressed_sibling_paths(source: &Path, settings: &TextureSettings) -> Vec<PathBuf> {
+    if settings.compression == CompressionPolicy::Never || !settings.bc_supported {
+        return vec![];
+    }
+    ["ktx2", "dds"]
+        .into_iter()
+        .map(|extension| source.with_extension(extension))
+        .filter(|path| path != source)
+        .collect()
+}
+
+/// Pre-compressed copy of `source` to load under `settings`, if any.
+pub fn compressed_sibling(source: &Path, settings: &TextureSettings) -> Option<PathBuf> {
+    compressed_sibling_paths(source, settings)
+        .into_iter()
+        .find(|path| path.is_file())
+}
+
+/// Prepares a decoded image to be sampled as `usage`, swapping in a
+/// compressed copy of its source file when `settings` allow.
+pub fn prepare_image(image: CpuImage, usage: TextureUsage, settings: &TextureSettings) -> CpuImage {
+    if let Some(compressed) = image.source.as_deref().and_then(|source| compressed_sibling(source, settings)) {
+        match load_container(&compressed, usage, settings) {
+            Ok(compressed) => return compressed,
+            Err(err) => log::warn!("Ignoring {}: {}", compressed.display(), err),
+        }
+    }
+    process_image(image, usage, settings)
+}
+
+/// Loads the image or KTX2/DDS file at `path` to be sampled as `usage`.
+pub fn load_texture(path: &Path, usage: TextureUsage, settings: &TextureSettings) -> Result<CpuImage, TextureError> {
+    if container_extension(path).is_some() {
+        return load_container(path, usage, settings);
+    }
+    Ok(prepare_image(decode_image(path)?, usage, settings))
+}
+
+fn container_extension(path: &Path) -> Option<&'static str> {
+    let extension = path.extension()?.to_str()?;
+    ["ktx2", "dds"]
+        .into_iter()
+        .find(|container| extension.eq_ignore_ascii_case(container))
+}
+
+/// Reads a KTX2 or DDS file, stored in the colour space of `usage`.
+fn load_container(path: &Path, usage: TextureUsage, settings: &TextureSettings) -> Result<CpuImage, TextureError> {
+    let mut image = match container_extension(path) {
+        Some("ktx2") => load_ktx2(path)?,
+        Some(_) => load_dds(path)?,
+        None => return Err(TextureError::UnsupportedFormat(path.display().to_string())),
+    };
+    if image.format.is_compressed() && !settings.bc_supported {
+        return Err(TextureError::UnsupportedFormat(format!("{:?} on this device", image.format)));
+    }
+    image.color_space = usage.color_space();
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_sizes_round_up_to_blocks() {
+        assert_eq!(TextureFormat::Rgba8.level_size(3, 5), 60);
+        assert_eq!(TextureFormat::Rgba32Float.level_size(2, 2), 64);
+        assert_eq!(TextureFormat::Bc1.level_size(5, 4), 16);
+        assert_eq!(TextureFormat::Bc7.level_size(1, 1), 16);
+
+        let image = CpuImage {
+            mip_levels: 3,
+            pixels: vec![0; 16 * 4 + 4 * 4 + 4],
+            ..CpuImage::rgba8(4, 4, vec![])
+        };
+        assert_eq!(image.level_offsets(), vec![0, 64, 80]);
+        assert_eq!(image.level(2).len(), 4);
+    }
+
+    #[test]
+    fn formats_follow_the_usage_colour_space() {
+        let color = TextureUsage::Color.color_space();
+        let data = TextureUsage::Data.color_space();
+        assert_eq!(TextureFormat::Rgba8.vk_format(color), vk::Format::R8G8B8A8_SRGB);
+        assert_eq!(TextureFormat::Rgba8.vk_format(data), vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(TextureFormat::Bc7.vk_format(color), vk::Format::BC7_SRGB_BLOCK);
+        // Formats without an sRGB variant are stored linear.
+        assert_eq!(TextureFormat::Bc5.vk_format(color), vk::Format::BC5_UNORM_BLOCK);
+        assert_eq!(TextureFormat::Rgba16.vk_format(color), vk::Format::R16G16B16A16_UNORM);
+    }
+}
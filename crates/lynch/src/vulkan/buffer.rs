@@ -10,6 +10,7 @@ pub struct Buffer {
     pub memory_req: vk::MemoryRequirements,
     pub memory_location: MemoryLocation,
     pub size: u64,
+    pub usage: vk::BufferUsageFlags,
     pub debug_name: String,
     device: Arc<Device>,
 }
@@ -59,12 +60,63 @@ impl Buffer {
                 memory_req: buffer_memory_req,
                 memory_location,
                 size,
+                usage: usage_flags,
                 debug_name: debug_name.unwrap_or_else(|| String::from("un_buffer")),
                 device,
             }
         }
     }
 
+    /// A device local buffer that gets memory later, see
+    /// [`Buffer::bind_memory`].
+    pub fn new_unbound(
+        device: Arc<Device>,
+        size: u64,
+        usage_flags: vk::BufferUsageFlags,
+        debug_name: &str,
+    ) -> Buffer {
+        let buffer_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage_flags)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        unsafe {
+            let buffer = device
+                .ash_device
+                .create_buffer(&buffer_info, None)
+                .expect("Failed to create buffer");
+
+            Buffer {
+                vk_buffer: buffer,
+                allocation: Allocation::default(),
+                memory_req: device.ash_device.get_buffer_memory_requirements(buffer),
+                memory_location: MemoryLocation::GpuOnly,
+                size,
+                usage: usage_flags,
+                debug_name: debug_name.to_string(),
+                device,
+            }
+        }
+    }
+
+    pub fn bind_memory(&mut self, memory: vk::DeviceMemory, offset: u64) {
+        unsafe {
+            self.device
+                .ash_device
+                .bind_buffer_memory(self.vk_buffer, memory, offset)
+                .expect("Unable to bind device memory to buffer");
+        }
+        let debug_name = self.debug_name.clone();
+        self.set_debug_name(&debug_name);
+    }
+
+    /// Replaces a buffer made by [`Buffer::new_unbound`] with an unbound
+    /// copy, as buffers cannot be bound to memory twice.
+    pub fn unbind(&mut self) {
+        self.clean_vk_resources();
+        *self = Buffer::new_unbound(self.device.clone(), self.size, self.usage, &self.debug_name);
+    }
+
     pub fn new<T: Copy>(
         device: Arc<Device>,
         initial_data: Option<&[T]>,
@@ -144,6 +196,18 @@ impl Buffer {
             }
         }
     }
+    /// Writes `data` over the elements starting at `index` of a host visible
+    /// buffer, leaving the rest untouched.
+    pub fn update_memory_at<T: Copy>(&mut self, index: usize, data: &[T]) {
+        assert!(self.memory_location != MemoryLocation::GpuOnly, "buffer is not host visible");
+        let offset = index * mem::size_of::<T>();
+        let src_bytes = data.len() * mem::size_of::<T>();
+        assert!(offset + src_bytes <= self.allocation.size() as usize);
+        unsafe {
+            let dst = self.allocation.mapped_ptr().unwrap().as_ptr() as *mut u8;
+            copy_nonoverlapping(data.as_ptr() as *const u8, dst.add(offset), src_bytes);
+        }
+    }
     pub fn copy_to_buffer(&self, cb: vk::CommandBuffer, dst: &Buffer) {
         let buffer_copy_regions = BufferCopy::builder()
             .size(self.size)
@@ -162,18 +226,33 @@ impl Buffer {
     }
 
     pub fn copy_to_image(&self, cb: vk::CommandBuffer, image: &Image) {
-        let buffer_copy_regions = vk::BufferImageCopy::builder()
-            .image_subresource(
-                vk::ImageSubresourceLayers::builder()
-                    .aspect_mask(image.desc.aspect_flags)
-                    .layer_count(1)
-                    .build(),
-            )
-            .image_extent(vk::Extent3D {
-                width: image.width(),
-                height: image.height(),
-                depth: 1,
-            });
+        self.copy_levels_to_image(cb, image, &[0]);
+    }
+
+    /// Copies mip level `i` of `image` from `level_offsets[i]` bytes into
+    /// the buffer.
+    pub fn copy_levels_to_image(&self, cb: vk::CommandBuffer, image: &Image, level_offsets: &[usize]) {
+        let buffer_copy_regions: Vec<vk::BufferImageCopy> = level_offsets
+            .iter()
+            .enumerate()
+            .map(|(level, offset)| {
+                vk::BufferImageCopy::builder()
+                    .buffer_offset(*offset as u64)
+                    .image_subresource(
+                        vk::ImageSubresourceLayers::builder()
+                            .aspect_mask(image.desc.aspect_flags)
+                            .mip_level(level as u32)
+                            .layer_count(1)
+                            .build(),
+                    )
+                    .image_extent(vk::Extent3D {
+                        width: (image.width() >> level).max(1),
+                        height: (image.height() >> level).max(1),
+                        depth: 1,
+                    })
+                    .build()
+            })
+            .collect();
 
         unsafe {
             self.device.ash_device.cmd_copy_buffer_to_image(
@@ -181,7 +260,7 @@ impl Buffer {
                 self.vk_buffer,
                 image.image,
                 vk::ImageLayout::TRANSFER_DST_OPTIMAL,
-                &[buffer_copy_regions.build()],
+                &buffer_copy_regions,
             );
         }
     }
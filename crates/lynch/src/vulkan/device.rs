@@ -7,6 +7,8 @@ use gpu_allocator::AllocatorDebugSettings;
 
 use std::sync::{Arc, Mutex};
 
+use super::pipeline_cache::{CacheIdentity, PipelineCache, PIPELINE_CACHE_FILE};
+use super::spirv_cache::{SpirvCache, SPIRV_CACHE_DIR};
 use super::swapchain::SwapchainSupportDetails;
 use super::Image;
 
@@ -17,13 +19,22 @@ pub struct Device {
     pub cmd_pool: vk::CommandPool,
     pub setup_cmd_buf: vk::CommandBuffer,
     pub device_memory_properties: vk::PhysicalDeviceMemoryProperties,
+    /// Core features enabled on the device, every supported one is.
+    pub features: vk::PhysicalDeviceFeatures,
     pub queue_family_index: u32,
     pub gpu_allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
     pub debug_utils: Option<ash::extensions::ext::DebugUtils>,
+    /// Pipelines compiled by earlier runs, saved when the device is dropped.
+    pub pipeline_cache: PipelineCache,
+    pub spirv_cache: SpirvCache,
 }
 
 impl Drop for Device {
     fn drop(&mut self) {
+        if let Err(err) = self.pipeline_cache.save(&self.ash_device) {
+            log::warn!("Failed to save pipeline cache: {}", err);
+        }
+        self.pipeline_cache.destroy(&self.ash_device);
         unsafe {
             self.ash_device.destroy_device(None);
             self.ash_device.device_wait_idle().unwrap()
@@ -98,6 +109,8 @@ impl Device {
 
             instance.get_physical_device_features2(physical_device, &mut features2);
 
+            let features = features2.features;
+
             let queue_priorities = [1.0];
             let queue_info = vk::DeviceQueueCreateInfo::builder()
                 .queue_family_index(queue_family_index)
@@ -136,7 +149,11 @@ impl Device {
             })
             .expect("Failed to create GPU allocator");
 
-            let _properties = instance.get_physical_device_properties(physical_device);
+            let pipeline_cache = PipelineCache::load(
+                &device,
+                CacheIdentity::new(instance, physical_device),
+                std::path::Path::new(PIPELINE_CACHE_FILE),
+            );
 
             Device {
                 ash_device: device,
@@ -144,10 +161,13 @@ impl Device {
                 queue: present_queue,
                 queue_family_index,
                 device_memory_properties,
+                features,
                 cmd_pool,
                 setup_cmd_buf,
                 gpu_allocator: Arc::new(Mutex::new(gpu_allocator)),
                 debug_utils,
+                pipeline_cache,
+                spirv_cache: SpirvCache::new(SPIRV_CACHE_DIR),
             }
         }
     }
@@ -396,3 +416,36 @@ pub fn image_pipeline_barrier(
     next_access
 }
 
+/// Makes `image`, placed in memory other resources used before, wait for
+/// their `previous_accesses` and discards whatever they left in it.
+pub fn image_aliasing_barrier(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: &Image,
+    previous_accesses: &[vk_sync::AccessType],
+    next_access: vk_sync::AccessType,
+) -> vk_sync::AccessType {
+    vk_sync::cmd::pipeline_barrier(
+        &device.ash_device,
+        command_buffer,
+        None,
+        &[],
+        &[vk_sync::ImageBarrier {
+            previous_accesses,
+            next_accesses: &[next_access],
+            previous_layout: vk_sync::ImageLayout::Optimal,
+            next_layout: vk_sync::ImageLayout::Optimal,
+            discard_contents: true,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            image: image.image,
+            range: vk::ImageSubresourceRange::builder()
+                .aspect_mask(image.desc.aspect_flags)
+                .layer_count(vk::REMAINING_ARRAY_LAYERS)
+                .level_count(vk::REMAINING_MIP_LEVELS)
+                .build(),
+        }],
+    );
+
+    next_access
+}
@@ -93,6 +93,27 @@ impl ImageDesc {
         self.mip_levels = mip_levels;
         self
     }
+
+    /// View type of the whole image. An array keeps its array view type
+    /// even with a single layer.
+    pub fn view_type(&self) -> vk::ImageViewType {
+        match self.image_type {
+            ImageType::Tex2d if self.array_layers == 1 => vk::ImageViewType::TYPE_2D,
+            ImageType::Tex2dArray => vk::ImageViewType::TYPE_2D_ARRAY,
+            ImageType::Cube => vk::ImageViewType::CUBE,
+            _ => unimplemented!(),
+        }
+    }
+
+    /// View type of the single-layer views an array or cube image gets, so
+    /// each layer can be attached on its own.
+    pub fn layer_view_type(&self) -> Option<vk::ImageViewType> {
+        match self.image_type {
+            ImageType::Tex2dArray => Some(vk::ImageViewType::TYPE_2D_ARRAY),
+            ImageType::Cube => Some(vk::ImageViewType::TYPE_2D),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -121,39 +142,10 @@ impl Image {
         }
     }
     pub fn new_from_desc(device: Arc<Device>, desc: ImageDesc) -> Image {
+        let mut image = Image::new_unbound(device.clone(), desc);
         unsafe {
-            let initial_layout = vk::ImageLayout::UNDEFINED;
-            let image_create_info = vk::ImageCreateInfo {
-                image_type: vk::ImageType::TYPE_2D,
-                format: desc.format,
-                extent: vk::Extent3D {
-                    width: desc.width,
-                    height: desc.height,
-                    depth: 1,
-                },
-                mip_levels: desc.mip_levels,
-                array_layers: desc.array_layers,
-                samples: vk::SampleCountFlags::TYPE_1,
-                tiling: vk::ImageTiling::OPTIMAL,
-                usage: desc.usage,
-                sharing_mode: vk::SharingMode::EXCLUSIVE,
-                initial_layout,
-                flags: if desc.image_type == ImageType::Cube
-                    || desc.image_type == ImageType::CubeArray
-                {
-                    vk::ImageCreateFlags::CUBE_COMPATIBLE
-                } else {
-                    vk::ImageCreateFlags::empty()
-                },
-                ..Default::default()
-            };
-            let image = device
-                .ash_device
-                .create_image(&image_create_info, None)
-                .expect("Unable to create image");
-
             // Memory allocation
-            let image_memory_req = device.ash_device.get_image_memory_requirements(image);
+            let image_memory_req = image.memory_requirements();
             let image_memory_index = device
                 .find_memory_type_index(&image_memory_req, vk::MemoryPropertyFlags::DEVICE_LOCAL)
                 .expect("Unable to find suitable memory index for image");
@@ -167,93 +159,85 @@ impl Image {
                 .allocate_memory(&image_allocate_info, None)
                 .expect("Unable to allocate image device memory");
 
-            device
-                .ash_device
-                .bind_image_memory(image, device_memory, 0)
-                .expect("Unable to bind device memory to image");
+            image.bind_memory(device_memory, 0);
+        }
+        image
+    }
 
-            let view_type = if desc.image_type == ImageType::Tex2d && desc.array_layers == 1 {
-                vk::ImageViewType::TYPE_2D
-            } else if desc.image_type == ImageType::Tex2dArray && desc.array_layers > 1 {
-                vk::ImageViewType::TYPE_2D_ARRAY
-            } else if desc.image_type == ImageType::Cube {
-                vk::ImageViewType::CUBE
+    /// Creates the image without memory or views, see [`Image::bind_memory`].
+    pub fn new_unbound(device: Arc<Device>, desc: ImageDesc) -> Image {
+        let initial_layout = vk::ImageLayout::UNDEFINED;
+        let image_create_info = vk::ImageCreateInfo {
+            image_type: vk::ImageType::TYPE_2D,
+            format: desc.format,
+            extent: vk::Extent3D {
+                width: desc.width,
+                height: desc.height,
+                depth: 1,
+            },
+            mip_levels: desc.mip_levels,
+            array_layers: desc.array_layers,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage: desc.usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            initial_layout,
+            flags: if desc.image_type == ImageType::Cube
+                || desc.image_type == ImageType::CubeArray
+            {
+                vk::ImageCreateFlags::CUBE_COMPATIBLE
             } else {
-                unimplemented!()
-            };
-
-            let image_view = Image::create_image_view(
-                &device,
-                image,
-                desc.format,
-                desc.aspect_flags,
-                view_type,
-                0,
-                desc.array_layers,
-                desc.mip_levels,
-            );
+                vk::ImageCreateFlags::empty()
+            },
+            ..Default::default()
+        };
+        let image = unsafe {
+            device
+                .ash_device
+                .create_image(&image_create_info, None)
+                .expect("Unable to create image")
+        };
 
-            let mut layer_views = vec![];
+        Image {
+            image,
+            image_view: vk::ImageView::null(),
+            layer_views: vec![],
+            device_memory: vk::DeviceMemory::null(),
+            current_layout: initial_layout,
+            desc,
+            debug_name: "unnamed_image".to_string(),
+            device,
+        }
+    }
 
-            if desc.array_layers > 1 {
-                for layer in 0..desc.array_layers {
-                    let view = Image::create_image_view(
-                        &device,
-                        image,
-                        desc.format,
-                        desc.aspect_flags,
-                        if desc.image_type == ImageType::Cube {
-                            vk::ImageViewType::TYPE_2D
-                        } else {
-                            view_type
-                        },
-                        layer,
-                        1,
-                        desc.mip_levels,
-                    );
-                    layer_views.push(view);
-                }
-            }
+    pub fn memory_requirements(&self) -> vk::MemoryRequirements {
+        unsafe { self.device.ash_device.get_image_memory_requirements(self.image) }
+    }
 
-            Image {
-                image,
-                image_view,
-                layer_views,
-                device_memory,
-                current_layout: initial_layout,
-                desc,
-                debug_name: "unnamed_image".to_string(),
-                device,
-            }
+    /// Binds an image made by [`Image::new_unbound`] to `offset` in `memory`
+    /// and creates its views.
+    pub fn bind_memory(&mut self, memory: vk::DeviceMemory, offset: u64) {
+        let (device, desc, image) = (&self.device, self.desc, self.image);
+        unsafe {
+            device
+                .ash_device
+                .bind_image_memory(image, memory, offset)
+                .expect("Unable to bind device memory to image");
         }
+
+        let (image_view, layer_views) = Image::create_views(device, image, &desc);
+        self.image_view = image_view;
+        self.layer_views = layer_views;
+        self.device_memory = memory;
     }
 
     pub fn new_from_handle(device: Arc<Device>, image: vk::Image, desc: ImageDesc) -> Image {
-        let view_type = if desc.image_type == ImageType::Tex2d && desc.array_layers == 1 {
-            vk::ImageViewType::TYPE_2D
-        } else if desc.image_type == ImageType::Tex2dArray && desc.array_layers > 1 {
-            vk::ImageViewType::TYPE_2D_ARRAY
-        } else if desc.image_type == ImageType::Cube {
-            vk::ImageViewType::CUBE
-        } else {
-            unimplemented!()
-        };
-
-        let image_view = Image::create_image_view(
-            &device,
-            image,
-            desc.format,
-            desc.aspect_flags,
-            view_type,
-            0,
-            1,
-            desc.mip_levels,
-        );
+        let (image_view, layer_views) = Image::create_views(&device, image, &desc);
 
         Image {
             image,
             image_view,
-            layer_views: vec![],
+            layer_views,
             device_memory: vk::DeviceMemory::null(),
             current_layout: vk::ImageLayout::UNDEFINED,
             desc,
@@ -262,6 +246,43 @@ impl Image {
         }
     }
 
+    /// The view of the whole image, plus one view per layer of an array or
+    /// cube image.
+    fn create_views(
+        device: &Device,
+        image: vk::Image,
+        desc: &ImageDesc,
+    ) -> (vk::ImageView, Vec<vk::ImageView>) {
+        let image_view = Image::create_image_view(
+            device,
+            image,
+            desc.format,
+            desc.aspect_flags,
+            desc.view_type(),
+            0,
+            desc.array_layers,
+            desc.mip_levels,
+        );
+        let layer_views = match desc.layer_view_type() {
+            Some(layer_view_type) => (0..desc.array_layers)
+                .map(|layer| {
+                    Image::create_image_view(
+                        device,
+                        image,
+                        desc.format,
+                        desc.aspect_flags,
+                        layer_view_type,
+                        layer,
+                        1,
+                        desc.mip_levels,
+                    )
+                })
+                .collect(),
+            None => vec![],
+        };
+        (image_view, layer_views)
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn create_image_view(
         device: &Device,
@@ -557,3 +578,37 @@ impl ImageCopyDescBuilder {
         self.image_copy
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shadows::ShadowSettings;
+
+    #[test]
+    fn single_cascade_shadow_map_is_an_array() {
+        let settings = ShadowSettings {
+            cascade_count: 1,
+            ..Default::default()
+        };
+        let desc = ImageDesc::new_2d_array(
+            settings.cascade_resolution,
+            settings.cascade_resolution,
+            settings.cascades(),
+            vk::Format::D32_SFLOAT,
+        );
+        assert_eq!(desc.array_layers, 1);
+        assert_eq!(desc.view_type(), vk::ImageViewType::TYPE_2D_ARRAY);
+        assert_eq!(desc.layer_view_type(), Some(vk::ImageViewType::TYPE_2D_ARRAY));
+    }
+
+    #[test]
+    fn only_layered_images_get_layer_views() {
+        let desc = ImageDesc::new_2d(4, 4, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(desc.view_type(), vk::ImageViewType::TYPE_2D);
+        assert_eq!(desc.layer_view_type(), None);
+
+        let desc = ImageDesc::new_cubemap(4, 4, vk::Format::R8G8B8A8_UNORM);
+        assert_eq!(desc.view_type(), vk::ImageViewType::CUBE);
+        assert_eq!(desc.layer_view_type(), Some(vk::ImageViewType::TYPE_2D));
+    }
+}
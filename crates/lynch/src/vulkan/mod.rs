@@ -5,8 +5,12 @@ mod descriptor;
 mod device;
 mod image;
 mod pipeline;
+pub mod pipeline_cache;
 pub(crate) mod render_pass;
 pub mod shader;
+pub mod shader_source;
+pub mod spirv_cache;
+mod spirv_interface;
 mod surface;
 mod swapchain;
 
@@ -15,7 +19,7 @@ pub use buffer::Buffer;
 pub use descriptor::{DescriptorIdentifier, DescriptorSet};
 pub use device::Device;
 pub use image::{Image, ImageCopyDescBuilder, ImageDesc, ImageType};
-pub use pipeline::{Pipeline, PipelineDesc, PipelineDescBuilder, PipelineType};
+pub use pipeline::{Pipeline, PipelineDesc, PipelineDescBuilder, PipelineKey, PipelineType};
 pub use render_pass::RenderPass;
 
-pub use device::{global_pipeline_barrier, image_pipeline_barrier};
+pub use device::{global_pipeline_barrier, image_aliasing_barrier, image_pipeline_barrier};
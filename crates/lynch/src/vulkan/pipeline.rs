@@ -1,14 +1,18 @@
 use ash::vk;
 use log::info;
+use std::collections::BTreeMap;
 use std::ffi::CStr;
 use std::hash::{Hash, Hasher};
 use std::io::Cursor;
+use std::path::PathBuf;
 use vulkan::shader::{create_layouts_from_reflection, create_shader_module, ShaderReflect};
 
 use crate::mesh::Primitive;
 use crate::*;
 
 use super::shader::compile_glsl_shader;
+use super::shader_source::{permutation_key, ShaderError};
+use super::spirv_cache::SpirvCache;
 use super::Device;
 
 #[derive(Clone)]
@@ -20,6 +24,20 @@ pub struct PipelineDesc {
     pub vertex_input_attribute_descriptions: Vec<vk::VertexInputAttributeDescription>,
     pub color_attachment_formats: Vec<vk::Format>,
     pub depth_stencil_attachment_format: vk::Format,
+    /// Preprocessor defines every stage is compiled with.
+    pub defines: BTreeMap<String, String>,
+}
+
+/// What a pipeline is looked up by: its shaders, the permutation key of its
+/// defines and the attachment formats it renders to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineKey {
+    pub vertex_path: Option<&'static str>,
+    pub fragment_path: Option<&'static str>,
+    pub compute_path: Option<&'static str>,
+    pub permutation: u64,
+    pub color_attachment_formats: Vec<vk::Format>,
+    pub depth_stencil_attachment_format: vk::Format,
 }
 
 pub struct PipelineDescBuilder {
@@ -33,8 +51,19 @@ pub struct Pipeline {
     pub reflection: ShaderReflect,
     pub pipeline_desc: PipelineDesc,
     pub pipeline_type: PipelineType,
+    /// The shaders and the files they include, recompiled when any of them
+    /// changes.
+    pub shader_files: Vec<PathBuf>,
 }
 
+type ShaderModules = (
+    Vec<vk::PipelineShaderStageCreateInfo>,
+    ShaderReflect,
+    vk::PipelineLayout,
+    Vec<vk::DescriptorSetLayout>,
+    Vec<PathBuf>,
+);
+
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum PipelineType {
     Graphics,
@@ -49,18 +78,13 @@ impl Default for PipelineDescBuilder {
 
 impl Hash for PipelineDesc {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.vertex_path.hash(state);
-        self.fragment_path.hash(state);
-        self.color_attachment_formats.hash(state);
-        self.depth_stencil_attachment_format.hash(state);
+        self.key().hash(state);
     }
 }
 
 impl PartialEq for PipelineDesc {
     fn eq(&self, other: &Self) -> bool {
-        self.vertex_path == other.vertex_path
-            && self.fragment_path == other.fragment_path
-            && self.compute_path == other.compute_path
+        self.key() == other.key()
     }
 }
 
@@ -82,6 +106,7 @@ impl Pipeline {
             reflection: ShaderReflect::default(),
             pipeline_desc,
             pipeline_type,
+            shader_files: vec![],
         };
 
         Self::create_pipeline(&mut pipeline, device, bindless_descriptor_set_layout)
@@ -110,27 +135,32 @@ impl Pipeline {
         bindless_descriptor_set_layout: Option<vk::DescriptorSetLayout>,
     ) -> Result<(), ()> {
         let desc = &self.pipeline_desc;
-        let (shader_stage_create_infos, reflection, pipeline_layout, descriptor_set_layouts) =
+        let (shader_stage_create_infos, reflection, pipeline_layout, descriptor_set_layouts, shader_files) =
             match self.pipeline_type {
                 PipelineType::Graphics => Self::create_graphics_shader_modules(
                     &device.device(),
                     desc.vertex_path.unwrap(),
                     desc.fragment_path.unwrap(),
+                    &desc.defines,
+                    &device.spirv_cache,
                     bindless_descriptor_set_layout,
                 ),
                 PipelineType::Compute => Self::create_compute_shader_modules(
                     &device.device(),
                     desc.compute_path.unwrap(),
+                    &desc.defines,
+                    &device.spirv_cache,
                     bindless_descriptor_set_layout,
                 ),
             }
             .map_err(|error| {
-                println!("Failed to compile shader: {:#?}", error);
+                log::error!("Failed to compile shader:\n{}", error);
             })?;
 
         let new_handle = match self.pipeline_type {
             PipelineType::Graphics => Pipeline::create_graphics_pipeline(
                 &device.ash_device,
+                device.pipeline_cache.handle,
                 shader_stage_create_infos,
                 desc.color_attachment_formats.as_slice(),
                 desc.depth_stencil_attachment_format,
@@ -139,6 +169,7 @@ impl Pipeline {
             ),
             PipelineType::Compute => Pipeline::create_compute_pipeline(
                 &device.ash_device,
+                device.pipeline_cache.handle,
                 shader_stage_create_infos,
                 pipeline_layout,
             ),
@@ -147,6 +178,7 @@ impl Pipeline {
         self.pipeline_layout = pipeline_layout;
         self.descriptor_set_layouts = descriptor_set_layouts;
         self.reflection = reflection;
+        self.shader_files = shader_files;
 
         Ok(())
     }
@@ -155,23 +187,24 @@ impl Pipeline {
         device: &ash::Device,
         vertex_shader_path: &str,
         fragment_shader_path: &str,
+        defines: &BTreeMap<String, String>,
+        spirv_cache: &SpirvCache,
         bindless_descriptor_set_layout: Option<vk::DescriptorSetLayout>,
-    ) -> Result<
-        (
-            Vec<vk::PipelineShaderStageCreateInfo>,
-            ShaderReflect,
-            vk::PipelineLayout,
-            Vec<vk::DescriptorSetLayout>,
-        ),
-        shaderc::Error,
-    > {
-        let vertex_spv_file = compile_glsl_shader(vertex_shader_path)?;
-        let fragment_spv_file = compile_glsl_shader(fragment_shader_path)?;
-
-        let vertex_spv_file = vertex_spv_file.as_binary_u8();
-        let fragment_spv_file = fragment_spv_file.as_binary_u8();
-
-        let reflection = ShaderReflect::new(&[vertex_spv_file, fragment_spv_file]);
+    ) -> Result<ShaderModules, ShaderError> {
+        let (vertex_spv_file, vertex_source) = compile_glsl_shader(vertex_shader_path, defines, spirv_cache)?;
+        let (fragment_spv_file, fragment_source) =
+            compile_glsl_shader(fragment_shader_path, defines, spirv_cache)?;
+        let mut shader_files = vertex_source.files;
+        for file in fragment_source.files {
+            if !shader_files.contains(&file) {
+                shader_files.push(file);
+            }
+        }
+
+        let vertex_spv_file = vertex_spv_file.as_slice();
+        let fragment_spv_file = fragment_spv_file.as_slice();
+
+        let reflection = ShaderReflect::new(&[vertex_spv_file, fragment_spv_file])?;
 
         let (pipeline_layout, descriptor_set_layouts, _) =
             create_layouts_from_reflection(device, &reflection, bindless_descriptor_set_layout);
@@ -203,11 +236,13 @@ impl Pipeline {
             reflection,
             pipeline_layout,
             descriptor_set_layouts,
+            shader_files,
         ))
     }
 
     fn create_graphics_pipeline(
         device: &ash::Device,
+        pipeline_cache: vk::PipelineCache,
         shader_stage_create_infos: Vec<vk::PipelineShaderStageCreateInfo>,
         color_attachment_formats: &[vk::Format],
         depth_stencil_attachment_format: vk::Format,
@@ -302,7 +337,7 @@ impl Pipeline {
         let graphics_pipelines = unsafe {
             device
                 .create_graphics_pipelines(
-                    vk::PipelineCache::null(),
+                    pipeline_cache,
                     &[graphic_pipeline_info.build()],
                     None,
                 )
@@ -315,20 +350,14 @@ impl Pipeline {
     fn create_compute_shader_modules(
         device: &ash::Device,
         compute_shader_path: &str,
+        defines: &BTreeMap<String, String>,
+        spirv_cache: &SpirvCache,
         bindless_descriptor_set_layout: Option<vk::DescriptorSetLayout>,
-    ) -> Result<
-        (
-            Vec<vk::PipelineShaderStageCreateInfo>,
-            ShaderReflect,
-            vk::PipelineLayout,
-            Vec<vk::DescriptorSetLayout>,
-        ),
-        shaderc::Error,
-    > {
-        let compute_spv_file = compile_glsl_shader(compute_shader_path)?;
-        let compute_spv_file = compute_spv_file.as_binary_u8();
-
-        let reflection = ShaderReflect::new(&[compute_spv_file]);
+    ) -> Result<ShaderModules, ShaderError> {
+        let (compute_spv_file, compute_source) = compile_glsl_shader(compute_shader_path, defines, spirv_cache)?;
+        let compute_spv_file = compute_spv_file.as_slice();
+
+        let reflection = ShaderReflect::new(&[compute_spv_file])?;
 
         let (pipeline_layout, descriptor_set_layouts, _) =
             create_layouts_from_reflection(device, &reflection, bindless_descriptor_set_layout);
@@ -350,11 +379,13 @@ impl Pipeline {
             reflection,
             pipeline_layout,
             descriptor_set_layouts,
+            compute_source.files,
         ))
     }
 
     fn create_compute_pipeline(
         device: &ash::Device,
+        pipeline_cache: vk::PipelineCache,
         shader_stage_create_infos: Vec<vk::PipelineShaderStageCreateInfo>,
         pipeline_layout: vk::PipelineLayout,
     ) -> vk::Pipeline {
@@ -365,7 +396,7 @@ impl Pipeline {
 
         let compute_pipelines = unsafe {
             device
-                .create_compute_pipelines(vk::PipelineCache::null(), &[create_info], None)
+                .create_compute_pipelines(pipeline_cache, &[create_info], None)
                 .expect("Unable to create compute pipeline")
         };
 
@@ -377,6 +408,23 @@ impl PipelineDesc {
     pub fn builder() -> PipelineDescBuilder {
         PipelineDescBuilder::new()
     }
+
+    /// Tells the variants of a pipeline compiled with different defines
+    /// apart.
+    pub fn permutation_key(&self) -> u64 {
+        permutation_key(&self.defines)
+    }
+
+    pub fn key(&self) -> PipelineKey {
+        PipelineKey {
+            vertex_path: self.vertex_path,
+            fragment_path: self.fragment_path,
+            compute_path: self.compute_path,
+            permutation: self.permutation_key(),
+            color_attachment_formats: self.color_attachment_formats.clone(),
+            depth_stencil_attachment_format: self.depth_stencil_attachment_format,
+        }
+    }
 }
 
 impl PipelineDescBuilder {
@@ -390,6 +438,7 @@ impl PipelineDescBuilder {
                 vertex_input_attribute_descriptions: Vec::new(),
                 color_attachment_formats: Vec::new(),
                 depth_stencil_attachment_format: vk::Format::UNDEFINED,
+                defines: BTreeMap::new(),
             },
         }
     }
@@ -409,6 +458,13 @@ impl PipelineDescBuilder {
         self
     }
 
+    /// Defines `name` as `value` in every stage. Each set of defines is a
+    /// separate pipeline, compiled when a pass first uses it.
+    pub fn define(mut self, name: &str, value: impl ToString) -> Self {
+        self.descriptor.defines.insert(name.to_owned(), value.to_string());
+        self
+    }
+
     pub fn vertex_input_binding_descriptions(
         mut self,
         descriptions: Vec<vk::VertexInputBindingDescription>,
@@ -451,3 +507,39 @@ impl PipelineDescBuilder {
         self.descriptor
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pipelines_are_keyed_by_shaders_defines_and_formats() {
+        let desc = || PipelineDesc::builder().vertex_path("a.vert").fragment_path("a.frag");
+        let pcf = desc().define("SHADOW_FILTER", 1).define("SUN_SHADOWS", 1).build();
+
+        let reordered = desc().define("SUN_SHADOWS", 1).define("SHADOW_FILTER", 1).build();
+        assert_eq!(pcf.key(), reordered.key());
+        let formats = desc()
+            .define("SHADOW_FILTER", 1)
+            .define("SUN_SHADOWS", 1)
+            .color_attachment_formats(vec![vk::Format::R8G8B8A8_UNORM])
+            .build();
+        assert_ne!(pcf.key(), formats.key());
+        let depth = desc()
+            .define("SHADOW_FILTER", 1)
+            .define("SUN_SHADOWS", 1)
+            .depth_stencil_attachment_format(vk::Format::D32_SFLOAT)
+            .build();
+        assert_ne!(pcf.key(), depth.key());
+
+        assert_ne!(pcf.key(), desc().define("SHADOW_FILTER", 2).define("SUN_SHADOWS", 1).build().key());
+        assert_ne!(pcf.key(), desc().build().key());
+        let other_shader = PipelineDesc::builder()
+            .vertex_path("a.vert")
+            .fragment_path("b.frag")
+            .define("SHADOW_FILTER", 1)
+            .define("SUN_SHADOWS", 1)
+            .build();
+        assert_ne!(pcf.key(), other_shader.key());
+    }
+}
@@ -0,0 +1,283 @@
+//! The driver's pipeline cache, saved on exit and loaded on start when it
+//! was written by the same cache format, device and driver.
+
+use std::{
+    fmt::{Display, Formatter},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ash::vk;
+
+use crate::hash::ContentHash;
+
+use super::spirv_cache::write_atomically;
+
+pub const PIPELINE_CACHE_FILE: &str = "cache/pipelines.bin";
+
+const MAGIC: [u8; 4] = *b"LYPC";
+
+/// Changed whenever the file layout changes.
+const PIPELINE_CACHE_VERSION: u32 = 1;
+
+/// Magic, version, the [`CacheIdentity`], the data's length and checksum.
+const HEADER_SIZE: usize = 4 + 4 + 12 + 16 + 16 + 8 + 8;
+
+/// Size of `VK_PIPELINE_CACHE_HEADER_VERSION_ONE` headers.
+const VK_HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+/// The device and driver a saved cache can only be used with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CacheIdentity {
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub driver_version: u32,
+    pub driver_uuid: [u8; vk::UUID_SIZE],
+    pub pipeline_cache_uuid: [u8; vk::UUID_SIZE],
+}
+
+impl CacheIdentity {
+    pub fn new(instance: &ash::Instance, physical_device: vk::PhysicalDevice) -> Self {
+        let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+        let mut properties = vk::PhysicalDeviceProperties2::builder().push_next(&mut id_properties);
+        unsafe { instance.get_physical_device_properties2(physical_device, &mut properties) };
+        let properties = properties.properties;
+        CacheIdentity {
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            driver_version: properties.driver_version,
+            driver_uuid: id_properties.driver_uuid,
+            pipeline_cache_uuid: properties.pipeline_cache_uuid,
+        }
+    }
+}
+
+/// Why a saved cache was not used.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CacheRejected {
+    /// Truncated, or its data does not match its checksum.
+    Corrupt,
+    /// Written by another version of the file layout.
+    Version(u32),
+    /// Written for another device or driver.
+    OtherDevice,
+}
+
+impl Display for CacheRejected {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            CacheRejected::Corrupt => write!(f, "it is corrupt"),
+            CacheRejected::Version(version) => write!(
+                f,
+                "it has version {}, expected {}",
+                version, PIPELINE_CACHE_VERSION
+            ),
+            CacheRejected::OtherDevice => write!(f, "it was written for another device or driver"),
+        }
+    }
+}
+
+/// `data` from `vkGetPipelineCacheData` behind a header recording
+/// `identity`.
+pub fn encode(identity: &CacheIdentity, data: &[u8]) -> Vec<u8> {
+    let mut checksum = ContentHash::default();
+    checksum.update(data);
+
+    let mut file = Vec::with_capacity(HEADER_SIZE + data.len());
+    file.extend_from_slice(&MAGIC);
+    file.extend_from_slice(&PIPELINE_CACHE_VERSION.to_le_bytes());
+    file.extend_from_slice(&identity.vendor_id.to_le_bytes());
+    file.extend_from_slice(&identity.device_id.to_le_bytes());
+    file.extend_from_slice(&identity.driver_version.to_le_bytes());
+    file.extend_from_slice(&identity.driver_uuid);
+    file.extend_from_slice(&identity.pipeline_cache_uuid);
+    file.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    file.extend_from_slice(&checksum.finish().to_le_bytes());
+    file.extend_from_slice(data);
+    file
+}
+
+/// The cache data in `file` if it was encoded for `identity` and is intact,
+/// including the header Vulkan puts in front of it.
+pub fn decode<'a>(identity: &CacheIdentity, file: &'a [u8]) -> Result<&'a [u8], CacheRejected> {
+    if file.len() < HEADER_SIZE || file[..4] != MAGIC {
+        return Err(CacheRejected::Corrupt);
+    }
+    let u32_at = |offset: usize| u32::from_le_bytes(file[offset..offset + 4].try_into().unwrap());
+    let u64_at = |offset: usize| u64::from_le_bytes(file[offset..offset + 8].try_into().unwrap());
+    let uuid_at = |offset: usize| -> [u8; vk::UUID_SIZE] { file[offset..offset + vk::UUID_SIZE].try_into().unwrap() };
+
+    let version = u32_at(4);
+    if version != PIPELINE_CACHE_VERSION {
+        return Err(CacheRejected::Version(version));
+    }
+    let written_for = CacheIdentity {
+        vendor_id: u32_at(8),
+        device_id: u32_at(12),
+        driver_version: u32_at(16),
+        driver_uuid: uuid_at(20),
+        pipeline_cache_uuid: uuid_at(36),
+    };
+    if written_for != *identity {
+        return Err(CacheRejected::OtherDevice);
+    }
+
+    let data = &file[HEADER_SIZE..];
+    let mut checksum = ContentHash::default();
+    checksum.update(data);
+    if u64_at(52) != data.len() as u64 || u64_at(60) != checksum.finish() {
+        return Err(CacheRejected::Corrupt);
+    }
+
+    // The driver checks this header too, but not every driver survives
+    // data it did not write.
+    if data.len() < VK_HEADER_SIZE
+        || u32_at(HEADER_SIZE) as usize != VK_HEADER_SIZE
+        || u32_at(HEADER_SIZE + 4) != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+    {
+        return Err(CacheRejected::Corrupt);
+    }
+    if u32_at(HEADER_SIZE + 8) != identity.vendor_id
+        || u32_at(HEADER_SIZE + 12) != identity.device_id
+        || uuid_at(HEADER_SIZE + 16) != identity.pipeline_cache_uuid
+    {
+        return Err(CacheRejected::OtherDevice);
+    }
+    Ok(data)
+}
+
+pub struct PipelineCache {
+    pub handle: vk::PipelineCache,
+    identity: CacheIdentity,
+    path: PathBuf,
+}
+
+impl PipelineCache {
+    /// Creates a cache from the file at `path`, or an empty one when the
+    /// file is missing or cannot be used.
+    pub fn load(device: &ash::Device, identity: CacheIdentity, path: &Path) -> Self {
+        let file = fs::read(path).ok();
+        let data = file.as_deref().and_then(|file| match decode(&identity, file) {
+            Ok(data) => Some(data),
+            Err(rejected) => {
+                log::warn!("Not using pipeline cache {} as {}", path.display(), rejected);
+                None
+            }
+        });
+
+        let create = |data: &[u8]| unsafe {
+            device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::builder().initial_data(data), None)
+        };
+        let handle = match data.map(create) {
+            Some(Ok(handle)) => handle,
+            Some(Err(err)) => {
+                log::warn!("Driver rejected pipeline cache {}: {}", path.display(), err);
+                create(&[]).expect("Failed to create pipeline cache")
+            }
+            None => create(&[]).expect("Failed to create pipeline cache"),
+        };
+
+        PipelineCache {
+            handle,
+            identity,
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Writes the pipelines compiled so far to the file the cache was
+    /// loaded from.
+    pub fn save(&self, device: &ash::Device) -> std::io::Result<()> {
+        let data = unsafe { device.get_pipeline_cache_data(self.handle) }
+            .map_err(std::io::Error::other)?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        write_atomically(&self.path, &encode(&self.identity, &data))
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe { device.destroy_pipeline_cache(self.handle, None) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDENTITY: CacheIdentity = CacheIdentity {
+        vendor_id: 0x10de,
+        device_id: 0x2684,
+        driver_version: 7,
+        driver_uuid: [1; vk::UUID_SIZE],
+        pipeline_cache_uuid: [2; vk::UUID_SIZE],
+    };
+
+    /// Cache data as a driver would return it for `IDENTITY`.
+    fn driver_data() -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&(VK_HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(&IDENTITY.vendor_id.to_le_bytes());
+        data.extend_from_slice(&IDENTITY.device_id.to_le_bytes());
+        data.extend_from_slice(&IDENTITY.pipeline_cache_uuid);
+        data.extend_from_slice(b"pipelines");
+        data
+    }
+
+    #[test]
+    fn caches_are_read_back_for_the_same_device() {
+        let data = driver_data();
+        assert_eq!(decode(&IDENTITY, &encode(&IDENTITY, &data)), Ok(&data[..]));
+    }
+
+    #[test]
+    fn caches_from_other_versions_and_drivers_are_rejected() {
+        let file = encode(&IDENTITY, &driver_data());
+
+        let mut other_version = file.clone();
+        other_version[4] = 9;
+        assert_eq!(decode(&IDENTITY, &other_version), Err(CacheRejected::Version(9)));
+
+        let updated_driver = CacheIdentity {
+            driver_version: 8,
+            ..IDENTITY
+        };
+        assert_eq!(decode(&updated_driver, &file), Err(CacheRejected::OtherDevice));
+        let other_driver = CacheIdentity {
+            driver_uuid: [3; vk::UUID_SIZE],
+            ..IDENTITY
+        };
+        assert_eq!(decode(&other_driver, &file), Err(CacheRejected::OtherDevice));
+
+        let mut foreign_data = driver_data();
+        foreign_data[8] = 0;
+        assert_eq!(
+            decode(&IDENTITY, &encode(&IDENTITY, &foreign_data)),
+            Err(CacheRejected::OtherDevice)
+        );
+    }
+
+    #[test]
+    fn corrupt_caches_are_rejected() {
+        let file = encode(&IDENTITY, &driver_data());
+
+        assert_eq!(decode(&IDENTITY, &[]), Err(CacheRejected::Corrupt));
+        assert_eq!(decode(&IDENTITY, &file[..file.len() - 1]), Err(CacheRejected::Corrupt));
+        let mut flipped = file.clone();
+        let last = flipped.len() - 1;
+        flipped[last] ^= 1;
+        assert_eq!(decode(&IDENTITY, &flipped), Err(CacheRejected::Corrupt));
+
+        let mut bad_magic = file.clone();
+        bad_magic[0] = b'X';
+        assert_eq!(decode(&IDENTITY, &bad_magic), Err(CacheRejected::Corrupt));
+
+        let mut bad_vk_header = driver_data();
+        bad_vk_header[4] = 2;
+        assert_eq!(
+            decode(&IDENTITY, &encode(&IDENTITY, &bad_vk_header)),
+            Err(CacheRejected::Corrupt)
+        );
+        assert_eq!(decode(&IDENTITY, &encode(&IDENTITY, &[])), Err(CacheRejected::Corrupt));
+    }
+}
@@ -29,6 +29,9 @@ pub struct RenderPass {
     pub copy_command: Option<TextureCopy>,
     pub uniform_buffer: Option<BufferId>,
     pub extra_barriers: Option<Vec<(BufferId, AccessType)>>,
+    /// Left out of the frame as it does not fit the shaders of its
+    /// pipeline, see [`crate::render_graph::validate`].
+    pub skipped: bool,
     device: Arc<Device>,
 }
 
@@ -60,6 +63,7 @@ impl RenderPass {
             uniform_descriptor_set: None,
             copy_command,
             extra_barriers,
+            skipped: false,
             device,
         }
     }
@@ -120,9 +124,10 @@ impl RenderPass {
     ) {
         if !self.uniforms.is_empty() && self.uniform_descriptor_set.is_none() {
             let uniform_name = &self.uniforms.values().next().unwrap().0;
-            let binding = pipelines[self.pipeline_handle]
-                .reflection
-                .get_binding(uniform_name);
+            let binding = match pipelines[self.pipeline_handle].reflection.get_binding(uniform_name) {
+                Ok(binding) => binding,
+                Err(_) => return,
+            };
             let descriptor_set = DescriptorSet::new(
                 self.device.clone(),
                 pipelines[self.pipeline_handle].descriptor_set_layouts[binding.set as usize],
@@ -394,6 +399,17 @@ impl RenderPassBuilder {
         self
     }
 
+    /// Renders to `depth_attachment` with `load_op`, to keep what earlier
+    /// passes rendered with [`vk::AttachmentLoadOp::LOAD`].
+    pub fn depth_attachment_load_op(mut self, depth_attachment: TextureId, load_op: vk::AttachmentLoadOp) -> Self {
+        self.depth_attachment = Some(DepthAttachment::GraphHandle(Attachment {
+            texture: depth_attachment,
+            view: ViewType::Full(),
+            load_op,
+        }));
+        self
+    }
+
     pub fn external_depth_attachment(
         mut self,
         depth_attachment: Image,
@@ -403,28 +419,42 @@ impl RenderPassBuilder {
         self
     }
 
-    pub fn uniforms<T: Copy + std::fmt::Debug>(mut self, name: &str, data: &T) -> Self {
+    pub fn uniforms<T: Copy + std::fmt::Debug>(self, name: &str, data: &T) -> Self {
         unsafe {
             let ptr = data as *const _ as *const MaybeUninit<u8>;
             let size = std::mem::size_of::<T>();
-            let data_u8 = std::slice::from_raw_parts(ptr, size);
-
-            assert!(data_u8.len() < MAX_UNIFORMS_SIZE);
+            self.uniform_bytes(name, std::slice::from_raw_parts(ptr, size))
+        }
+    }
 
-            let unique_name = self.name.clone() + "_" + name;
+    /// Uniform block `name` holding `values`, for blocks only known at run
+    /// time.
+    pub fn uniform_floats(self, name: &str, values: &[f32]) -> Self {
+        let data_u8: Vec<MaybeUninit<u8>> = values
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .map(MaybeUninit::new)
+            .collect();
+        self.uniform_bytes(name, &data_u8)
+    }
 
-            if let Some(entry) = self.uniforms.get_mut(&unique_name) {
-                entry.1.data[..data_u8.len()].copy_from_slice(data_u8);
-                entry.1.size = size as u64;
-            } else {
-                let mut new_entry = UniformData {
-                    data: [MaybeUninit::zeroed(); MAX_UNIFORMS_SIZE],
-                    size: size as u64,
-                };
-                new_entry.data[..data_u8.len()].copy_from_slice(data_u8);
-                self.uniforms
-                    .insert(unique_name.to_string(), (name.to_string(), new_entry));
-            }
+    fn uniform_bytes(mut self, name: &str, data_u8: &[MaybeUninit<u8>]) -> Self {
+        assert!(data_u8.len() < MAX_UNIFORMS_SIZE);
+
+        let size = data_u8.len() as u64;
+        let unique_name = self.name.clone() + "_" + name;
+
+        if let Some(entry) = self.uniforms.get_mut(&unique_name) {
+            entry.1.data[..data_u8.len()].copy_from_slice(data_u8);
+            entry.1.size = size;
+        } else {
+            let mut new_entry = UniformData {
+                data: [MaybeUninit::zeroed(); MAX_UNIFORMS_SIZE],
+                size,
+            };
+            new_entry.data[..data_u8.len()].copy_from_slice(data_u8);
+            self.uniforms
+                .insert(unique_name.to_string(), (name.to_string(), new_entry));
         }
         self
     }
@@ -462,6 +492,9 @@ impl RenderPassBuilder {
                     .format()
             })
             .collect();
+        if pass.is_pres_pass && pass.writes.is_empty() && graph.present_format != vk::Format::UNDEFINED {
+            graph.pipeline_descs[pass.pipeline_handle].color_attachment_formats = vec![graph.present_format];
+        }
 
         if let Some(depth) = &pass.depth_attachment {
             match depth {
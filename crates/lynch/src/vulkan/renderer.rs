@@ -1,6 +1,11 @@
 use crate::{
-    gltf_loader::Model, render_graph::RenderGraph, render_tools, renderer::Renderer,
-    vulkan::cont::*, vulkan::debug::*, window::window::Window, Camera, Texture,
+    assets::{AssetManager, Assets, Finished, Handle},
+    bounds::Aabb,
+    culling::{cull_meshes, CullCandidate, CullingSettings, CullingStatistics, OcclusionBuffer},
+    lights::Light,
+    shadows::ShadowSettings,
+    gltf_loader::{upload_model, LodLevel, Model}, lod::{self, LodSettings}, mesh::{Primitive, Vertex}, render_graph::{MemoryUsage, RenderGraph}, render_tools, renderer::Renderer,
+    scene::ScenePose, skinning::deform_vertices, vulkan::cont::*, vulkan::debug::*, window::window::Window, Camera, Texture,
 };
 use ash::{
     extensions::{
@@ -16,7 +21,7 @@ use imgui::DrawData;
 
 use super::{descriptor::DescriptorSet, Buffer, Device, Image, ImageDesc};
 use glam::{self, Mat4, Vec3, Vec4};
-use std::{ffi::CString, sync::{Arc, Mutex}, time::Instant};
+use std::{collections::HashMap, ffi::CString, sync::{Arc, Mutex}, time::Instant};
 pub const MAX_NUM_GPU_MATERIALS: usize = 1024;
 pub const MAX_NUM_GPU_MESHES: usize = 1024;
 pub const DESCRIPTOR_SET_INDEX_BINDLESS: u32 = 0;
@@ -26,8 +31,59 @@ pub const DEFAULT_TEXTURE: u32 = u32::MAX;
 
 use crate::vulkan::surface::create_surface;
 pub struct ModelInstance {
-    pub model: Model,
+    pub model: Handle<Model>,
     pub transform: glam::Mat4,
+    /// Node transform of every mesh, see [`Model::meshes`]. The per mesh
+    /// state is empty until the model has loaded.
+    pub transforms: Vec<Mat4>,
+    /// Level of detail drawn for every mesh.
+    pub lods: Vec<usize>,
+    /// Whether every mesh survived culling for the main view.
+    pub visible: Vec<bool>,
+    /// Copies of skinned and morphed meshes, made on the first pose so
+    /// instances sharing a model animate independently.
+    deformed: Vec<Option<DeformedMesh>>,
+}
+
+/// A skinned or morphed mesh of one instance. Every frame in flight draws its
+/// own host visible copy, so writing the copy of the current frame never
+/// touches vertices the GPU may still be reading.
+struct DeformedMesh {
+    /// Empty until [`RendererInternal::begin_frame`] creates the copies.
+    frames: Vec<LodLevel>,
+    /// Which of `frames` hold `vertices`.
+    written: Vec<bool>,
+    vertices: Vec<Vertex>,
+    /// Bounds of `vertices`, culled against instead of the bind pose.
+    aabb: Aabb,
+    /// The pose `vertices` were deformed to.
+    palette: Vec<Mat4>,
+    weights: Vec<f32>,
+    /// Bindless slots of `frames`, freed along with them.
+    slots: BindlessSlots,
+}
+
+/// Frees the GPU resources of a model that is no longer drawn.
+fn release_model(model: Model) {
+    for texture in &model.textures {
+        texture.clean_vk_resources();
+    }
+    let primitives = model
+        .meshes
+        .iter()
+        .flat_map(|mesh| std::iter::once(&mesh.primitive).chain(mesh.lods.iter().map(|lod| &lod.primitive)));
+    for primitive in primitives {
+        primitive.vertex_buffer.clean_vk_resources();
+        primitive.index_buffer.clean_vk_resources();
+    }
+}
+
+/// The model of `instance` once it has loaded and the instance has been set
+/// up for it.
+fn loaded<'a>(models: &'a Assets<Model>, instance: &ModelInstance) -> Option<&'a Model> {
+    models
+        .get(&instance.model)
+        .filter(|model| model.meshes.len() == instance.transforms.len())
 }
 pub struct Frame {
     pub command_buffer: vk::CommandBuffer,
@@ -71,17 +127,100 @@ pub struct RendererInternal {
     next_bindless_image_index: u32,
     next_bindless_vertex_buffer_index: u32,
     next_bindless_index_buffer_index: u32,
+    /// Bindless slots of unloaded assets no frame in flight reads any more,
+    /// handed out again before new ones.
+    free_slots: BindlessSlots,
     pub recreate_environment: bool,
+    pub lod_settings: LodSettings,
+    pub culling_settings: CullingSettings,
+    /// Gathered from the world each frame and assigned to clusters before
+    /// the deferred pass shades with them.
+    pub lights: Vec<Light>,
+    pub shadow_settings: ShadowSettings,
+    frames_in_flight: usize,
+    /// Frame in flight being recorded, see [`RendererInternal::begin_frame`].
+    frame: usize,
+    /// Textures and models no handle refers to any more, by the frame in
+    /// flight that unloaded them, freed when that frame comes round again.
+    retired: Vec<Retired>,
+    occlusion_buffer: OcclusionBuffer,
+    pub assets: AssetManager,
+    default_textures: Vec<Handle<Texture>>,
+    /// Bindless slots of every loaded model and texture, by asset id, taken
+    /// over by the new version when one is reloaded.
+    model_slots: HashMap<usize, BindlessSlots>,
+    texture_slots: HashMap<usize, u32>,
+    device: Arc<Device>,
+}
+
+/// Bindless indices a model was registered with, in registration order.
+#[derive(Default)]
+struct BindlessSlots {
+    images: Vec<u32>,
+    vertex_buffers: Vec<u32>,
+    index_buffers: Vec<u32>,
+    materials: Vec<u32>,
+    meshes: Vec<u32>,
+}
+
+impl BindlessSlots {
+    /// Moves every slot of `other` into `self`.
+    fn append(&mut self, other: &mut BindlessSlots) {
+        self.images.append(&mut other.images);
+        self.vertex_buffers.append(&mut other.vertex_buffers);
+        self.index_buffers.append(&mut other.index_buffers);
+        self.materials.append(&mut other.materials);
+        self.meshes.append(&mut other.meshes);
+    }
+
+    /// Moves the slots of `self` past those `used` took into `free`.
+    fn release_unused(mut self, used: &BindlessSlots, free: &mut BindlessSlots) {
+        let rest = |slots: &mut Vec<u32>, used: &[u32]| slots.split_off(used.len().min(slots.len()));
+        free.images.extend(rest(&mut self.images, &used.images));
+        free.vertex_buffers.extend(rest(&mut self.vertex_buffers, &used.vertex_buffers));
+        free.index_buffers.extend(rest(&mut self.index_buffers, &used.index_buffers));
+        free.materials.extend(rest(&mut self.materials, &used.materials));
+        free.meshes.extend(rest(&mut self.meshes, &used.meshes));
+    }
+}
+
+/// What a frame in flight unloaded, see [`RendererInternal::retired`].
+#[derive(Default)]
+struct Retired {
+    textures: Vec<Texture>,
+    models: Vec<Model>,
+    slots: BindlessSlots,
+}
+
+/// The slot of `free` to fill next, or a new one past `next`.
+fn allocate(free: &mut Vec<u32>, next: &mut u32) -> u32 {
+    free.pop().unwrap_or_else(|| {
+        *next += 1;
+        *next - 1
+    })
+}
+
+/// The slot of `previous` to reuse for the next index pushed to `used`.
+fn reused(previous: &[u32], used: &[u32]) -> Option<u32> {
+    previous.get(used.len()).copied()
 }
 
 
 pub struct RenderStatistics {
     pub full_render_time: f32,
     pub render_graph_time: f32,
+    pub culling: CullingStatistics,
+    /// Memory the render graph's transient resources took this frame.
+    pub graph_memory: MemoryUsage,
 }
 impl Default for RenderStatistics {
     fn default() -> Self {
-        Self { full_render_time: Default::default(), render_graph_time: Default::default() }
+        Self {
+            full_render_time: Default::default(),
+            render_graph_time: Default::default(),
+            culling: Default::default(),
+            graph_memory: Default::default(),
+        }
     }
 }
 
@@ -152,8 +291,14 @@ struct GpuMesh {
     material: u32,
 }
 impl VulkanRenderer {
-    pub fn load_model(&self, path: &str) -> Model {
-        crate::gltf_loader::load_gltf(self.vk_context.arc_device(), path)
+    /// Starts loading the model at `path` in the background, or hands out
+    /// the already cached one.
+    pub fn load_model(&mut self, path: &str) -> Handle<Model> {
+        self.internal_renderer.assets.load_model(path)
+    }
+
+    pub fn load_texture(&mut self, path: &str) -> Handle<Texture> {
+        self.internal_renderer.assets.load_texture(path)
     }
     fn setup_swapchain_images(
         vk_context: &VkContext,
@@ -218,7 +363,9 @@ impl VulkanRenderer {
             (present_images, depth_image)
         }
     }
-    pub fn present_frame(&self, present_index: usize, current_frame: usize) {
+    /// Presents the frame, returning whether the swapchain no longer matches
+    /// the surface.
+    pub fn present_frame(&self, present_index: usize, current_frame: usize) -> bool {
         unsafe {
             let wait_semaphores = [self.sync_frames[current_frame].render_finished_semaphore];
             let swapchains = [self.swapchain];
@@ -249,12 +396,56 @@ impl VulkanRenderer {
                 .swapchains(&swapchains)
                 .image_indices(&image_indices);
 
-            self.swapchain_loader
-                .queue_present(self.device().queue, &present_info)
-                .unwrap();
+            match self.swapchain_loader.queue_present(self.device().queue, &present_info) {
+                Ok(suboptimal) => suboptimal,
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => true,
+                Err(err) => panic!("Failed to present: {err}"),
+            }
         }
     }
 
+    /// Recreates the swapchain and the images sized to it. Graph textures
+    /// follow when the graph is next built, through
+    /// [`RenderGraph::swapchain_extent`].
+    fn recreate_swapchain(&mut self) {
+        self.wait_gpu_idle();
+        let old_swapchain = self.swapchain;
+        let (swapchain, _, surface_format, surface_resolution, _) =
+            Self::create_swapchain(&self.vk_context, old_swapchain);
+
+        unsafe {
+            self.swapchain_loader.destroy_swapchain(old_swapchain, None);
+            for present_image in &self.present_images {
+                self.ash_device().destroy_image_view(present_image.image_view, None);
+            }
+            self.depth_image.clean_vk_resources();
+            self.ash_device().free_memory(self.depth_image.device_memory, None);
+        }
+
+        let (present_images, depth_image) = Self::setup_swapchain_images(
+            &self.vk_context,
+            swapchain,
+            &self.swapchain_loader,
+            surface_format,
+            surface_resolution,
+        );
+        self.swapchain = swapchain;
+        self.surface_format = surface_format;
+        self.surface_resolution = surface_resolution;
+        self.present_images = present_images;
+        self.depth_image = depth_image;
+        self.view_data.viewport_width = surface_resolution.width;
+        self.view_data.viewport_height = surface_resolution.height;
+        self.swapchain_recreate_needed = false;
+    }
+
+    fn frame_delta_time(&mut self) -> f32 {
+        let now = Instant::now();
+        let delta_time = now.duration_since(self.last_frame_end).as_secs_f32();
+        self.last_frame_end = now;
+        delta_time
+    }
+
     pub fn submit_commands(&self, frame_index: usize) {
         unsafe {
             let command_buffers = [self.sync_frames[frame_index].command_buffer];
@@ -359,6 +550,7 @@ impl VulkanRenderer {
 
     fn create_swapchain(
         context: &VkContext,
+        old_swapchain: vk::SwapchainKHR,
     ) -> (
         vk::SwapchainKHR,
         Swapchain,
@@ -412,7 +604,8 @@ impl VulkanRenderer {
                 .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
                 .present_mode(present_mode)
                 .clipped(true)
-                .image_array_layers(1);
+                .image_array_layers(1)
+                .old_swapchain(old_swapchain);
 
             let swapchain = swapchain_loader
                 .create_swapchain(&swapchain_create_info, None)
@@ -467,13 +660,29 @@ impl VulkanRenderer {
         self.vk_context.ash_device()
     }
 
-    pub fn render(&mut self, graph: &mut RenderGraph,  camera: &Camera, draw_data: &DrawData, _render_statistics: &RenderStatistics) -> f32 
+    pub fn render(&mut self, graph: &mut RenderGraph,  camera: &Camera, draw_data: &DrawData, render_statistics: &mut RenderStatistics) -> f32 
 
     {
+        if self.swapchain_recreate_needed {
+            // Minimized, there is nothing to present to.
+            if self.surface_resolution.width == 0 || self.surface_resolution.height == 0 {
+                return self.frame_delta_time();
+            }
+            self.recreate_swapchain();
+        }
         self.update_view_to_camera(&camera);
+        self.internal_renderer.update_lods(
+            camera.get_position(),
+            lod::projection_scale(&camera.get_projection(), self.surface_resolution.height as f32),
+        );
+        render_statistics.culling = self
+            .internal_renderer
+            .cull(&(camera.get_projection() * camera.get_view()), camera.get_position());
         let command_buffer = self.sync_frames[self.current_frame].command_buffer;
         let wait_fence = self.sync_frames[self.current_frame].command_buffer_reuse_fence;
-        let present_index = self.begin_frame();
+        let Some(present_index) = self.begin_frame() else {
+            return self.frame_delta_time();
+        };
         unsafe {
             {
                 self.ash_device()
@@ -484,6 +693,7 @@ impl VulkanRenderer {
                     .reset_fences(&[wait_fence])
                     .expect("Reset fences failed.");
             }
+            self.internal_renderer.begin_frame(self.current_frame);
 
             self.ash_device()
                 .reset_command_buffer(
@@ -504,6 +714,8 @@ impl VulkanRenderer {
 
             graph.new_frame(self.current_frame);
             graph.clear();
+            graph.present_format = self.surface_format.format;
+            graph.swapchain_extent = self.surface_resolution;
 
             self.internal_renderer.instances[0]
                 .transform
@@ -541,6 +753,8 @@ impl VulkanRenderer {
             //     true,
             //     true
             // );
+            graph.compile();
+            render_statistics.graph_memory = graph.resources.memory_plan.usage();
             graph.prepare(&self);
             let image = self.present_images[present_index].clone();
             graph.render(&command_buffer, &self, &image);
@@ -573,15 +787,14 @@ impl VulkanRenderer {
                 vk::ImageLayout::PRESENT_SRC_KHR;
 
             self.submit_commands(self.current_frame);
-            self.present_frame(present_index, self.current_frame);
+            if self.present_frame(present_index, self.current_frame) {
+                self.swapchain_recreate_needed = true;
+            }
             self.current_frame = (self.current_frame + 1) % self.num_frames_in_flight as usize;
             self.internal_renderer.recreate_environment = false;
             graph.current_frame = self.current_frame;
         }
-        let now = Instant::now();
-        let delta_time = now.duration_since(self.last_frame_end).as_secs_f32();
-        self.last_frame_end = now;
-        delta_time
+        self.frame_delta_time()
     }
 
 }
@@ -602,9 +815,8 @@ impl Renderer for VulkanRenderer {
         self.vk_context.device()
     }
 
-    fn add_model(self: &mut Self, model: crate::gltf_loader::Model, transform: glam::Mat4) {
-        let device = self.vk_context.device();
-        self.internal_renderer.add_model(device, model, transform);
+    fn add_model(self: &mut Self, model: Handle<Model>, transform: glam::Mat4) {
+        self.internal_renderer.add_model(model, transform);
     }
     fn create(window: &Window, camera: &Camera,  gui: &mut imgui::Context) -> Self {
         let entry = ash::Entry::linked();
@@ -619,7 +831,7 @@ impl Renderer for VulkanRenderer {
         let vk_context = VkContext::new(entry, instance, surface, surface_khr, device);
 
         let (swapchain, swapchain_loader, surface_format, surface_resolution, image_count) =
-            Self::create_swapchain(&vk_context);
+            Self::create_swapchain(&vk_context, vk::SwapchainKHR::null());
 
         let (present_images, depth_image) = Self::setup_swapchain_images(
             &vk_context,
@@ -633,7 +845,7 @@ impl Renderer for VulkanRenderer {
         let sync_frames =
             Self::create_synchronization_frames(&vk_context, command_pool, image_count);
 
-        let internal_renderer = RendererInternal::new(&vk_context);
+        let internal_renderer = RendererInternal::new(&vk_context, image_count as usize);
         let view_data = ViewUniformData::new(&camera, surface_resolution);
         let camera_uniform_buffer = (0..image_count)
             .map(|_| view_data.create_camera_buffer(&vk_context))
@@ -691,18 +903,27 @@ impl Renderer for VulkanRenderer {
         }
     }
     
-    fn begin_frame(self: &mut Self) -> usize {
-        unsafe {
-            let (present_index, _) = self
-                .swapchain_loader
-                .acquire_next_image(
-                    self.swapchain,
-                    std::u64::MAX,
-                    self.sync_frames[self.current_frame].image_available_semaphore,
-                    vk::Fence::null(),
-                )
-                .expect("Error acquiring next swapchain image");
-            present_index as usize
+    fn begin_frame(self: &mut Self) -> Option<usize> {
+        let acquired = unsafe {
+            self.swapchain_loader.acquire_next_image(
+                self.swapchain,
+                u64::MAX,
+                self.sync_frames[self.current_frame].image_available_semaphore,
+                vk::Fence::null(),
+            )
+        };
+        match acquired {
+            Ok((present_index, suboptimal)) => {
+                self.swapchain_recreate_needed |= suboptimal;
+                Some(present_index as usize)
+            }
+            // Skips the frame, the next one recreates the swapchain unless
+            // the window is minimized.
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.swapchain_recreate_needed = true;
+                None
+            }
+            Err(err) => panic!("Error acquiring next swapchain image: {err}"),
         }
     }
 
@@ -752,7 +973,7 @@ impl Drop for VulkanRenderer {
     }
 }
 impl RendererInternal {
-    pub fn new(vk_context: &VkContext) -> Self {
+    pub fn new(vk_context: &VkContext, frames_in_flight: usize) -> Self {
         let bindless_descriptor_set_layout =
             create_bindless_descriptor_set_layout(vk_context.device());
         let bindless_descriptor_set =
@@ -801,103 +1022,467 @@ impl RendererInternal {
             next_bindless_image_index: 0,
             next_bindless_vertex_buffer_index: 0,
             next_bindless_index_buffer_index: 0,
+            free_slots: BindlessSlots::default(),
             default_diffuse_map_index: 0,
             default_normal_map_index: 0,
             default_occlusion_map_index: 0,
             default_metallic_roughness_map_index: 0,
             recreate_environment: true,
+            lod_settings: LodSettings::default(),
+            culling_settings: CullingSettings::default(),
+            lights: vec![],
+            shadow_settings: ShadowSettings::default(),
+            frames_in_flight,
+            frame: 0,
+            retired: (0..frames_in_flight).map(|_| Retired::default()).collect(),
+            occlusion_buffer: OcclusionBuffer::new(0, 0),
+            assets: AssetManager::default(),
+            default_textures: vec![],
+            model_slots: HashMap::new(),
+            texture_slots: HashMap::new(),
+            device: vk_context.arc_device(),
         }
     }
 
     pub fn initialize(&mut self, device: Arc<Device>) {
-        let default_diffuse_map = Texture::load(device.clone(), "assets/textures/def/white_texture.png");
-        let default_normal_map = Texture::load(device.clone(), "assets/textures/def/flat_normal_map.png");
-        let default_occlusion_map = Texture::load(device.clone(), "assets/textures/def/white_texture.png");
-        let default_metallic_roughness_map = Texture::load(device.clone(), "assets/textures/def/metallic_roughness.png");
-
-        self.default_diffuse_map_index = self.add_bindless_texture(&device, &default_diffuse_map);
-        self.default_normal_map_index = self.add_bindless_texture(&device, &default_normal_map);
-        self.default_occlusion_map_index =
-            self.add_bindless_texture(&device, &default_occlusion_map);
-        self.default_metallic_roughness_map_index =
-            self.add_bindless_texture(&device, &default_metallic_roughness_map);
+        let paths = [
+            "assets/textures/def/white_texture.png",
+            "assets/textures/def/flat_normal_map.png",
+            "assets/textures/def/white_texture.png",
+            "assets/textures/def/metallic_roughness.png",
+        ];
+        self.assets.enable_hot_reload();
+        let bc_supported = device.features.texture_compression_bc == vk::TRUE;
+        self.assets.models.settings.textures.bc_supported = bc_supported;
+        self.assets.textures.settings.textures.bc_supported = bc_supported;
+        let mut indices = [0; 4];
+        for (path, index) in paths.iter().zip(indices.iter_mut()) {
+            let handle = self.assets.load_texture(path);
+            for finished in self.assets.textures.poll_until(&handle) {
+                self.complete_texture(finished);
+            }
+            // Falls back to the placeholder texture if the file is missing.
+            let descriptor_info = self.assets.textures.get(&handle).unwrap().descriptor_info;
+            *index = self.add_bindless_image(&device, &descriptor_info, None);
+            self.texture_slots.insert(handle.id(), *index);
+            self.default_textures.push(handle);
+        }
+        [
+            self.default_diffuse_map_index,
+            self.default_normal_map_index,
+            self.default_occlusion_map_index,
+            self.default_metallic_roughness_map_index,
+        ] = indices;
     }
 
-    pub fn add_model(&mut self, device: &Device, mut model: Model, transform: glam::Mat4) {
-        for mesh in &mut model.meshes {
-            let diffuse_bindless_index = match mesh.material.diffuse_map {
-                DEFAULT_TEXTURE => self.default_diffuse_map_index,
-                _ => self.add_bindless_texture(
-                    device,
-                    &model.textures[mesh.material.diffuse_map as usize],
-                ),
-            };
+    /// Uploads models and textures whose background import finished, and
+    /// sets up the instances waiting on them. Assets changed on disk are
+    /// queued for import again first. New assets only write bindless slots
+    /// no frame in flight reads, so this runs from
+    /// [`RendererInternal::begin_frame`] without waiting on the device.
+    fn update_assets(&mut self) {
+        for path in self.assets.reload_changed() {
+            log::info!("Reloading {}", path.display());
+        }
+        for finished in self.assets.textures.poll() {
+            self.complete_texture(finished);
+        }
+        for finished in self.assets.models.poll() {
+            self.complete_model(finished);
+        }
+        self.prepare_instances();
+    }
 
-            let normal_bindless_index = match mesh.material.normal_map {
-                DEFAULT_TEXTURE => self.default_normal_map_index,
-                _ => self.add_bindless_texture(
-                    device,
-                    &model.textures[mesh.material.normal_map as usize],
-                ),
-            };
+    fn complete_texture(&mut self, Finished { id, path, cpu, error }: Finished<Texture>) {
+        let path = path.to_string_lossy();
+        let texture = Texture::from_cpu_image(self.device.clone(), &cpu, &path);
+        if let Some(previous) = self.assets.textures.take(id) {
+            self.wait_idle();
+            if let Some(slot) = self.texture_slots.get(&id).copied() {
+                let device = self.device.clone();
+                self.add_bindless_image(&device, &texture.descriptor_info, Some(slot));
+            }
+            previous.clean_vk_resources();
+        }
+        self.assets.textures.complete(id, error, texture);
+    }
 
-            let metallic_roughness_bindless_index = match mesh.material.metallic_roughness_map {
-                DEFAULT_TEXTURE => self.default_metallic_roughness_map_index,
-                _ => self.add_bindless_texture(
-                    device,
-                    &model.textures[mesh.material.metallic_roughness_map as usize],
-                ),
-            };
+    /// Registers a loaded model, or swaps a reloaded one in on the bindless
+    /// slots of its previous version.
+    fn complete_model(&mut self, Finished { id, path, cpu, error }: Finished<Model>) {
+        let mut model = upload_model(self.device.clone(), cpu, &path.to_string_lossy());
+        let previous = self.assets.models.take(id);
+        if previous.is_some() {
+            self.wait_idle();
+        }
+        let previous_slots = self.model_slots.remove(&id).unwrap_or_default();
+        let slots = self.register_model(&mut model, &previous_slots);
+        // The device is idle, slots the new version does not take are free
+        // right away.
+        previous_slots.release_unused(&slots, &mut self.free_slots);
+        self.model_slots.insert(id, slots);
+        if let Some(previous) = previous {
+            release_model(previous);
+            self.reset_instances(id);
+        }
+        self.assets.models.complete(id, error, model);
+    }
+
+    /// Drops the per mesh state of the instances of a reloaded model, so
+    /// [`RendererInternal::prepare_instances`] sets them up again. Only call
+    /// with the device idle.
+    fn reset_instances(&mut self, id: usize) {
+        for instance in self.instances.iter_mut().filter(|instance| instance.model.id() == id) {
+            for mut deformed in instance.deformed.drain(..).flatten() {
+                self.free_slots.append(&mut deformed.slots);
+                for level in deformed.frames {
+                    level.primitive.vertex_buffer.clean_vk_resources();
+                    level.primitive.index_buffer.clean_vk_resources();
+                }
+            }
+            instance.transforms.clear();
+        }
+    }
+
+    fn wait_idle(&self) {
+        unsafe { self.device.ash_device.device_wait_idle().unwrap() }
+    }
 
-            let occlusion_bindless_index = match mesh.material.occlusion_map {
-                DEFAULT_TEXTURE => self.default_occlusion_map_index,
-                _ => self.add_bindless_texture(
-                    device,
-                    &model.textures[mesh.material.occlusion_map as usize],
-                ),
+    fn prepare_instances(&mut self) {
+        for instance in &mut self.instances {
+            let Some(model) = self.assets.models.get(&instance.model) else {
+                continue;
             };
+            if model.meshes.len() == instance.transforms.len() {
+                continue;
+            }
+            instance.transforms = model.transforms.clone();
+            instance.lods = vec![0; model.meshes.len()];
+            instance.visible = vec![true; model.meshes.len()];
+            instance.deformed = model.meshes.iter().map(|_| None).collect();
+        }
+    }
 
-            let vertex_buffer_bindless_idx =
-                self.add_bindless_vertex_buffer(device, &mesh.primitive.vertex_buffer);
-            let index_buffer_bindless_idx =
-                self.add_bindless_index_buffer(device, &mesh.primitive.index_buffer);
-            let material_index = self.add_material(GpuMaterial {
-                diffuse_map: diffuse_bindless_index,
-                normal_map: normal_bindless_index,
-                metallic_roughness_map: metallic_roughness_bindless_index,
-                occlusion_map: occlusion_bindless_index,
-                base_color_factor: mesh.material.base_color_factor,
-                metallic_factor: mesh.material.metallic_factor,
-                roughness_factor: mesh.material.roughness_factor,
-                padding: [0.0; 2],
-            });
+    /// Unloads the models and textures no handle refers to any more. Their
+    /// GPU resources and bindless slots are retired to the current frame.
+    fn unload_unused_assets(&mut self) {
+        let retired = &mut self.retired[self.frame];
+        for (id, texture) in self.assets.textures.unload_unused() {
+            retired.slots.images.extend(self.texture_slots.remove(&id));
+            retired.textures.push(texture);
+        }
+        for (id, model) in self.assets.models.unload_unused() {
+            if let Some(mut slots) = self.model_slots.remove(&id) {
+                retired.slots.append(&mut slots);
+            }
+            retired.models.push(model);
+        }
+    }
 
-            let mesh_index = self.add_mesh(GpuMesh {
-                vertex_buffer: vertex_buffer_bindless_idx,
-                index_buffer: index_buffer_bindless_idx,
-                material: material_index,
-            });
+    /// Draws `model` with `transform` once it has loaded.
+    pub fn add_model(&mut self, model: Handle<Model>, transform: glam::Mat4) {
+        self.instances.push(ModelInstance {
+            model,
+            transform,
+            transforms: vec![],
+            lods: vec![],
+            visible: vec![],
+            deformed: vec![],
+        });
+        self.prepare_instances();
+    }
+
+    /// Registers the meshes, materials and textures of a freshly uploaded
+    /// model with the bindless tables, reusing the `previous` slots in order
+    /// before taking free or new ones. A reloaded model with the same layout
+    /// ends up on exactly the same indices.
+    fn register_model(&mut self, model: &mut Model, previous: &BindlessSlots) -> BindlessSlots {
+        let device = self.device.clone();
+        let device = &*device;
+        let mut slots = BindlessSlots::default();
+        for mesh in &mut model.meshes {
+            let defaults = [
+                self.default_diffuse_map_index,
+                self.default_normal_map_index,
+                self.default_metallic_roughness_map_index,
+                self.default_occlusion_map_index,
+            ];
+            let maps = [
+                mesh.material.diffuse_map,
+                mesh.material.normal_map,
+                mesh.material.metallic_roughness_map,
+                mesh.material.occlusion_map,
+            ];
+            let mut bindless_indices = defaults;
+            for (map, index) in maps.into_iter().zip(bindless_indices.iter_mut()) {
+                if map == DEFAULT_TEXTURE {
+                    continue;
+                }
+                let descriptor_info = &model.textures[map as usize].descriptor_info;
+                *index = self.add_bindless_image(device, descriptor_info, reused(&previous.images, &slots.images));
+                slots.images.push(*index);
+            }
+            let [diffuse_bindless_index, normal_bindless_index, metallic_roughness_bindless_index, occlusion_bindless_index] =
+                bindless_indices;
+
+            let material_index = self.add_material(
+                GpuMaterial {
+                    diffuse_map: diffuse_bindless_index,
+                    normal_map: normal_bindless_index,
+                    metallic_roughness_map: metallic_roughness_bindless_index,
+                    occlusion_map: occlusion_bindless_index,
+                    base_color_factor: mesh.material.base_color_factor,
+                    metallic_factor: mesh.material.metallic_factor,
+                    roughness_factor: mesh.material.roughness_factor,
+                    padding: [0.0; 2],
+                },
+                reused(&previous.materials, &slots.materials),
+            );
+            slots.materials.push(material_index);
 
-            mesh.gpu_mesh = mesh_index;
+            mesh.gpu_mesh = self.add_primitive(device, &mesh.primitive, material_index, previous, &mut slots);
+            for lod in &mut mesh.lods {
+                lod.gpu_mesh = self.add_primitive(device, &lod.primitive, material_index, previous, &mut slots);
+            }
         }
+        slots
+    }
 
-        self.gpu_meshes_buffer
-            .update_memory(self.gpu_meshes.as_slice());
-        self.gpu_materials_buffer
-            .update_memory(self.gpu_materials.as_slice());
+    /// Registers the buffers of `primitive` as a bindless mesh, see
+    /// [`RendererInternal::register_model`].
+    fn add_primitive(
+        &mut self,
+        device: &Device,
+        primitive: &Primitive,
+        material: u32,
+        previous: &BindlessSlots,
+        slots: &mut BindlessSlots,
+    ) -> u32 {
+        let vertex_buffer = self.add_bindless_vertex_buffer(
+            device,
+            &primitive.vertex_buffer,
+            reused(&previous.vertex_buffers, &slots.vertex_buffers),
+        );
+        slots.vertex_buffers.push(vertex_buffer);
+        let index_buffer = self.add_bindless_index_buffer(
+            device,
+            &primitive.index_buffer,
+            reused(&previous.index_buffers, &slots.index_buffers),
+        );
+        slots.index_buffers.push(index_buffer);
+        let mesh = self.add_mesh(
+            GpuMesh {
+                vertex_buffer,
+                index_buffer,
+                material,
+            },
+            reused(&previous.meshes, &slots.meshes),
+        );
+        slots.meshes.push(mesh);
+        mesh
+    }
+
+    /// Culls every mesh against the main view, see [`ModelInstance::visible`].
+    /// Meshes are drawn into the occlusion buffer at their current level of
+    /// detail. Deformed meshes never occlude, their CPU vertices are the
+    /// bind pose.
+    pub fn cull(&mut self, view_projection: &Mat4, eye: Vec3) -> CullingStatistics {
+        let settings = self.culling_settings;
+        if (self.occlusion_buffer.width(), self.occlusion_buffer.height())
+            != (settings.occlusion_width, settings.occlusion_height)
+        {
+            self.occlusion_buffer = OcclusionBuffer::new(settings.occlusion_width, settings.occlusion_height);
+        }
+
+        let models = &self.assets.models;
+        let candidates: Vec<CullCandidate> = self
+            .instances
+            .iter()
+            .filter_map(|instance| Some((instance, loaded(models, instance)?)))
+            .flat_map(|(instance, model)| {
+                model.meshes.iter().enumerate().map(|(i, mesh)| {
+                    let (primitive, _) = mesh.level(instance.lods[i]);
+                    // Deformed meshes are culled in their current pose.
+                    let bounds = instance.deformed[i].as_ref().map_or(mesh.aabb, |deformed| deformed.aabb);
+                    CullCandidate {
+                        bounds,
+                        transform: instance.transform * instance.transforms[i],
+                        occluder: mesh
+                            .deform
+                            .is_none()
+                            .then_some((primitive.vertices.as_slice(), primitive.indices.as_slice())),
+                    }
+                })
+            })
+            .collect();
+        let (visible, statistics) =
+            cull_meshes(&candidates, view_projection, eye, &settings, &mut self.occlusion_buffer);
+
+        let mut visible = visible.into_iter();
+        for instance in &mut self.instances {
+            if loaded(models, instance).is_none() {
+                continue;
+            }
+            for (mesh_visible, culled) in instance.visible.iter_mut().zip(visible.by_ref()) {
+                *mesh_visible = culled;
+            }
+        }
+        statistics
+    }
+
+    /// Picks the level of detail of every mesh for a camera at `eye`, see
+    /// [`lod::projection_scale`].
+    pub fn update_lods(&mut self, eye: Vec3, projection_scale: f32) {
+        for instance in &mut self.instances {
+            let Some(model) = loaded(&self.assets.models, instance) else {
+                continue;
+            };
+            for (i, mesh) in model.meshes.iter().enumerate() {
+                let transform = instance.transform * instance.transforms[i];
+                let distance = lod::model_distance(&mesh.bounds, &transform, eye);
+                instance.lods[i] = lod::select_lod(
+                    &mesh.lod_errors,
+                    distance,
+                    projection_scale,
+                    instance.lods[i],
+                    &self.lod_settings,
+                );
+            }
+        }
+    }
+
+    /// Moves the meshes of `instance` to an animated pose. Skinned and
+    /// morphed meshes are only deformed again when their pose changed, and
+    /// reach the GPU in [`RendererInternal::begin_frame`].
+    pub fn apply_pose(&mut self, index: usize, pose: &ScenePose) {
+        let instance = &mut self.instances[index];
+        let Some(model) = loaded(&self.assets.models, instance) else {
+            return;
+        };
+        for (i, mesh) in model.meshes.iter().enumerate() {
+            if let Some(global) = pose.globals.get(mesh.node) {
+                instance.transforms[i] = *global;
+            }
+            let Some(deform) = &mesh.deform else {
+                continue;
+            };
+            let palette = pose.palettes.get(mesh.node).map_or(&[][..], Vec::as_slice);
+            let weights = pose.weights.get(mesh.node).map_or(&[][..], Vec::as_slice);
+            if let Some(deformed) = &instance.deformed[i] {
+                if deformed.palette == palette && deformed.weights == weights {
+                    continue;
+                }
+            }
+            let vertices = deform_vertices(
+                &mesh.primitive.vertices,
+                &deform.joints,
+                &deform.joint_weights,
+                &deform.morph_targets,
+                palette,
+                weights,
+            );
+            let deformed = instance.deformed[i].get_or_insert_with(|| DeformedMesh {
+                frames: vec![],
+                written: vec![false; self.frames_in_flight],
+                vertices: vec![],
+                aabb: mesh.aabb,
+                palette: vec![],
+                weights: vec![],
+                slots: BindlessSlots::default(),
+            });
+            deformed.aabb = Aabb::from_points(vertices.iter().map(|v| v.pos.truncate()));
+            deformed.vertices = vertices;
+            deformed.palette = palette.to_vec();
+            deformed.weights = weights.to_vec();
+            deformed.written.fill(false);
+        }
+    }
+
+    /// Creates the per frame copies of deformed meshes posed for the first
+    /// time, see [`RendererInternal::apply_pose`].
+    fn create_deformed_copies(&mut self) {
+        let device = self.device.clone();
+        for index in 0..self.instances.len() {
+            let Some(model) = loaded(&self.assets.models, &self.instances[index]) else {
+                continue;
+            };
+            let created: Vec<_> = self.instances[index]
+                .deformed
+                .iter()
+                .enumerate()
+                .filter_map(|(i, deformed)| Some((i, deformed.as_ref()?)))
+                .filter(|(_, deformed)| deformed.frames.is_empty())
+                .map(|(i, deformed)| {
+                    let mesh = &model.meshes[i];
+                    let primitives = (0..self.frames_in_flight)
+                        .map(|_| Primitive::new_dynamic(device.clone(), mesh.primitive.indices.clone(), deformed.vertices.clone()))
+                        .collect::<Vec<_>>();
+                    (i, primitives, self.gpu_meshes[mesh.gpu_mesh as usize].material)
+                })
+                .collect();
+            for (i, primitives, material) in created {
+                let mut slots = BindlessSlots::default();
+                let frames = primitives
+                    .into_iter()
+                    .map(|primitive| {
+                        let gpu_mesh = self.add_primitive(&device, &primitive, material, &BindlessSlots::default(), &mut slots);
+                        LodLevel { primitive, gpu_mesh }
+                    })
+                    .collect();
+                let deformed = self.instances[index].deformed[i].as_mut().unwrap();
+                deformed.frames = frames;
+                deformed.written.fill(true);
+                deformed.slots = slots;
+            }
+        }
+    }
 
-        self.instances.push(ModelInstance { model, transform });
+    /// Starts recording frame in flight `frame`: frees what the frame
+    /// retired last time round, uploads finished imports, unloads unused
+    /// assets and writes the latest pose of every deformed mesh into the copy
+    /// that frame draws, creating the copies first if needed. Only call once
+    /// the fence of `frame` has been waited on.
+    pub fn begin_frame(&mut self, frame: usize) {
+        self.frame = frame;
+        // Frames recorded since these were retired have finished along with
+        // this one, so nothing reads them any more.
+        let retired = std::mem::take(&mut self.retired[frame]);
+        for texture in retired.textures {
+            texture.clean_vk_resources();
+        }
+        for model in retired.models {
+            release_model(model);
+        }
+        let mut slots = retired.slots;
+        self.free_slots.append(&mut slots);
+        self.update_assets();
+        self.unload_unused_assets();
+        self.create_deformed_copies();
+
+        for deformed in self.instances.iter_mut().flat_map(|instance| instance.deformed.iter_mut().flatten()) {
+            if !deformed.written[frame] {
+                deformed.frames[frame].primitive.vertex_buffer.update_memory(&deformed.vertices);
+                deformed.written[frame] = true;
+            }
+        }
     }
 
-    fn add_bindless_texture(&mut self, device: &Device, texture: &Texture) -> u32 {
-        let new_image_index = self.next_bindless_image_index;
+    /// Writes `descriptor_info` to the `reuse` slot, or to a free or new one.
+    fn add_bindless_image(
+        &mut self,
+        device: &Device,
+        descriptor_info: &vk::DescriptorImageInfo,
+        reuse: Option<u32>,
+    ) -> u32 {
+        let new_image_index = reuse
+            .unwrap_or_else(|| allocate(&mut self.free_slots.images, &mut self.next_bindless_image_index));
 
         let descriptor_write = vk::WriteDescriptorSet::builder()
             .dst_set(self.bindless_descriptor_set)
             .dst_binding(0)
             .dst_array_element(new_image_index)
             .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-            .image_info(std::slice::from_ref(&texture.descriptor_info))
+            .image_info(std::slice::from_ref(descriptor_info))
             .build();
 
         unsafe {
@@ -906,13 +1491,13 @@ impl RendererInternal {
                 .update_descriptor_sets(std::slice::from_ref(&descriptor_write), &[])
         };
 
-        self.next_bindless_image_index += 1;
-
         new_image_index
     }
 
-    fn add_bindless_vertex_buffer(&mut self, device: &Device, buffer: &Buffer) -> u32 {
-        let new_buffer_index = self.next_bindless_vertex_buffer_index;
+    fn add_bindless_vertex_buffer(&mut self, device: &Device, buffer: &Buffer, reuse: Option<u32>) -> u32 {
+        let new_buffer_index = reuse.unwrap_or_else(|| {
+            allocate(&mut self.free_slots.vertex_buffers, &mut self.next_bindless_vertex_buffer_index)
+        });
 
         let buffer_info = vk::DescriptorBufferInfo::builder()
             .buffer(buffer.vk_buffer)
@@ -933,13 +1518,13 @@ impl RendererInternal {
                 .update_descriptor_sets(std::slice::from_ref(&descriptor_write), &[])
         };
 
-        self.next_bindless_vertex_buffer_index += 1;
-
         new_buffer_index
     }
 
-    fn add_bindless_index_buffer(&mut self, device: &Device, buffer: &Buffer) -> u32 {
-        let new_buffer_index = self.next_bindless_index_buffer_index;
+    fn add_bindless_index_buffer(&mut self, device: &Device, buffer: &Buffer, reuse: Option<u32>) -> u32 {
+        let new_buffer_index = reuse.unwrap_or_else(|| {
+            allocate(&mut self.free_slots.index_buffers, &mut self.next_bindless_index_buffer_index)
+        });
 
         let buffer_info = vk::DescriptorBufferInfo::builder()
             .buffer(buffer.vk_buffer)
@@ -960,22 +1545,34 @@ impl RendererInternal {
                 .update_descriptor_sets(std::slice::from_ref(&descriptor_write), &[])
         };
 
-        self.next_bindless_index_buffer_index += 1;
-
         new_buffer_index
     }
 
-    fn add_material(&mut self, gpu_material: GpuMaterial) -> u32 {
-        let material_index = self.gpu_materials.len() as u32;
-        self.gpu_materials.push(gpu_material);
+    /// Writes `gpu_material` to the `reuse` slot, or to a free or new one.
+    /// Only that entry of the materials buffer is written, the others may be
+    /// read by frames in flight.
+    fn add_material(&mut self, gpu_material: GpuMaterial, reuse: Option<u32>) -> u32 {
+        let material_index = reuse.or_else(|| self.free_slots.materials.pop()).unwrap_or_else(|| {
+            self.gpu_materials.push(gpu_material);
+            self.gpu_materials.len() as u32 - 1
+        });
+        self.gpu_materials[material_index as usize] = gpu_material;
+        self.gpu_materials_buffer
+            .update_memory_at(material_index as usize, &[gpu_material]);
 
         material_index
     }
 
-    fn add_mesh(&mut self, gpu_mesh: GpuMesh) -> u32 {
-        let gpu_index = self.gpu_meshes.len() as u32;
-        self.gpu_meshes.push(gpu_mesh);
-        println!("{}", self.gpu_meshes.len());
+    /// Writes `gpu_mesh` to the `reuse` slot, or to a free or new one, see
+    /// [`RendererInternal::add_material`].
+    fn add_mesh(&mut self, gpu_mesh: GpuMesh, reuse: Option<u32>) -> u32 {
+        let gpu_index = reuse.or_else(|| self.free_slots.meshes.pop()).unwrap_or_else(|| {
+            self.gpu_meshes.push(gpu_mesh);
+            self.gpu_meshes.len() as u32 - 1
+        });
+        self.gpu_meshes[gpu_index as usize] = gpu_mesh;
+        self.gpu_meshes_buffer
+            .update_memory_at(gpu_index as usize, &[gpu_mesh]);
         gpu_index
     }
     pub fn draw_meshes(
@@ -983,17 +1580,49 @@ impl RendererInternal {
         device: &Device,
         command_buffer: vk::CommandBuffer,
         pipeline_layout: vk::PipelineLayout,
+    ) {
+        self.draw_meshes_where(device, command_buffer, pipeline_layout, |_, _| true);
+    }
+
+    /// Draws the meshes that survived [`RendererInternal::cull`], for passes
+    /// seen through the main camera.
+    pub fn draw_visible_meshes(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+    ) {
+        self.draw_meshes_where(device, command_buffer, pipeline_layout, |instance, i| instance.visible[i]);
+    }
+
+    fn draw_meshes_where(
+        &self,
+        device: &Device,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        draw: impl Fn(&ModelInstance, usize) -> bool,
     ) {
         unsafe {
             for instance in &self.instances {
-                for (i, mesh) in instance.model.meshes.iter().enumerate() {
+                let Some(model) = loaded(&self.assets.models, instance) else {
+                    continue;
+                };
+                for (i, mesh) in model.meshes.iter().enumerate() {
+                    if !draw(instance, i) {
+                        continue;
+                    }
+                    let deformed = instance.deformed[i].as_ref().and_then(|deformed| deformed.frames.get(self.frame));
+                    let (primitive, gpu_mesh) = match deformed {
+                        Some(level) => (&level.primitive, level.gpu_mesh),
+                        None => mesh.level(instance.lods[i]),
+                    };
                     device.cmd_push_constants(
                         command_buffer,
                         pipeline_layout,
                         (
-                            instance.transform * instance.model.transforms[i],
+                            instance.transform * instance.transforms[i],
                             glam::Vec4::new(1.0, 0.5, 0.2, 1.0),
-                            mesh.gpu_mesh,
+                            gpu_mesh,
                             [0; 3],
                         ),
                     );
@@ -1001,18 +1630,18 @@ impl RendererInternal {
                     device.device().cmd_bind_vertex_buffers(
                         command_buffer,
                         0,
-                        &[mesh.primitive.vertex_buffer.vk_buffer],
+                        &[primitive.vertex_buffer.vk_buffer],
                         &[0],
                     );
                     device.device().cmd_bind_index_buffer(
                         command_buffer,
-                        mesh.primitive.index_buffer.vk_buffer,
+                        primitive.index_buffer.vk_buffer,
                         0,
                         vk::IndexType::UINT32,
                     );
                     device.device().cmd_draw_indexed(
                         command_buffer,
-                        mesh.primitive.indices.len() as u32,
+                        primitive.indices.len() as u32,
                         1,
                         0,
                         0,
@@ -1070,10 +1699,14 @@ pub fn create_bindless_descriptor_set_layout(device: &Device) -> vk::DescriptorS
             .build(),
     ];
 
+    // Textures and mesh buffers are written to slots no frame in flight
+    // reads while earlier frames are still executing.
+    let bindless_array_flags = vk::DescriptorBindingFlags::PARTIALLY_BOUND
+        | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING;
     let binding_flags: [vk::DescriptorBindingFlags; 5] = [
-        vk::DescriptorBindingFlags::PARTIALLY_BOUND,
-        vk::DescriptorBindingFlags::PARTIALLY_BOUND,
-        vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+        bindless_array_flags,
+        bindless_array_flags,
+        bindless_array_flags,
         vk::DescriptorBindingFlags::PARTIALLY_BOUND,
         vk::DescriptorBindingFlags::PARTIALLY_BOUND
             | vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT,
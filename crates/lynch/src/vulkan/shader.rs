@@ -4,17 +4,14 @@ use rspirv_reflect::DescriptorInfo;
 use rspirv_reflect::DescriptorType;
 use rspirv_reflect::PushConstantInfo;
 use rspirv_reflect::Reflection;
-use shaderc::CompilationArtifact;
 use shaderc::EnvVersion;
 use shaderc::Error;
-use shaderc::IncludeCallbackResult;
-use shaderc::ResolvedInclude;
 use shaderc::ShaderKind;
 use shaderc::TargetEnv;
 
-use std::fs::read_to_string;
 use std::{
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{Display, Formatter},
     io::Cursor,
     path::Path,
 };
@@ -22,6 +19,10 @@ use std::{
 use rspirv_reflect;
 use shaderc;
 
+use super::shader_source::{ShaderError, ShaderSource};
+use super::spirv_cache::{CompileSettings, SpirvCache};
+use super::spirv_interface::ShaderInterface;
+
 #[derive(Debug, Clone)]
 pub struct Binding {
     pub set: u32,
@@ -40,43 +41,103 @@ impl std::ops::Deref for Binding {
 type DescriptorSetMap = BTreeMap<u32, BTreeMap<u32, DescriptorInfo>>;
 pub type BindingMap = BTreeMap<String, Binding>;
 
+/// Why the shaders of a pipeline could not be reflected.
+#[derive(Debug, PartialEq)]
+pub enum ShaderReflectError {
+    /// A stage is not valid SPIR-V.
+    Spirv(String),
+    /// Two stages declare `binding` of `set` as different descriptors.
+    InconsistentBinding {
+        set: u32,
+        binding: u32,
+        first: String,
+        second: String,
+    },
+    /// Two stages declare push constant blocks of different sizes.
+    PushConstantSize { first: u32, second: u32 },
+    UnknownBinding(String),
+}
+
+impl Display for ShaderReflectError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ShaderReflectError::Spirv(error) => write!(f, "Invalid SPIR-V: {}", error),
+            ShaderReflectError::InconsistentBinding {
+                set,
+                binding,
+                first,
+                second,
+            } => write!(
+                f,
+                "Set {} binding {} is {} in one stage and {} in another",
+                set, binding, first, second
+            ),
+            ShaderReflectError::PushConstantSize { first, second } => write!(
+                f,
+                "Push constants are {} bytes in one stage and {} in another",
+                first, second
+            ),
+            ShaderReflectError::UnknownBinding(name) => write!(f, "No binding is called \"{}\"", name),
+        }
+    }
+}
+
+impl std::error::Error for ShaderReflectError {}
+
+fn invalid(error: impl Display) -> ShaderReflectError {
+    ShaderReflectError::Spirv(error.to_string())
+}
 
+fn describe(descriptor: &DescriptorInfo) -> String {
+    format!("{:?} \"{}\"", descriptor.ty, descriptor.name)
+}
 
 #[derive(Default)]
 pub struct ShaderReflect {
     pub descriptor_set_reflections: DescriptorSetMap,
     pub push_constant_reflections: Vec<PushConstantInfo>,
     pub binding_mappings: HashMap<String, Binding>,
+    /// Bytes each uniform block takes, keyed by set and binding.
+    pub uniform_block_sizes: BTreeMap<(u32, u32), u32>,
+    /// Locations the fragment stage writes colours to.
+    pub fragment_outputs: BTreeSet<u32>,
 }
 
 const MAIN_ENTRY_POINT: &'static str = r#"main"#;
 
+/// What every shader is compiled with, part of its SPIR-V cache key.
+const COMPILE_SETTINGS: CompileSettings = CompileSettings {
+    entry_point: MAIN_ENTRY_POINT,
+    target_env_version: EnvVersion::Vulkan1_2 as u32,
+    debug_info: true,
+};
+
 impl ShaderReflect {
-    pub fn new(shader_stages: &[&[u8]]) -> ShaderReflect {
+    pub fn new(shader_stages: &[&[u8]]) -> Result<ShaderReflect, ShaderReflectError> {
         let mut descriptor_sets_combined: DescriptorSetMap = BTreeMap::new();
         let mut push_constant_ranges: Vec<PushConstantInfo> = vec![];
+        let mut uniform_block_sizes = BTreeMap::new();
+        let mut fragment_outputs = BTreeSet::new();
 
         for shader_stage in shader_stages {
-            let stage_reflection = Reflection::new_from_spirv(shader_stage)
-                .expect("Shader reflection failed!");
+            let stage_reflection = Reflection::new_from_spirv(shader_stage).map_err(invalid)?;
 
-            let descriptor_sets = stage_reflection.get_descriptor_sets().unwrap();
+            let descriptor_sets = stage_reflection.get_descriptor_sets().map_err(invalid)?;
 
             for (set, descriptor_set) in descriptor_sets {
                 if let Some(existing_descriptor_set) = descriptor_sets_combined.get_mut(&set) {
                     for (binding, descriptor) in descriptor_set {
                         if let Some(existing_descriptor) = existing_descriptor_set.get(&binding) {
-                            assert!(
-                                (descriptor.ty == existing_descriptor.ty && descriptor.name == existing_descriptor.name),
-                                "Set: {} binding: {} inconsistent between shader stages:\n{:#?} {:#?}",
-                                set,
-                                binding,
-                                descriptor,
-                                *existing_descriptor,
-                            );
+                            if descriptor.ty != existing_descriptor.ty || descriptor.name != existing_descriptor.name {
+                                return Err(ShaderReflectError::InconsistentBinding {
+                                    set,
+                                    binding,
+                                    first: describe(existing_descriptor),
+                                    second: describe(&descriptor),
+                                });
+                            }
                         } else {
                             existing_descriptor_set.insert(binding, descriptor);
-                            println!("Set: {} binding: {} does not exist, adding!", set, binding);
                         }
                     }
                 } else {
@@ -84,11 +145,21 @@ impl ShaderReflect {
                 }
             }
 
-            if let Some(push_constant_reflection) =
-                stage_reflection.get_push_constant_range().unwrap()
-            {
+            if let Some(push_constant_reflection) = stage_reflection.get_push_constant_range().map_err(invalid)? {
+                if let Some(first) = push_constant_ranges.first() {
+                    if first.size != push_constant_reflection.size {
+                        return Err(ShaderReflectError::PushConstantSize {
+                            first: first.size,
+                            second: push_constant_reflection.size,
+                        });
+                    }
+                }
                 push_constant_ranges.push(push_constant_reflection);
             }
+
+            let interface = ShaderInterface::parse(shader_stage)?;
+            uniform_block_sizes.extend(interface.uniform_block_sizes);
+            fragment_outputs.extend(interface.fragment_outputs);
         }
 
         let binding_mappings: HashMap<String, Binding> = descriptor_sets_combined
@@ -112,11 +183,13 @@ impl ShaderReflect {
             })
             .collect();
 
-        ShaderReflect {
+        Ok(ShaderReflect {
             descriptor_set_reflections: descriptor_sets_combined,
             push_constant_reflections: push_constant_ranges,
             binding_mappings,
-        }
+            uniform_block_sizes,
+            fragment_outputs,
+        })
     }
 
     pub fn get_set_mappings(&self, set: u32) -> BindingMap {
@@ -132,65 +205,62 @@ impl ShaderReflect {
             .collect::<BindingMap>()
     }
 
-    pub fn get_binding(&self, name: &str) -> Binding {
-        match self.binding_mappings.get(name) {
-            Some(binding) => binding.clone(),
-            None => panic!("Binding with \"{}\" name not available", name),
-        }
+    pub fn get_binding(&self, name: &str) -> Result<&Binding, ShaderReflectError> {
+        self.binding_mappings
+            .get(name)
+            .ok_or_else(|| ShaderReflectError::UnknownBinding(name.to_owned()))
     }
 }
 
-#[must_use]
-pub fn compile_glsl_shader(path: &str) -> Result<CompilationArtifact, Error> {
-    let binding = read_to_string(path);
-    let source = match &binding {
-        Ok(source) => source,
-        Err(err) => panic!(
-            "Error compiling shader: Cannot find path: {}.\nOs Error:({})",
-            path, err
-        ),
-    };
-
-    let shader_kind = match path.get(path.len().saturating_sub(5)..) {
-        Some(".vert") => ShaderKind::Vertex,
-        Some(".frag") => ShaderKind::Fragment,
-        Some(".comp") => ShaderKind::Compute,
-        _ => todo!("Unsupported."),
+/// Compiles the shader at `path` with `defines`, expanding its includes
+/// first so errors name the file and line they come from, unless `cache`
+/// holds it already.
+pub fn compile_glsl_shader(
+    path: &str,
+    defines: &BTreeMap<String, String>,
+    cache: &SpirvCache,
+) -> Result<(Vec<u8>, ShaderSource), ShaderError> {
+    let shader_kind = match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+        Some("vert") => ShaderKind::Vertex,
+        Some("frag") => ShaderKind::Fragment,
+        Some("comp") => ShaderKind::Compute,
+        _ => return Err(ShaderError::UnknownStage(path.into())),
     };
+    let source = ShaderSource::load(Path::new(path))?;
+    let key = SpirvCache::key(&source, defines, &COMPILE_SETTINGS);
+    if let Some(spirv) = cache.get(key) {
+        return Ok((spirv, source));
+    }
 
-    let mut compiler = shaderc::Compiler::new().unwrap();
+    let compiler = shaderc::Compiler::new().unwrap();
     let mut options = shaderc::CompileOptions::new().unwrap();
-    options.add_macro_definition("EP", Some(MAIN_ENTRY_POINT));
-    options.set_target_env(TargetEnv::Vulkan, EnvVersion::Vulkan1_2 as u32);
-    options.set_generate_debug_info();
-    options.set_include_callback(|include_request, _include_type, _source, _size| -> Result<ResolvedInclude, String> {
-        let include_path = Path::new(path).parent().unwrap();
-        let mut include_path = include_path.join(include_request);
-        if !Path::new(&include_path).exists() {
-            include_path = Path::new("assets/shaders").join(include_request);
-        }
-
-        let include_source =
-            &read_to_string(include_path).expect("Error reading included file")[..];
-
-        IncludeCallbackResult::Ok(ResolvedInclude {
-            resolved_name: include_request.to_string(),
-            content: include_source.to_string(),
-        })
-    });
+    options.add_macro_definition("EP", Some(COMPILE_SETTINGS.entry_point));
+    for (name, value) in defines {
+        options.add_macro_definition(name, Some(value.as_str()));
+    }
+    options.set_target_env(TargetEnv::Vulkan, COMPILE_SETTINGS.target_env_version);
+    if COMPILE_SETTINGS.debug_info {
+        options.set_generate_debug_info();
+    }
 
-    let binary_result =
-        compiler.compile_into_spirv(source, shader_kind, path, MAIN_ENTRY_POINT, Some(&options))?;
+    let binary_result = compiler
+        .compile_into_spirv(&source.text, shader_kind, path, COMPILE_SETTINGS.entry_point, Some(&options))
+        .map_err(|error| match error {
+            Error::CompilationError(_, log) => ShaderError::Compile(source.map_log(&log)),
+            error => ShaderError::Compile(error.to_string()),
+        })?;
 
     debug_assert_eq!(Some(&119734787), binary_result.as_binary().first());
 
-    let text_result = compiler
-        .compile_into_spirv_assembly(source, shader_kind, path, MAIN_ENTRY_POINT, Some(&options))
-        .unwrap();
-
-    debug_assert!(text_result.as_text().starts_with("; SPIR-V\n"));
+    if binary_result.get_num_warnings() > 0 {
+        log::warn!("{}", source.map_log(&binary_result.get_warning_messages()));
+    }
 
-    Ok(binary_result)
+    let spirv = binary_result.as_binary_u8().to_vec();
+    if let Err(err) = cache.insert(key, &spirv) {
+        log::warn!("Failed to cache {}: {}", path, err);
+    }
+    Ok((spirv, source))
 }
 /// TODO Owner MUST clean this up
 #[must_use]
@@ -302,3 +372,53 @@ pub fn create_shader_module(mut spv_file: Cursor<&[u8]>, device: &ash::Device) -
             .expect("Error creating shader module on device.")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::shader_path;
+
+    fn reflect(stages: &[&str]) -> Result<ShaderReflect, ShaderReflectError> {
+        let spirv: Vec<Vec<u8>> = stages.iter().map(|stage| std::fs::read(shader_path(stage)).unwrap()).collect();
+        let stages: Vec<&[u8]> = spirv.iter().map(Vec::as_slice).collect();
+        ShaderReflect::new(&stages)
+    }
+
+    #[test]
+    fn stages_are_combined() {
+        let blur = reflect(&["fullscreen.vert.spv", "blur.frag.spv"]).unwrap();
+
+        let binding = blur.get_binding("blur_settings").unwrap();
+        assert_eq!((binding.set, binding.binding), (3, 0));
+        assert_eq!(binding.ty, DescriptorType::UNIFORM_BUFFER);
+        assert_eq!(blur.uniform_block_sizes.get(&(3, 0)), Some(&112));
+        assert_eq!(blur.get_set_mappings(2).len(), 2);
+        assert_eq!(blur.push_constant_reflections.len(), 1);
+        assert_eq!(blur.fragment_outputs, BTreeSet::from([0, 1]));
+        assert_eq!(
+            blur.get_binding("missing").unwrap_err(),
+            ShaderReflectError::UnknownBinding("missing".to_owned())
+        );
+    }
+
+    #[test]
+    fn stages_that_disagree_are_errors() {
+        assert_eq!(
+            reflect(&["blur.frag.spv", "mismatched.frag.spv"]).err(),
+            Some(ShaderReflectError::InconsistentBinding {
+                set: 2,
+                binding: 0,
+                first: "COMBINED_IMAGE_SAMPLER \"in_color\"".to_owned(),
+                second: "STORAGE_IMAGE \"in_color\"".to_owned(),
+            })
+        );
+        assert_eq!(
+            reflect(&["push_constants.vert.spv", "blur.frag.spv"]).err(),
+            Some(ShaderReflectError::PushConstantSize { first: 64, second: 4 })
+        );
+        assert!(matches!(
+            ShaderReflect::new(&[&[0; 20]]),
+            Err(ShaderReflectError::Spirv(_))
+        ));
+    }
+}
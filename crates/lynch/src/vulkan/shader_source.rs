@@ -0,0 +1,339 @@
+//! Shader sources with their includes expanded, so compile errors can point
+//! at the file and line they come from and the files a shader depends on
+//! are known before it is compiled.
+
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Formatter},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::hash::ContentHash;
+
+use super::shader::ShaderReflectError;
+
+/// Directory `#include`s are looked up in when they are not next to the
+/// including file.
+pub const SHADER_DIR: &str = "assets/shaders";
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io { path: PathBuf, error: std::io::Error },
+    /// The included file is neither next to the including file nor in the
+    /// shader directory.
+    MissingInclude { file: PathBuf, line: u32, include: String },
+    /// A file includes itself, directly or through other includes.
+    RecursiveInclude { file: PathBuf, line: u32, include: PathBuf },
+    /// Shaders are `.vert`, `.frag` or `.comp` files.
+    UnknownStage(PathBuf),
+    /// Compiler output, with lines pointing into the original files.
+    Compile(String),
+    /// The compiled stages do not fit together.
+    Reflection(ShaderReflectError),
+}
+
+impl Display for ShaderError {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        match self {
+            ShaderError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ShaderError::MissingInclude { file, line, include } => {
+                write!(f, "{}:{}: cannot find include {}", file.display(), line, include)
+            }
+            ShaderError::RecursiveInclude { file, line, include } => write!(
+                f,
+                "{}:{}: {} includes itself",
+                file.display(),
+                line,
+                include.display()
+            ),
+            ShaderError::UnknownStage(path) => write!(f, "Unknown shader stage of {}", path.display()),
+            ShaderError::Compile(log) => write!(f, "{}", log),
+            ShaderError::Reflection(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+impl From<ShaderReflectError> for ShaderError {
+    fn from(error: ShaderReflectError) -> Self {
+        ShaderError::Reflection(error)
+    }
+}
+
+/// Stable hash of `defines`, telling the variants of a pipeline apart
+/// across runs.
+pub fn permutation_key(defines: &BTreeMap<String, String>) -> u64 {
+    // Names and values are terminated so ("AB", "") and ("A", "B") differ.
+    let mut hash = ContentHash::default();
+    for (name, value) in defines {
+        hash.update(name.as_bytes());
+        hash.update(&[0]);
+        hash.update(value.as_bytes());
+        hash.update(&[0]);
+    }
+    hash.finish()
+}
+
+pub struct ShaderSource {
+    pub path: PathBuf,
+    /// The source with every `#include` replaced by the included file.
+    pub text: String,
+    /// The shader and the files it includes, in the order they were first
+    /// included.
+    pub files: Vec<PathBuf>,
+    /// Index into `files` and line number of each line of `text`.
+    lines: Vec<(usize, u32)>,
+}
+
+impl ShaderSource {
+    pub fn load(path: &Path) -> Result<Self, ShaderError> {
+        Self::expand(path, Path::new(SHADER_DIR), |file| std::fs::read_to_string(file))
+    }
+
+    /// Reads `path` and its includes with `read`, looking includes up next
+    /// to the including file and then in `shader_dir`.
+    pub fn expand(
+        path: &Path,
+        shader_dir: &Path,
+        mut read: impl FnMut(&Path) -> std::io::Result<String>,
+    ) -> Result<Self, ShaderError> {
+        let text = read(path).map_err(|error| ShaderError::Io {
+            path: path.to_path_buf(),
+            error,
+        })?;
+        let mut source = ShaderSource {
+            path: path.to_path_buf(),
+            text: String::new(),
+            files: vec![],
+            lines: vec![],
+        };
+        source.include(path, &text, shader_dir, &mut read, &mut vec![])?;
+        Ok(source)
+    }
+
+    fn include(
+        &mut self,
+        file: &Path,
+        text: &str,
+        shader_dir: &Path,
+        read: &mut impl FnMut(&Path) -> std::io::Result<String>,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), ShaderError> {
+        let index = match self.files.iter().position(|known| known == file) {
+            Some(index) => index,
+            None => {
+                self.files.push(file.to_path_buf());
+                self.files.len() - 1
+            }
+        };
+        stack.push(file.to_path_buf());
+
+        for (line, content) in (1..).zip(text.lines()) {
+            let Some(include) = include_directive(content) else {
+                self.text.push_str(content);
+                self.text.push('\n');
+                self.lines.push((index, line));
+                continue;
+            };
+
+            let beside = normalize(&file.parent().unwrap_or(Path::new("")).join(include));
+            let (included, included_text) = match read(&beside) {
+                Ok(text) => (beside, text),
+                Err(_) => {
+                    let in_shader_dir = normalize(&shader_dir.join(include));
+                    match read(&in_shader_dir) {
+                        Ok(text) => (in_shader_dir, text),
+                        Err(_) => {
+                            return Err(ShaderError::MissingInclude {
+                                file: file.to_path_buf(),
+                                line,
+                                include: include.to_string(),
+                            })
+                        }
+                    }
+                }
+            };
+            if stack.contains(&included) {
+                return Err(ShaderError::RecursiveInclude {
+                    file: file.to_path_buf(),
+                    line,
+                    include: included,
+                });
+            }
+            self.include(&included, &included_text, shader_dir, read, stack)?;
+        }
+
+        stack.pop();
+        Ok(())
+    }
+
+    /// File and line that line `line` of `text` was read from, counting
+    /// from 1 like compilers do.
+    pub fn origin(&self, line: u32) -> Option<(&Path, u32)> {
+        let (file, line) = self.lines.get(line.checked_sub(1)? as usize)?;
+        Some((&self.files[*file], *line))
+    }
+
+    /// `log` with each `path:line:` of the expanded source replaced by the
+    /// file and line it came from.
+    pub fn map_log(&self, log: &str) -> String {
+        let prefix = format!("{}:", self.path.display());
+        let mut mapped = String::with_capacity(log.len());
+        let mut rest = log;
+        while let Some(start) = rest.find(&prefix) {
+            let after = &rest[start + prefix.len()..];
+            let digits = after.len() - after.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let origin = after[..digits]
+                .parse()
+                .ok()
+                .filter(|_| after[digits..].starts_with(':'))
+                .and_then(|line| self.origin(line));
+
+            mapped.push_str(&rest[..start]);
+            match origin {
+                Some((file, line)) => {
+                    mapped.push_str(&format!("{}:{}", file.display(), line));
+                    rest = &after[digits..];
+                }
+                None => {
+                    mapped.push_str(&prefix);
+                    rest = after;
+                }
+            }
+        }
+        mapped.push_str(rest);
+        mapped
+    }
+}
+
+/// `path` without `.` and with `..` removed where it follows a directory,
+/// so files included along different routes compare equal.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir if matches!(normalized.components().next_back(), Some(Component::Normal(_))) => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The file named by an `#include "file"` or `#include <file>` line.
+fn include_directive(line: &str) -> Option<&str> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+    let name = directive.strip_prefix("include")?.trim();
+    name.strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+        .or_else(|| name.strip_prefix('<').and_then(|name| name.strip_suffix('>')))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io};
+
+    use super::*;
+
+    fn expand(files: &[(&str, &str)], path: &str) -> Result<ShaderSource, ShaderError> {
+        let files: HashMap<PathBuf, String> = files
+            .iter()
+            .map(|(path, text)| (PathBuf::from(path), text.to_string()))
+            .collect();
+        ShaderSource::expand(Path::new(path), Path::new("shaders"), |path| {
+            files
+                .get(path)
+                .cloned()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+        })
+    }
+
+    const FILES: &[(&str, &str)] = &[
+        (
+            "shaders/passes/blur.frag",
+            "#version 450\n#include \"common.glsl\"\n  #  include <include/view.glsl>\nvoid main() {}\n",
+        ),
+        ("shaders/passes/common.glsl", "float common;\n"),
+        ("shaders/include/view.glsl", "#include \"../passes/common.glsl\"\nfloat view;\n"),
+    ];
+
+    #[test]
+    fn includes_are_expanded_with_their_origin() {
+        let source = expand(FILES, "shaders/passes/blur.frag").unwrap();
+        assert_eq!(
+            source.text,
+            "#version 450\nfloat common;\nfloat common;\nfloat view;\nvoid main() {}\n"
+        );
+        assert_eq!(
+            source.files,
+            vec![
+                PathBuf::from("shaders/passes/blur.frag"),
+                PathBuf::from("shaders/passes/common.glsl"),
+                PathBuf::from("shaders/include/view.glsl"),
+            ]
+        );
+        let origin = |line| source.origin(line).map(|(file, line)| (file.to_str().unwrap(), line));
+        assert_eq!(origin(1), Some(("shaders/passes/blur.frag", 1)));
+        assert_eq!(origin(3), Some(("shaders/passes/common.glsl", 1)));
+        assert_eq!(origin(4), Some(("shaders/include/view.glsl", 2)));
+        assert_eq!(origin(5), Some(("shaders/passes/blur.frag", 4)));
+        assert_eq!(origin(0), None);
+        assert_eq!(origin(6), None);
+    }
+
+    #[test]
+    fn compiler_output_points_at_the_original_lines() {
+        let source = expand(FILES, "shaders/passes/blur.frag").unwrap();
+        let log = "shaders/passes/blur.frag:4: error: 'view' : redefinition\n\
+                   shaders/passes/blur.frag:9: error: past the end\n\
+                   1 error generated.";
+        assert_eq!(
+            source.map_log(log),
+            "shaders/include/view.glsl:2: error: 'view' : redefinition\n\
+             shaders/passes/blur.frag:9: error: past the end\n\
+             1 error generated."
+        );
+    }
+
+    #[test]
+    fn missing_and_recursive_includes_are_errors() {
+        let missing = expand(&[("shaders/a.frag", "\n#include \"b.glsl\"\n")], "shaders/a.frag");
+        assert_eq!(
+            missing.err().unwrap().to_string(),
+            "shaders/a.frag:2: cannot find include b.glsl"
+        );
+
+        let recursive = expand(
+            &[
+                ("shaders/a.frag", "#include \"b.glsl\"\n"),
+                ("shaders/b.glsl", "#include \"a.frag\"\n"),
+            ],
+            "shaders/a.frag",
+        );
+        assert_eq!(
+            recursive.err().unwrap().to_string(),
+            "shaders/b.glsl:1: shaders/a.frag includes itself"
+        );
+    }
+
+    #[test]
+    fn permutation_keys_follow_the_defines() {
+        let defines = |pairs: &[(&str, &str)]| -> BTreeMap<String, String> {
+            pairs
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+        let pcf = permutation_key(&defines(&[("PCF", "1"), ("CASCADES", "4")]));
+        assert_eq!(pcf, permutation_key(&defines(&[("CASCADES", "4"), ("PCF", "1")])));
+        assert_ne!(pcf, permutation_key(&defines(&[("PCF", "1"), ("CASCADES", "2")])));
+        assert_ne!(
+            permutation_key(&defines(&[("AB", "")])),
+            permutation_key(&defines(&[("A", "B")]))
+        );
+        assert_ne!(pcf, permutation_key(&BTreeMap::new()));
+    }
+}
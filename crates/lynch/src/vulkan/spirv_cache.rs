@@ -0,0 +1,204 @@
+//! Compiled shaders kept on disk, so unchanged shaders are not compiled
+//! again on the next run.
+
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::hash::ContentHash;
+
+use super::shader_source::{permutation_key, ShaderSource};
+
+pub const SPIRV_CACHE_DIR: &str = "cache/spirv";
+
+/// Changed whenever the cached files or anything else about compiling that
+/// [`CompileSettings`] does not cover changes, invalidating every cached
+/// shader.
+const SPIRV_CACHE_VERSION: u32 = 2;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+
+/// Checksum of the SPIR-V that follows it.
+const HEADER_SIZE: usize = 8;
+
+/// Compiler options every shader is built with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompileSettings {
+    pub entry_point: &'static str,
+    /// `shaderc::EnvVersion` of the Vulkan version targeted.
+    pub target_env_version: u32,
+    pub debug_info: bool,
+}
+
+pub struct SpirvCache {
+    dir: PathBuf,
+}
+
+impl SpirvCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        SpirvCache { dir: dir.into() }
+    }
+
+    /// Hash of everything the SPIR-V of `source` depends on: its path, its
+    /// text with the includes expanded, the files they came from, `defines`
+    /// and the compiler `settings`.
+    pub fn key(source: &ShaderSource, defines: &BTreeMap<String, String>, settings: &CompileSettings) -> u64 {
+        let mut hash = ContentHash::default();
+        hash.update(&SPIRV_CACHE_VERSION.to_le_bytes());
+        hash.update(settings.entry_point.as_bytes());
+        hash.update(&[0]);
+        hash.update(&settings.target_env_version.to_le_bytes());
+        hash.update(&[settings.debug_info as u8]);
+        for file in &source.files {
+            hash.update(file.to_string_lossy().as_bytes());
+            hash.update(&[0]);
+        }
+        hash.update(source.text.as_bytes());
+        hash.update(&permutation_key(defines).to_le_bytes());
+        hash.finish()
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.spv", key))
+    }
+
+    /// The SPIR-V cached under `key`. Truncated or otherwise corrupt files
+    /// are removed and compiled again.
+    pub fn get(&self, key: u64) -> Option<Vec<u8>> {
+        let path = self.path(key);
+        let mut file = fs::read(&path).ok()?;
+        if !is_valid(&file) {
+            log::warn!("Discarding corrupt cached shader {}", path.display());
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        file.drain(..HEADER_SIZE);
+        Some(file)
+    }
+
+    pub fn insert(&self, key: u64, spirv: &[u8]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut hash = ContentHash::default();
+        hash.update(spirv);
+        let mut file = hash.finish().to_le_bytes().to_vec();
+        file.extend_from_slice(spirv);
+        write_atomically(&self.path(key), &file)
+    }
+}
+
+fn is_valid(file: &[u8]) -> bool {
+    if file.len() < HEADER_SIZE + 20 || !(file.len() - HEADER_SIZE).is_multiple_of(4) {
+        return false;
+    }
+    let (checksum, spirv) = file.split_at(HEADER_SIZE);
+    let mut hash = ContentHash::default();
+    hash.update(spirv);
+    checksum == hash.finish().to_le_bytes() && spirv[..4] == SPIRV_MAGIC.to_le_bytes()
+}
+
+/// Writes next to `path` and renames, so a crash never leaves half a file
+/// under the final name.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, contents)?;
+    fs::rename(&temporary, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(text: &str, include: &str) -> ShaderSource {
+        ShaderSource::expand(Path::new("shaders/a.frag"), Path::new("shaders"), |path| {
+            match path.to_str().unwrap() {
+                "shaders/a.frag" => Ok(text.to_owned()),
+                "shaders/b.glsl" => Ok(include.to_owned()),
+                _ => Err(io::Error::from(io::ErrorKind::NotFound)),
+            }
+        })
+        .unwrap()
+    }
+
+    fn spirv(words: &[u32]) -> Vec<u8> {
+        [SPIRV_MAGIC, 0x0001_0500, 0, 16, 0]
+            .iter()
+            .chain(words)
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+
+    const SETTINGS: CompileSettings = CompileSettings {
+        entry_point: "main",
+        target_env_version: 1 << 22 | 2 << 12,
+        debug_info: true,
+    };
+
+    #[test]
+    fn keys_cover_sources_includes_defines_and_settings() {
+        let defines = BTreeMap::from([("PCF".to_owned(), "1".to_owned())]);
+        let main = || source("#include \"b.glsl\"\nvoid main() {}\n", "float b;\n");
+        let key = SpirvCache::key(&main(), &defines, &SETTINGS);
+
+        assert_eq!(key, SpirvCache::key(&main(), &defines, &SETTINGS));
+        assert_ne!(
+            key,
+            SpirvCache::key(&source("#include \"b.glsl\"\nvoid main() { }\n", "float b;\n"), &defines, &SETTINGS)
+        );
+        assert_ne!(
+            key,
+            SpirvCache::key(&source("#include \"b.glsl\"\nvoid main() {}\n", "float c;\n"), &defines, &SETTINGS)
+        );
+        assert_ne!(key, SpirvCache::key(&main(), &BTreeMap::new(), &SETTINGS));
+
+        let changed = [
+            CompileSettings {
+                entry_point: "entry",
+                ..SETTINGS
+            },
+            CompileSettings {
+                target_env_version: 1 << 22 | 3 << 12,
+                ..SETTINGS
+            },
+            CompileSettings {
+                debug_info: false,
+                ..SETTINGS
+            },
+        ];
+        for settings in changed {
+            assert_ne!(key, SpirvCache::key(&main(), &defines, &settings), "{:?}", settings);
+        }
+    }
+
+    #[test]
+    fn cached_shaders_are_read_back_and_corrupt_ones_discarded() {
+        let dir = std::env::temp_dir().join(format!("lynch_spirv_cache_{}", std::process::id()));
+        let cache = SpirvCache::new(&dir);
+        assert_eq!(cache.get(1), None);
+
+        let code = spirv(&[1, 2, 3]);
+        cache.insert(1, &code).unwrap();
+        assert_eq!(cache.get(1), Some(code.clone()));
+
+        cache.insert(2, &code).unwrap();
+        let path = cache.path(2);
+        let mut file = fs::read(&path).unwrap();
+        let last = file.len() - 1;
+        file[last] ^= 1;
+        fs::write(&path, &file).unwrap();
+        assert_eq!(cache.get(2), None);
+        assert!(!path.exists());
+
+        cache.insert(3, &code).unwrap();
+        let path = cache.path(3);
+        let file = fs::read(&path).unwrap();
+        fs::write(&path, &file[..file.len() - 4]).unwrap();
+        assert_eq!(cache.get(3), None);
+
+        cache.insert(4, b"not spir-v but a multiple of 4 bytes").unwrap();
+        assert_eq!(cache.get(4), None);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+//! What reflection through `rspirv_reflect` leaves out: the sizes of uniform
+//! blocks and the colour outputs of fragment shaders, read straight from the
+//! SPIR-V words.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::shader::ShaderReflectError;
+
+const SPIRV_MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+
+const EXECUTION_MODEL_FRAGMENT: u32 = 4;
+
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_OUTPUT: u32 = 3;
+
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_ROW_MAJOR: u32 = 4;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ShaderInterface {
+    /// Bytes up to the end of the last member of each uniform block, keyed
+    /// by set and binding.
+    pub uniform_block_sizes: BTreeMap<(u32, u32), u32>,
+    /// Locations a fragment shader writes colours to.
+    pub fragment_outputs: BTreeSet<u32>,
+}
+
+enum Type {
+    Scalar { bytes: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, columns: u32 },
+    Array { element: u32, length: u32 },
+    RuntimeArray,
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Default)]
+struct Member {
+    offset: u32,
+    matrix_stride: Option<u32>,
+    row_major: bool,
+}
+
+#[derive(Default)]
+struct Module {
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    /// Type and storage class of every global variable.
+    variables: Vec<(u32, u32, u32)>,
+    decorations: HashMap<u32, Vec<(u32, u32)>>,
+    members: HashMap<(u32, u32), Member>,
+    fragment: bool,
+}
+
+impl Module {
+    fn decoration(&self, id: u32, decoration: u32) -> Option<u32> {
+        self.decorations
+            .get(&id)?
+            .iter()
+            .find(|(kind, _)| *kind == decoration)
+            .map(|&(_, value)| value)
+    }
+
+    fn size(&self, id: u32, member: Option<&Member>) -> Result<u32, ShaderReflectError> {
+        let ty = self
+            .types
+            .get(&id)
+            .ok_or_else(|| ShaderReflectError::Spirv(format!("type %{} is not defined", id)))?;
+        Ok(match *ty {
+            Type::Scalar { bytes } => bytes,
+            Type::Vector { component, count } => count * self.size(component, None)?,
+            Type::Matrix { column, columns } => {
+                let rows = match self.types.get(&column) {
+                    Some(Type::Vector { count, .. }) => *count,
+                    _ => 1,
+                };
+                match member.and_then(|member| member.matrix_stride.map(|stride| (stride, member.row_major))) {
+                    Some((stride, false)) => columns * stride,
+                    Some((stride, true)) => rows * stride,
+                    None => columns * self.size(column, None)?,
+                }
+            }
+            Type::Array { element, length } => {
+                let length = *self.constants.get(&length).ok_or_else(|| {
+                    ShaderReflectError::Spirv(format!("array length %{} is not a constant", length))
+                })?;
+                match self.decoration(id, DECORATION_ARRAY_STRIDE) {
+                    Some(stride) => length * stride,
+                    None => length * self.size(element, member)?,
+                }
+            }
+            Type::RuntimeArray => 0,
+            Type::Struct { ref members } => {
+                let mut size = 0;
+                for (index, &member_type) in members.iter().enumerate() {
+                    let member = self.members.get(&(id, index as u32));
+                    let offset = member.map_or(0, |member| member.offset);
+                    size = size.max(offset + self.size(member_type, member)?);
+                }
+                size
+            }
+            Type::Pointer { .. } => 8,
+        })
+    }
+}
+
+impl ShaderInterface {
+    pub fn parse(spirv: &[u8]) -> Result<ShaderInterface, ShaderReflectError> {
+        if spirv.len() < HEADER_WORDS * 4 || !spirv.len().is_multiple_of(4) {
+            return Err(ShaderReflectError::Spirv("truncated module".to_owned()));
+        }
+        let words: Vec<u32> = spirv
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+        if words[0] != SPIRV_MAGIC {
+            return Err(ShaderReflectError::Spirv("not a SPIR-V module".to_owned()));
+        }
+
+        let mut module = Module::default();
+        let mut at = HEADER_WORDS;
+        while at < words.len() {
+            let count = (words[at] >> 16) as usize;
+            let opcode = words[at] & 0xffff;
+            if count == 0 || at + count > words.len() {
+                return Err(ShaderReflectError::Spirv(format!("malformed instruction at word {}", at)));
+            }
+            let operands = &words[at + 1..at + count];
+            at += count;
+
+            let operand = |index: usize| {
+                operands.get(index).copied().ok_or_else(|| {
+                    ShaderReflectError::Spirv(format!("instruction {} is missing operands", opcode))
+                })
+            };
+            let ty = match opcode {
+                OP_ENTRY_POINT => {
+                    module.fragment |= operand(0)? == EXECUTION_MODEL_FRAGMENT;
+                    continue;
+                }
+                OP_TYPE_INT | OP_TYPE_FLOAT => Type::Scalar { bytes: operand(1)? / 8 },
+                OP_TYPE_VECTOR => Type::Vector {
+                    component: operand(1)?,
+                    count: operand(2)?,
+                },
+                OP_TYPE_MATRIX => Type::Matrix {
+                    column: operand(1)?,
+                    columns: operand(2)?,
+                },
+                OP_TYPE_ARRAY => Type::Array {
+                    element: operand(1)?,
+                    length: operand(2)?,
+                },
+                OP_TYPE_RUNTIME_ARRAY => Type::RuntimeArray,
+                OP_TYPE_STRUCT => Type::Struct {
+                    members: operands[1..].to_vec(),
+                },
+                OP_TYPE_POINTER => Type::Pointer { pointee: operand(2)? },
+                OP_CONSTANT => {
+                    module.constants.insert(operand(1)?, operand(2)?);
+                    continue;
+                }
+                OP_VARIABLE => {
+                    module.variables.push((operand(1)?, operand(0)?, operand(2)?));
+                    continue;
+                }
+                OP_DECORATE => {
+                    let value = operands.get(2).copied().unwrap_or(0);
+                    module.decorations.entry(operand(0)?).or_default().push((operand(1)?, value));
+                    continue;
+                }
+                OP_MEMBER_DECORATE => {
+                    let member = module.members.entry((operand(0)?, operand(1)?)).or_default();
+                    match operand(2)? {
+                        DECORATION_OFFSET => member.offset = operand(3)?,
+                        DECORATION_MATRIX_STRIDE => member.matrix_stride = Some(operand(3)?),
+                        DECORATION_ROW_MAJOR => member.row_major = true,
+                        _ => {}
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            module.types.insert(operand(0)?, ty);
+        }
+
+        let mut interface = ShaderInterface::default();
+        for &(id, pointer, storage_class) in &module.variables {
+            let pointee = match module.types.get(&pointer) {
+                Some(Type::Pointer { pointee }) => *pointee,
+                _ => continue,
+            };
+            match storage_class {
+                STORAGE_CLASS_UNIFORM => {
+                    let block = match module.types.get(&pointee) {
+                        Some(Type::Array { element, .. }) => *element,
+                        _ => pointee,
+                    };
+                    let set = module.decoration(id, DECORATION_DESCRIPTOR_SET);
+                    let binding = module.decoration(id, DECORATION_BINDING);
+                    if let (Some(set), Some(binding), Some(_)) =
+                        (set, binding, module.decoration(block, DECORATION_BLOCK))
+                    {
+                        interface.uniform_block_sizes.insert((set, binding), module.size(block, None)?);
+                    }
+                }
+                STORAGE_CLASS_OUTPUT if module.fragment => {
+                    if let Some(location) = module.decoration(id, DECORATION_LOCATION) {
+                        interface.fragment_outputs.insert(location);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(interface)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::shader_path;
+
+    fn fixture(name: &str) -> Vec<u8> {
+        std::fs::read(shader_path(name)).unwrap()
+    }
+
+    #[test]
+    fn uniform_block_sizes_follow_offsets_and_strides() {
+        let blur = ShaderInterface::parse(&fixture("blur.frag.spv")).unwrap();
+        // A vec4, a mat4 and two floats each padded to 16 bytes.
+        assert_eq!(blur.uniform_block_sizes, BTreeMap::from([((3, 0), 16 + 64 + 32)]));
+
+        let histogram = ShaderInterface::parse(&fixture("histogram.comp.spv")).unwrap();
+        assert_eq!(histogram.uniform_block_sizes, BTreeMap::from([((3, 0), 8)]));
+    }
+
+    #[test]
+    fn only_fragment_shaders_have_colour_outputs() {
+        let blur = ShaderInterface::parse(&fixture("blur.frag.spv")).unwrap();
+        assert_eq!(blur.fragment_outputs, BTreeSet::from([0, 1]));
+
+        let fullscreen = ShaderInterface::parse(&fixture("fullscreen.vert.spv")).unwrap();
+        assert_eq!(fullscreen, ShaderInterface::default());
+    }
+
+    #[test]
+    fn malformed_modules_are_errors() {
+        let blur = fixture("blur.frag.spv");
+        assert!(ShaderInterface::parse(&blur[..10]).is_err());
+        assert!(ShaderInterface::parse(&blur[..blur.len() - 2]).is_err());
+        let mut overrunning = blur.clone();
+        overrunning[22..24].copy_from_slice(&0xffffu16.to_le_bytes());
+        assert!(ShaderInterface::parse(&overrunning).is_err());
+        assert!(ShaderInterface::parse(&[0; 20]).is_err());
+    }
+}